@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use fcpw::bench::standard_positions;
+use fcpw::sliders::SliderBackend;
+use fcpw::{generate, Bitboard, MoveList, Square};
+
+fn movegen(c: &mut Criterion) {
+    let positions = standard_positions();
+
+    c.bench_function("generate::legal", |b| {
+        b.iter(|| {
+            for pos in &positions {
+                black_box(generate::legal(black_box(pos)));
+            }
+        })
+    });
+    c.bench_function("generate::pseudo_legal", |b| {
+        b.iter(|| {
+            for pos in &positions {
+                black_box(generate::pseudo_legal(black_box(pos)));
+            }
+        })
+    });
+}
+
+fn make_move(c: &mut Criterion) {
+    let mut positions = standard_positions();
+    let moves: Vec<MoveList> = positions.iter().map(generate::legal).collect();
+
+    // Every legal move in every position, made and unmade.
+    c.bench_function("Position::make_move", |b| {
+        b.iter(|| {
+            for (pos, list) in positions.iter_mut().zip(&moves) {
+                for m in list {
+                    pos.make_move(black_box(m));
+                    pos.unmake_move(m);
+                }
+            }
+        })
+    });
+}
+
+fn slider_attacks(c: &mut Criterion) {
+    let occupancies: Vec<Bitboard> = standard_positions().iter().map(|p| p.all()).collect();
+    let squares: Vec<Square> = Bitboard::new(u64::MAX).into_iter().collect();
+
+    // Every backend compiled in, so one run with all the features compares them.
+    for backend in SliderBackend::ALL.into_iter().filter(|b| b.available()) {
+        c.bench_function(&format!("bishop_attacks/{:?}", backend), |b| {
+            b.iter(|| {
+                for &occ in &occupancies {
+                    for &sq in &squares {
+                        black_box(backend.bishop_attacks(sq, black_box(occ)));
+                    }
+                }
+            })
+        });
+        c.bench_function(&format!("rook_attacks/{:?}", backend), |b| {
+            b.iter(|| {
+                for &occ in &occupancies {
+                    for &sq in &squares {
+                        black_box(backend.rook_attacks(sq, black_box(occ)));
+                    }
+                }
+            })
+        });
+    }
+}
+
+criterion_group!(benches, movegen, make_move, slider_attacks);
+criterion_main!(benches);
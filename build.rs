@@ -0,0 +1,279 @@
+// Generates the slider attack tables for the `magic` feature, so nothing has to be searched or
+// filled in at startup. The output is `$OUT_DIR/magics.rs`, pulled into `src/magic.rs` with
+// `include!`. Without `magic` this does nothing.
+//
+// The plain magics come from the search the engine used to run on first use: a xorshift64*
+// generator per rank, sparse candidates, and the "fancy" layout where every square gets exactly
+// `1 << bits` slots. `pext` adds the same table reordered for indexing by the extracted
+// occupancy bits, and `black-magic` a second search for fixed-shift black magics.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+// One per rank. These happen to find every magic quickly; others work, just slower.
+const DEFAULT_SEEDS: [u64; 8] = [728, 10316, 55013, 32803, 12281, 15100, 16645, 255];
+
+const ROOK_DIRS: [(i32, i32); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
+const BISHOP_DIRS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(2685821657736338717)
+    }
+    fn sparse_u64(&mut self) -> u64 {
+        self.next_u64() & self.next_u64() & self.next_u64()
+    }
+}
+
+struct Entry {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+// Index bits for every black magic: enough for the busiest square of each piece, so every
+// square's slots start at `square << bits` and the shift is the same everywhere.
+const BLACK_BISHOP_BITS: u32 = 9;
+const BLACK_ROOK_BITS: u32 = 12;
+
+fn slider(square: usize, occupancy: u64, dirs: &[(i32, i32); 4]) -> u64 {
+    let mut attacks = 0;
+    for &(df, dr) in dirs {
+        let (mut f, mut r) = ((square % 8) as i32, (square / 8) as i32);
+        loop {
+            f += df;
+            r += dr;
+            if !(0..8).contains(&f) || !(0..8).contains(&r) {
+                break;
+            }
+            let bit = 1 << (r * 8 + f);
+            attacks |= bit;
+            if occupancy & bit != 0 {
+                break;
+            }
+        }
+    }
+    attacks
+}
+
+// Software `pext`, only used here to lay the table out the way the instruction will read it.
+fn pext(value: u64, mut mask: u64) -> u64 {
+    let mut out = 0;
+    let mut bit = 0;
+    while mask != 0 {
+        if value & mask & mask.wrapping_neg() != 0 {
+            out |= 1 << bit;
+        }
+        mask &= mask - 1;
+        bit += 1;
+    }
+    out
+}
+
+// The squares whose occupancy matters to a slider on `square`: its attacks on an empty board,
+// less the edge squares past which there is nothing left to block.
+fn relevant_mask(square: usize, dirs: &[(i32, i32); 4]) -> u64 {
+    let (file, rank) = (square % 8, square / 8);
+    let edges = ((0xFFu64 | 0xFF << 56) & !(0xFF << (rank * 8)))
+        | ((0x0101_0101_0101_0101u64 | 0x8080_8080_8080_8080) & !(0x0101_0101_0101_0101 << file));
+    slider(square, 0, dirs) & !edges
+}
+
+// Every subset of the mask, with the attacks it gives.
+fn subsets(square: usize, mask: u64, dirs: &[(i32, i32); 4]) -> Vec<(u64, u64)> {
+    let mut out = Vec::new();
+    let mut b = 0u64;
+    loop {
+        out.push((b, slider(square, b, dirs)));
+        b = b.wrapping_sub(mask) & mask;
+        if b == 0 {
+            break;
+        }
+    }
+    out
+}
+
+// Looks for a multiplier that sends every subset to a slot holding its attacks (or an equal
+// one), and writes them into `table` at `offset`. `fill` is how occupancy outside the mask is
+// treated: none of it for plain magics, all of it for black ones.
+fn search(
+    table: &mut [u64],
+    offset: usize,
+    rng: &mut Rng,
+    subsets: &[(u64, u64)],
+    mask: u64,
+    fill: u64,
+    shift: u32,
+) -> u64 {
+    let mut epoch = vec![0u32; 1 << (64 - shift)];
+    let mut count = 0;
+    'search: loop {
+        let magic = rng.sparse_u64();
+        if (magic.wrapping_mul(mask) >> 56).count_ones() < 6 {
+            continue;
+        }
+
+        count += 1;
+        for &(occ, att) in subsets {
+            let index = ((occ | fill).wrapping_mul(magic) >> shift) as usize;
+            if epoch[index] < count {
+                epoch[index] = count;
+                table[offset + index] = att;
+            } else if table[offset + index] != att {
+                continue 'search;
+            }
+        }
+        return magic;
+    }
+}
+
+// Appends the plain magic attacks for every square to `table`, which bishops and rooks share.
+fn build(table: &mut Vec<u64>, dirs: &[(i32, i32); 4], seeds: &[u64; 8]) -> Vec<Entry> {
+    let mut entries = Vec::with_capacity(64);
+
+    for square in 0..64 {
+        let mask = relevant_mask(square, dirs);
+        let bits = mask.count_ones();
+        let shift = 64 - bits;
+        let offset = table.len();
+        table.resize(offset + (1 << bits), 0);
+
+        let mut rng = Rng(seeds[square / 8]);
+        let subsets = subsets(square, mask, dirs);
+        let magic = search(table, offset, &mut rng, &subsets, mask, 0, shift);
+        entries.push(Entry {
+            mask,
+            magic,
+            shift,
+            offset,
+        });
+    }
+
+    entries
+}
+
+// The same slots as the plain magics use, ordered by `pext` of the occupancy instead.
+fn build_pext(table: &mut [u64], entries: &[Entry], dirs: &[(i32, i32); 4]) {
+    for (square, e) in entries.iter().enumerate() {
+        for (occ, att) in subsets(square, e.mask, dirs) {
+            table[e.offset + pext(occ, e.mask) as usize] = att;
+        }
+    }
+}
+
+// Appends the black magic attacks for every square to `table`, at `square << bits` past where
+// it started. The entries keep the complement of the mask, which is what the lookup ORs in.
+fn build_black(
+    table: &mut Vec<u64>,
+    dirs: &[(i32, i32); 4],
+    bits: u32,
+    seeds: &[u64; 8],
+) -> Vec<Entry> {
+    let mut entries = Vec::with_capacity(64);
+    let base = table.len();
+    table.resize(base + (64 << bits), 0);
+
+    for square in 0..64 {
+        let mask = relevant_mask(square, dirs);
+        let offset = base + (square << bits);
+
+        let mut rng = Rng(seeds[square / 8]);
+        let subsets = subsets(square, mask, dirs);
+        let magic = search(table, offset, &mut rng, &subsets, mask, !mask, 64 - bits);
+        entries.push(Entry {
+            mask: !mask,
+            magic,
+            shift: 64 - bits,
+            offset,
+        });
+    }
+
+    entries
+}
+
+fn emit_magics(out: &mut String, name: &str, entries: &[Entry]) {
+    writeln!(out, "const {name}: [Magic; 64] = [").unwrap();
+    for e in entries {
+        writeln!(
+            out,
+            "    Magic {{ mask: {:#018x}, magic: {:#018x}, shift: {}, offset: {:#x} }},",
+            e.mask, e.magic, e.shift, e.offset
+        )
+        .unwrap();
+    }
+    writeln!(out, "];").unwrap();
+}
+
+fn emit_table(out: &mut String, name: &str, table: &[u64]) {
+    writeln!(out, "static {name}: [u64; {:#x}] = [", table.len()).unwrap();
+    for row in table.chunks(8) {
+        out.push_str("   ");
+        for a in row {
+            write!(out, " {a:#x},").unwrap();
+        }
+        out.push('\n');
+    }
+    writeln!(out, "];").unwrap();
+}
+
+// `FCPW_MAGIC_SEEDS` takes eight comma-separated seeds, one per rank.
+fn seeds() -> [u64; 8] {
+    let Ok(var) = env::var("FCPW_MAGIC_SEEDS") else {
+        return DEFAULT_SEEDS;
+    };
+    let parsed: Vec<u64> = var
+        .split(',')
+        .map(|s| s.trim().parse().expect("FCPW_MAGIC_SEEDS: not a number"))
+        .collect();
+    parsed
+        .try_into()
+        .expect("FCPW_MAGIC_SEEDS: needs exactly eight seeds")
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=FCPW_MAGIC_SEEDS");
+
+    if env::var_os("CARGO_FEATURE_MAGIC").is_none() {
+        return;
+    }
+    let seeds = seeds();
+
+    let mut out = String::new();
+    writeln!(out, "// Generated by build.rs. Do not edit.").unwrap();
+    writeln!(out, "pub(crate) const SEEDS: [u64; 8] = {seeds:?};").unwrap();
+
+    let mut table = Vec::new();
+    let bishops = build(&mut table, &BISHOP_DIRS, &seeds);
+    let rooks = build(&mut table, &ROOK_DIRS, &seeds);
+    emit_magics(&mut out, "BISHOP_MAGICS", &bishops);
+    emit_magics(&mut out, "ROOK_MAGICS", &rooks);
+    emit_table(&mut out, "ATTACKS", &table);
+
+    let x86_64 = env::var("CARGO_CFG_TARGET_ARCH").is_ok_and(|arch| arch == "x86_64");
+    if env::var_os("CARGO_FEATURE_PEXT").is_some() && x86_64 {
+        build_pext(&mut table, &bishops, &BISHOP_DIRS);
+        build_pext(&mut table, &rooks, &ROOK_DIRS);
+        emit_table(&mut out, "PEXT_ATTACKS", &table);
+    }
+
+    if env::var_os("CARGO_FEATURE_BLACK_MAGIC").is_some() {
+        let mut table = Vec::new();
+        let bishops = build_black(&mut table, &BISHOP_DIRS, BLACK_BISHOP_BITS, &seeds);
+        let rooks = build_black(&mut table, &ROOK_DIRS, BLACK_ROOK_BITS, &seeds);
+        emit_magics(&mut out, "BLACK_BISHOP_MAGICS", &bishops);
+        emit_magics(&mut out, "BLACK_ROOK_MAGICS", &rooks);
+        emit_table(&mut out, "BLACK_ATTACKS", &table);
+    }
+
+    let path = Path::new(&env::var_os("OUT_DIR").unwrap()).join("magics.rs");
+    fs::write(path, out).unwrap();
+}
@@ -12,3 +12,6 @@ hyperfine --warmup 5\
     "$(perft_features "-F pext,inline-aggressive")"\
     "$(perft_features "-F magic,inline")"\
     "$(perft_features "-F magic,inline-aggressive")"\
+    "$(perft_features "-F bitboard-only")"\
+    "$(perft_features "-F pext,bitboard-only")"\
+    "$(perft_features "-F magic,inline,bitboard-only")"\
@@ -0,0 +1,670 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::bitboard::Bitboard;
+use crate::color::Color;
+use crate::eval;
+use crate::movegen::Move;
+use crate::piece::{Piece, PieceType};
+use crate::pool;
+use crate::position::Position;
+use crate::search::{Score, SearchResult, Searcher};
+use crate::square::Square;
+use crate::time::{Limits, TimeManager};
+use crate::tt::TranspositionTable;
+use crate::values;
+
+// How much the best move matters: its score, and how far behind the next best is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Criticality {
+    pub best_move: Move,
+    pub score: i32,
+    // The best score minus the second best, `None` when there is only one legal move.
+    pub gap: Option<i32>,
+    pub forced: bool,
+}
+
+impl Criticality {
+    // The annotation for the best move: `!` when it's the only move that holds.
+    pub fn symbol(&self) -> Option<&'static str> {
+        self.forced.then_some("!")
+    }
+}
+
+// Whether the best move at `depth` is forced, meaning every other move scores at least
+// `threshold` centipawns worse (or there is no other move). A forced move deserves a `!` in
+// annotated games, and is worth spending more time on, as a mistake there loses the most.
+// `None` if there are no legal moves.
+pub fn criticality(pos: &Position, depth: u32, threshold: i32) -> Option<Criticality> {
+    let mut pos = pos.clone();
+    let lines = Searcher::new().search_lines(&mut pos, depth, 2);
+
+    let &(best_move, score) = lines.first()?;
+    let gap = lines.get(1).map(|&(_, second)| score - second);
+    Some(Criticality {
+        best_move,
+        score,
+        gap,
+        forced: gap.is_none_or(|g| g >= threshold),
+    })
+}
+
+// One line of a MultiPV analysis: the moves from the analysed position, the score after them
+// from its side to move, and the depth it was searched to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnalysisLine {
+    pub moves: Vec<Move>,
+    pub score: i32,
+    pub depth: u32,
+}
+
+// The `lines` best moves of `pos` at `depth`, best first, each followed by the replies `tt`
+// holds for the positions after it, to at most `depth` moves in all. The table is where the
+// lines are read back from, so one kept across calls holds the analysis of them all.
+pub fn analyse(
+    pos: &Position,
+    depth: u32,
+    lines: usize,
+    tt: &Arc<TranspositionTable>,
+) -> Vec<AnalysisLine> {
+    let mut pos = pos.clone();
+    let mut searcher = Searcher::new();
+    searcher.set_tt(Arc::clone(tt));
+
+    let mut found = Vec::new();
+    for (first, score) in searcher.search_lines(&mut pos, depth, lines) {
+        pos.make_move(first);
+        let mut moves = vec![first];
+        moves.extend(table_line(&pos, tt, (depth as usize).saturating_sub(1)));
+        pos.undo();
+        found.push(AnalysisLine {
+            moves,
+            score,
+            depth,
+        });
+    }
+    found
+}
+
+// The moves `tt` holds as best from `pos` on, at most `max` of them. Stops early at a move
+// that isn't legal (the entry of another position with the same slot) or at a position the
+// line has already been through.
+pub fn table_line(pos: &Position, tt: &TranspositionTable, max: usize) -> Vec<Move> {
+    let mut pos = pos.detached();
+    let mut seen = vec![pos.key()];
+    let mut line = Vec::new();
+
+    while line.len() < max {
+        let Some(m) = tt.probe(pos.key(), 0).and_then(|e| e.best_move) else {
+            break;
+        };
+        if !pos.is_pseudo_legal(m) || !pos.is_legal(m) {
+            break;
+        }
+        pos.make_move(m);
+        if seen.contains(&pos.key()) {
+            break;
+        }
+        seen.push(pos.key());
+        line.push(m);
+    }
+    line
+}
+
+// What `analyse_all` may spend on a set of positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Nodes(u64),
+    // Search time summed over the positions: with threads free in `pool` several are searched
+    // at once, and it's over sooner.
+    Time(Duration),
+}
+
+// One position's analysis from `analyse_all`: the search, what share of the budget it was
+// given, and how unsettled the first look at it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scheduled {
+    pub result: SearchResult,
+    pub share: Budget,
+    pub volatility: i32,
+}
+
+// The depths of the first look at each position, whose scores and best moves are compared.
+const PROBE_DEPTHS: [u32; 2] = [2, 3];
+// Volatility for a best move that changed between them, and the most any position counts as,
+// so a mate turning up doesn't take the whole budget.
+const MOVE_CHANGE: i32 = 100;
+const MAX_VOLATILITY: i32 = 1000;
+
+// Searches each of `positions` to at most `depth`, with `budget` shared out among them: the
+// more a position's score and best move moved between two shallow searches, the larger its
+// part of what those left, as the answer there is the least settled. Meant for annotating a
+// whole game, where most positions need little and a few critical ones need a lot. Results
+// are in the order of `positions`, and every search finishes at least one iteration, however
+// little it's given. The table is shared by all the searches.
+pub fn analyse_all(
+    positions: &[Position],
+    budget: Budget,
+    depth: u32,
+    tt: &Arc<TranspositionTable>,
+) -> Vec<Scheduled> {
+    let searcher = || {
+        let mut searcher = Searcher::new();
+        searcher.set_tt(Arc::clone(tt));
+        searcher
+    };
+
+    let probes = pool::map(positions, |pos| {
+        let started = Instant::now();
+        let mut pos = pos.clone();
+        let mut searcher = searcher();
+        let [shallow, deeper] = PROBE_DEPTHS.map(|d| {
+            let result = searcher.search(&mut pos, d);
+            (result, searcher.nodes())
+        });
+
+        let mut volatility = (deeper.0.score - shallow.0.score).abs();
+        if deeper.0.best_move != shallow.0.best_move {
+            volatility += MOVE_CHANGE;
+        }
+        (
+            volatility.min(MAX_VOLATILITY),
+            shallow.1 + deeper.1,
+            started.elapsed(),
+        )
+    });
+
+    // Every position gets something, the most volatile up to a thousand times as much.
+    let weights: Vec<u128> = probes.iter().map(|&(v, ..)| 1 + v as u128).collect();
+    let total: u128 = weights.iter().sum();
+    let share = |left: u128, i: usize| left * weights[i] / total.max(1);
+    let shares: Vec<Budget> = match budget {
+        Budget::Nodes(nodes) => {
+            let spent: u64 = probes.iter().map(|&(_, n, _)| n).sum();
+            let left = nodes.saturating_sub(spent) as u128;
+            (0..positions.len())
+                .map(|i| Budget::Nodes(share(left, i) as u64))
+                .collect()
+        }
+        Budget::Time(time) => {
+            let spent: Duration = probes.iter().map(|&(.., t)| t).sum();
+            let left = time.saturating_sub(spent).as_nanos();
+            (0..positions.len())
+                .map(|i| Budget::Time(Duration::from_nanos(share(left, i) as u64)))
+                .collect()
+        }
+    };
+
+    let jobs: Vec<(&Position, Budget, i32)> = positions
+        .iter()
+        .zip(&shares)
+        .zip(&probes)
+        .map(|((pos, &share), &(volatility, ..))| (pos, share, volatility))
+        .collect();
+    pool::map(&jobs, |&(pos, share, volatility)| {
+        let mut pos = pos.clone();
+        let mut searcher = searcher();
+        match share {
+            Budget::Nodes(nodes) => searcher.set_node_limit(Some(nodes)),
+            Budget::Time(time) => {
+                let limits = Limits {
+                    movetime: Some(time),
+                    ..Limits::default()
+                };
+                searcher.set_time(TimeManager::new(&limits, pos.to_move()));
+            }
+        }
+        Scheduled {
+            result: searcher.search(&mut pos, depth),
+            share,
+            volatility,
+        }
+    })
+}
+
+// The tactical point of a move, as far as exchanges on the board show it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tactic {
+    // The line mates.
+    Mate,
+    // Material won outright on the move's square: its static exchange value.
+    WinsMaterial(i32),
+    // Material given up on the move's square, by its static exchange value, for what the line
+    // gets back.
+    Sacrifice(i32),
+    // The moved piece attacks pieces that are loose or worth more than it, too many to save at
+    // once; a check counts as one of them.
+    Fork(Vec<Square>),
+    // The same, but only one piece.
+    Threat(Square),
+}
+
+// A short account of why the first move of a line was chosen. Swings are from the side to
+// move's point of view, between the positions at the two ends of the line: material from the
+// pieces that changed hands, and placement, what the evaluation thinks of where the rest stand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub mov: Move,
+    pub san: String,
+    pub score: i32,
+    pub depth: u32,
+    pub check: bool,
+    pub tactic: Option<Tactic>,
+    pub material_swing: i32,
+    pub placement_swing: i32,
+}
+
+// Explains `line`, as `analyse` found it for `pos`. `None` for a line with no moves.
+pub fn explain(pos: &Position, line: &AnalysisLine) -> Option<Explanation> {
+    let &mov = line.moves.first()?;
+    let us = pos.to_move();
+    let see = pos.see(mov);
+    let check = pos.gives_check(mov);
+
+    let mut after = pos.detached();
+    after.make_move(mov);
+    let threats = threatened(&after, mov.to());
+    let mut end = after.detached();
+    end.make_moves(&line.moves[1..]).ok()?;
+
+    let tactic = if Score::from_raw(line.score).is_mate() && line.score > 0 {
+        Some(Tactic::Mate)
+    } else if see > 0 {
+        Some(Tactic::WinsMaterial(see))
+    } else if see < 0 {
+        Some(Tactic::Sacrifice(-see))
+    } else if threats.len() + check as usize >= 2 {
+        Some(Tactic::Fork(threats))
+    } else {
+        threats.first().map(|&s| Tactic::Threat(s))
+    };
+
+    // Material only differs on the squares that changed, so they are all that need counting.
+    let value = |piece: Option<Piece>| {
+        piece.map_or(0, |p| {
+            let v = values::piece_value(p.kind());
+            if p.color() == us {
+                v
+            } else {
+                -v
+            }
+        })
+    };
+    let material_swing = pos
+        .diff(&end)
+        .squares
+        .into_iter()
+        .map(|(_, before, after)| value(after) - value(before))
+        .sum();
+    let ours = |p: &Position| {
+        if p.to_move() == us {
+            eval::quick(p)
+        } else {
+            -eval::quick(p)
+        }
+    };
+
+    Some(Explanation {
+        mov,
+        san: mov.to_san(pos),
+        score: line.score,
+        depth: line.depth,
+        check,
+        tactic,
+        material_swing,
+        placement_swing: ours(&end) - ours(pos) - material_swing,
+    })
+}
+
+// The pieces the piece on `square` attacks that are undefended or worth more than it, kings
+// aside. `pos` has the other side to move, as after the moving piece has just arrived.
+fn threatened(pos: &Position, square: Square) -> Vec<Square> {
+    let Some(attacker) = pos.piece_on(square) else {
+        return Vec::new();
+    };
+    let them = pos.to_move();
+    let targets = pos.attacks_from(square) & pos.color(them) & !pos.pieces(PieceType::King);
+    targets
+        .into_iter()
+        .filter(|&t| {
+            let kind = pos.piece_on(t).expect("a target is occupied").kind();
+            values::piece_value(kind) > values::piece_value(attacker.kind())
+                || !pos.is_attacked(t, them)
+        })
+        .collect()
+}
+
+impl std::fmt::Display for Explanation {
+    // `Nxe5 (+1.20, depth 8): wins 1.00 by exchange, with check; over the line, material
+    // +1.00, placement -0.15`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let score = match Score::from_raw(self.score) {
+            Score::Cp(cp) => pawns(cp),
+            Score::Mate(n) => format!("mate in {}", n),
+        };
+        write!(f, "{} ({}, depth {}): ", self.san, score, self.depth)?;
+        match &self.tactic {
+            Some(Tactic::Mate) => write!(f, "mates")?,
+            Some(Tactic::WinsMaterial(cp)) => write!(f, "wins {} by exchange", &pawns(*cp)[1..])?,
+            Some(Tactic::Sacrifice(cp)) => write!(f, "gives up {}", &pawns(*cp)[1..])?,
+            Some(Tactic::Fork(squares)) => {
+                let names: Vec<String> = squares.iter().map(|s| s.to_string()).collect();
+                write!(f, "forks {}", names.join(", "))?
+            }
+            Some(Tactic::Threat(square)) => write!(f, "threatens {}", square)?,
+            None => write!(f, "quiet")?,
+        }
+        if self.check {
+            write!(f, ", with check")?;
+        }
+        write!(
+            f,
+            "; over the line, material {}, placement {}",
+            pawns(self.material_swing),
+            pawns(self.placement_swing)
+        )
+    }
+}
+
+// Centipawns as signed pawns: `+1.20`.
+fn pawns(cp: i32) -> String {
+    format!("{:+.2}", cp as f64 / 100.0)
+}
+
+// The attack/defence picture of the whole board as one JSON object, for frontends drawing
+// arrows and overlays:
+//
+// `{"to_move":"w","checkers":[..],"pins":[{"pinned":"e2","pinner":"e8"},..],"squares":[..]}`
+//
+// with one entry per square, a1 to h8: `{"square":"e4","occupant":"P","attackers":[..],"defenders":[..]}`.
+// For an occupied square the defenders are the occupant's own side, for an empty square they
+// are the side to move's. Only direct attacks count; nothing is seen through another piece.
+pub fn board_graph(pos: &Position) -> String {
+    let us = pos.to_move();
+
+    let mut pins = Vec::new();
+    for color in [Color::White, Color::Black] {
+        let king = pos.king(color);
+        for pinner in pos.pinners(!color) {
+            let pinned = Bitboard::interval(king, pinner) & pos.color(color);
+            if pinned.nonzero() {
+                pins.push(format!(
+                    r#"{{"pinned":"{}","pinner":"{}"}}"#,
+                    pinned.lsb(),
+                    pinner
+                ));
+            }
+        }
+    }
+
+    let mut squares = Vec::with_capacity(64);
+    for square in Square::iter() {
+        let occupant = pos.piece_on(square);
+        let side = occupant.map_or(us, |p| p.color());
+
+        squares.push(format!(
+            r#"{{"square":"{}","occupant":{},"attackers":{},"defenders":{}}}"#,
+            square,
+            occupant.map_or("null".to_string(), |p| format!("\"{}\"", char::from(p))),
+            square_list(pos.attackers_to(square, !side)),
+            square_list(pos.attackers_to(square, side)),
+        ));
+    }
+
+    format!(
+        r#"{{"to_move":"{}","checkers":{},"pins":[{}],"squares":[{}]}}"#,
+        if us == Color::White { 'w' } else { 'b' },
+        square_list(pos.checkers()),
+        pins.join(","),
+        squares.join(","),
+    )
+}
+
+// Groups move sequences by the position they end in, so lines that transpose into each other
+// (e.g. book lines, or the same puzzle reached from different games) can be merged. Each line is
+// a start position and the moves played from it. Positions are compared by `canonical_key`, so
+// a castling right or en passant square that makes no difference doesn't split a group.
+//
+// Groups hold indices into `lines`, in order of first appearance; those with more than one
+// entry are the transpositions. Fails with the index of the first line that has an illegal move.
+pub fn group_transpositions(lines: &[(&Position, &[Move])]) -> Result<Vec<Vec<usize>>, usize> {
+    let mut groups: Vec<(u64, Vec<usize>)> = Vec::new();
+
+    for (i, &(start, moves)) in lines.iter().enumerate() {
+        let mut pos = start.detached();
+        pos.make_moves(moves).map_err(|_| i)?;
+
+        let key = pos.canonical_key();
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, group)) => group.push(i),
+            None => groups.push((key, vec![i])),
+        }
+    }
+
+    Ok(groups.into_iter().map(|(_, group)| group).collect())
+}
+
+fn square_list(bb: Bitboard) -> String {
+    let names: Vec<String> = bb.into_iter().map(|s| format!("\"{}\"", s)).collect();
+    format!("[{}]", names.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE;
+    use crate::square::Square::*;
+
+    #[test]
+    fn pins_checks_and_squares() {
+        // The e2 knight is pinned by the e8 rook, and the b4 bishop checks through d2.
+        let pos = Position::new_from_fen("k3r3/8/8/8/1b6/8/4N3/4K3 w - - 0 1");
+        let json = board_graph(&pos);
+
+        assert!(json.starts_with(
+            r#"{"to_move":"w","checkers":["b4"],"pins":[{"pinned":"e2","pinner":"e8"}],"#
+        ));
+        assert!(json
+            .contains(r#"{"square":"e2","occupant":"N","attackers":["e8"],"defenders":["e1"]}"#));
+        assert!(json
+            .contains(r#"{"square":"d2","occupant":null,"attackers":["b4"],"defenders":["e1"]}"#));
+        assert!(json.ends_with("]}"));
+        assert_eq!(json.matches(r#"{"square":"#).count(), 64);
+    }
+
+    #[test]
+    fn transpositions() {
+        use crate::square::Square::*;
+
+        let start = Position::default();
+        let (nf3, nf6, nc3, nc6) = (
+            Move::new(G1, F3),
+            Move::new(G8, F6),
+            Move::new(B1, C3),
+            Move::new(B8, C6),
+        );
+        // The same position as the first two lines, set up directly.
+        let fen =
+            Position::new_from_fen("r1bqkbnr/pppppppp/2n5/8/8/2N5/PPPPPPPP/R1BQKBNR w KQkq - 2 2");
+        let lines: [(&Position, &[Move]); 5] = [
+            (&start, &[nc3, nc6]),
+            (
+                &start,
+                &[nf3, nf6, nc3, nc6, Move::new(F3, G1), Move::new(F6, G8)],
+            ),
+            (&start, &[nf3, nf6]),
+            (&fen, &[]),
+            (&start, &[nf3, nc6]),
+        ];
+        assert_eq!(
+            group_transpositions(&lines).unwrap(),
+            [vec![0, 1, 3], vec![2], vec![4]]
+        );
+
+        let illegal: [(&Position, &[Move]); 2] = [(&start, &[nf3]), (&start, &[nf6])];
+        assert_eq!(group_transpositions(&illegal), Err(1));
+    }
+
+    #[test]
+    fn only_moves() {
+        use crate::square::Square::*;
+
+        // Only the recapture keeps the material level.
+        let pos = Position::new_from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1");
+        let crit = criticality(&pos, 2, 200).unwrap();
+        assert_eq!(crit.best_move, Move::new(D2, D5));
+        assert!(crit.forced && crit.gap.unwrap() >= 200);
+        assert_eq!(crit.symbol(), Some("!"));
+
+        // Plenty of equal moves at the start.
+        let crit = criticality(&Position::default(), 2, 50).unwrap();
+        assert!(!crit.forced);
+        assert_eq!(crit.symbol(), None);
+
+        // A single legal move is forced whatever the threshold.
+        let pos = Position::new_from_fen("k7/1R6/8/8/8/8/8/K6R b - - 0 1");
+        let crit = criticality(&pos, 1, i32::MAX).unwrap();
+        assert_eq!((crit.best_move, crit.gap), (Move::new(A8, B7), None));
+        assert!(crit.forced);
+
+        let mated = Position::new_from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(criticality(&mated, 1, 100), None);
+    }
+
+    #[test]
+    fn lines_follow_the_table() {
+        let tt = Arc::new(TranspositionTable::new(1));
+        let pos = Position::new_from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        );
+        assert!(table_line(&pos, &tt, 10).is_empty());
+
+        let lines = analyse(&pos, 4, 3, &tt);
+        assert_eq!(lines.len(), 3);
+        assert!(lines.windows(2).all(|w| w[0].score >= w[1].score));
+        assert!(lines.iter().all(|l| l.depth == 4 && l.moves.len() <= 4));
+        assert!(lines[0].moves.len() > 1);
+        for line in &lines {
+            let mut after = pos.clone();
+            after.make_moves(&line.moves).unwrap();
+        }
+        let firsts: Vec<Move> = lines.iter().map(|l| l.moves[0]).collect();
+        assert!(!firsts[1..].contains(&firsts[0]) && firsts[1] != firsts[2]);
+
+        // The table keeps the best line for later.
+        let stored = table_line(&pos, &tt, 4);
+        assert_eq!(stored.first(), Some(&lines[0].moves[0]));
+    }
+
+    #[test]
+    fn shares_the_budget_by_volatility() {
+        let tt = Arc::new(TranspositionTable::new(1));
+        let positions = [
+            Position::default(),
+            Position::new_from_fen(Position::KIWIPETE_FEN),
+            // A loose queen for the knight to take.
+            Position::new_from_fen("r3k3/8/8/3q4/8/2N5/8/4K3 w - - 0 1"),
+        ];
+
+        let analysed = analyse_all(&positions, Budget::Nodes(300_000), 64, &tt);
+        assert_eq!(analysed.len(), 3);
+        assert!(analysed.iter().all(|a| a.result.best_move.is_some()));
+
+        // The more volatile a position, the more of the budget it gets.
+        let mut shares: Vec<(i32, u64)> = analysed
+            .iter()
+            .map(|a| match a.share {
+                Budget::Nodes(n) => (a.volatility, n),
+                Budget::Time(_) => unreachable!(),
+            })
+            .collect();
+        assert!(shares.iter().map(|&(_, n)| n).sum::<u64>() <= 300_000);
+        shares.sort();
+        assert!(shares.windows(2).all(|w| w[0].1 <= w[1].1));
+
+        // With nothing to share out, each still gets its first iteration.
+        let analysed = analyse_all(&positions, Budget::Nodes(0), 64, &tt);
+        assert!(analysed
+            .iter()
+            .all(|a| a.share == Budget::Nodes(0) && a.result.best_move.is_some()));
+
+        let started = Instant::now();
+        let analysed = analyse_all(
+            &positions,
+            Budget::Time(Duration::from_millis(150)),
+            64,
+            &tt,
+        );
+        assert!(analysed.iter().all(|a| a.result.best_move.is_some()));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    // `sans` from `fen`, as a line found at depth 4.
+    fn line(fen: &str, sans: &[&str], score: i32) -> (Position, AnalysisLine) {
+        let pos = Position::new_from_fen(fen);
+        let mut end = pos.clone();
+        let moves = sans
+            .iter()
+            .map(|san| {
+                let m = Move::from_san(san, &end).unwrap();
+                end.make_move(m);
+                m
+            })
+            .collect();
+        let line = AnalysisLine {
+            moves,
+            score,
+            depth: 4,
+        };
+        (pos, line)
+    }
+
+    #[test]
+    fn explains_tactics() {
+        let (pos, fork) = line(
+            "r3k3/8/8/1N6/8/8/8/4K3 w - - 0 1",
+            &["Nc7+", "Kd7", "Nxa8"],
+            480,
+        );
+        let explained = explain(&pos, &fork).unwrap();
+        assert_eq!(explained.tactic, Some(Tactic::Fork(vec![A8])));
+        assert!(explained.check);
+        assert_eq!(explained.material_swing, 500);
+        assert!(explained.to_string().starts_with(
+            "Nc7+ (+4.80, depth 4): forks a8, with check; over the line, material +5.00, placement"
+        ));
+
+        let (pos, capture) = line("4k3/8/8/3q4/8/2N5/8/4K3 w - - 0 1", &["Nxd5"], 900);
+        let explained = explain(&pos, &capture).unwrap();
+        assert_eq!(explained.tactic, Some(Tactic::WinsMaterial(900)));
+        assert!(!explained.check);
+
+        // The pawn is defended by the king.
+        let (pos, sacrifice) = line("4k3/3p4/8/8/8/8/8/3QK3 w - - 0 1", &["Qxd7+", "Kxd7"], -800);
+        let explained = explain(&pos, &sacrifice).unwrap();
+        assert_eq!(explained.tactic, Some(Tactic::Sacrifice(800)));
+        assert_eq!(explained.material_swing, -800);
+
+        let (pos, mate) = line("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", &["Ra8#"], MATE - 1);
+        let explained = explain(&pos, &mate).unwrap();
+        assert_eq!(explained.tactic, Some(Tactic::Mate));
+        assert!(explained
+            .to_string()
+            .starts_with("Ra8# (mate in 1, depth 4): mates, with check;"));
+
+        let quiet = AnalysisLine {
+            moves: Vec::new(),
+            score: 0,
+            depth: 1,
+        };
+        assert_eq!(explain(&pos, &quiet), None);
+
+        // Straight from an analysis.
+        let tt = Arc::new(TranspositionTable::new(1));
+        let pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        let best = &analyse(&pos, 3, 1, &tt)[0];
+        let explained = explain(&pos, best).unwrap();
+        assert_eq!(explained.mov, best.moves[0]);
+        assert!(explained.to_string().starts_with(&explained.san));
+    }
+}
@@ -0,0 +1,33 @@
+use crate::bitboard::Bitboard;
+use crate::position::Position;
+use crate::precompute;
+use crate::square::Square;
+
+// Entry points for benchmarks (`benches/`, `bench` over UCI) that want the same positions
+// every time, or internals that aren't otherwise public.
+
+// A spread of openings, middlegames and endgames, with plenty of checks, pins, castling,
+// en passant and promotions between them. Changing these changes every benchmark number.
+pub const STANDARD_FENS: [&str; 6] = [
+    Position::STARTING_FEN,
+    Position::KIWIPETE_FEN,
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "3r1rk1/1p2b1p1/n2pp1np/4p3/1P2P3/2q1NNB1/Q4PPP/R2R2K1 w - - 0 22",
+];
+
+pub fn standard_positions() -> Vec<Position> {
+    STANDARD_FENS
+        .iter()
+        .map(|fen| Position::new_from_fen(fen))
+        .collect()
+}
+
+// The sliding attack lookups movegen uses, whichever backend is compiled in.
+pub fn bishop_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    precompute::bishop_attacks(square, occupied)
+}
+pub fn rook_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    precompute::rook_attacks(square, occupied)
+}
@@ -1,11 +1,13 @@
 use std::hint::assert_unchecked;
 use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Neg, Not};
 use std::ops::{Shl, ShlAssign, Shr, ShrAssign};
+use std::str::FromStr;
 
+use crate::errors::MoveParseError;
 use crate::precompute;
 use crate::square::{Direction, File, Rank, Square};
 
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Clone, Copy)]
+#[derive(PartialEq, PartialOrd, Eq, Ord, Clone, Copy)]
 pub struct Bitboard(u64);
 
 impl Bitboard {
@@ -28,6 +30,8 @@ impl Bitboard {
         // SAFETY: This index is less than 64, since the internal u64 is nonzero.
         unsafe { std::mem::transmute(index) }
     }
+    /// # Safety
+    /// The bitboard must not be empty.
     #[cfg_attr(feature = "inline", inline)]
     pub unsafe fn lsb_unchecked(self) -> Square {
         assert_unchecked(self.0 != 0);
@@ -45,10 +49,12 @@ impl Bitboard {
         // SAFETY: This index is less than 64, since the internal u64 is nonzero.
         unsafe { std::mem::transmute(63 - index) }
     }
+    /// # Safety
+    /// The bitboard must not be empty.
     #[cfg_attr(feature = "inline", inline)]
     pub unsafe fn msb_unchecked(self) -> Square {
         assert_unchecked(self.0 != 0);
-        std::mem::transmute(63 - self.0.trailing_zeros() as u8)
+        std::mem::transmute(63 - self.0.leading_zeros() as u8)
     }
 
     #[cfg_attr(feature = "inline", inline)]
@@ -79,13 +85,10 @@ impl Bitboard {
         self.0.count_ones() as i32
     }
 
+    // The squares strictly between `a` and `b`; see `precompute::between`.
     #[cfg_attr(feature = "inline-aggressive", inline)]
     pub fn interval(a: Square, b: Square) -> Self {
-        if let Some(dir) = a.dir_to(b) {
-            precompute::ray(a, dir) & precompute::ray(b, !dir)
-        } else {
-            Self::new(0)
-        }
+        precompute::between(a, b)
     }
 
     #[cfg_attr(feature = "inline", inline)]
@@ -190,6 +193,18 @@ impl Bitboard {
             SouthWest => self.shift(South).shift(West),
         }
     }
+    // `n` single steps in `dir`: whatever would leave the board along the way is dropped, so
+    // nothing wraps around to the other edge however far it goes.
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn shift_n(self, dir: Direction, n: u32) -> Self {
+        let mut rv = self;
+        let mut i = 0;
+        while i < n && rv.nonzero() {
+            rv = rv.shift(dir);
+            i += 1;
+        }
+        rv
+    }
 
     #[cfg_attr(feature = "inline", inline)]
     pub const fn sub(self, other: Self) -> Self {
@@ -208,6 +223,41 @@ impl Bitboard {
 #[derive(Debug)]
 pub struct BitboardIter(Bitboard);
 
+impl Bitboard {
+    // The same grid as Display, with the ranks down the left and the files along the bottom.
+    pub fn pretty(self) -> String {
+        let mut out = String::new();
+        for rank in (0..8).rev() {
+            out.push((b'1' + rank) as char);
+            for file in 0..8 {
+                let square = Square::from_index(rank * 8 + file).unwrap();
+                out += if self.has(square) { " X" } else { " ." };
+            }
+            out.push('\n');
+        }
+        out += "  a b c d e f g h\n";
+        out
+    }
+}
+
+impl std::fmt::Debug for Bitboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bitboard({:#018x})", self.0)
+    }
+}
+
+// Square names separated by whitespace or commas: "a1 b2 c3". An empty string is an empty board.
+impl FromStr for Bitboard {
+    type Err = MoveParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|name| !name.is_empty())
+            .try_fold(Self::new(0), |bb, name| {
+                Ok(bb | Bitboard::from(Square::try_from(name.as_bytes())?))
+            })
+    }
+}
+
 impl std::fmt::Display for Bitboard {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut bb_str = String::new();
@@ -527,3 +577,47 @@ impl ShlAssign<Direction> for Bitboard {
         *self = self.shift(rhs);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Square::*;
+
+    #[test]
+    fn parses_and_prints() {
+        let bb: Bitboard = "a1 b2, c3\th8".parse().unwrap();
+        assert_eq!(bb, Bitboard::from([A1, B2, C3, H8]));
+        assert_eq!("".parse::<Bitboard>(), Ok(Bitboard::new(0)));
+        assert_eq!(
+            "a1 i9".parse::<Bitboard>(),
+            Err(MoveParseError::Square("i9".to_string()))
+        );
+        assert!("a1b2".parse::<Bitboard>().is_err());
+
+        assert_eq!(format!("{:?}", bb), "Bitboard(0x8000000000040201)");
+        let pretty = Bitboard::from([A1, H8]).pretty();
+        let lines: Vec<&str> = pretty.lines().collect();
+        assert_eq!(lines[0], "8 . . . . . . . X");
+        assert_eq!(lines[7], "1 X . . . . . . .");
+        assert_eq!(lines[8], "  a b c d e f g h");
+    }
+
+    #[test]
+    fn shift_n_masks_the_edges() {
+        let bb = Bitboard::from([B2, G7]);
+        assert_eq!(bb.shift_n(Direction::North, 0), bb);
+        assert_eq!(bb.shift_n(Direction::North, 1), bb.shift(Direction::North));
+        assert_eq!(bb.shift_n(Direction::North, 3), Bitboard::from(B5));
+        assert_eq!(bb.shift_n(Direction::East, 2), Bitboard::from(D2));
+        assert_eq!(
+            bb.shift_n(Direction::SouthWest, 1),
+            Bitboard::from([A1, F6])
+        );
+        assert_eq!(bb.shift_n(Direction::SouthWest, 2), Bitboard::from(E5));
+        assert_eq!(bb.shift_n(Direction::West, 8), Bitboard::EMPTY);
+        assert_eq!(
+            Bitboard::FULL.shift_n(Direction::South, 100),
+            Bitboard::EMPTY
+        );
+    }
+}
@@ -13,7 +13,7 @@ impl Color {
     pub const fn relative_rank(self, rank: Rank) -> Rank {
         match self {
             Color::White => rank,
-            Color::Black => unsafe { std::mem::transmute(7 - rank as u8) },
+            Color::Black => unsafe { std::mem::transmute::<u8, Rank>(7 - rank as u8) },
         }
     }
 
@@ -0,0 +1,260 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread;
+
+use crate::errors::UciError;
+use crate::movegen::{generate, Move};
+use crate::pool;
+use crate::position::Position;
+use crate::search::{SearchResult, Searcher, Signals, MAX_PLY};
+use crate::time::{Limits, TimeManager};
+use crate::tt::TranspositionTable;
+
+// The game a driver (UCI or otherwise) is playing, and the searches run on it. Searches run on
+// their own thread, so that a bug in search costs at most that one search and not the process.
+#[derive(Debug, Clone)]
+pub struct Engine {
+    start_fen: String,
+    moves: Vec<Move>,
+    // Threads per search (Lazy SMP), all sharing `tt`. The table outlives single searches.
+    threads: usize,
+    tt: Arc<TranspositionTable>,
+}
+
+// What came of a `go`. `best_move` is always a legal move when there is one, even if the search
+// itself died, in which case `error` says why and `result` is `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoOutcome {
+    pub best_move: Option<Move>,
+    pub result: Option<SearchResult>,
+    pub error: Option<String>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self {
+            start_fen: Position::STARTING_FEN.to_string(),
+            moves: Vec::new(),
+            threads: 1,
+            tt: Arc::new(TranspositionTable::new(TranspositionTable::DEFAULT_MB)),
+        }
+    }
+
+    // Back to the start position, forgetting everything learnt in the last game.
+    pub fn new_game(&mut self) {
+        self.start_fen = Position::STARTING_FEN.to_string();
+        self.moves.clear();
+        self.tt.clear();
+    }
+
+    // How many threads each search runs on. They still come out of the library's budget (see
+    // `pool`), so this is a most, not a promise.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+    pub const fn threads(&self) -> usize {
+        self.threads
+    }
+
+    // Sets up `fen` (the standard start if `None`) and plays `uci_moves` on it. Leaves the
+    // current game alone if any of that fails.
+    pub fn set_position(&mut self, fen: Option<&str>, uci_moves: &[&str]) -> Result<(), UciError> {
+        let fen = fen.unwrap_or(Position::STARTING_FEN);
+        let mut pos = Position::try_from_fen(fen)?;
+
+        let mut moves = Vec::with_capacity(uci_moves.len());
+        for &uci in uci_moves {
+            moves.push(pos.play_uci(uci)?);
+        }
+
+        self.start_fen = fen.to_string();
+        self.moves = moves;
+        Ok(())
+    }
+
+    // The current position, with the game's moves behind it to unmake.
+    pub fn position(&self) -> Position {
+        let mut pos = Position::new_from_fen(&self.start_fen);
+        for &m in &self.moves {
+            pos.make_move(m);
+        }
+        pos
+    }
+
+    pub fn go(&self, depth: u32) -> GoOutcome {
+        let limits = Limits {
+            depth: Some(depth),
+            ..Limits::default()
+        };
+        self.go_with(&limits, Signals::new())
+    }
+    // A search bounded by `limits`: the time, the depth, or both. With neither it goes as deep
+    // as the search can, or until `signals` stops it.
+    //
+    // With more than one thread, the others search the same root alongside the main one with no
+    // limits of their own, and only help by filling the shared table; they are stopped as soon
+    // as the main search is done, and its result is the one that counts.
+    pub fn go_with(&self, limits: &Limits, signals: Signals) -> GoOutcome {
+        let start = Position::new_from_fen(&self.start_fen);
+        let moves = self.moves.clone();
+        // The clock starts now, not once the thread is up.
+        let time = TimeManager::new(limits, self.position().to_move());
+        let depth = limits.depth.unwrap_or(MAX_PLY as u32);
+        let tt = Arc::clone(&self.tt);
+        let ids: Vec<usize> = (0..self.threads).collect();
+
+        self.run_contained(move |root| {
+            let helpers = Signals::new();
+            let results = pool::map(&ids, |&id| {
+                // With too few threads free, a helper may only get going once it's all over.
+                if id > 0 && helpers.is_stopped() {
+                    return None;
+                }
+                let mut pos = root.clone();
+                let mut searcher = Searcher::new();
+                searcher.set_history(&start, &moves);
+                searcher.set_tt(Arc::clone(&tt));
+                if id == 0 {
+                    let _stop_helpers = StopOnDrop(&helpers);
+                    searcher.set_time(time);
+                    searcher.set_signals(signals.clone());
+                    Some(searcher.search(&mut pos, depth))
+                } else {
+                    searcher.set_signals(helpers.clone());
+                    Some(searcher.search(&mut pos, depth))
+                }
+            });
+
+            let nodes = results.iter().flatten().map(|r| r.nodes).sum();
+            let main = results[0].expect("the main thread always searches");
+            SearchResult { nodes, ..main }
+        })
+    }
+
+    // Runs `search` on the current position on a worker thread. A panic in there is turned
+    // into an error, with the first legal move as the answer instead.
+    fn run_contained<F>(&self, search: F) -> GoOutcome
+    where
+        F: FnOnce(&mut Position) -> SearchResult + Send + 'static,
+    {
+        let game = self.clone();
+        let worker = thread::Builder::new()
+            .name("search".to_string())
+            .spawn(move || search(&mut game.position()));
+
+        let joined = match worker {
+            Ok(handle) => handle.join().map_err(|payload| panic_message(&*payload)),
+            Err(e) => Err(format!("could not start search thread: {}", e)),
+        };
+
+        match joined {
+            Ok(result) => GoOutcome {
+                best_move: result.best_move,
+                result: Some(result),
+                error: None,
+            },
+            Err(error) => GoOutcome {
+                best_move: self.fallback_move(),
+                result: None,
+                error: Some(error),
+            },
+        }
+    }
+
+    // Any legal move will do, as long as the game goes on. Even this is guarded, since whatever
+    // broke the search might well break this too.
+    fn fallback_move(&self) -> Option<Move> {
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            let pos = self.position();
+            generate::legal(&pos).get(0)
+        }))
+        .ok()
+        .flatten()
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Stops the helper threads when the main search ends, even by panicking: they'd never stop
+// otherwise, and the panic couldn't get out.
+struct StopOnDrop<'a>(&'a Signals);
+
+impl Drop for StopOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.stop();
+    }
+}
+
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::square::Square::*;
+
+    #[test]
+    fn go_finds_a_move() {
+        let mut engine = Engine::new();
+        engine
+            .set_position(Some("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"), &[])
+            .unwrap();
+        let outcome = engine.go(2);
+        assert_eq!(outcome.best_move, Some(Move::new(A1, A8)));
+        assert!(outcome.error.is_none());
+    }
+
+    #[test]
+    fn searches_on_several_threads() {
+        let mut engine = Engine::new();
+        engine.set_threads(3);
+        engine
+            .set_position(Some("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"), &[])
+            .unwrap();
+        let outcome = engine.go(4);
+        assert_eq!(outcome.best_move, Some(Move::new(A1, A8)));
+        assert_eq!(outcome.result.unwrap().depth, 4);
+
+        // What was learnt is kept for the next search, until a new game.
+        let pos = engine.position();
+        assert!(engine.tt.probe(pos.key(), 0).is_some());
+        engine.new_game();
+        assert!(engine.tt.probe(pos.key(), 0).is_none());
+        assert_eq!(engine.position().to_fen(), Position::STARTING_FEN);
+        assert_eq!(engine.threads(), 3);
+    }
+
+    #[test]
+    fn bad_positions_leave_the_game_alone() {
+        let mut engine = Engine::new();
+        engine.set_position(None, &["e2e4"]).unwrap();
+        assert!(engine.set_position(None, &["e2e4", "e2e4"]).is_err());
+        assert!(engine.set_position(Some("not a fen"), &[]).is_err());
+        assert_eq!(engine.moves, vec![Move::new(E2, E4)]);
+    }
+
+    #[test]
+    fn search_panic_falls_back_to_a_legal_move() {
+        let mut engine = Engine::new();
+        engine.set_position(None, &["e2e4", "e7e5"]).unwrap();
+
+        let outcome = engine.run_contained(|_| panic!("search blew up"));
+        assert_eq!(outcome.error.as_deref(), Some("search blew up"));
+        assert!(outcome.result.is_none());
+
+        let pos = engine.position();
+        let m = outcome.best_move.unwrap();
+        assert!(pos.is_pseudo_legal(m) && pos.is_legal(m));
+    }
+}
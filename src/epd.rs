@@ -0,0 +1,270 @@
+use crate::errors::{EpdError, MoveError, MoveParseError};
+use crate::mate::MateSolver;
+use crate::movegen::Move;
+use crate::perft::perft;
+use crate::position::Position;
+use crate::search::Searcher;
+
+// One line of an EPD test suite: a position, and what the operations after it ask of it.
+// `hmvc` and `fmvn` go into the position's counters; anything else not listed here is kept
+// as written in `other`.
+#[derive(Debug, Clone)]
+pub struct Epd {
+    pub position: Position,
+    pub id: Option<String>,
+    // `bm`: any of these is a correct answer.
+    pub best_moves: Vec<Move>,
+    // `am`: none of these is.
+    pub avoid_moves: Vec<Move>,
+    // `dm`: the side to move mates in exactly this many moves.
+    pub mate_in: Option<u32>,
+    // Perft counts, as (depth, nodes), from `perft <depth> <nodes>` or the `D<depth> <nodes>`
+    // that perft suites use.
+    pub perft: Vec<(usize, usize)>,
+    pub other: Vec<(String, String)>,
+}
+
+impl Epd {
+    pub fn parse(line: &str) -> Result<Self, EpdError> {
+        let position = Position::try_from_epd(line)?;
+        let mut epd = Self {
+            position,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            mate_in: None,
+            perft: Vec::new(),
+            other: Vec::new(),
+        };
+
+        for (opcode, operands) in operations(line)? {
+            let operand = operands.join(" ");
+            let number = |s: &str| {
+                s.parse::<usize>()
+                    .map_err(|_| EpdError::BadOperand(opcode.clone(), operand.clone()))
+            };
+
+            match opcode.as_str() {
+                "id" => epd.id = Some(operand.clone()),
+                "bm" => epd.best_moves = epd.moves(&operands)?,
+                "am" => epd.avoid_moves = epd.moves(&operands)?,
+                "dm" => match number(&operand)? {
+                    0 => return Err(EpdError::BadOperand(opcode, operand)),
+                    n => epd.mate_in = Some(n as u32),
+                },
+                "perft" => match operands[..] {
+                    [ref depth, ref nodes] => epd.perft.push((number(depth)?, number(nodes)?)),
+                    _ => return Err(EpdError::BadOperand(opcode, operand)),
+                },
+                "hmvc" | "fmvn" => (),
+                _ => match opcode.strip_prefix('D').map(number) {
+                    Some(Ok(depth)) => epd.perft.push((depth, number(&operand)?)),
+                    _ => epd.other.push((opcode, operand)),
+                },
+            }
+        }
+
+        Ok(epd)
+    }
+
+    // Moves are SAN, as the standard has it, though UCI is taken too.
+    fn moves(&self, operands: &[String]) -> Result<Vec<Move>, EpdError> {
+        operands
+            .iter()
+            .map(|s| {
+                Move::from_san(s, &self.position)
+                    .or_else(|| {
+                        let m = Move::new_from_uci(s.as_bytes(), &self.position)?;
+                        let pos = &self.position;
+                        (pos.is_pseudo_legal(m) && pos.is_legal(m)).then_some(m)
+                    })
+                    .ok_or_else(|| {
+                        EpdError::Move(MoveError::Parse(MoveParseError::San(s.to_string())))
+                    })
+            })
+            .collect()
+    }
+
+    // Whether the engine, searching to `depth`, does what the operations ask. A line with
+    // nothing to check passes.
+    pub fn check(&self, depth: u32) -> bool {
+        let mut pos = self.position.detached();
+
+        if !self.best_moves.is_empty() || !self.avoid_moves.is_empty() {
+            let Some(found) = Searcher::new().search(&mut pos, depth).best_move else {
+                return false;
+            };
+            if !self.best_moves.is_empty() && !self.best_moves.contains(&found) {
+                return false;
+            }
+            if self.avoid_moves.contains(&found) {
+                return false;
+            }
+        }
+
+        if let Some(n) = self.mate_in {
+            let line = MateSolver::new().solve(&mut pos, n);
+            if line.is_none_or(|l| l.len() != 2 * n as usize - 1) {
+                return false;
+            }
+        }
+
+        self.perft
+            .iter()
+            .all(|&(depth, nodes)| perft(&mut pos, depth) == nodes)
+    }
+}
+
+// How a suite went, naming each line that failed by its `id` or, without one, its line number.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SuiteReport {
+    pub total: usize,
+    pub failed: Vec<String>,
+}
+
+impl SuiteReport {
+    pub fn passed(&self) -> usize {
+        self.total - self.failed.len()
+    }
+}
+
+// Checks every line of an EPD suite at `depth`. Blank lines and `#` comments are skipped; a line
+// that doesn't parse stops the run, with its (1 based) line number.
+pub fn run_suite(suite: &str, depth: u32) -> Result<SuiteReport, (usize, EpdError)> {
+    let mut report = SuiteReport::default();
+    for (i, line) in suite.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let epd = Epd::parse(line).map_err(|e| (i + 1, e))?;
+        report.total += 1;
+        if !epd.check(depth) {
+            report
+                .failed
+                .push(epd.id.unwrap_or_else(|| format!("line {}", i + 1)));
+        }
+    }
+    Ok(report)
+}
+
+// The operations after the four position fields, each as its opcode and operands. Operands may
+// be quoted strings, which keep their spaces and semicolons.
+fn operations(line: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut rest = line.trim_start();
+    for _ in 0..4 {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+
+    let mut ops = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut chars = rest.chars().peekable();
+    loop {
+        match chars.next() {
+            None | Some(';') => {
+                if !words.is_empty() {
+                    let opcode = words.remove(0);
+                    ops.push((opcode, std::mem::take(&mut words)));
+                }
+                if chars.peek().is_none() {
+                    break;
+                }
+            }
+            Some(c) if c.is_whitespace() => (),
+            Some('"') => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(EpdError::UnterminatedString),
+                    }
+                }
+                words.push(word);
+            }
+            Some(c) => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                words.push(word);
+            }
+        }
+    }
+
+    Ok(ops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::square::Square::*;
+
+    #[test]
+    fn parses_operations() {
+        let epd = Epd::parse(
+            r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC 001; a";"#,
+        )
+        .unwrap();
+        assert_eq!(epd.best_moves, [Move::new(G3, G6)]);
+        assert_eq!(epd.id.as_deref(), Some("WAC 001; a"));
+
+        let epd = Epd::parse(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400; hmvc 3; c0 \"x y\"",
+        )
+        .unwrap();
+        assert_eq!(epd.perft, [(1, 20), (2, 400)]);
+        assert_eq!(epd.position.rule50(), 3);
+        assert_eq!(epd.other, [("c0".to_string(), "x y".to_string())]);
+
+        let epd = Epd::parse("7k/8/8/8/8/8/R7/1R4K1 w - - dm 2; am Kf2 g1h1; perft 1 19;").unwrap();
+        assert_eq!(epd.mate_in, Some(2));
+        assert_eq!(epd.avoid_moves, [Move::new(G1, F2), Move::new(G1, H1)]);
+        assert_eq!(epd.perft, [(1, 19)]);
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert!(matches!(
+            Epd::parse("8/8 w - - bm e4;"),
+            Err(EpdError::Fen(_))
+        ));
+        assert!(matches!(
+            Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - bm Qh5;"),
+            Err(EpdError::Move(_))
+        ));
+        assert_eq!(
+            Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - dm x;").unwrap_err(),
+            EpdError::BadOperand("dm".to_string(), "x".to_string())
+        );
+        assert!(Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - dm 0;").is_err());
+        assert_eq!(
+            Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - id \"open").unwrap_err(),
+            EpdError::UnterminatedString
+        );
+    }
+
+    #[test]
+    fn runs_a_suite() {
+        let suite = "
+            # Mate in one, a mate in two, and some perft.
+            6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8; id \"back rank\";
+            7k/8/8/8/8/8/R7/1R4K1 w - - dm 2;
+            r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - D1 48; D2 2039;
+            6k1/5ppp/8/8/8/8/8/R5K1 w - - am Ra8; id \"wrong\";
+            4k3/8/8/8/8/8/8/4K3 w - - perft 1 6;
+        ";
+        let report = run_suite(suite, 2).unwrap();
+        assert_eq!(report.total, 5);
+        assert_eq!(report.failed, ["wrong", "line 7"]);
+        assert_eq!(report.passed(), 3);
+
+        assert_eq!(run_suite("x", 1).unwrap_err().0, 1);
+    }
+}
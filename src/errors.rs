@@ -0,0 +1,438 @@
+use std::fmt;
+
+use crate::color::Color;
+use crate::movegen::Move;
+use crate::position::CastleFlag;
+use crate::square::{Rank, Square};
+
+// Every error the library hands back. Each has a `Display` meant for a user (a GUI's log, a
+// command line) and implements `std::error::Error`; the wider ones convert from the narrower
+// ones, so `?` works across them.
+
+// Everything `Position::try_from_fen` can reject a FEN for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(&'static str),
+    WrongRankCount(usize),
+    BadRankLength(Rank),
+    BadPiece(char),
+    BadSideToMove(String),
+    BadCastling(char),
+    BadEnPassant(String),
+    BadCounter(String),
+    TrailingField(String),
+    MissingKing(Color),
+    TooManyKings(Color),
+    PawnOnBackRank,
+    OpponentInCheck,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingField(field) => write!(f, "FEN ended early, no {} given", field),
+            Self::WrongRankCount(n) => write!(f, "expected 8 ranks, found {}", n),
+            Self::BadRankLength(r) => write!(f, "rank {} does not have 8 squares", char::from(*r)),
+            Self::BadPiece(c) => write!(f, "unknown piece: {}", c),
+            Self::BadSideToMove(s) => write!(f, "unknown side to move: {}", s),
+            Self::BadCastling(c) => write!(f, "bad or repeated castling right: {}", c),
+            Self::BadEnPassant(s) => write!(f, "invalid en passant square: {}", s),
+            Self::BadCounter(s) => write!(f, "invalid move counter: {}", s),
+            Self::TrailingField(s) => write!(f, "unexpected text after the move counters: {}", s),
+            Self::MissingKing(c) => write!(f, "no {:?} king", c),
+            Self::TooManyKings(c) => write!(f, "more than one {:?} king", c),
+            Self::PawnOnBackRank => write!(f, "pawn on the first or last rank"),
+            Self::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+// What `PositionBuilder::build` can turn a setup down for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SetupError {
+    MissingKing(Color),
+    TooManyKings(Color),
+    PawnOnBackRank(Square),
+    OpponentInCheck,
+    // A right without the king and that rook on their starting squares.
+    BadCastling(CastleFlag),
+    // Not on the rank a pawn that just moved two squares would have passed.
+    BadEnPassant(Square),
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingKing(c) => write!(f, "no {:?} king", c),
+            Self::TooManyKings(c) => write!(f, "more than one {:?} king", c),
+            Self::PawnOnBackRank(s) => write!(f, "pawn on the first or last rank: {}", s),
+            Self::OpponentInCheck => write!(f, "the side not to move is in check"),
+            Self::BadCastling(cf) => {
+                write!(f, "castling right without its king and rook: {:?}", cf)
+            }
+            Self::BadEnPassant(s) => write!(f, "invalid en passant square: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for SetupError {}
+
+// What `Position::validate` found wrong. Anything but `Setup` means the position's own
+// bookkeeping has gone wrong, rather than that it was set up badly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    Setup(SetupError),
+    // A square in two piece or color bitboards, or in one kind but not the other.
+    Bitboards,
+    // The board holds something other than what the bitboards say is on this square.
+    Board(Square),
+    // Kept although the side to move can't take there.
+    EnPassant(Square),
+    Key,
+    // The checkers, pinners or blockers kept in the state.
+    State,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Setup(e) => write!(f, "{}", e),
+            Self::Bitboards => write!(f, "piece and color bitboards disagree"),
+            Self::Board(s) => write!(f, "board and bitboards disagree on {}", s),
+            Self::EnPassant(s) => write!(f, "unusable en passant square kept: {}", s),
+            Self::Key => write!(f, "stored key does not match the position"),
+            Self::State => write!(f, "stored checkers or pins do not match the position"),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Setup(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<SetupError> for ValidationError {
+    fn from(e: SetupError) -> Self {
+        Self::Setup(e)
+    }
+}
+
+// Notation that doesn't describe a square, piece or move at all, before any position is involved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveParseError {
+    Square(String),
+    File(u8),
+    Rank(u8),
+    Piece(char),
+    // A 16 bit move encoding with no valid move behind it.
+    Code(u16),
+    Uci(String),
+    San(String),
+}
+
+impl fmt::Display for MoveParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Square(s) => write!(f, "not a square: {}", s),
+            Self::File(n) => write!(f, "file index out of range: {}", n),
+            Self::Rank(n) => write!(f, "rank index out of range: {}", n),
+            Self::Piece(c) => write!(f, "not a piece: {}", c),
+            Self::Code(code) => write!(f, "invalid move encoding: {:#06x}", code),
+            Self::Uci(s) => write!(f, "not a UCI move: {}", s),
+            Self::San(s) => write!(f, "not a SAN move here: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for MoveParseError {}
+
+// A move that can't be played in the position it was given for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    Parse(MoveParseError),
+    Illegal(Move),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "{}", e),
+            Self::Illegal(m) => write!(f, "illegal move: {}", m),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parse(e) => Some(e),
+            Self::Illegal(_) => None,
+        }
+    }
+}
+
+impl From<MoveParseError> for MoveError {
+    fn from(e: MoveParseError) -> Self {
+        Self::Parse(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    // A tag pair line that doesn't read `[Name "value"]`.
+    BadTag(String),
+    // An unclosed comment, or variations that don't balance.
+    BadMovetext,
+    Fen(FenError),
+    // The move at (0 based) `ply` of the mainline.
+    Move { ply: usize, error: MoveError },
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadTag(line) => write!(f, "malformed tag pair: {}", line),
+            Self::BadMovetext => write!(f, "unbalanced comment or variation in movetext"),
+            Self::Fen(e) => write!(f, "bad FEN tag: {}", e),
+            Self::Move { ply, error } => {
+                let dots = if ply % 2 == 0 { "." } else { "..." };
+                write!(f, "at {}{}: {}", ply / 2 + 1, dots, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PgnError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Fen(e) => Some(e),
+            Self::Move { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<FenError> for PgnError {
+    fn from(e: FenError) -> Self {
+        Self::Fen(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciError {
+    // A command missing a part, or with one that makes no sense.
+    Syntax(String),
+    UnknownCommand(String),
+    UnknownOption(String),
+    Fen(FenError),
+    Move(MoveError),
+}
+
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(s) => write!(f, "{}", s),
+            Self::UnknownCommand(s) => write!(f, "unknown command: {}", s),
+            Self::UnknownOption(s) => write!(f, "unknown option: {}", s),
+            Self::Fen(e) => write!(f, "{}", e),
+            Self::Move(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for UciError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Fen(e) => Some(e),
+            Self::Move(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<FenError> for UciError {
+    fn from(e: FenError) -> Self {
+        Self::Fen(e)
+    }
+}
+impl From<MoveError> for UciError {
+    fn from(e: MoveError) -> Self {
+        Self::Move(e)
+    }
+}
+
+// Why `Snapshot::decode` turned a buffer down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    BadMagic,
+    // Written by a newer version of the format than this one reads.
+    UnsupportedVersion(u8),
+    // The buffer ended part way through a field.
+    Truncated,
+    BadPiece(u8),
+    // A variable length number with more bytes than a `u64` needs.
+    BadVarint,
+    Fen(FenError),
+    // The move at (0 based) `ply` of the history.
+    Move { ply: usize, error: MoveError },
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a game snapshot"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported snapshot version: {}", v),
+            Self::Truncated => write!(f, "snapshot ends early"),
+            Self::BadPiece(code) => write!(f, "unknown piece code: {}", code),
+            Self::BadVarint => write!(f, "number too long"),
+            Self::Fen(e) => write!(f, "bad start position: {}", e),
+            Self::Move { ply, error } => write!(f, "at ply {}: {}", ply, error),
+            Self::TrailingBytes(n) => write!(f, "{} unexpected bytes after the snapshot", n),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Fen(e) => Some(e),
+            Self::Move { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<FenError> for CodecError {
+    fn from(e: FenError) -> Self {
+        Self::Fen(e)
+    }
+}
+
+// Why `Epd::parse` turned a line down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdError {
+    Fen(FenError),
+    // A `bm` or `am` move that isn't legal in the position.
+    Move(MoveError),
+    // An opcode with operands it can't use: (opcode, operands).
+    BadOperand(String, String),
+    UnterminatedString,
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fen(e) => write!(f, "bad position: {}", e),
+            Self::Move(e) => write!(f, "{}", e),
+            Self::BadOperand(op, operand) => write!(f, "bad operand for {}: {}", op, operand),
+            Self::UnterminatedString => write!(f, "unterminated string operand"),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Fen(e) => Some(e),
+            Self::Move(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<FenError> for EpdError {
+    fn from(e: FenError) -> Self {
+        Self::Fen(e)
+    }
+}
+
+// Why `EvalParams::from_text` couldn't read a set of parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamsError {
+    UnknownName(String),
+    // A word that is neither a section name nor a number, or a number before any name.
+    BadValue(String),
+    // A section with the wrong amount of numbers: (section, expected, found).
+    WrongCount(&'static str, usize, usize),
+    Repeated(&'static str),
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownName(s) => write!(f, "unknown parameter: {}", s),
+            Self::BadValue(s) => write!(f, "unexpected value: {}", s),
+            Self::WrongCount(name, expected, found) => {
+                write!(f, "{} needs {} values, found {}", name, expected, found)
+            }
+            Self::Repeated(name) => write!(f, "{} given twice", name),
+        }
+    }
+}
+
+impl std::error::Error for ParamsError {}
+
+// A line of tuning data that isn't a position with a game result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelError {
+    Fen(FenError),
+    MissingResult(String),
+}
+
+impl fmt::Display for LabelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fen(e) => write!(f, "{}", e),
+            Self::MissingResult(s) => write!(f, "no game result: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for LabelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Fen(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<FenError> for LabelError {
+    fn from(e: FenError) -> Self {
+        Self::Fen(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::square::Square::*;
+    use std::error::Error;
+
+    #[test]
+    fn messages_and_sources() {
+        let illegal = MoveError::Illegal(Move::new(E2, E5));
+        assert_eq!(illegal.to_string(), "illegal move: e2e5");
+
+        let pgn = PgnError::Move {
+            ply: 3,
+            error: illegal.clone(),
+        };
+        assert_eq!(pgn.to_string(), "at 2...: illegal move: e2e5");
+        assert_eq!(pgn.source().unwrap().to_string(), illegal.to_string());
+
+        let uci = UciError::from(MoveError::from(MoveParseError::Uci("e9e4".to_string())));
+        assert_eq!(uci.to_string(), "not a UCI move: e9e4");
+        assert!(uci.source().unwrap().source().is_some());
+
+        let fen = PgnError::from(FenError::MissingKing(Color::Black));
+        assert_eq!(fen.to_string(), "bad FEN tag: no Black king");
+    }
+}
@@ -0,0 +1,394 @@
+use crate::color::Color::{self, *};
+use crate::errors::{FenError, ParamsError};
+use crate::piece::PieceType::{self, *};
+use crate::position::Position;
+use crate::square::Square;
+use crate::values;
+
+// Piece-square tables, from White's point of view and laid out as the board is drawn
+// (rank 8 on the first row). Black looks them up mirrored.
+#[rustfmt::skip]
+const PAWN_PST: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    10,  10,  20,  30,  30,  20,  10,  10,
+     5,   5,  10,  25,  25,  10,   5,   5,
+     0,   0,   0,  20,  20,   0,   0,   0,
+     5,  -5, -10,   0,   0, -10,  -5,   5,
+     5,  10,  10, -20, -20,  10,  10,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+#[rustfmt::skip]
+const KNIGHT_PST: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+#[rustfmt::skip]
+const BISHOP_PST: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+#[rustfmt::skip]
+const ROOK_PST: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,  10,  10,  10,  10,  10,  10,   5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+     0,   0,   0,   5,   5,   0,   0,   0,
+];
+#[rustfmt::skip]
+const QUEEN_PST: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+#[rustfmt::skip]
+const KING_MG_PST: [i32; 64] = [
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+     20,  20,   0,   0,   0,   0,  20,  20,
+     20,  30,  10,   0,   0,  10,  30,  20,
+];
+#[rustfmt::skip]
+const KING_EG_PST: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+// Everything the evaluation weighs, gathered up so it can be tuned, saved and loaded. The
+// tables are from White's point of view and laid out as the board is drawn, like the ones above;
+// the king's entry in `pst` is its middlegame table, blended into `king_endgame` by phase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalParams {
+    pub material: [i32; 6],
+    pub pst: [[i32; 64]; 6],
+    pub king_endgame: [i32; 64],
+}
+
+// What `evaluate` and `quick` use.
+static DEFAULT_PARAMS: EvalParams = EvalParams::DEFAULT;
+
+// The names of the sections in `EvalParams::to_text`, in order.
+const SECTIONS: [&str; 8] = [
+    "material",
+    "pawn",
+    "knight",
+    "bishop",
+    "rook",
+    "queen",
+    "king",
+    "king_endgame",
+];
+
+impl EvalParams {
+    pub const DEFAULT: Self = Self {
+        material: values::PIECE_VALUES,
+        pst: [
+            PAWN_PST,
+            KNIGHT_PST,
+            BISHOP_PST,
+            ROOK_PST,
+            QUEEN_PST,
+            KING_MG_PST,
+        ],
+        king_endgame: KING_EG_PST,
+    };
+    // How many numbers `to_vec` gives: the material values, then each table in turn.
+    pub const LEN: usize = 6 + 7 * 64;
+
+    // `quick`, with these parameters.
+    pub fn quick(&self, pos: &Position) -> i32 {
+        let phase = phase(pos);
+        let score = self.side_score(pos, White, phase) - self.side_score(pos, Black, phase);
+        match pos.to_move() {
+            White => score,
+            Black => -score,
+        }
+    }
+
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn psqt(&self, kind: PieceType, color: Color, square: Square, phase: i32) -> i32 {
+        let index = match color {
+            White => square.flip_vertical() as usize,
+            Black => square as usize,
+        };
+        let table = self.pst[kind as usize][index];
+        if kind != King {
+            return table;
+        }
+        (table * phase + self.king_endgame[index] * (values::TOTAL_PHASE - phase))
+            / values::TOTAL_PHASE
+    }
+
+    fn side_score(&self, pos: &Position, color: Color, phase: i32) -> i32 {
+        let mut score = 0;
+        for kind in PieceType::all() {
+            for sq in pos.spec(kind, color) {
+                score += self.material[kind as usize] + self.psqt(kind, color, sq, phase);
+            }
+        }
+        score
+    }
+
+    pub fn to_vec(&self) -> Vec<i32> {
+        let mut out = self.material.to_vec();
+        for table in self.pst.iter().chain([&self.king_endgame]) {
+            out.extend_from_slice(table);
+        }
+        out
+    }
+    // The inverse of `to_vec`. Panics unless given exactly `LEN` numbers.
+    pub fn from_slice(values: &[i32]) -> Self {
+        assert_eq!(
+            values.len(),
+            Self::LEN,
+            "EvalParams::from_slice: wrong length"
+        );
+        let mut params = Self::DEFAULT;
+        let (material, tables) = values.split_at(6);
+        params.material.copy_from_slice(material);
+        for (i, table) in tables.chunks(64).enumerate() {
+            match params.pst.get_mut(i) {
+                Some(pst) => pst.copy_from_slice(table),
+                None => params.king_endgame.copy_from_slice(table),
+            }
+        }
+        params
+    }
+
+    // A plain text form for saving: each section's name on a line of its own, then its numbers,
+    // the tables eight to a line.
+    pub fn to_text(&self) -> String {
+        let values = self.to_vec();
+        let mut text = format!("{}\n", SECTIONS[0]);
+        text += &join(&values[..6]);
+        text.push('\n');
+        for (name, table) in SECTIONS[1..].iter().zip(values[6..].chunks(64)) {
+            text += &format!("\n{}\n", name);
+            for row in table.chunks(8) {
+                text += &join(row);
+                text.push('\n');
+            }
+        }
+        text
+    }
+    // Reads what `to_text` writes. Line breaks don't matter, only the order of the numbers after
+    // each name, and `#` starts a comment. Sections left out keep their default values.
+    pub fn from_text(text: &str) -> Result<Self, ParamsError> {
+        let mut values = Self::DEFAULT.to_vec();
+        let mut section: Option<(usize, usize)> = None;
+        let mut seen = [false; SECTIONS.len()];
+
+        let words = text
+            .lines()
+            .flat_map(|l| l.split('#').next().unwrap_or("").split_whitespace());
+        for word in words.chain([""]) {
+            if let Ok(n) = word.parse::<i32>() {
+                let (index, count) = section.ok_or(ParamsError::BadValue(word.to_string()))?;
+                let len = section_len(index);
+                if count == len {
+                    return Err(ParamsError::WrongCount(SECTIONS[index], len, count + 1));
+                }
+                values[section_start(index) + count] = n;
+                section = Some((index, count + 1));
+                continue;
+            }
+
+            if let Some((index, count)) = section {
+                if count != section_len(index) {
+                    return Err(ParamsError::WrongCount(
+                        SECTIONS[index],
+                        section_len(index),
+                        count,
+                    ));
+                }
+            }
+            if word.is_empty() {
+                break;
+            }
+            let index = SECTIONS
+                .iter()
+                .position(|&s| s == word)
+                .ok_or_else(|| ParamsError::UnknownName(word.to_string()))?;
+            if std::mem::replace(&mut seen[index], true) {
+                return Err(ParamsError::Repeated(SECTIONS[index]));
+            }
+            section = Some((index, 0));
+        }
+
+        Ok(Self::from_slice(&values))
+    }
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+fn section_len(index: usize) -> usize {
+    if index == 0 {
+        6
+    } else {
+        64
+    }
+}
+fn section_start(index: usize) -> usize {
+    if index == 0 {
+        0
+    } else {
+        6 + 64 * (index - 1)
+    }
+}
+fn join(values: &[i32]) -> String {
+    values
+        .iter()
+        .map(|v| format!("{:4}", v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Static evaluation in centipawns, from the side to move's point of view.
+pub fn evaluate(pos: &Position) -> i32 {
+    quick(pos)
+}
+
+// Material and piece-square tables only, in centipawns from the side to move's point of view.
+// Meant for ranking large numbers of positions (candidate FENs, book entries) where even a
+// full evaluation costs too much; terms that need attack information never go in here, so it
+// stays cheap as `evaluate` grows.
+pub fn quick(pos: &Position) -> i32 {
+    DEFAULT_PARAMS.quick(pos)
+}
+
+// `quick` straight from a FEN, for callers (bindings, scripts) that only hold the text.
+pub fn quick_fen(fen: &str) -> Result<i32, FenError> {
+    Position::try_from_fen(fen).map(|pos| quick(&pos))
+}
+
+// How much non-pawn material is left, from `values::TOTAL_PHASE` (opening) down to 0 (endgame).
+pub fn phase(pos: &Position) -> i32 {
+    let mut phase = 0;
+    for kind in [Knight, Bishop, Rook, Queen] {
+        phase += pos.pieces(kind).popcount() * values::phase_weight(kind);
+    }
+    phase.min(values::TOTAL_PHASE)
+}
+
+#[cfg_attr(feature = "inline", inline)]
+pub fn psqt(kind: PieceType, color: Color, square: Square, phase: i32) -> i32 {
+    DEFAULT_PARAMS.psqt(kind, color, square, phase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_position_is_balanced() {
+        let pos = Position::default();
+        assert_eq!(evaluate(&pos), 0);
+        assert_eq!(phase(&pos), values::TOTAL_PHASE);
+    }
+
+    #[test]
+    fn mirrored_positions_agree() {
+        let white = Position::new_from_fen(Position::KIWIPETE_FEN);
+        let black = Position::new_from_fen(
+            "r3k2r/pppbbppp/2n2q1P/1P2p3/3pn3/BN2PNP1/P1PPQPB1/R3K2R b KQkq - 0 1",
+        );
+        assert_eq!(evaluate(&white), evaluate(&black));
+    }
+
+    #[test]
+    fn quick_eval() {
+        let pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        assert_eq!(quick(&pos), evaluate(&pos));
+        assert_eq!(quick_fen(Position::KIWIPETE_FEN), Ok(quick(&pos)));
+        assert_eq!(
+            quick_fen("8/8/8/8/8/8/8/8 w - - 0 1"),
+            Err(FenError::MissingKing(White))
+        );
+
+        // Up a rook beats up a pawn, whoever is to move.
+        let rook = quick_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap();
+        let pawn = quick_fen("4k3/8/8/8/8/8/P7/4K3 b - - 0 1").unwrap();
+        assert!(rook < pawn && pawn < 0);
+    }
+
+    #[test]
+    fn params_round_trip() {
+        let params = EvalParams::DEFAULT;
+        assert_eq!(EvalParams::from_slice(&params.to_vec()), params);
+        assert_eq!(EvalParams::from_text(&params.to_text()), Ok(params.clone()));
+
+        let pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        let mut tuned =
+            EvalParams::from_text("material 100 300 300 500 900 0 # flat minors").unwrap();
+        assert_eq!(tuned.pst, params.pst);
+        assert_eq!(tuned.quick(&pos), quick(&pos));
+        tuned.material[Queen as usize] = 1000;
+        assert_eq!(tuned.quick(&pos), quick(&pos));
+        tuned.pst[Knight as usize][0] = 5;
+        assert_eq!(EvalParams::from_text(&tuned.to_text()), Ok(tuned));
+
+        assert_eq!(
+            EvalParams::from_text("material 1 2 3"),
+            Err(ParamsError::WrongCount("material", 6, 3))
+        );
+        assert_eq!(
+            EvalParams::from_text("material 1 2 3 4 5 6 7"),
+            Err(ParamsError::WrongCount("material", 6, 7))
+        );
+        assert_eq!(
+            EvalParams::from_text("mobility 3"),
+            Err(ParamsError::UnknownName("mobility".to_string()))
+        );
+        assert_eq!(
+            EvalParams::from_text("4 material"),
+            Err(ParamsError::BadValue("4".to_string()))
+        );
+        assert_eq!(
+            EvalParams::from_text("material 1 2 3 4 5 6 material 1 2 3 4 5 6"),
+            Err(ParamsError::Repeated("material"))
+        );
+    }
+
+    #[test]
+    fn side_to_move_flips_sign() {
+        let w = Position::new_from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        let b = Position::new_from_fen("4k3/8/8/8/8/8/8/3QK3 b - - 0 1");
+        assert!(evaluate(&w) > values::QUEEN / 2);
+        assert_eq!(evaluate(&w), -evaluate(&b));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::bitboard::Bitboard;
+use crate::square::Square;
+
+// Hyperbola quintessence for files and diagonals, and a kindergarten-style table for ranks. Two
+// small tables and plain integer arithmetic, so it is quick everywhere, without magic's big
+// tables or BMI2: the default on ARM, wasm and the like.
+//
+// For a slider on `s` with `o` the blockers on one line through it (`s` itself left out), the
+// borrow in `o - s` runs up to the first blocker above `s`, so `o ^ (o - s)` on the line holds
+// the attacks toward the higher squares. A byte swap flips the board vertically and turns the
+// other direction into the same sum; ranks sit in a single byte, so they get a table instead.
+
+#[derive(Clone, Copy)]
+struct Lines {
+    file: u64,
+    diagonal: u64,
+    anti_diagonal: u64,
+}
+
+static LINES: [Lines; 64] = build_lines();
+// Attacks along the first rank, by file and the occupancy of the six inner files.
+static RANK_ATTACKS: [[u8; 64]; 8] = build_rank_attacks();
+
+// Every line through each square, the square itself left out.
+const fn build_lines() -> [Lines; 64] {
+    let mut lines = [Lines {
+        file: 0,
+        diagonal: 0,
+        anti_diagonal: 0,
+    }; 64];
+
+    let mut s = 0;
+    while s < 64 {
+        let (f, r) = ((s % 8) as i32, (s / 8) as i32);
+        let mut t = 0;
+        while t < 64 {
+            let (tf, tr) = ((t % 8) as i32, (t / 8) as i32);
+            if t != s {
+                let bit = 1u64 << t;
+                if tf == f {
+                    lines[s].file |= bit;
+                }
+                if tf - f == tr - r {
+                    lines[s].diagonal |= bit;
+                }
+                if tf - f == r - tr {
+                    lines[s].anti_diagonal |= bit;
+                }
+            }
+            t += 1;
+        }
+        s += 1;
+    }
+
+    lines
+}
+
+const fn build_rank_attacks() -> [[u8; 64]; 8] {
+    let mut table = [[0; 64]; 8];
+
+    let mut file = 0;
+    while file < 8 {
+        let mut inner = 0;
+        while inner < 64 {
+            let occupied = (inner << 1) as u8;
+            let mut attacks = 0u8;
+
+            let mut f = file + 1;
+            while f < 8 {
+                attacks |= 1 << f;
+                if occupied & (1 << f) != 0 {
+                    break;
+                }
+                f += 1;
+            }
+            let mut f = file;
+            while f > 0 {
+                f -= 1;
+                attacks |= 1 << f;
+                if occupied & (1 << f) != 0 {
+                    break;
+                }
+            }
+
+            table[file][inner] = attacks;
+            inner += 1;
+        }
+        file += 1;
+    }
+
+    table
+}
+
+#[cfg_attr(feature = "inline", inline)]
+fn line_attacks(occupancy: u64, square: usize, mask: u64) -> u64 {
+    let s = 1u64 << square;
+    let forward = occupancy & mask;
+    let reverse = forward.swap_bytes();
+    let forward = forward.wrapping_sub(s);
+    let reverse = reverse.wrapping_sub(s.swap_bytes());
+    (forward ^ reverse.swap_bytes()) & mask
+}
+
+#[cfg_attr(feature = "inline", inline)]
+fn rank_attacks(occupancy: u64, square: usize) -> u64 {
+    let shift = square & 56;
+    let inner = (occupancy >> (shift + 1)) & 63;
+    (RANK_ATTACKS[square & 7][inner as usize] as u64) << shift
+}
+
+#[cfg_attr(feature = "inline", inline)]
+pub(crate) fn bishop_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
+    let (s, occ) = (square as usize, occupancy.into_inner());
+    let lines = &LINES[s];
+    Bitboard::new(line_attacks(occ, s, lines.diagonal) | line_attacks(occ, s, lines.anti_diagonal))
+}
+#[cfg_attr(feature = "inline", inline)]
+pub(crate) fn rook_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
+    let (s, occ) = (square as usize, occupancy.into_inner());
+    Bitboard::new(line_attacks(occ, s, LINES[s].file) | rank_attacks(occ, s))
+}
@@ -0,0 +1,53 @@
+// Identifies exactly which build is running, for protocol id lines, benchmark headers and game logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub features: &'static [&'static str],
+    pub profile: &'static str,
+}
+
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "magic")]
+    "magic",
+    #[cfg(feature = "pext")]
+    "pext",
+    #[cfg(feature = "black-magic")]
+    "black-magic",
+    #[cfg(feature = "inline")]
+    "inline",
+    #[cfg(feature = "inline-aggressive")]
+    "inline-aggressive",
+    #[cfg(feature = "strict_checks")]
+    "strict_checks",
+];
+
+pub const fn engine_info() -> EngineInfo {
+    EngineInfo {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        features: FEATURES,
+        profile: if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+    }
+}
+
+impl EngineInfo {
+    // Just the name and version, e.g. for `id name`.
+    pub fn id(&self) -> String {
+        format!("{} {}", self.name, self.version)
+    }
+}
+
+impl std::fmt::Display for EngineInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} ({}", self.name, self.version, self.profile)?;
+        if !self.features.is_empty() {
+            write!(f, "; {}", self.features.join(", "))?;
+        }
+        write!(f, ")")
+    }
+}
@@ -0,0 +1,64 @@
+//! Bitboard move generation, and a small engine built on top of it.
+//!
+//! The core of the API is re-exported here: build a [`Position`] (from FEN, or a piece at a
+//! time with a [`PositionBuilder`]),
+//! list its moves with [`generate`], play them with [`Position::make_move`], and check the
+//! generator against known counts with [`perft()`].
+
+pub mod analysis;
+pub mod bench;
+pub mod bitboard;
+pub mod color;
+pub mod engine;
+pub mod epd;
+pub mod errors;
+pub mod eval;
+mod hyperbola;
+pub mod info;
+mod macros;
+#[cfg(feature = "magic")]
+mod magic;
+pub mod mate;
+pub mod movegen;
+pub mod ordering;
+pub mod pawns;
+pub mod perft;
+pub mod pgn;
+pub mod piece;
+pub mod pool;
+pub mod position;
+pub mod precompute;
+pub mod repro;
+pub mod rng;
+mod san;
+pub mod search;
+pub mod selfplay;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod sliders;
+pub mod snapshot;
+pub mod square;
+pub mod stats;
+#[cfg(test)]
+mod testing;
+pub mod time;
+pub mod tt;
+pub mod tune;
+pub mod uci;
+pub mod values;
+pub mod wdl;
+mod zobrist;
+
+pub use bitboard::Bitboard;
+pub use color::Color;
+pub use errors::{
+    CodecError, EpdError, FenError, LabelError, MoveError, MoveParseError, ParamsError, PgnError,
+    SetupError, UciError, ValidationError,
+};
+pub use movegen::{generate, GenOptions, Move, MoveKind, MoveList, Promotions, ScoredMoveList};
+pub use perft::perft;
+pub use piece::{Piece, PieceType};
+pub use position::{
+    CastleFlag, DrawReason, Edit, GameResult, Position, PositionBuilder, PositionDiff,
+};
+pub use square::{File, Rank, Square};
@@ -1,5 +1,17 @@
+// The `in pos;` forms dump a reproducer for `pos` (see `repro`) before panicking.
 #[macro_export]
 macro_rules! strict_eq {
+    (in $pos:expr; $left:expr, $right:expr) => {
+        if cfg!(feature = "strict_checks") {
+            match (&$left, &$right) {
+                (l, r) if l != r => $crate::repro::strict_failure(
+                    $pos,
+                    &format!("{} == {} ({:?} != {:?})", stringify!($left), stringify!($right), l, r),
+                ),
+                _ => (),
+            }
+        }
+    };
     ($left:expr, $right:expr) => {
         if cfg!(feature = "strict_checks") {
             assert_eq!($left, $right);
@@ -13,6 +25,17 @@ macro_rules! strict_eq {
 }
 #[macro_export]
 macro_rules! strict_ne {
+    (in $pos:expr; $left:expr, $right:expr) => {
+        if cfg!(feature = "strict_checks") {
+            match (&$left, &$right) {
+                (l, r) if l == r => $crate::repro::strict_failure(
+                    $pos,
+                    &format!("{} != {} (both {:?})", stringify!($left), stringify!($right), l),
+                ),
+                _ => (),
+            }
+        }
+    };
     ($left:expr, $right:expr) => {
         if cfg!(feature = "strict_checks") {
             assert_ne!($left, $right);
@@ -27,6 +50,11 @@ macro_rules! strict_ne {
 }
 #[macro_export]
 macro_rules! strict_cond {
+    (in $pos:expr; $e:expr) => {
+        if cfg!(feature = "strict_checks") && !($e) {
+            $crate::repro::strict_failure($pos, stringify!($e));
+        }
+    };
     ($e:expr) => {
         if cfg!(feature = "strict_checks") {
             assert!($e);
@@ -40,6 +68,11 @@ macro_rules! strict_cond {
 }
 #[macro_export]
 macro_rules! strict_not {
+    (in $pos:expr; $e:expr) => {
+        if cfg!(feature = "strict_checks") && ($e) {
+            $crate::repro::strict_failure($pos, concat!("!", stringify!($e)));
+        }
+    };
     ($e:expr) => {
         if cfg!(feature = "strict_checks") {
             assert!(!($e));
@@ -51,3 +84,61 @@ macro_rules! strict_not {
         }
     };
 }
+// Builds a `Bitboard` at compile time from squares, ranks and files, e.g.
+// `bb![A1, B2, C3]` or `bb!(rank 2 | file e | D4)`. Both `,` and `|` mean union.
+#[macro_export]
+macro_rules! bb {
+    (@acc [$acc:expr]) => { $acc };
+    (@acc [$acc:expr] , $($rest:tt)*) => { $crate::bb!(@acc [$acc] $($rest)*) };
+    (@acc [$acc:expr] | $($rest:tt)*) => { $crate::bb!(@acc [$acc] $($rest)*) };
+    (@acc [$acc:expr] rank $r:tt $($rest:tt)*) => {
+        $crate::bb!(@acc [$acc.bitor($crate::bitboard::Bitboard::from_rank($crate::bb!(@rank $r)))] $($rest)*)
+    };
+    (@acc [$acc:expr] file $f:tt $($rest:tt)*) => {
+        $crate::bb!(@acc [$acc.bitor($crate::bitboard::Bitboard::from_file($crate::bb!(@file $f)))] $($rest)*)
+    };
+    (@acc [$acc:expr] $sq:ident $($rest:tt)*) => {
+        $crate::bb!(@acc [$acc.bitor($crate::bitboard::Bitboard::from_square($crate::square::Square::$sq))] $($rest)*)
+    };
+
+    (@rank 1) => { $crate::square::Rank::One };
+    (@rank 2) => { $crate::square::Rank::Two };
+    (@rank 3) => { $crate::square::Rank::Three };
+    (@rank 4) => { $crate::square::Rank::Four };
+    (@rank 5) => { $crate::square::Rank::Five };
+    (@rank 6) => { $crate::square::Rank::Six };
+    (@rank 7) => { $crate::square::Rank::Seven };
+    (@rank 8) => { $crate::square::Rank::Eight };
+
+    (@file a) => { $crate::square::File::A };
+    (@file b) => { $crate::square::File::B };
+    (@file c) => { $crate::square::File::C };
+    (@file d) => { $crate::square::File::D };
+    (@file e) => { $crate::square::File::E };
+    (@file f) => { $crate::square::File::F };
+    (@file g) => { $crate::square::File::G };
+    (@file h) => { $crate::square::File::H };
+
+    () => { $crate::bitboard::Bitboard::EMPTY };
+    ($($toks:tt)+) => {
+        const { $crate::bb!(@acc [$crate::bitboard::Bitboard::EMPTY] $($toks)+) }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitboard::Bitboard;
+    use crate::square::{File, Rank, Square::*};
+
+    #[test]
+    fn bb_macro_builds_masks() {
+        assert_eq!(bb![], Bitboard::EMPTY);
+        assert_eq!(bb![A1, B2, C3], Bitboard::from([A1, B2, C3]));
+        assert_eq!(bb![A1, B2, C3,], Bitboard::from([A1, B2, C3]));
+        assert_eq!(bb!(rank 2), Bitboard::from(Rank::Two));
+        assert_eq!(
+            bb!(rank 2 | file e | H8),
+            Bitboard::from(Rank::Two) | Bitboard::from(File::E) | Bitboard::from(H8)
+        );
+    }
+}
@@ -1,222 +1,129 @@
-#[cfg(feature = "pext")]
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
 use std::arch::x86_64::_pext_u64;
 //use bitintr::Pext;
 
-#[cfg(feature = "pext")]
-#[cfg_attr(feature = "inline", inline)]
-fn pext(a: u64, b: u64) -> u64 {
-    unsafe { _pext_u64(a, b) }
+// `pext` is compiled in with the `pext` feature, but the CPU running the binary may not have it.
+// Everything that reaches the instruction is only called once this has said yes.
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+pub(crate) fn bmi2_supported() -> bool {
+    std::arch::is_x86_feature_detected!("bmi2")
 }
 
 use crate::bitboard::Bitboard;
-use crate::piece::PieceType::{self, Bishop, Rook};
-use crate::square::{Direction, File, Rank, Square};
-
+use crate::square::Square;
+
+// Where one square's attacks start in its table, and how to index them. Everything here, the
+// tables included, is written out by `build.rs`, so there is nothing to initialize at runtime and
+// nothing unsafe to read it: plain data, `Send` and `Sync`.
+//
+// There are up to three layouts, all shared between bishops and rooks:
+// - `ATTACKS`, plain magics with as many index bits as each square needs;
+// - `PEXT_ATTACKS` (with `pext`, on x86_64), the same slots indexed by the occupancy bits themselves;
+// - `BLACK_ATTACKS` (with `black-magic`), fixed-shift black magics, where the occupancy outside
+//   the mask is filled in rather than cleared and every square of a piece gets the same room.
 #[derive(Debug, Clone, Copy)]
 struct Magic {
-    pointer: *const Bitboard,
-    mask: Bitboard,
-    magic: Bitboard,
-    #[cfg(not(feature = "pext"))]
-    shift: i32,
+    // For black magics, the complement of the mask.
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
 }
 
-// Trust me bro.
-// In reality, we alter it here, but that const pointer truly will not be changed once initialized.
-// This means no dangling/nullity (also, it's for a static setup)
-
-#[derive(Debug, Clone, Copy)]
-struct SeededPRNG(u64);
-
-// https://vigna.di.unimi.it/ftp/papers/xorshift.pdf
-impl SeededPRNG {
-    #[cfg_attr(feature = "inline", inline)]
-    fn get(&mut self) -> u64 {
-        assert_ne!(self.0, 0);
-        self.0 ^= self.0 >> 12;
-        self.0 ^= self.0 << 25;
-        self.0 ^= self.0 >> 27;
-
-        self.0.wrapping_mul(2685821657736338717)
-    }
-
-    #[cfg_attr(feature = "inline", inline)]
-    fn roll(&mut self) -> u64 {
-        self.get() & self.get() & self.get()
-    }
-}
-
-static mut BISHOP_MAGICS: [Magic; 64] = [Magic::new(); 64];
-static mut ROOK_MAGICS: [Magic; 64] = [Magic::new(); 64];
-
-static mut BISHOP_ATTACKS: [Bitboard; 0x1480] = [Bitboard::new(0); 0x1480];
-static mut ROOK_ATTACKS: [Bitboard; 0x19000] = [Bitboard::new(0); 0x19000];
+include!(concat!(env!("OUT_DIR"), "/magics.rs"));
 
 impl Magic {
     #[cfg_attr(feature = "inline", inline)]
-    const fn new() -> Self {
-        Self {
-            pointer: std::ptr::null(),
-            mask: Bitboard::new(0),
-            magic: Bitboard::new(0),
-            #[cfg(not(feature = "pext"))]
-            shift: 0,
-        }
+    fn attack(&self, occupancy: Bitboard) -> Bitboard {
+        let index = (occupancy.into_inner() & self.mask).wrapping_mul(self.magic) >> self.shift;
+        Bitboard::new(ATTACKS[self.offset + index as usize])
     }
 
-    #[cfg(feature = "pext")]
-    #[cfg_attr(feature = "inline", inline)]
-    fn index(&self, occupancy: Bitboard) -> isize {
-        pext(u64::from(occupancy), u64::from(self.mask)) as isize
+    // SAFETY: Only sound where `bmi2_supported`.
+    #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+    #[target_feature(enable = "bmi2")]
+    unsafe fn pext_attack(&self, occupancy: Bitboard) -> Bitboard {
+        let index = _pext_u64(occupancy.into_inner(), self.mask);
+        Bitboard::new(PEXT_ATTACKS[self.offset + index as usize])
     }
 
-    #[cfg(not(feature = "pext"))]
+    #[cfg(feature = "black-magic")]
     #[cfg_attr(feature = "inline", inline)]
-    fn index(&self, occupancy: Bitboard) -> isize {
-        ((self.mask & occupancy).mul(self.magic) >> self.shift).into_inner() as isize
-    }
-
-    #[cfg_attr(feature = "inline", inline)]
-    fn attack(&self, occupancy: Bitboard) -> Bitboard {
-        assert!(!self.pointer.is_null());
-        unsafe { *self.pointer.offset(self.index(occupancy)) }
+    fn black_attack(&self, occupancy: Bitboard) -> Bitboard {
+        let index = (occupancy.into_inner() | self.mask).wrapping_mul(self.magic) >> self.shift;
+        Bitboard::new(BLACK_ATTACKS[self.offset + index as usize])
     }
 }
 
 #[cfg_attr(feature = "inline", inline)]
 pub(crate) fn bishop_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
-    unsafe { BISHOP_MAGICS[square as usize] }.attack(occupancy)
+    BISHOP_MAGICS[square as usize].attack(occupancy)
 }
 #[cfg_attr(feature = "inline", inline)]
 pub(crate) fn rook_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
-    unsafe { ROOK_MAGICS[square as usize] }.attack(occupancy)
+    ROOK_MAGICS[square as usize].attack(occupancy)
 }
 
-const fn slider_gen(square: Square, occ: Bitboard, is_rook: bool) -> Bitboard {
-    let dirs = if is_rook {
-        Direction::orthogonal()
-    } else {
-        Direction::diagonal()
-    };
-
-    let mut rv = Bitboard::new(0);
-    let mask = occ.not();
-
-    let mut i = 0;
-    while i < 4 {
-        let mut s = Bitboard::from_square(square);
-        let dir = dirs[i];
-        while s.nonzero() {
-            s = s.shift(dir);
-
-            rv.bitor_assign(s);
-
-            s.bitand_assign(mask);
-        }
-
-        i += 1;
-    }
-
-    rv
+// SAFETY (both): Only sound where `bmi2_supported`.
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+#[cfg_attr(feature = "inline", inline)]
+pub(crate) unsafe fn pext_bishop_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
+    BISHOP_MAGICS[square as usize].pext_attack(occupancy)
+}
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+#[cfg_attr(feature = "inline", inline)]
+pub(crate) unsafe fn pext_rook_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
+    ROOK_MAGICS[square as usize].pext_attack(occupancy)
 }
 
-fn init_magics_for(magic_table: *mut Magic, table: *mut Bitboard, is_rook: bool) {
-    #[cfg(not(feature = "pext"))]
-    let seeds = [728, 10316, 55013, 32803, 12281, 15100, 16645, 255];
-    #[cfg(not(feature = "pext"))]
-    let mut occupancy = [Bitboard::new(0); 4096];
-    #[cfg(not(feature = "pext"))]
-    let mut epoch = [0; 4096];
-    #[cfg(not(feature = "pext"))]
-    let mut count = 0;
-
-    let mut reference = [Bitboard::new(0); 4096];
-    let mut size = 0;
-
-    for square in Bitboard::new(0).not() {
-        let edges = (Bitboard::from([Rank::One, Rank::Eight]) & !Bitboard::from(square.rank()))
-            | (Bitboard::from([File::A, File::H]) & !Bitboard::from(square.file()));
-        let m = unsafe { &mut *magic_table.offset(square as isize) };
-        m.mask = slider_gen(square, Bitboard::EMPTY, is_rook) & !edges;
-
-        #[cfg(not(feature = "pext"))]
-        {
-            m.shift = 64 - m.mask.popcount();
-        }
-
-        m.pointer = if square == Square::A1 {
-            table as *const Bitboard
-        } else {
-            let ptr = unsafe { &*magic_table.offset((square as isize) - 1) }.pointer;
-            unsafe { ptr.offset(size as isize) }
-        };
-        size = 0;
-
-        let mut b: Bitboard = Bitboard::EMPTY;
-        loop {
-            #[cfg(not(feature = "pext"))]
-            {
-                occupancy[size] = b;
-            }
-
-            reference[size] = slider_gen(square, b, is_rook);
-
-            #[cfg(feature = "pext")]
-            unsafe {
-                let pxt = pext(b.into_inner(), m.mask.into_inner());
-                *(m.pointer.offset(pxt as isize) as *mut _) = reference[size];
-            }
-
-            size += 1;
-            b = (b.sub(m.mask)) & m.mask;
+#[cfg(feature = "black-magic")]
+#[cfg_attr(feature = "inline", inline)]
+pub(crate) fn black_bishop_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
+    BLACK_BISHOP_MAGICS[square as usize].black_attack(occupancy)
+}
+#[cfg(feature = "black-magic")]
+#[cfg_attr(feature = "inline", inline)]
+pub(crate) fn black_rook_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
+    BLACK_ROOK_MAGICS[square as usize].black_attack(occupancy)
+}
 
-            if b.zero() {
-                break;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+    use crate::square::Direction;
+
+    fn walk(square: Square, occupancy: Bitboard, dirs: [Direction; 4]) -> Bitboard {
+        let mut attacks = Bitboard::EMPTY;
+        for dir in dirs {
+            for s in square.ray_iter(dir) {
+                attacks |= Bitboard::from(s);
+                if bool::from(occupancy & Bitboard::from(s)) {
+                    break;
+                }
             }
         }
+        attacks
+    }
 
-        #[cfg(not(feature = "pext"))]
-        {
-            let mut prng = SeededPRNG(seeds[square.rank() as usize]);
-            let mut i = 0;
-
-            while i < size {
-                m.magic = Bitboard::EMPTY;
-                while (m.magic.mul(m.mask) >> 56).popcount() < 6 {
-                    m.magic = Bitboard::new(prng.roll());
-                }
-
-                count += 1;
-                i = 0;
-                while i < size {
-                    let index = m.index(occupancy[i]);
-
-                    if epoch[index as usize] < count {
-                        epoch[index as usize] = count;
-                        unsafe {
-                            *(m.pointer.offset(index) as *mut _) = reference[i];
-                        }
-                    } else if unsafe { *m.pointer.offset(index) } != reference[i] {
-                        break;
-                    }
-
-                    i += 1;
-                }
+    #[test]
+    fn tables_match_ray_walks() {
+        fn send_sync<T: Send + Sync>() {}
+        send_sync::<Magic>();
+
+        let mut rng = Rng::new(3531);
+        for square in Bitboard::new(!0) {
+            for _ in 0..64 {
+                let occ = Bitboard::new(rng.next_u64() & rng.next_u64());
+                assert_eq!(
+                    bishop_attacks(square, occ),
+                    walk(square, occ, Direction::diagonal())
+                );
+                assert_eq!(
+                    rook_attacks(square, occ),
+                    walk(square, occ, Direction::orthogonal())
+                );
             }
         }
     }
 }
-
-#[cfg_attr(feature = "inline", inline)]
-pub(crate) fn init_magics() {
-    init_magics_for(
-        &raw mut BISHOP_MAGICS as *mut Magic,
-        &raw mut BISHOP_ATTACKS as *mut Bitboard,
-        false,
-    );
-    init_magics_for(
-        &raw mut ROOK_MAGICS as *mut Magic,
-        &raw mut ROOK_ATTACKS as *mut Bitboard,
-        true,
-    );
-}
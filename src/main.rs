@@ -1,20 +1,38 @@
-#![allow(dead_code, unused_imports)]
-mod bitboard;
-mod color;
-mod macros;
-#[cfg(feature = "magic")]
-mod magic;
-mod movegen;
-mod perft;
-mod piece;
-mod position;
-mod precompute;
-mod square;
+use std::error::Error;
 
-use position::Position;
+use fcpw::{info, mate, tune, uci};
+
+type Command = fn(&[String]) -> Result<(), Box<dyn Error>>;
+
+fn mate_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let found = mate::command(args)?;
+    for san in &found.san {
+        println!("{}", san);
+    }
+    eprintln!("{} key moves, {} nodes", found.san.len(), found.nodes);
+    Ok(())
+}
 
 fn main() {
-    precompute::initialize();
-    let pos = Position::default();
-    println!("{pos}");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command: Option<Command> = match args.first().map(String::as_str) {
+        Some("tune") => Some(tune::command),
+        Some("mate") => Some(mate_command),
+        _ => None,
+    };
+    if let Some(command) = command {
+        if let Err(e) = command(&args[1..]) {
+            eprintln!("{}: {}", args[0], e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    println!("{}", info::engine_info());
+
+    let stdin = std::io::BufReader::new(std::io::stdin());
+    let mut stdout = std::io::stdout();
+    if let Err(e) = uci::run(stdin, &mut stdout) {
+        eprintln!("uci: {}", e);
+    }
 }
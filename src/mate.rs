@@ -0,0 +1,294 @@
+use std::error::Error;
+
+use crate::movegen::{generate, Move, MoveList};
+use crate::position::Position;
+
+// Proof search for forced mates, the backend for UCI's `go mate N` and the `mate` command.
+// Unlike the general search this answers exactly "is there a mate in N" and gives back the
+// forcing line, or nothing at all.
+#[derive(Debug, Default)]
+pub struct MateSolver {
+    nodes: u64,
+    checks_only_from: Option<u32>,
+    selfmate: bool,
+}
+
+impl MateSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // From the attacker's `n`th move on (counting from 0), only checks are tried. Much faster on
+    // long mates, but misses any that need a quiet move past that point. The mating move itself
+    // is always a check, so that one is never a loss.
+    pub fn checks_only_from(mut self, n: u32) -> Self {
+        self.checks_only_from = Some(n);
+        self
+    }
+
+    // Look for selfmates instead: the attacker forces the defender to give mate, while the
+    // defender does everything it can not to. `checks_only_from` is ignored.
+    pub fn selfmate(mut self) -> Self {
+        self.selfmate = true;
+        self
+    }
+
+    pub const fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    // The shortest forced mate in at most `moves` moves for the side to move: the attacker's moves
+    // and the defender's longest resistance, alternating and ending in mate. `None` if there is no
+    // such mate (or none the checks-only restriction can find).
+    pub fn solve(&mut self, pos: &mut Position, moves: u32) -> Option<Vec<Move>> {
+        self.nodes = 0;
+        (1..=moves).find_map(|n| self.attack(pos, n, 0))
+    }
+
+    // Every first move that forces mate in at most `moves`, in generation order. A sound problem
+    // has exactly one; more are cooks, none means it has no solution.
+    pub fn key_moves(&mut self, pos: &mut Position, moves: u32) -> Vec<Move> {
+        self.nodes = 0;
+        let mut keys = Vec::new();
+        if moves == 0 {
+            return keys;
+        }
+
+        for m in &generate::legal(pos) {
+            pos.make_move(m);
+            let forced = (!self.checks_only(moves, 0) || pos.in_check())
+                && self.defend(pos, moves, 0).is_some();
+            pos.unmake_move(m);
+
+            if forced {
+                keys.push(m);
+            }
+        }
+        keys
+    }
+
+    fn checks_only(&self, n: u32, move_index: u32) -> bool {
+        !self.selfmate && (n == 1 || self.checks_only_from.is_some_and(|k| move_index >= k))
+    }
+
+    fn attack(&mut self, pos: &mut Position, n: u32, move_index: u32) -> Option<Vec<Move>> {
+        self.nodes += 1;
+        let checks_only = self.checks_only(n, move_index);
+
+        for m in &generate::legal(pos) {
+            pos.make_move(m);
+            let line = if checks_only && !pos.in_check() {
+                None
+            } else {
+                self.defend(pos, n, move_index)
+            };
+            pos.unmake_move(m);
+
+            if let Some(mut line) = line {
+                line.insert(0, m);
+                return Some(line);
+            }
+        }
+
+        None
+    }
+
+    // `n` counts the attacker's moves, including the one just played.
+    fn defend(&mut self, pos: &mut Position, n: u32, move_index: u32) -> Option<Vec<Move>> {
+        self.nodes += 1;
+
+        let replies = generate::legal(pos);
+        if self.selfmate {
+            return self.defend_selfmate(pos, &replies, n, move_index);
+        }
+        if replies.is_empty() {
+            return pos.in_check().then(Vec::new);
+        }
+        if n == 1 || pos.rule50() >= 100 {
+            return None;
+        }
+
+        let mut longest: Option<Vec<Move>> = None;
+        for r in &replies {
+            pos.make_move(r);
+            let line = self.attack(pos, n - 1, move_index + 1);
+            pos.unmake_move(r);
+
+            let mut line = line?;
+            if longest.as_ref().is_none_or(|l| line.len() + 1 > l.len()) {
+                line.insert(0, r);
+                longest = Some(line);
+            }
+        }
+
+        longest
+    }
+
+    // The defender is lost if every reply either mates the attacker or runs into another forcing
+    // move; having no reply at all (mated or stalemated) is a failure for the attacker.
+    fn defend_selfmate(
+        &mut self,
+        pos: &mut Position,
+        replies: &MoveList,
+        n: u32,
+        move_index: u32,
+    ) -> Option<Vec<Move>> {
+        if replies.is_empty() {
+            return None;
+        }
+        let last = n == 1 || pos.rule50() >= 100;
+
+        let mut longest: Option<Vec<Move>> = None;
+        for r in replies {
+            pos.make_move(r);
+            let line = if pos.in_check() && !generate::has_legal_move(pos) {
+                Some(Vec::new())
+            } else if last {
+                None
+            } else {
+                self.attack(pos, n - 1, move_index + 1)
+            };
+            pos.unmake_move(r);
+
+            let mut line = line?;
+            if longest.as_ref().is_none_or(|l| line.len() + 1 > l.len()) {
+                line.insert(0, r);
+                longest = Some(line);
+            }
+        }
+
+        longest
+    }
+}
+
+// What `command` found: every key move in SAN, and the nodes it took to find them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMoves {
+    pub san: Vec<String>,
+    pub nodes: u64,
+}
+
+// `fcpw mate [--self] <fen> <moves>`: every key move of a mate (or selfmate) in `moves` problem.
+// The FEN may be given as one argument or spread over several.
+pub fn command(args: &[String]) -> Result<KeyMoves, Box<dyn Error>> {
+    let (selfmate, args) = match args.split_first() {
+        Some((flag, rest)) if flag == "--self" => (true, rest),
+        _ => (false, args),
+    };
+    let [fen @ .., moves] = args else {
+        return Err("usage: mate [--self] <fen> <moves>".into());
+    };
+    if fen.is_empty() {
+        return Err("usage: mate [--self] <fen> <moves>".into());
+    }
+    let moves: u32 = match moves.parse() {
+        Ok(n) if n > 0 => n,
+        _ => return Err(format!("bad move count: {}", moves).into()),
+    };
+    let mut pos = Position::try_from_fen(&fen.join(" "))?;
+
+    let mut solver = MateSolver::new();
+    if selfmate {
+        solver = solver.selfmate();
+    }
+    let keys = solver.key_moves(&mut pos, moves);
+    Ok(KeyMoves {
+        san: keys.iter().map(|&m| m.to_san(&pos)).collect(),
+        nodes: solver.nodes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::square::Square;
+
+    fn ends_in_mate(fen: &str, line: &[Move]) -> bool {
+        let mut pos = Position::new_from_fen(fen);
+        pos.make_moves(line).is_ok() && pos.in_check() && generate::legal(&pos).is_empty()
+    }
+
+    #[test]
+    fn mate_in_one() {
+        let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        let line = MateSolver::new()
+            .solve(&mut Position::new_from_fen(fen), 3)
+            .unwrap();
+        assert_eq!(line.len(), 1);
+        assert!(ends_in_mate(fen, &line));
+    }
+
+    #[test]
+    fn ladder_needs_a_quiet_move() {
+        let fen = "7k/8/8/8/8/8/R7/1R4K1 w - - 0 1";
+        let mut pos = Position::new_from_fen(fen);
+
+        assert_eq!(MateSolver::new().solve(&mut pos, 1), None);
+        let line = MateSolver::new().solve(&mut pos, 2).unwrap();
+        assert_eq!(line.len(), 3);
+        assert!(ends_in_mate(fen, &line));
+
+        assert_eq!(
+            MateSolver::new().checks_only_from(0).solve(&mut pos, 2),
+            None
+        );
+        // The position is left as it was found.
+        assert_eq!(pos.to_string(), Position::new_from_fen(fen).to_string());
+    }
+
+    #[test]
+    fn key_moves() {
+        let mut pos = Position::new_from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let keys = MateSolver::new().key_moves(&mut pos, 1);
+        assert_eq!(keys, [Move::new(Square::A1, Square::A8)]);
+        assert!(MateSolver::new().key_moves(&mut pos, 0).is_empty());
+
+        // The ladder has two quiet keys, one for each rook.
+        let mut pos = Position::new_from_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1");
+        let keys = MateSolver::new().key_moves(&mut pos, 2);
+        assert_eq!(
+            keys,
+            [
+                Move::new(Square::B1, Square::B7),
+                Move::new(Square::A2, Square::A7)
+            ]
+        );
+    }
+
+    #[test]
+    fn selfmate() {
+        // Qg7+ leaves Black nothing but Qxg7 mate.
+        let fen = "5kq1/8/6QK/7Q/8/8/8/8 w - - 0 1";
+        let mut pos = Position::new_from_fen(fen);
+        let mut solver = MateSolver::new().selfmate();
+        let keys = solver.key_moves(&mut pos, 1);
+        assert_eq!(keys, [Move::new(Square::G6, Square::G7)]);
+        let line = solver.solve(&mut pos, 2).unwrap();
+        assert_eq!(line.len(), 2);
+        assert!(ends_in_mate(fen, &line));
+        assert_eq!(pos.to_fen(), fen);
+    }
+
+    #[test]
+    fn command_line() {
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+        let found = command(&args("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1 2")).unwrap();
+        assert_eq!(found.san, ["Rb7", "Ra7"]);
+        assert!(found.nodes > 0);
+        let found = command(&args("--self 5kq1/8/6QK/7Q/8/8/8/8 w - - 0 1 1")).unwrap();
+        assert_eq!(found.san, ["Qg7+"]);
+
+        assert!(command(&args("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1 none")).is_err());
+        assert!(command(&args("3")).is_err());
+    }
+
+    #[test]
+    fn no_mate() {
+        let mut pos = Position::new_from_fen("7k/8/8/6K1/8/8/8/5Q2 w - - 0 1");
+        let line = MateSolver::new().solve(&mut pos, 1);
+        assert_eq!(line, None);
+
+        let mut pos = Position::default();
+        assert_eq!(MateSolver::new().solve(&mut pos, 2), None);
+    }
+}
@@ -1,12 +1,12 @@
 use std::num::NonZeroU16;
 
 use crate::bitboard::Bitboard;
-use crate::color::Color::{self, *};
+use crate::color::Color::*;
+use crate::errors::MoveParseError;
 use crate::piece::PieceType;
 use crate::position::Position;
 use crate::square::Direction::*;
-use crate::square::{Rank, Square};
-use crate::strict_ne;
+use crate::square::{Direction, Rank, Square};
 
 // Layout of Move.
 // Bits 0-5: From square
@@ -90,6 +90,44 @@ impl Move {
         Some(Self::new_with_kind(from_sq, to_sq, kind))
     }
 
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn to_u16(self) -> u16 {
+        self.0.get()
+    }
+    // The inverse of `to_u16`, rejecting anything which could not have come from a real move.
+    pub fn from_u16_checked(value: u16) -> Option<Self> {
+        if value & 0x8000 != 0 || (value >> 12) & 0x7 == 5 {
+            return None;
+        }
+
+        let inner = NonZeroU16::new(value)?;
+        let mov = Self(inner);
+        let (from, to) = (mov.from(), mov.to());
+        if from == to {
+            return None;
+        }
+
+        let valid = match mov.kind() {
+            MoveKind::Normal => true,
+            MoveKind::Castle => {
+                (from == Square::E1 && (to == Square::G1 || to == Square::C1))
+                    || (from == Square::E8 && (to == Square::G8 || to == Square::C8))
+            }
+            MoveKind::EnPassant => {
+                (from.file() as u8).abs_diff(to.file() as u8) == 1
+                    && ((from.rank() == Rank::Five && to.rank() == Rank::Six)
+                        || (from.rank() == Rank::Four && to.rank() == Rank::Three))
+            }
+            MoveKind::Promotion(_) => {
+                (from.file() as u8).abs_diff(to.file() as u8) <= 1
+                    && ((from.rank() == Rank::Seven && to.rank() == Rank::Eight)
+                        || (from.rank() == Rank::Two && to.rank() == Rank::One))
+            }
+        };
+
+        valid.then_some(mov)
+    }
+
     #[cfg_attr(feature = "inline", inline)]
     pub const fn from(self) -> Square {
         unsafe { std::mem::transmute((self.0.get() & 0x3f) as u8) }
@@ -103,7 +141,9 @@ impl Move {
         let bits = ((self.0.get() >> 12) & 0x7) as u8;
         match bits {
             0 => MoveKind::Normal,
-            x if x >= 1 && x <= 4 => MoveKind::Promotion(unsafe { std::mem::transmute(x) }),
+            x if x >= 1 && x <= 4 => {
+                MoveKind::Promotion(unsafe { std::mem::transmute::<u8, PieceType>(x) })
+            }
             6 => MoveKind::Castle,
             7 => MoveKind::EnPassant,
             _ => panic!("Illegal bit combination in 3 bits."),
@@ -111,10 +151,7 @@ impl Move {
     }
     #[cfg_attr(feature = "inline", inline)]
     pub const fn is_promo(self) -> bool {
-        match self.kind() {
-            MoveKind::Promotion(_) => true,
-            _ => false,
-        }
+        matches!(self.kind(), MoveKind::Promotion(_))
     }
     #[cfg_attr(feature = "inline", inline)]
     pub const fn get_promo(self) -> Option<PieceType> {
@@ -125,17 +162,37 @@ impl Move {
     }
 }
 
+impl From<Move> for u16 {
+    #[cfg_attr(feature = "inline", inline)]
+    fn from(value: Move) -> Self {
+        value.to_u16()
+    }
+}
+impl TryFrom<u16> for Move {
+    type Error = MoveParseError;
+    #[cfg_attr(feature = "inline-aggressive", inline)]
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Self::from_u16_checked(value).ok_or(MoveParseError::Code(value))
+    }
+}
+
+// Fixed capacity, stack allocated list of moves. The default capacity fits any legal position;
+// smaller lists are for callers who know they need fewer (and will panic when overfilled).
 #[derive(Debug, Clone)]
-pub struct MoveList {
-    inner: [Option<Move>; 256],
+pub struct MoveList<const N: usize = 256> {
+    // Only the first `length` are moves; the rest are filler, never handed out.
+    inner: [Move; N],
     length: usize,
 }
 
-impl MoveList {
+impl<const N: usize> MoveList<N> {
+    pub const CAPACITY: usize = N;
+    const FILLER: Move = Move(NonZeroU16::MIN);
+
     #[cfg_attr(feature = "inline", inline)]
     pub const fn new() -> Self {
         Self {
-            inner: [None; 256],
+            inner: [Self::FILLER; N],
             length: 0,
         }
     }
@@ -145,20 +202,33 @@ impl MoveList {
         if index >= self.length {
             None
         } else {
-            self.inner[index]
+            Some(self.inner[index])
         }
     }
     #[cfg_attr(feature = "inline", inline)]
     pub const fn len(&self) -> usize {
         self.length
     }
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn as_slice(&self) -> &[Move] {
+        &self.inner[..self.length]
+    }
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn as_mut_slice(&mut self) -> &mut [Move] {
+        &mut self.inner[..self.length]
+    }
 
     #[cfg_attr(feature = "inline", inline)]
     pub const fn push(&mut self, mov: Move) {
-        assert!(self.length < 256);
-        self.inner[self.length] = Some(mov);
+        assert!(self.length < N);
+        self.inner[self.length] = mov;
         self.length += 1;
     }
+    // Swaps the last move into `index`, so it's O(1) but doesn't keep the order.
     #[cfg_attr(feature = "inline", inline)]
     pub const fn remove(&mut self, index: usize) {
         assert!(index < self.length);
@@ -167,14 +237,136 @@ impl MoveList {
             self.inner[index] = self.inner[self.length];
         }
     }
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.as_mut_slice().swap(a, b);
+    }
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn clear(&mut self) {
+        self.length = 0;
+    }
+    // Keeps only the moves `keep` says yes to, in their original order.
+    pub fn retain(&mut self, mut keep: impl FnMut(Move) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.length {
+            let m = self.inner[i];
+            if keep(m) {
+                self.inner[kept] = m;
+                kept += 1;
+            }
+        }
+        self.length = kept;
+    }
+
+    pub fn sort_unstable_by_key<K: Ord>(&mut self, key: impl FnMut(&Move) -> K) {
+        self.as_mut_slice().sort_unstable_by_key(key);
+    }
+    // Highest score first. Equal scores may end up in any order.
+    pub fn sort_by_score(&mut self, mut score: impl FnMut(Move) -> i32) {
+        self.sort_unstable_by_key(|&m| std::cmp::Reverse(score(m)));
+    }
+    // Splits the list into (captures, quiets), each keeping the original move order.
+    pub fn partition_captures(&self, pos: &Position) -> (Self, Self) {
+        let mut captures = Self::new();
+        let mut quiets = Self::new();
+        for m in self {
+            if pos.is_capture(m) {
+                captures.push(m);
+            } else {
+                quiets.push(m);
+            }
+        }
+        (captures, quiets)
+    }
+}
+
+impl<const N: usize> Default for MoveList<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Moves with a score each, for search to take best first without sorting the whole list:
+// most nodes cut off after a move or two, so the rest never need to be put in order.
+#[derive(Debug, Clone)]
+pub struct ScoredMoveList<const N: usize = 256> {
+    moves: MoveList<N>,
+    scores: [i32; N],
+    // Everything before this has been handed out by `pick_best`.
+    picked: usize,
+}
+
+impl<const N: usize> ScoredMoveList<N> {
+    pub const fn new() -> Self {
+        Self {
+            moves: MoveList::new(),
+            scores: [0; N],
+            picked: 0,
+        }
+    }
+    pub fn from_list(list: &MoveList<N>, mut score: impl FnMut(Move) -> i32) -> Self {
+        let mut scored = Self::new();
+        for m in list {
+            scored.push(m, score(m));
+        }
+        scored
+    }
+
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn len(&self) -> usize {
+        self.moves.len()
+    }
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+    // How many moves `pick_best` has yet to hand out.
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn remaining(&self) -> usize {
+        self.moves.len() - self.picked
+    }
+
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn push(&mut self, mov: Move, score: i32) {
+        self.scores[self.moves.len()] = score;
+        self.moves.push(mov);
+    }
+
+    // One step of a selection sort: swaps the best of the moves not picked yet to the front of
+    // them and returns it. Ties go to whichever came first.
+    pub fn pick_best(&mut self) -> Option<(Move, i32)> {
+        let len = self.moves.len();
+        if self.picked >= len {
+            return None;
+        }
+        let mut best = self.picked;
+        for i in self.picked + 1..len {
+            if self.scores[i] > self.scores[best] {
+                best = i;
+            }
+        }
+        self.moves.swap(self.picked, best);
+        self.scores.swap(self.picked, best);
+        self.picked += 1;
+        Some((
+            self.moves.inner[self.picked - 1],
+            self.scores[self.picked - 1],
+        ))
+    }
+}
+
+impl<const N: usize> Default for ScoredMoveList<N> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-pub struct MoveListIter<'a>(std::slice::Iter<'a, Option<Move>>);
+pub struct MoveListIter<'a>(std::iter::Copied<std::slice::Iter<'a, Move>>);
 
 impl<'a> MoveListIter<'a> {
     #[cfg_attr(feature = "inline", inline)]
-    fn new(lst: &'a MoveList) -> Self {
-        Self(lst.inner[0..lst.length].iter())
+    fn new<const N: usize>(lst: &'a MoveList<N>) -> Self {
+        Self(lst.as_slice().iter().copied())
     }
 }
 
@@ -182,11 +374,15 @@ impl<'a> Iterator for MoveListIter<'a> {
     type Item = Move;
     #[cfg_attr(feature = "inline", inline)]
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next().copied().flatten()
+        self.0.next()
+    }
+    #[cfg_attr(feature = "inline", inline)]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
     }
 }
 
-impl<'a> IntoIterator for &'a MoveList {
+impl<'a, const N: usize> IntoIterator for &'a MoveList<N> {
     type Item = Move;
     type IntoIter = MoveListIter<'a>;
     #[cfg_attr(feature = "inline", inline)]
@@ -199,157 +395,454 @@ impl std::fmt::Display for Move {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let prom_s = self
             .get_promo()
-            .map_or_else(|| String::new(), |pt| format!("{pt}"));
+            .map_or_else(String::new, |pt| format!("{pt}"));
         write!(f, "{}{}{}", self.from(), self.to(), prom_s)
     }
 }
 
+// Which promotions the pawn generator emits. Search mostly only wants the queen; an
+// underpromotion is hardly ever better, except a knight promotion with check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Promotions {
+    #[default]
+    All,
+    QueenOnly,
+    QueenAndKnightChecks,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GenOptions {
+    pub promotions: Promotions,
+}
+
 pub mod generate {
     use crate::{position::CastleFlag, precompute};
 
     use super::*;
 
+    // Nothing under `generate` touches the heap; the `_into` variants fill a list the caller owns.
     #[cfg_attr(feature = "inline-aggressive", inline)]
     pub fn pseudo_legal(pos: &Position) -> MoveList {
         let mut moves = MoveList::new();
-
-        pawn_moves(pos, &mut moves);
-        knight_moves(pos, &mut moves);
-        //all_sliders_at_once(pos, &mut moves);
-        bishop_moves(pos, &mut moves);
-        rook_moves(pos, &mut moves);
-        queen_moves(pos, &mut moves);
-        king_moves(pos, &mut moves);
-
+        pseudo_legal_into(pos, &mut moves);
         moves
     }
+    #[cfg_attr(feature = "inline-aggressive", inline)]
+    pub fn pseudo_legal_into<const N: usize>(pos: &Position, moves: &mut MoveList<N>) {
+        pseudo_legal_with(pos, GenOptions::default(), moves);
+    }
+    #[cfg_attr(feature = "inline-aggressive", inline)]
+    pub fn pseudo_legal_with<const N: usize>(
+        pos: &Position,
+        options: GenOptions,
+        moves: &mut MoveList<N>,
+    ) {
+        generate_all(pos, options, Bitboard::EMPTY, Bitboard::FULL, moves);
+    }
+
+    // Every piece's moves onto `targets`, except that the `pinned` pieces only move along their
+    // pin.
+    #[cfg_attr(feature = "inline-aggressive", inline)]
+    fn generate_all<const N: usize>(
+        pos: &Position,
+        options: GenOptions,
+        pinned: Bitboard,
+        targets: Bitboard,
+        moves: &mut MoveList<N>,
+    ) {
+        pawn_moves(pos, options, pinned, targets, moves);
+        knight_moves(pos, pinned, targets, moves);
+        bishop_moves(pos, pinned, targets, moves);
+        rook_moves(pos, pinned, targets, moves);
+        queen_moves(pos, pinned, targets, moves);
+        king_moves(pos, targets, moves);
+    }
 
     #[cfg_attr(feature = "inline", inline)]
     pub fn legal(pos: &Position) -> MoveList {
-        let mut moves = pseudo_legal(pos);
-        prune_to_legal(pos, &mut moves);
+        let mut moves = MoveList::new();
+        legal_into(pos, &mut moves);
         moves
     }
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn legal_into<const N: usize>(pos: &Position, moves: &mut MoveList<N>) {
+        legal_with(pos, GenOptions::default(), moves);
+    }
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn legal_with<const N: usize>(
+        pos: &Position,
+        options: GenOptions,
+        moves: &mut MoveList<N>,
+    ) {
+        legal_onto(pos, options, Bitboard::FULL, moves);
+    }
+
+    // The legal captures (en passant included) and the legal quiet moves, for searches that
+    // want to look at one before generating the other. Between them they are all of `legal`.
+    pub fn legal_captures_into<const N: usize>(pos: &Position, moves: &mut MoveList<N>) {
+        legal_onto(pos, GenOptions::default(), pos.color(!pos.to_move()), moves);
+    }
+    pub fn legal_quiets_into<const N: usize>(pos: &Position, moves: &mut MoveList<N>) {
+        legal_onto(pos, GenOptions::default(), !pos.all(), moves);
+    }
+
+    fn legal_onto<const N: usize>(
+        pos: &Position,
+        options: GenOptions,
+        targets: Bitboard,
+        moves: &mut MoveList<N>,
+    ) {
+        if pos.in_check() {
+            evasions(pos, options, targets, moves);
+        } else {
+            generate_all(pos, options, pinned(pos), targets, moves);
+            prune_to_legal(pos, moves);
+        }
+    }
+
+    // The number of legal moves, without building the list: out of check, each piece's targets
+    // are counted straight off its attack set, cut down to the pin line for a pinned piece. Only
+    // king moves, castling and en passant are checked one by one. In check it falls back to
+    // generating the (short) list of evasions.
+    pub fn count_legal(pos: &Position) -> usize {
+        if pos.in_check() {
+            return legal(pos).len();
+        }
 
-    #[cfg_attr(feature = "inline-aggressive", inline)]
-    fn prune_to_legal(pos: &Position, list: &mut MoveList) {
-        let mut i = 0;
         let us = pos.to_move();
         let king = pos.king(us);
-        // TODO list.filter(...)
-        while i < list.len() {
-            // SAFETY: Cannot be none, since i < length
-            let m = unsafe { list.get(i).unwrap_unchecked() };
-            if (m.from() == king
-                || pos.blockers(us).has(m.from())
-                || m.kind() == MoveKind::EnPassant
-                || pos.in_check())
-                && !pos.is_legal(m)
-            {
-                list.remove(i);
-                continue;
+        let own = pos.color(us);
+        let occupied = pos.all();
+        let pinned = pinned(pos);
+        let allowed = |from: Square, targets: Bitboard| targets & pin_mask(pos, pinned, from);
+
+        let mut count = 0;
+        for from in pos.spec(PieceType::Knight, us) & !pinned {
+            count += (precompute::knight_attacks(from) & !own).popcount();
+        }
+        for from in pos.spec_list(&[PieceType::Bishop, PieceType::Queen], us) {
+            count += allowed(from, precompute::bishop_attacks(from, occupied) & !own).popcount();
+        }
+        for from in pos.spec_list(&[PieceType::Rook, PieceType::Queen], us) {
+            count += allowed(from, precompute::rook_attacks(from, occupied) & !own).popcount();
+        }
+
+        let forward = if us == White { North } else { South };
+        let last_rank = Bitboard::from(us.relative_rank(Rank::Eight));
+        for from in pos.spec(PieceType::Pawn, us) {
+            let mut targets = precompute::pawn_attacks(from, us) & pos.color(!us);
+            if let Some(up) = from.shift(forward).filter(|&sq| pos.empty(sq)) {
+                targets |= Bitboard::from(up);
+                if from.rank() == us.relative_rank(Rank::Two) {
+                    targets |= Bitboard::from(up.shift(forward).filter(|&sq| pos.empty(sq)));
+                }
+            }
+            let targets = allowed(from, targets);
+            count += (targets & !last_rank).popcount() + 4 * (targets & last_rank).popcount();
+
+            if let Some(ep) = pos.ep() {
+                let m = Move::new_with_kind(from, ep, MoveKind::EnPassant);
+                if precompute::pawn_attacks(from, us).has(ep) && pos.is_legal(m) {
+                    count += 1;
+                }
             }
-            i += 1;
         }
-    }
 
-    // Generation helpers.
-    fn pawn_moves(pos: &Position, list: &mut MoveList) {
-        let us = pos.to_move();
+        for to in precompute::king_attacks(king) & !own {
+            if pos.is_legal(Move::new(king, to)) {
+                count += 1;
+            }
+        }
+        for cf in CastleFlag::variants_for(us) {
+            if pos.has_castle(cf) && pos.can_castle(cf) && pos.castle_is_safe(cf) {
+                count += 1;
+            }
+        }
 
-        let enemies = pos.color(!us) | Bitboard::from(pos.ep());
-        let empty = !pos.all();
+        count as usize
+    }
 
-        let pawns = pos.spec(PieceType::Pawn, us);
-        let potential_promotions = pawns & Bitboard::from(us.relative_rank(Rank::Seven));
-        let non_promotions = pawns ^ potential_promotions;
+    // Legal moves generated a piece type at a time, as they're asked for. Anything that only
+    // needs the first legal move, or to know there is one, stops long before the whole list
+    // would have been built. The order is the same as `legal`.
+    pub struct LegalMoves<'a> {
+        pos: &'a Position,
+        // The next piece type to generate for, as an index into `LegalMoves::STAGES`.
+        stage: usize,
+        buffer: MoveList,
+        index: usize,
+    }
 
-        let third_rank = Bitboard::from(us.relative_rank(Rank::Three));
-        let forward = if us == White { North } else { South };
+    impl<'a> LegalMoves<'a> {
+        const STAGES: [PieceType; 6] = [
+            PieceType::Pawn,
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Rook,
+            PieceType::Queen,
+            PieceType::King,
+        ];
+
+        pub const fn new(pos: &'a Position) -> Self {
+            Self {
+                pos,
+                stage: 0,
+                buffer: MoveList::new(),
+                index: 0,
+            }
+        }
 
-        // All promotions
-        for p in potential_promotions {
-            unsafe {
-                let up = p.shift_unchecked(forward);
-                if pos.empty(up) {
-                    add_prom(p, up, list);
+        fn fill(&mut self, kind: PieceType) {
+            let (pos, list) = (self.pos, &mut self.buffer);
+            let pinned = pinned(pos);
+            match kind {
+                PieceType::Pawn => {
+                    pawn_moves(pos, GenOptions::default(), pinned, Bitboard::FULL, list)
                 }
+                PieceType::Knight => knight_moves(pos, pinned, Bitboard::FULL, list),
+                PieceType::Bishop => bishop_moves(pos, pinned, Bitboard::FULL, list),
+                PieceType::Rook => rook_moves(pos, pinned, Bitboard::FULL, list),
+                PieceType::Queen => queen_moves(pos, pinned, Bitboard::FULL, list),
+                PieceType::King => king_moves(pos, Bitboard::FULL, list),
+            }
+        }
+    }
+
+    impl Iterator for LegalMoves<'_> {
+        type Item = Move;
 
-                let proms = Bitboard::from([up.shift(East), up.shift(West)]) & enemies;
-                for dest in proms {
-                    add_prom(p, dest, list);
+        fn next(&mut self) -> Option<Move> {
+            // In check there are few enough moves to just make them all at once.
+            if self.stage == 0 && self.pos.in_check() {
+                evasions(
+                    self.pos,
+                    GenOptions::default(),
+                    Bitboard::FULL,
+                    &mut self.buffer,
+                );
+                self.stage = Self::STAGES.len();
+            }
+            loop {
+                while let Some(m) = self.buffer.get(self.index) {
+                    self.index += 1;
+                    if !needs_check(self.pos, m) || self.pos.is_legal(m) {
+                        return Some(m);
+                    }
                 }
+
+                let &kind = Self::STAGES.get(self.stage)?;
+                self.stage += 1;
+                self.buffer.clear();
+                self.index = 0;
+                self.fill(kind);
             }
         }
+    }
 
-        // Pushes
-        let one_ups = (non_promotions << forward) & empty;
-        let two_ups = ((one_ups & third_rank) << forward) & empty;
+    // Whether there's any legal move at all, usually found after generating only the pawns.
+    pub fn has_legal_move(pos: &Position) -> bool {
+        LegalMoves::new(pos).next().is_some()
+    }
 
-        for p in one_ups {
-            list.push(Move::new(unsafe { p.shift_unchecked(!forward) }, p));
+    // Only these can be illegal once generated with the pins masked out; everything else is kept
+    // unchecked. `LegalMoves` also runs it over the evasions it made in check: those are legal
+    // already, so the king moves and en passant it picks out are only checked a second time.
+    #[cfg_attr(feature = "inline", inline)]
+    fn needs_check(pos: &Position, m: Move) -> bool {
+        match m.kind() {
+            MoveKind::EnPassant => true,
+            // Only generated when safe.
+            MoveKind::Castle => false,
+            _ => m.from() == pos.king(pos.to_move()),
         }
-        for p in two_ups {
-            list.push(Move::new(
-                unsafe { p.shift_unchecked(!forward).shift_unchecked(!forward) },
-                p,
-            ));
+    }
+
+    // Our pieces that can't leave the line between a slider and our king.
+    #[cfg_attr(feature = "inline", inline)]
+    fn pinned(pos: &Position) -> Bitboard {
+        let us = pos.to_move();
+        pos.blockers(us) & pos.color(us)
+    }
+    // Where the piece on `from` can go without uncovering its king: along the pin if it's one of
+    // `pinned`, anywhere if not.
+    #[cfg_attr(feature = "inline", inline)]
+    fn pin_mask(pos: &Position, pinned: Bitboard, from: Square) -> Bitboard {
+        if pinned.has(from) {
+            precompute::line(pos.king(pos.to_move()), from)
+        } else {
+            Bitboard::FULL
         }
+    }
 
-        // Captures
-        let up_east = non_promotions.shift(forward).shift(East) & enemies;
-        let up_west = non_promotions.shift(forward).shift(West) & enemies;
+    // The legal moves out of check onto `targets`, a piece type at a time like everywhere else.
+    // The king can step anywhere not attacked once it has moved off its square (so not back
+    // along a slider's ray). Against a single checker, any other piece can also take it or block
+    // the line to the king. Pinned pieces get the same pin mask as out of check, which leaves
+    // them nothing here. Only en passant is left to `is_legal`.
+    fn evasions<const N: usize>(
+        pos: &Position,
+        options: GenOptions,
+        targets: Bitboard,
+        list: &mut MoveList<N>,
+    ) {
+        let us = pos.to_move();
+        let king = pos.king(us);
+        let checkers = pos.checkers();
 
-        for x in up_east {
-            let f = unsafe { x.shift_unchecked(forward.not()).shift_unchecked(West) };
-            let t = if Some(x) == pos.ep() {
-                MoveKind::EnPassant
-            } else {
-                MoveKind::Normal
-            };
-            list.push(Move::new_with_kind(f, x, t));
+        if !checkers.more_than_one() {
+            let block = (precompute::between(king, checkers.lsb()) | checkers) & targets;
+            // A pin line meets the checking line only at the king, so the mask rules out every
+            // move a pinned piece has.
+            let pinned = pinned(pos);
+            pawn_moves(pos, options, pinned, block, list);
+            knight_moves(pos, pinned, block, list);
+            bishop_moves(pos, pinned, block, list);
+            rook_moves(pos, pinned, block, list);
+            queen_moves(pos, pinned, block, list);
         }
-        for x in up_west {
-            let f = unsafe { x.shift_unchecked(forward.not()).shift_unchecked(East) };
-            let t = if Some(x) == pos.ep() {
-                MoveKind::EnPassant
-            } else {
-                MoveKind::Normal
-            };
-            list.push(Move::new_with_kind(f, x, t));
+
+        let without_king = pos.all() ^ Bitboard::from(king);
+        let safe = (precompute::king_attacks(king) & targets)
+            .into_iter()
+            .filter(|&to| !bool::from(pos.attacks_to_with_occ(to, !us, without_king)))
+            .fold(Bitboard::EMPTY, |bb, to| bb | Bitboard::from(to));
+        // No castling target is ever next to the king, so this never castles out of check.
+        king_moves(pos, safe, list);
+        list.retain(|m| m.kind() != MoveKind::EnPassant || pos.is_legal(m));
+    }
+
+    #[cfg_attr(feature = "inline-aggressive", inline)]
+    fn prune_to_legal<const N: usize>(pos: &Position, list: &mut MoveList<N>) {
+        list.retain(|m| !needs_check(pos, m) || pos.is_legal(m));
+    }
+
+    // Generation helpers.
+
+    // Every pawn move onto `targets`, for all the pawns at once. For en passant it's the pawn
+    // taken that has to be a target, so enemy pieces give just the captures, empty squares just
+    // the quiet moves, and the checker and the line to it the evasions.
+    fn pawn_moves<const N: usize>(
+        pos: &Position,
+        options: GenOptions,
+        pinned: Bitboard,
+        targets: Bitboard,
+        list: &mut MoveList<N>,
+    ) {
+        let us = pos.to_move();
+        let their_king = pos.king(!us);
+        let forward = us.forward();
+        let back = !forward;
+
+        let pawns = pos.spec(PieceType::Pawn, us);
+        let empty = !pos.all();
+        let enemies = pos.color(!us);
+        let last_rank = Bitboard::from(us.relative_rank(Rank::Eight));
+        let third_rank = Bitboard::from(us.relative_rank(Rank::Three));
+
+        let ups = pawns.shift(forward) & empty;
+        let pushes = ups & targets;
+        let double_pushes = (ups & third_rank).shift(forward) & empty & targets;
+        let east_captures = pawns.shift(forward).shift(East) & enemies & targets;
+        let west_captures = pawns.shift(forward).shift(West) & enemies & targets;
+
+        // The moves onto `tos`, each by the pawn found by stepping `steps` back from it.
+        let mut emit = |tos: Bitboard, steps: &[Direction], promotion: bool| {
+            for to in tos {
+                // SAFETY: every square in `tos` came from a pawn shifted the opposite way.
+                let from = steps
+                    .iter()
+                    .fold(to, |sq, &dir| unsafe { sq.shift_unchecked(dir) });
+                if !pin_mask(pos, pinned, from).has(to) {
+                    continue;
+                }
+                if promotion {
+                    add_prom(from, to, options.promotions, their_king, list);
+                } else {
+                    list.push(Move::new(from, to));
+                }
+            }
+        };
+
+        emit(pushes & last_rank, &[back], true);
+        emit(east_captures & last_rank, &[back, West], true);
+        emit(west_captures & last_rank, &[back, East], true);
+        emit(pushes & !last_rank, &[back], false);
+        emit(double_pushes, &[back, back], false);
+        emit(east_captures & !last_rank, &[back, West], false);
+        emit(west_captures & !last_rank, &[back, East], false);
+
+        if let Some(ep) = pos.ep() {
+            if ep.shift(back).is_some_and(|taken| targets.has(taken)) {
+                for from in precompute::pawn_attacks(ep, !us) & pawns {
+                    if pin_mask(pos, pinned, from).has(ep) {
+                        list.push(Move::new_with_kind(from, ep, MoveKind::EnPassant));
+                    }
+                }
+            }
         }
     }
 
-    fn add_prom(from: Square, to: Square, list: &mut MoveList) {
+    fn add_prom<const N: usize>(
+        from: Square,
+        to: Square,
+        promotions: Promotions,
+        their_king: Square,
+        list: &mut MoveList<N>,
+    ) {
         for kind in PieceType::promotable() {
-            list.push(Move::new_with_kind(from, to, MoveKind::Promotion(kind)));
+            let wanted = match promotions {
+                Promotions::All => true,
+                Promotions::QueenOnly => kind == PieceType::Queen,
+                Promotions::QueenAndKnightChecks => {
+                    kind == PieceType::Queen
+                        || (kind == PieceType::Knight
+                            && precompute::knight_attacks(to).has(their_king))
+                }
+            };
+            if wanted {
+                list.push(Move::new_with_kind(from, to, MoveKind::Promotion(kind)));
+            }
         }
     }
 
-    fn knight_moves(pos: &Position, list: &mut MoveList) {
+    // The rest take the same `targets` as `pawn_moves`; our own pieces are never among them.
+    fn knight_moves<const N: usize>(
+        pos: &Position,
+        pinned: Bitboard,
+        targets: Bitboard,
+        list: &mut MoveList<N>,
+    ) {
         let us = pos.to_move();
-        let knights = pos.spec(PieceType::Knight, us);
+        let targets = targets & !pos.color(us);
+        // A pinned knight can never stay on the line.
+        let knights = pos.spec(PieceType::Knight, us) & !pinned;
 
         for k in knights {
-            let movs = precompute::knight_attacks(k) & !pos.color(us);
+            let movs = precompute::knight_attacks(k) & targets;
 
             for m in movs {
                 list.push(Move::new(k, m));
             }
         }
     }
-    fn king_moves(pos: &Position, list: &mut MoveList) {
+    // Castling counts as a move onto the king's destination square, and is only generated if
+    // it's legal.
+    fn king_moves<const N: usize>(pos: &Position, targets: Bitboard, list: &mut MoveList<N>) {
         let us = pos.to_move();
         let king = pos.king(us);
 
-        let movs = precompute::king_attacks(king) & !pos.color(us);
+        let movs = precompute::king_attacks(king) & targets & !pos.color(us);
 
         for m in movs {
             list.push(Move::new(king, m));
         }
 
         for cf in CastleFlag::variants_for(us) {
-            if pos.has_castle(cf) && pos.can_castle(cf) {
+            if pos.has_castle(cf)
+                && pos.can_castle(cf)
+                && pos.castle_is_safe(cf)
+                && targets.has(cf.to_square())
+            {
                 list.push(Move::new_with_kind(
                     cf.from_square(),
                     cf.to_square(),
@@ -359,68 +852,64 @@ pub mod generate {
         }
     }
 
-    fn bishop_moves(pos: &Position, list: &mut MoveList) {
+    fn bishop_moves<const N: usize>(
+        pos: &Position,
+        pinned: Bitboard,
+        targets: Bitboard,
+        list: &mut MoveList<N>,
+    ) {
         let us = pos.to_move();
         let bishops = pos.spec(PieceType::Bishop, us);
-        let targets = !pos.color(us); // XXX Can change if not wanting captures
+        let targets = targets & !pos.color(us);
 
         for b in bishops {
-            let atts = precompute::bishop_attacks(b, pos.all()) & targets;
+            let atts =
+                precompute::bishop_attacks(b, pos.all()) & targets & pin_mask(pos, pinned, b);
             for t in atts {
                 list.push(Move::new(b, t));
             }
         }
     }
-    fn rook_moves(pos: &Position, list: &mut MoveList) {
+    fn rook_moves<const N: usize>(
+        pos: &Position,
+        pinned: Bitboard,
+        targets: Bitboard,
+        list: &mut MoveList<N>,
+    ) {
         let us = pos.to_move();
         let rooks = pos.spec(PieceType::Rook, us);
-        let targets = !pos.color(us); // XXX Can change if not wanting captures
+        let targets = targets & !pos.color(us);
 
         for r in rooks {
-            let atts = precompute::rook_attacks(r, pos.all()) & targets;
+            let atts = precompute::rook_attacks(r, pos.all()) & targets & pin_mask(pos, pinned, r);
             for t in atts {
                 list.push(Move::new(r, t));
             }
         }
     }
-    fn queen_moves(pos: &Position, list: &mut MoveList) {
+    fn queen_moves<const N: usize>(
+        pos: &Position,
+        pinned: Bitboard,
+        targets: Bitboard,
+        list: &mut MoveList<N>,
+    ) {
         let us = pos.to_move();
         let queens = pos.spec(PieceType::Queen, us);
-        let targets = !pos.color(us); // XXX Can change if not wanting captures
+        let targets = targets & !pos.color(us);
 
         for q in queens {
-            let atts = precompute::queen_attacks(q, pos.all()) & targets;
+            let atts = precompute::queen_attacks(q, pos.all()) & targets & pin_mask(pos, pinned, q);
             for t in atts {
                 list.push(Move::new(q, t));
             }
         }
     }
-
-    fn all_sliders_at_once(pos: &Position, list: &mut MoveList) {
-        let us = pos.to_move();
-        let queens = pos.spec(PieceType::Queen, us);
-        let bishops = pos.spec(PieceType::Bishop, us);
-        let rooks = pos.spec(PieceType::Rook, us);
-        let targets = !pos.color(us); // XXX Can change if not wanting captures
-
-        for b in bishops | queens {
-            let atts = precompute::bishop_attacks(b, pos.all()) & targets;
-            for t in atts {
-                list.push(Move::new(b, t));
-            }
-        }
-        for r in rooks | queens {
-            let atts = precompute::rook_attacks(r, pos.all()) & targets;
-            for t in atts {
-                list.push(Move::new(r, t));
-            }
-        }
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::allocations;
     use MoveKind::*;
     use PieceType::*;
     use Square::*;
@@ -450,8 +939,8 @@ mod tests {
         assert_eq!(m2.to(), E8);
         assert_eq!(m2.kind(), Promotion(Queen));
 
-        assert_eq!(m1.is_promo(), true);
-        assert_eq!(m2.is_promo(), true);
+        assert!(m1.is_promo());
+        assert!(m2.is_promo());
 
         assert_eq!(m1.get_promo(), Some(Knight));
         assert_eq!(m2.get_promo(), Some(Queen));
@@ -471,4 +960,240 @@ mod tests {
         assert_eq!(m4.kind(), EnPassant);
         assert_eq!(m5.kind(), Promotion(Queen));
     }
+
+    #[test]
+    fn generation_does_not_allocate() {
+        let positions = [
+            Position::default(),
+            Position::new_from_fen(Position::KIWIPETE_FEN),
+            Position::new_from_fen(
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            ),
+            Position::new_from_fen("8/8/8/K2pP3/8/8/8/7k w - d6 0 1"),
+        ];
+
+        let before = allocations();
+        for pos in &positions {
+            let _ = generate::legal(pos);
+            let _ = generate::pseudo_legal(pos);
+            let mut small = MoveList::<64>::new();
+            generate::legal_into(pos, &mut small);
+            let _ = generate::LegalMoves::new(pos).count();
+        }
+        assert_eq!(allocations(), before);
+    }
+
+    #[test]
+    fn lazy_legal_moves_match_the_list() {
+        let positions = [
+            Position::default(),
+            Position::new_from_fen(Position::KIWIPETE_FEN),
+            Position::new_from_fen(
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            ),
+            Position::new_from_fen("8/8/8/K2pP3/8/8/8/7k w - d6 0 1"),
+            // Mate and stalemate.
+            Position::new_from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1"),
+            Position::new_from_fen("7k/5Q2/8/8/8/8/8/6K1 b - - 0 1"),
+        ];
+
+        for pos in &positions {
+            let lazy: Vec<Move> = generate::LegalMoves::new(pos).collect();
+            assert_eq!(lazy, generate::legal(pos).as_slice(), "{}", pos.to_fen());
+            assert_eq!(generate::has_legal_move(pos), !lazy.is_empty());
+        }
+    }
+
+    #[test]
+    fn evasions_are_exactly_the_legal_moves() {
+        let fens = [
+            // Double check: king moves only.
+            "4k3/8/8/8/8/5n2/8/r3K3 w - - 0 1",
+            // A pinned knight can't block, the bishop can; the king can't step back along the ray.
+            "4k3/8/8/8/b7/8/2N1NB2/r3K3 w - - 0 1",
+            // Blocking by a double push or a promotion, or taking the checker en passant.
+            "K6r/1P6/4k3/8/8/8/8/8 w - - 0 1",
+            "8/8/8/8/r6K/8/4P3/2k5 w - - 0 1",
+            "8/8/3k4/3pP3/4K3/8/8/8 w - d6 0 1",
+            "8/8/8/2k5/3Pp3/8/8/7K b - d3 0 1",
+        ];
+        let mut positions: Vec<Position> = fens.iter().map(|f| Position::new_from_fen(f)).collect();
+        // And every check a move away in some busy positions.
+        for fen in [
+            Position::KIWIPETE_FEN,
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            let mut pos = Position::new_from_fen(fen);
+            for m in &generate::legal(&pos) {
+                pos.make_move(m);
+                if pos.in_check() {
+                    positions.push(pos.detached());
+                }
+                pos.unmake_move(m);
+            }
+        }
+
+        for pos in &positions {
+            assert!(pos.in_check(), "{}", pos.to_fen());
+            let mut evasions: Vec<Move> = generate::legal(pos).as_slice().to_vec();
+            let mut checked: Vec<Move> = (&generate::pseudo_legal(pos))
+                .into_iter()
+                .filter(|&m| pos.is_legal(m))
+                .collect();
+            evasions.sort_by_key(|m| m.to_u16());
+            checked.sort_by_key(|m| m.to_u16());
+            assert_eq!(evasions, checked, "{}", pos.to_fen());
+        }
+    }
+
+    #[test]
+    fn pinned_pieces_stay_on_the_pin() {
+        // Pinned to the e4 king: the e6 rook on its file, the d5 pawn and the f3 knight on
+        // diagonals, and the c4 pawn and g4 bishop along the rank.
+        let pos = Position::new_from_fen("4r2k/8/2b1R3/3P4/r1P1K1Br/5N2/8/7b w - - 0 1");
+        let mut legal: Vec<Move> = generate::legal(&pos).as_slice().to_vec();
+        let mut checked: Vec<Move> = (&generate::pseudo_legal(&pos))
+            .into_iter()
+            .filter(|&m| pos.is_legal(m))
+            .collect();
+        legal.sort_by_key(|m| m.to_u16());
+        checked.sort_by_key(|m| m.to_u16());
+        assert_eq!(legal, checked);
+        assert!(generate::pseudo_legal(&pos).len() > legal.len());
+        assert!(legal.contains(&Move::new(D5, C6)) && legal.contains(&Move::new(E6, E8)));
+        assert!(!legal.contains(&Move::new(D5, D6)) && !legal.contains(&Move::new(E6, D6)));
+        assert!(!legal.iter().any(|m| [C4, F3, G4].contains(&m.from())));
+        assert_eq!(generate::count_legal(&pos), legal.len());
+    }
+
+    #[test]
+    fn captures_then_quiets() {
+        let fens = [
+            Position::KIWIPETE_FEN,
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/8/3k4/3pP3/4K3/8/8/8 w - d6 0 1",
+            "4k3/8/8/8/b7/8/2N1NB2/r3K3 w - - 0 1",
+        ];
+        for fen in fens {
+            let pos = Position::new_from_fen(fen);
+            let mut captures = MoveList::<256>::new();
+            let mut quiets = MoveList::<256>::new();
+            generate::legal_captures_into(&pos, &mut captures);
+            generate::legal_quiets_into(&pos, &mut quiets);
+            assert!(
+                (&captures).into_iter().all(|m| pos.is_capture(m)),
+                "{}",
+                fen
+            );
+            assert!((&quiets).into_iter().all(|m| !pos.is_capture(m)), "{}", fen);
+
+            let mut both: Vec<Move> = captures.as_slice().to_vec();
+            both.extend_from_slice(quiets.as_slice());
+            let mut all = generate::legal(&pos).as_slice().to_vec();
+            both.sort_by_key(|m| m.to_u16());
+            all.sort_by_key(|m| m.to_u16());
+            assert_eq!(both, all, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn promotion_filtering() {
+        // Promoting on a8 or taking on b8; only a knight on b8 checks the d7 king.
+        let pos = Position::new_from_fen("1r6/P2k4/8/8/8/8/8/4K3 w - - 0 1");
+        let promos = |promotions| {
+            let mut list = MoveList::<256>::new();
+            generate::legal_with(&pos, GenOptions { promotions }, &mut list);
+            let mut proms: Vec<_> = (&list)
+                .into_iter()
+                .filter(|m| m.is_promo())
+                .map(|m| (m.to(), m.get_promo().unwrap()))
+                .collect();
+            proms.sort_by_key(|&(s, p)| (s as u8, p as u8));
+            proms
+        };
+
+        assert_eq!(promos(Promotions::All).len(), 8);
+        assert_eq!(
+            promos(Promotions::QueenOnly),
+            vec![(A8, Queen), (B8, Queen)]
+        );
+        assert_eq!(
+            promos(Promotions::QueenAndKnightChecks),
+            vec![(A8, Queen), (B8, Knight), (B8, Queen)]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn overfilled_list_panics() {
+        let mut list = MoveList::<4>::new();
+        generate::legal_into(&Position::default(), &mut list);
+    }
+
+    #[test]
+    fn partition_captures_splits_kiwipete() {
+        let pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        let moves = generate::legal(&pos);
+        let (captures, quiets) = moves.partition_captures(&pos);
+
+        assert_eq!(captures.len(), 8);
+        assert_eq!(captures.len() + quiets.len(), moves.len());
+        assert!((&captures).into_iter().all(|m| pos.is_capture(m)));
+        assert!((&quiets).into_iter().all(|m| !pos.is_capture(m)));
+    }
+
+    #[test]
+    fn list_manipulation() {
+        let pos = Position::default();
+        let mut list = generate::legal(&pos);
+        let all: Vec<Move> = (&list).into_iter().collect();
+        assert_eq!(list.as_slice(), &all[..]);
+
+        list.retain(|m| m.from().rank() == Rank::Two);
+        let pawns: Vec<Move> = all
+            .iter()
+            .copied()
+            .filter(|m| m.from().rank() == Rank::Two)
+            .collect();
+        assert_eq!(list.as_slice(), &pawns[..]);
+
+        list.sort_unstable_by_key(|m| m.to() as u8);
+        assert!(list.as_slice().windows(2).all(|w| w[0].to() <= w[1].to()));
+        list.swap(0, 1);
+        assert!(list.get(0).unwrap().to() > list.get(1).unwrap().to());
+
+        list.clear();
+        assert!(list.is_empty());
+        assert_eq!(list.as_slice(), &[]);
+    }
+
+    #[test]
+    fn u16_round_trip() {
+        let moves = [
+            Move::new(A2, A5),
+            Move::new_with_kind(E1, G1, Castle),
+            Move::new_with_kind(E8, C8, Castle),
+            Move::new_with_kind(D5, E6, EnPassant),
+            Move::new_with_kind(B2, A1, Promotion(Knight)),
+            Move::new_with_kind(G7, G8, Promotion(Queen)),
+        ];
+
+        for m in moves {
+            assert_eq!(Move::try_from(m.to_u16()), Ok(m));
+        }
+    }
+
+    #[test]
+    fn u16_rejects_garbage() {
+        assert_eq!(Move::try_from(0), Err(MoveParseError::Code(0)));
+        assert!(Move::try_from(0x8000 | Move::new(A2, A5).to_u16()).is_err());
+        // Flag 101 is unused.
+        assert!(Move::try_from(0x5000 | Move::new(A2, A5).to_u16()).is_err());
+        // Same from and to square.
+        assert!(Move::try_from((B3 as u16) | ((B3 as u16) << 6)).is_err());
+
+        assert!(Move::try_from(Move::new_with_kind(A1, A7, Castle).to_u16()).is_err());
+        assert!(Move::try_from(Move::new_with_kind(A1, F4, EnPassant).to_u16()).is_err());
+        assert!(Move::try_from(Move::new_with_kind(A1, E8, Promotion(Queen)).to_u16()).is_err());
+    }
 }
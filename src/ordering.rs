@@ -0,0 +1,200 @@
+use crate::color::Color;
+use crate::movegen::{Move, MoveKind, MoveList, ScoredMoveList};
+use crate::piece::PieceType;
+use crate::position::Position;
+use crate::search::MAX_PLY;
+use crate::values::{piece_value, promotion_delta};
+
+// Scores moves for search, best first: the hash move, then captures by MVV-LVA (and
+// promotions by what they gain), then the killers for this ply, then quiets by history, and
+// last the captures that lose material by static exchange.
+#[derive(Debug, Clone)]
+pub struct MoveOrderer {
+    killers: [[Option<Move>; 2]; MAX_PLY as usize],
+    // Indexed by mover, from square and to square.
+    history: [[[i32; 64]; 64]; 2],
+}
+
+const HASH_MOVE: i32 = 1_000_000;
+const CAPTURE: i32 = 100_000;
+const KILLERS: [i32; 2] = [90_000, 89_000];
+// History scores are kept below the killers.
+const HISTORY_MAX: i32 = 80_000;
+const LOSING_CAPTURE: i32 = -100_000;
+
+impl MoveOrderer {
+    pub fn new() -> Self {
+        Self {
+            killers: [[None; 2]; MAX_PLY as usize],
+            history: [[[0; 64]; 64]; 2],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn score(&self, pos: &Position, mov: Move, hash_move: Option<Move>, ply: usize) -> i32 {
+        if Some(mov) == hash_move {
+            return HASH_MOVE;
+        }
+
+        let victim = if mov.kind() == MoveKind::EnPassant {
+            Some(PieceType::Pawn)
+        } else {
+            pos.piece_on(mov.to()).map(|p| p.kind())
+        };
+        let gain = victim.map_or(0, piece_value) + mov.get_promo().map_or(0, promotion_delta);
+
+        if victim.is_some() {
+            let see = pos.see(mov);
+            if see < 0 {
+                return LOSING_CAPTURE + see;
+            }
+        }
+        if victim.is_some() || mov.is_promo() {
+            // Most valuable victim first, and of those, the least valuable attacker.
+            let attacker = pos
+                .piece_on(mov.from())
+                .map_or(0, |p| piece_value(p.kind()));
+            return CAPTURE + 10 * gain - attacker / 10;
+        }
+
+        if let Some(i) = self
+            .killers
+            .get(ply)
+            .and_then(|k| k.iter().position(|&k| k == Some(mov)))
+        {
+            return KILLERS[i];
+        }
+
+        self.history[pos.to_move() as usize][mov.from() as usize][mov.to() as usize]
+    }
+
+    // Sorts `list` best first.
+    pub fn order<const N: usize>(
+        &self,
+        pos: &Position,
+        list: &mut MoveList<N>,
+        hash_move: Option<Move>,
+        ply: usize,
+    ) {
+        list.sort_by_score(|m| self.score(pos, m, hash_move, ply));
+    }
+    // Scores `list` for taking best first with `pick_best`, which is cheaper than `order` when
+    // only the first few moves are likely to be searched.
+    pub fn scored<const N: usize>(
+        &self,
+        pos: &Position,
+        list: &MoveList<N>,
+        hash_move: Option<Move>,
+        ply: usize,
+    ) -> ScoredMoveList<N> {
+        ScoredMoveList::from_list(list, |m| self.score(pos, m, hash_move, ply))
+    }
+
+    // A quiet move caused a beta cutoff at `ply`.
+    pub fn store_killer(&mut self, mov: Move, ply: usize) {
+        let Some(killers) = self.killers.get_mut(ply) else {
+            return;
+        };
+        if killers[0] != Some(mov) {
+            killers[1] = killers[0];
+            killers[0] = Some(mov);
+        }
+    }
+    pub fn update_history(&mut self, color: Color, mov: Move, depth: u32) {
+        let entry = &mut self.history[color as usize][mov.from() as usize][mov.to() as usize];
+        *entry += (depth * depth) as i32;
+
+        // Halve everything once one entry gets too big, so the table keeps up with the search.
+        if *entry >= HISTORY_MAX {
+            for x in self.history.iter_mut().flatten().flatten() {
+                *x /= 2;
+            }
+        }
+    }
+}
+
+impl Default for MoveOrderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::generate;
+    use crate::square::Square::*;
+
+    #[test]
+    fn orders_hash_captures_killers_history() {
+        // The queen on d5 can be taken by the pawn or the rook; the pawn should come first.
+        let pos = Position::new_from_fen("4k3/8/8/3q4/4P3/8/8/3RK3 w - - 0 1");
+        let mut orderer = MoveOrderer::new();
+        orderer.store_killer(Move::new(E1, F2), 3);
+        orderer.update_history(Color::White, Move::new(D1, C1), 4);
+
+        let mut list = generate::legal(&pos);
+        orderer.order(&pos, &mut list, Some(Move::new(E1, E2)), 3);
+        let ordered: Vec<Move> = (&list).into_iter().collect();
+
+        assert_eq!(
+            ordered[..5],
+            [
+                Move::new(E1, E2),
+                Move::new(E4, D5),
+                Move::new(D1, D5),
+                Move::new(E1, F2),
+                Move::new(D1, C1),
+            ]
+        );
+
+        // Killers are per ply.
+        orderer.order(&pos, &mut list, None, 4);
+        assert_eq!(list.get(2), Some(Move::new(D1, C1)));
+    }
+
+    #[test]
+    fn pick_best_matches_sorting() {
+        let pos = Position::new_from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        );
+        let mut orderer = MoveOrderer::new();
+        orderer.update_history(Color::White, Move::new(A1, B1), 3);
+        let list = generate::legal(&pos);
+
+        let mut scored = orderer.scored(&pos, &list, None, 0);
+        assert_eq!(scored.len(), list.len());
+        let mut picked = Vec::new();
+        while let Some((m, score)) = scored.pick_best() {
+            assert_eq!(score, orderer.score(&pos, m, None, 0));
+            picked.push(score);
+        }
+        assert_eq!(scored.remaining(), 0);
+        assert_eq!(picked.len(), list.len());
+        assert!(picked.is_sorted_by(|a, b| a >= b));
+    }
+
+    #[test]
+    fn losing_captures_go_last() {
+        // Qxd5 gives the queen up for a pawn.
+        let pos = Position::new_from_fen("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1");
+        let mut list = generate::legal(&pos);
+        MoveOrderer::new().order(&pos, &mut list, None, 0);
+        assert_eq!(list.get(list.len() - 1), Some(Move::new(D1, D5)));
+    }
+
+    #[test]
+    fn killers_shift_down() {
+        let mut orderer = MoveOrderer::new();
+        orderer.store_killer(Move::new(A2, A3), 0);
+        orderer.store_killer(Move::new(B2, B3), 0);
+        orderer.store_killer(Move::new(B2, B3), 0);
+        assert_eq!(
+            orderer.killers[0],
+            [Some(Move::new(B2, B3)), Some(Move::new(A2, A3))]
+        );
+    }
+}
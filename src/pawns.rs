@@ -0,0 +1,133 @@
+// Pawn structure, set-wise: everything here takes whole pawn bitboards and answers for all of
+// them at once, so evaluation never has to loop over pawns one by one. `color` is always the
+// side the pawns in the first argument belong to, and "in front" means towards its promotion
+// rank.
+use crate::bitboard::Bitboard;
+use crate::color::Color;
+use crate::precompute;
+use crate::square::{Direction, Square};
+
+// Every square on the same files as `bb`, from each one to the edge in `color`'s forward
+// direction, the starting squares included.
+#[cfg_attr(feature = "inline", inline)]
+pub fn fill_forward(bb: Bitboard, color: Color) -> Bitboard {
+    let mut bb = bb;
+    match color {
+        Color::White => {
+            bb |= bb << 8;
+            bb |= bb << 16;
+            bb |= bb << 32;
+        }
+        Color::Black => {
+            bb |= bb >> 8;
+            bb |= bb >> 16;
+            bb |= bb >> 32;
+        }
+    }
+    bb
+}
+#[cfg_attr(feature = "inline", inline)]
+pub fn fill_backward(bb: Bitboard, color: Color) -> Bitboard {
+    fill_forward(bb, !color)
+}
+// Whole files holding at least one of `bb`.
+#[cfg_attr(feature = "inline", inline)]
+pub fn file_fill(bb: Bitboard) -> Bitboard {
+    fill_forward(bb, Color::White) | fill_forward(bb, Color::Black)
+}
+
+// The squares in front of the pawns on their own files, not counting the pawns themselves.
+#[cfg_attr(feature = "inline", inline)]
+pub fn front_spans(pawns: Bitboard, color: Color) -> Bitboard {
+    fill_forward(pawns.shift(color.forward()), color)
+}
+#[cfg_attr(feature = "inline", inline)]
+pub fn rear_spans(pawns: Bitboard, color: Color) -> Bitboard {
+    front_spans(pawns, !color)
+}
+// Every square the pawns could ever attack by pushing on: the front spans of the files beside
+// them.
+#[cfg_attr(feature = "inline", inline)]
+pub fn attack_spans(pawns: Bitboard, color: Color) -> Bitboard {
+    let spans = front_spans(pawns, color);
+    spans.shift(Direction::East) | spans.shift(Direction::West)
+}
+
+// The front span of `square` plus the files beside it: no enemy pawn there means a passer.
+#[cfg_attr(feature = "inline", inline)]
+pub fn passed_mask(square: Square, color: Color) -> Bitboard {
+    precompute::passed_pawn_mask(square, color)
+}
+// Pawns with no enemy pawn in front of them on their own or a neighbouring file.
+#[cfg_attr(feature = "inline", inline)]
+pub fn passed(ours: Bitboard, theirs: Bitboard, color: Color) -> Bitboard {
+    ours & !(front_spans(theirs, !color) | attack_spans(theirs, !color))
+}
+// Pawns with no pawn of their own on either neighbouring file.
+#[cfg_attr(feature = "inline", inline)]
+pub fn isolated(pawns: Bitboard) -> Bitboard {
+    let files = file_fill(pawns);
+    pawns & !(files.shift(Direction::East) | files.shift(Direction::West))
+}
+// Pawns with another of their own further up the same file; the front pawn of each stack is
+// not included.
+#[cfg_attr(feature = "inline", inline)]
+pub fn doubled(pawns: Bitboard, color: Color) -> Bitboard {
+    pawns & rear_spans(pawns, color)
+}
+// Pawns that can't be supported by their neighbours any more, and can't step up to them either:
+// the square in front is covered by an enemy pawn and no pawn of ours can ever defend it.
+#[cfg_attr(feature = "inline", inline)]
+pub fn backward(ours: Bitboard, theirs: Bitboard, color: Color) -> Bitboard {
+    let stops = ours.shift(color.forward());
+    let weak = stops & precompute::pawn_attacks_bb(theirs, !color) & !attack_spans(ours, color);
+    weak.shift((!color).forward())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bb;
+    use crate::color::Color::*;
+
+    #[test]
+    fn spans_and_fills() {
+        assert_eq!(front_spans(bb![E6], White), bb![E7, E8]);
+        assert_eq!(front_spans(bb![E3, B2], Black), bb![E2, E1, B1]);
+        assert_eq!(rear_spans(bb![C3], White), bb![C2, C1]);
+        assert_eq!(attack_spans(bb![A6], White), bb![B7, B8]);
+        assert_eq!(attack_spans(bb![D3], Black), bb![C2, C1, E2, E1]);
+        assert_eq!(file_fill(bb![D4]), Bitboard::from(crate::square::File::D));
+
+        for square in Square::iter() {
+            for color in [White, Black] {
+                let single = Bitboard::from(square);
+                assert_eq!(
+                    front_spans(single, color),
+                    precompute::front_span(square, color)
+                );
+                assert_eq!(
+                    front_spans(single, color) | attack_spans(single, color),
+                    passed_mask(square, color)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn structure() {
+        assert_eq!(passed(bb![C3, C5, F2], bb![A7, E6], White), bb![C3, C5]);
+        assert_eq!(passed(bb![B4, H3], bb![D2, H2], Black), bb![B4]);
+
+        assert_eq!(isolated(bb![A2, C3, C5, D4, H5]), bb![A2, H5]);
+        assert_eq!(doubled(bb![C3, C5, D4], White), bb![C3]);
+        assert_eq!(doubled(bb![C7, C5, C4], Black), bb![C7, C5]);
+
+        assert_eq!(
+            backward(bb![D3, E4], bb![D5, E6, F5], White),
+            Bitboard::EMPTY
+        );
+        assert_eq!(backward(bb![D2, E4], bb![C4, E6], White), bb![D2]);
+        assert_eq!(backward(bb![D7, E5], bb![C5, E3], Black), bb![D7]);
+    }
+}
@@ -1,67 +1,188 @@
-use crate::movegen::generate;
+use crate::movegen::{generate, Move};
+use crate::pool;
 use crate::position::Position;
+use std::time::{Duration, Instant};
 
+// The number of leaf nodes `depth` plies below `pos`.
 pub fn perft(pos: &mut Position, depth: usize) -> usize {
     if depth == 0 {
         return 1;
     }
 
-    let mut nodes = 0;
     let moves = generate::legal(pos);
-
     if depth == 1 {
         return moves.len();
     }
 
+    let mut nodes = 0;
     for x in &moves {
         pos.make_move(x);
-        let c = perft__(pos, depth - 1);
-        nodes += c;
-        println!("{x}: {c}");
+        nodes += perft(pos, depth - 1);
         pos.unmake_move(x);
     }
 
     nodes
 }
 
-fn perft__(pos: &mut Position, depth: usize) -> usize {
-    if depth == 0 {
-        return 1;
+// `perft` in the standard benchmark mode: the last ply is counted with
+// `generate::count_legal` instead of a full legal move list.
+pub fn perft_bulk(pos: &mut Position, depth: usize) -> usize {
+    match depth {
+        0 => 1,
+        1 => generate::count_legal(pos),
+        _ => {
+            let mut nodes = 0;
+            for x in &generate::legal(pos) {
+                pos.make_move(x);
+                nodes += perft_bulk(pos, depth - 1);
+                pos.unmake_move(x);
+            }
+            nodes
+        }
+    }
+}
+
+// How far `perft_limited` got, and how fast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerftReport {
+    // Leaf nodes counted: the perft count if `complete`, otherwise just past the budget.
+    pub nodes: usize,
+    pub complete: bool,
+    pub elapsed: Duration,
+}
+
+impl PerftReport {
+    pub fn nps(&self) -> u64 {
+        (self.nodes as u128 * 1_000_000_000 / self.elapsed.as_nanos().max(1)) as u64
     }
+}
 
+// `perft` that gives up once it has counted more than `max_nodes` leaves, for smoke tests and
+// fuzzing that can't wait for a full count. Nothing is allocated besides the move lists on the
+// stack, and `pos` is left as it was either way.
+pub fn perft_limited(pos: &mut Position, depth: usize, max_nodes: usize) -> PerftReport {
+    let start = Instant::now();
     let mut nodes = 0;
-    let moves = generate::legal(pos);
+    let complete = limited(pos, depth, max_nodes, &mut nodes);
+    PerftReport {
+        nodes,
+        complete,
+        elapsed: start.elapsed(),
+    }
+}
+
+// Adds the leaves below `pos` to `nodes`, and returns false as soon as that passes `max_nodes`.
+fn limited(pos: &mut Position, depth: usize, max_nodes: usize, nodes: &mut usize) -> bool {
+    if depth == 0 {
+        *nodes += 1;
+        return *nodes <= max_nodes;
+    }
 
+    let moves = generate::legal(pos);
     if depth == 1 {
-        return moves.len();
+        *nodes += moves.len();
+        return *nodes <= max_nodes;
     }
 
     for x in &moves {
         pos.make_move(x);
-        nodes += perft__(pos, depth - 1);
+        let within = limited(pos, depth - 1, max_nodes, nodes);
+        pos.unmake_move(x);
+        if !within {
+            return false;
+        }
+    }
+    true
+}
+
+// One cached subtree count. `depth` 0 marks an empty slot, as depth 0 is never stored.
+#[derive(Debug, Clone, Copy, Default)]
+struct Entry {
+    key: u64,
+    depth: usize,
+    nodes: usize,
+}
+
+// `perft`, with subtree counts cached by Zobrist key and depth in a table of about `hash_mb`
+// megabytes. Transpositions are common enough in perft trees to make this several times
+// faster from depth 5 or so.
+pub fn perft_hashed(pos: &mut Position, depth: usize, hash_mb: usize) -> usize {
+    let slots =
+        (hash_mb.max(1) * 1024 * 1024 / std::mem::size_of::<Entry>()).next_power_of_two() / 2;
+    let mut table = vec![Entry::default(); slots.max(1)];
+    hashed(pos, depth, &mut table)
+}
+
+fn hashed(pos: &mut Position, depth: usize, table: &mut [Entry]) -> usize {
+    if depth <= 1 {
+        return perft(pos, depth);
+    }
+
+    let slot = pos.key() as usize & (table.len() - 1);
+    let entry = table[slot];
+    if entry.key == pos.key() && entry.depth == depth {
+        return entry.nodes;
+    }
+
+    let mut nodes = 0;
+    for x in &generate::legal(pos) {
+        pos.make_move(x);
+        nodes += hashed(pos, depth - 1, table);
         pos.unmake_move(x);
     }
 
+    table[slot] = Entry {
+        key: pos.key(),
+        depth,
+        nodes,
+    };
     nodes
 }
 
-#[cfg(test)]
-#[ctor::ctor]
-fn test_inits() {
-    use crate::precompute;
+// The perft count split by root move, in generation order, for finding where a generator
+// disagrees with a reference one. The counts add up to `perft(pos, depth)`.
+pub fn divide(pos: &mut Position, depth: usize) -> Vec<(Move, usize)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+
+    let mut counts = Vec::new();
+    for x in &generate::legal(pos) {
+        pos.make_move(x);
+        counts.push((x, perft(pos, depth - 1)));
+        pos.unmake_move(x);
+    }
+
+    counts
+}
+
+// The same count as `perft`, with the moves from the root split over the shared thread pool.
+pub fn parallel(pos: &Position, depth: usize) -> usize {
+    if depth <= 1 {
+        return perft(&mut pos.clone(), depth);
+    }
 
-    precompute::initialize();
+    let moves: Vec<_> = (&generate::legal(pos)).into_iter().collect();
+    pool::map(&moves, |&m| {
+        let mut pos = pos.clone();
+        pos.make_move(m);
+        perft(&mut pos, depth - 1)
+    })
+    .into_iter()
+    .sum()
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     macro_rules! create_suite {
         ($name:ident, $fen:expr, $results:expr) => {
             mod $name {
                 const RES: [usize; 5] = $results;
                 const FEN: &str = $fen;
 
-                use super::super::{perft, Position};
+                use super::super::{parallel, perft, perft_bulk, perft_hashed, Position};
 
                 #[test]
                 fn depth_1() {
@@ -86,6 +207,33 @@ mod tests {
                     }
                 }
                 #[test]
+                fn bulk_depth_4() {
+                    let mut pos = Position::new_from_fen(FEN);
+                    if RES[3] > 0 {
+                        assert_eq!(perft_bulk(&mut pos, 4), RES[3]);
+                    }
+                }
+                #[test]
+                fn hashed_depth_4() {
+                    let mut pos = Position::new_from_fen(FEN);
+                    if RES[3] > 0 {
+                        assert_eq!(perft_hashed(&mut pos, 4, 4), RES[3]);
+                    }
+                }
+                #[test]
+                #[cfg_attr(debug_assertions, ignore = "depth 5 is only quick enough in release")]
+                fn hashed_depth_5() {
+                    let mut pos = Position::new_from_fen(FEN);
+                    if RES[4] > 0 {
+                        assert_eq!(perft_hashed(&mut pos, 5, 16), RES[4]);
+                    }
+                }
+                #[test]
+                fn parallel_depth_3() {
+                    let pos = Position::new_from_fen(FEN);
+                    assert_eq!(parallel(&pos, 3), RES[2]);
+                }
+                #[test]
                 #[ignore = "depth 5 generally takes too long"]
                 fn depth_5() {
                     let mut pos = Position::new_from_fen(FEN);
@@ -97,6 +245,41 @@ mod tests {
         };
     }
 
+    #[test]
+    fn divide_splits_the_count() {
+        use crate::square::Square::*;
+
+        let mut pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        let counts = divide(&mut pos, 2);
+        assert_eq!(counts.len(), 48);
+        assert_eq!(counts.iter().map(|&(_, n)| n).sum::<usize>(), 2039);
+        assert!(counts.contains(&(Move::new_with_kind(E1, G1, crate::MoveKind::Castle), 43)));
+        assert!(divide(&mut pos, 0).is_empty());
+    }
+
+    #[test]
+    fn limited_stops_at_the_budget() {
+        let mut pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        let key = pos.key();
+
+        let full = perft_limited(&mut pos, 3, usize::MAX);
+        assert!(full.complete);
+        assert_eq!(full.nodes, 97862);
+
+        let cut = perft_limited(&mut pos, 3, 5000);
+        assert!(!cut.complete);
+        assert!(cut.nodes > 5000 && cut.nodes < 5000 + 256);
+        assert_eq!(pos.key(), key);
+        assert_eq!(
+            pos.to_fen(),
+            Position::new_from_fen(Position::KIWIPETE_FEN).to_fen()
+        );
+
+        let exact = perft_limited(&mut pos, 2, 2039);
+        assert!(exact.complete);
+        assert_eq!(exact.nodes, 2039);
+    }
+
     create_suite!(
         startpos,
         Position::STARTING_FEN,
@@ -0,0 +1,532 @@
+use crate::analysis::AnalysisLine;
+use crate::color::Color;
+use crate::errors::{MoveError, MoveParseError, PgnError};
+use crate::movegen::Move;
+use crate::position::Position;
+use crate::search::Score;
+
+// One game in Portable Game Notation: its tag pairs, in order, and the mainline moves.
+// Comments, NAGs and variations are skipped on import and not kept.
+#[derive(Debug, Clone)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<Move>,
+}
+
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+impl Game {
+    // An empty game from the standard start, with the seven required tags left unknown.
+    pub fn new() -> Self {
+        let tags = SEVEN_TAG_ROSTER
+            .iter()
+            .map(|&t| {
+                let value = match t {
+                    "Date" => "????.??.??",
+                    "Result" => "*",
+                    _ => "?",
+                };
+                (t.to_string(), value.to_string())
+            })
+            .collect();
+
+        Self {
+            tags,
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(t, _)| t == name)
+            .map(|(_, v)| v.as_str())
+    }
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(t, _)| t == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn result(&self) -> &str {
+        self.tag("Result").unwrap_or("*")
+    }
+
+    // Where the game starts, taken from the `FEN` tag when there is one. Panics on a bad FEN tag,
+    // which `parse` never lets through.
+    pub fn start_position(&self) -> Position {
+        match self.tag("FEN") {
+            Some(fen) => Position::new_from_fen(fen),
+            None => Position::default(),
+        }
+    }
+    // The position after every move has been played, with the full history to unmake.
+    pub fn final_position(&self) -> Position {
+        let mut pos = self.start_position();
+        for &m in &self.moves {
+            pos.make_move(m);
+        }
+        pos
+    }
+
+    // Parses a single game. Fails on a malformed tag or on a move that is not legal where it is played.
+    pub fn parse(pgn: &str) -> Result<Self, PgnError> {
+        let mut game = Self {
+            tags: Vec::new(),
+            moves: Vec::new(),
+        };
+
+        let mut movetext = String::new();
+        for line in pgn.lines() {
+            let line = line.trim();
+            if line.starts_with('[') && movetext.trim().is_empty() {
+                let (name, value) =
+                    parse_tag(line).ok_or_else(|| PgnError::BadTag(line.to_string()))?;
+                game.set_tag(&name, &value);
+            } else if !line.starts_with('%') {
+                movetext += line;
+                movetext.push('\n');
+            }
+        }
+
+        if let Some(fen) = game.tag("FEN") {
+            Position::try_from_fen(fen)?;
+        }
+
+        let mut pos = game.start_position();
+        for token in tokens(&movetext).ok_or(PgnError::BadMovetext)? {
+            if RESULTS.contains(&token) {
+                game.set_tag("Result", token);
+                break;
+            }
+
+            let san = strip_move_number(token);
+            if san.is_empty() {
+                continue;
+            }
+
+            let mov = Move::from_san(san, &pos).ok_or_else(|| PgnError::Move {
+                ply: game.moves.len(),
+                error: MoveError::Parse(MoveParseError::San(san.to_string())),
+            })?;
+            pos.make_move(mov);
+            game.moves.push(mov);
+        }
+
+        Ok(game)
+    }
+
+    // Parses every game in a file of them, each starting at its first tag pair after the last
+    // game's moves. Stops at the first game that doesn't parse, with its (1 based) number.
+    pub fn parse_all(pgn: &str) -> Result<Vec<Self>, (usize, PgnError)> {
+        let mut texts = Vec::new();
+        let mut text = String::new();
+        let mut in_movetext = false;
+        for line in pgn.lines() {
+            let tag = line.trim_start().starts_with('[');
+            if tag && in_movetext {
+                texts.push(std::mem::take(&mut text));
+                in_movetext = false;
+            }
+            in_movetext |= !tag && !line.trim().is_empty();
+            text += line;
+            text.push('\n');
+        }
+        if !text.trim().is_empty() {
+            texts.push(text);
+        }
+
+        texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| Self::parse(text).map_err(|e| (i + 1, e)))
+            .collect()
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut pos = self.start_position();
+        let mut words = Vec::new();
+        let mut numbered = false;
+        for &m in &self.moves {
+            push_move(&mut words, &pos, m, &mut numbered);
+            pos.make_move(m);
+        }
+        words.push(self.result().to_string());
+
+        self.tag_section() + &wrap(words)
+    }
+
+    // The game with engine analysis worked in: each `(ply, lines)` annotates the position
+    // before the game's move at `ply`. The game's move gets the score of a line that starts
+    // with it, and every other line follows as a variation, scored after its first move.
+    // Lines for the position after the last move (or for a game with no moves, as a single
+    // analysed position is written) go on from there: the first as the mainline, the rest as
+    // its variations. Scores are `[%eval]` comments, from White's side in pawns.
+    pub fn to_annotated_pgn(&self, analysis: &[(usize, Vec<AnalysisLine>)]) -> String {
+        let lines_at = |ply| {
+            analysis
+                .iter()
+                .find(|(p, _)| *p == ply)
+                .map_or(&[][..], |(_, lines)| &lines[..])
+        };
+
+        let mut pos = self.start_position();
+        let mut words = Vec::new();
+        let mut numbered = false;
+        for (ply, &m) in self.moves.iter().enumerate() {
+            let lines = lines_at(ply);
+            push_move(&mut words, &pos, m, &mut numbered);
+            if let Some(line) = lines.iter().find(|l| l.moves.first() == Some(&m)) {
+                push_eval(&mut words, &pos, line, &mut numbered);
+            }
+            for line in lines.iter().filter(|l| l.moves.first() != Some(&m)) {
+                push_variation(&mut words, &pos, line, &mut numbered);
+            }
+            pos.make_move(m);
+        }
+
+        if let Some((best, others)) = lines_at(self.moves.len()).split_first() {
+            if let Some((&first, rest)) = best.moves.split_first() {
+                push_move(&mut words, &pos, first, &mut numbered);
+                push_eval(&mut words, &pos, best, &mut numbered);
+                for line in others {
+                    push_variation(&mut words, &pos, line, &mut numbered);
+                }
+                pos.make_move(first);
+                for &m in rest {
+                    push_move(&mut words, &pos, m, &mut numbered);
+                    pos.make_move(m);
+                }
+            }
+        }
+        words.push(self.result().to_string());
+
+        self.tag_section() + &wrap(words)
+    }
+
+    fn tag_section(&self) -> String {
+        let mut pgn = String::new();
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn += &format!("[{} \"{}\"]\n", name, value);
+        }
+        pgn.push('\n');
+        pgn
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Display for Game {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_pgn())
+    }
+}
+
+// `mov` in SAN, after its move number: always before White's move, and before Black's when it
+// follows a comment or variation (or starts the movetext), as `12...`. `numbered` is whether
+// the movetext so far would carry the number on.
+fn push_move(words: &mut Vec<String>, pos: &Position, mov: Move, numbered: &mut bool) {
+    if pos.to_move() == Color::White {
+        words.push(format!("{}.", pos.fullmoves()));
+    } else if !*numbered {
+        words.push(format!("{}...", pos.fullmoves()));
+    }
+    words.push(mov.to_san(pos));
+    *numbered = true;
+}
+
+// The score of `line`, analysed at `pos`, as a comment.
+fn push_eval(words: &mut Vec<String>, pos: &Position, line: &AnalysisLine, numbered: &mut bool) {
+    let score = match pos.to_move() {
+        Color::White => line.score,
+        Color::Black => -line.score,
+    };
+    let eval = match Score::from_raw(score) {
+        Score::Cp(cp) => format!("{:.2}", cp as f64 / 100.0),
+        Score::Mate(n) => format!("#{}", n),
+    };
+    words.push(format!("{{[%eval {},{}]}}", eval, line.depth));
+    *numbered = false;
+}
+
+// `line`, analysed at `pos`, as a variation with its score after the first move.
+fn push_variation(
+    words: &mut Vec<String>,
+    pos: &Position,
+    line: &AnalysisLine,
+    numbered: &mut bool,
+) {
+    let Some((&first, rest)) = line.moves.split_first() else {
+        return;
+    };
+    let mut pos = pos.detached();
+    let mut inner = Vec::new();
+    let mut inner_numbered = false;
+    push_move(&mut inner, &pos, first, &mut inner_numbered);
+    push_eval(&mut inner, &pos, line, &mut inner_numbered);
+    pos.make_move(first);
+    for &m in rest {
+        push_move(&mut inner, &pos, m, &mut inner_numbered);
+        pos.make_move(m);
+    }
+
+    inner[0].insert(0, '(');
+    inner.last_mut().expect("a move and its score").push(')');
+    words.extend(inner);
+    *numbered = false;
+}
+
+// Movetext words joined into lines under 80 columns.
+fn wrap(words: Vec<String>) -> String {
+    let mut text = String::new();
+    let mut line_len = 0;
+    for w in words {
+        if line_len > 0 && line_len + 1 + w.len() > 79 {
+            text.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            text.push(' ');
+            line_len += 1;
+        }
+        line_len += w.len();
+        text += &w;
+    }
+    text.push('\n');
+    text
+}
+
+// `[Name "value"]`, with `\"` and `\\` escapes inside the value.
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let (name, rest) = inner.split_once(char::is_whitespace)?;
+    let rest = rest.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut value = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            value.push(chars.next()?);
+        } else {
+            value.push(c);
+        }
+    }
+
+    Some((name.to_string(), value))
+}
+
+// Splits movetext into words, dropping comments, NAGs and (nested) variations.
+fn tokens(movetext: &str) -> Option<Vec<&str>> {
+    let mut out = Vec::new();
+    let mut depth = 0usize;
+    let mut start = None;
+    let mut chars = movetext.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let breaks = c.is_whitespace() || matches!(c, '{' | ';' | '(' | ')');
+        if breaks {
+            if let Some(s) = start.take() {
+                if depth == 0 {
+                    out.push(&movetext[s..i]);
+                }
+            }
+        }
+
+        match c {
+            '{' => {
+                chars.by_ref().find(|&(_, c)| c == '}')?;
+            }
+            ';' => {
+                chars.by_ref().find(|&(_, c)| c == '\n');
+            }
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            _ if breaks => (),
+            _ => {
+                if start.is_none() {
+                    start = Some(i);
+                }
+            }
+        }
+    }
+    if let Some(s) = start {
+        if depth == 0 {
+            out.push(&movetext[s..]);
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+
+    Some(out.into_iter().filter(|t| !t.starts_with('$')).collect())
+}
+
+// `12.Nf3` and `12...Nf6` become the bare move; a lone `12.` becomes empty.
+fn strip_move_number(token: &str) -> &str {
+    let digits = token.bytes().take_while(u8::is_ascii_digit).count();
+    if digits > 0 && token[digits..].starts_with('.') {
+        token[digits..].trim_start_matches('.')
+    } else {
+        token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::generate;
+
+    const SCHOLARS_MATE: &str = r#"[Event "Casual game"]
+[Site "?"]
+[Date "2024.01.01"]
+[Round "-"]
+[White "A \"Quoted\" Player"]
+[Black "B"]
+[Result "1-0"]
+
+1. e4 {The king's pawn} e5 2. Bc4 $1 (2. Nf3 Nc6 (2... d6) 3. Bb5) Nc6
+3.Qh5 Nf6?? ; loses at once
+4. Qxf7# 1-0
+"#;
+
+    #[test]
+    fn parses_mainline_and_tags() {
+        let game = Game::parse(SCHOLARS_MATE).unwrap();
+        assert_eq!(game.moves.len(), 7);
+        assert_eq!(game.tag("White"), Some("A \"Quoted\" Player"));
+        assert_eq!(game.result(), "1-0");
+
+        let pos = game.final_position();
+        assert!(pos.in_check());
+        assert_eq!(generate::legal(&pos).len(), 0);
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let game = Game::parse(SCHOLARS_MATE).unwrap();
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[White \"A \\\"Quoted\\\" Player\"]"));
+        assert!(pgn.ends_with("1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0\n"));
+
+        let again = Game::parse(&pgn).unwrap();
+        assert_eq!(again.moves, game.moves);
+        assert_eq!(again.tags, game.tags);
+    }
+
+    #[test]
+    fn starts_from_fen_tag() {
+        let pgn = r#"[FEN "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 7"]
+
+7... O-O-O 8. O-O *"#;
+        let game = Game::parse(pgn).unwrap();
+        assert_eq!(game.moves.len(), 2);
+        assert!(game.to_pgn().ends_with("7... O-O-O 8. O-O *\n"));
+    }
+
+    #[test]
+    fn rejects_illegal_moves() {
+        assert_eq!(
+            Game::parse("1. e4 e5 2. Ke3 *").unwrap_err().to_string(),
+            "at 2.: not a SAN move here: Ke3"
+        );
+        assert_eq!(
+            Game::parse("1. e4 (1. d4 *").unwrap_err(),
+            PgnError::BadMovetext
+        );
+        assert!(matches!(
+            Game::parse("[Event broken]\n\n1. e4 *"),
+            Err(PgnError::BadTag(_))
+        ));
+        assert!(matches!(
+            Game::parse("[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n\n*"),
+            Err(PgnError::Fen(_))
+        ));
+    }
+
+    #[test]
+    fn parses_several_games() {
+        let pgn = format!("{}\n[Event \"Second\"]\n\n1. d4 d5 *\n", SCHOLARS_MATE);
+        let games = Game::parse_all(&pgn).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].moves.len(), 7);
+        assert_eq!(games[1].tag("Event"), Some("Second"));
+        assert_eq!(games[1].moves.len(), 2);
+        assert_eq!(Game::parse_all("").unwrap().len(), 0);
+
+        let broken = format!(
+            "{}\n[Event \"Second\"]\n\n1. e4 e5 2. Ke3 *\n",
+            SCHOLARS_MATE
+        );
+        assert!(matches!(
+            Game::parse_all(&broken),
+            Err((2, PgnError::Move { ply: 2, .. }))
+        ));
+    }
+
+    // `sans` played on from `pos`, as an analysis line.
+    fn line(pos: &Position, sans: &[&str], score: i32) -> AnalysisLine {
+        let mut pos = pos.clone();
+        let moves = sans
+            .iter()
+            .map(|san| {
+                let m = Move::from_san(san, &pos).unwrap();
+                pos.make_move(m);
+                m
+            })
+            .collect();
+        AnalysisLine {
+            moves,
+            score,
+            depth: 10,
+        }
+    }
+
+    #[test]
+    fn annotates_with_variations() {
+        let game = Game::parse("1. e4 e5 *").unwrap();
+        let start = Position::default();
+        let mut after_e4 = start.clone();
+        after_e4.make_move(game.moves[0]);
+        let analysis = [
+            (0, vec![line(&start, &["d4", "d5"], 40)]),
+            // Black to move: the scores are turned round to White's side.
+            (
+                1,
+                vec![
+                    line(&after_e4, &["c5", "Nf3"], 20),
+                    line(&after_e4, &["e5"], -30),
+                ],
+            ),
+        ];
+
+        let pgn = game.to_annotated_pgn(&analysis);
+        assert!(pgn.ends_with(
+            "1. e4 (1. d4 {[%eval 0.40,10]} 1... d5) 1... e5 {[%eval 0.30,10]} (1... c5\n\
+             {[%eval -0.20,10]} 2. Nf3) *\n"
+        ));
+        let again = Game::parse(&pgn).unwrap();
+        assert_eq!(again.moves, game.moves);
+
+        // A lone position: its best line becomes the mainline.
+        let mut game = Game::new();
+        let pos = Position::new_from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        game.set_tag("SetUp", "1");
+        game.set_tag("FEN", &pos.to_fen());
+        let analysis = [(
+            0,
+            vec![
+                line(&pos, &["Ra8#"], crate::search::MATE - 1),
+                line(&pos, &["Kf1", "h6"], 0),
+            ],
+        )];
+        let pgn = game.to_annotated_pgn(&analysis);
+        assert!(pgn.ends_with("1. Ra8# {[%eval #1,10]} (1. Kf1 {[%eval 0.00,10]} 1... h6) *\n"));
+        assert_eq!(Game::parse(&pgn).unwrap().moves, analysis[0].1[0].moves);
+    }
+}
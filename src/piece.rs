@@ -1,6 +1,7 @@
 use std::num::NonZeroU8;
 
 use crate::color::Color;
+use crate::errors::MoveParseError;
 
 #[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Clone, Copy)]
 pub enum PieceType {
@@ -34,6 +35,12 @@ impl Piece {
 }
 
 impl PieceType {
+    // Every piece type, least valuable first.
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn all() -> [Self; 6] {
+        use PieceType::*;
+        [Pawn, Knight, Bishop, Rook, Queen, King]
+    }
     #[cfg_attr(feature = "inline", inline)]
     pub const fn promotable() -> [Self; 4] {
         use PieceType::*;
@@ -67,7 +74,7 @@ impl From<Piece> for char {
 }
 
 impl TryFrom<char> for Piece {
-    type Error = ();
+    type Error = MoveParseError;
     #[cfg_attr(feature = "inline-aggressive", inline)]
     fn try_from(value: char) -> Result<Self, Self::Error> {
         let kind = match value.to_ascii_lowercase() {
@@ -77,7 +84,7 @@ impl TryFrom<char> for Piece {
             'r' => PieceType::Rook,
             'q' => PieceType::Queen,
             'k' => PieceType::King,
-            _ => Err(())?,
+            _ => return Err(MoveParseError::Piece(value)),
         };
         let col = match value.is_lowercase() {
             false => Color::White,
@@ -112,9 +119,9 @@ mod tests {
         assert_eq!(Piece::try_from('Q'), Ok(Piece::new(Queen, White)));
         assert_eq!(Piece::try_from('b'), Ok(Piece::new(Bishop, Black)));
 
-        assert_eq!(Piece::try_from('Z'), Err(()));
-        assert_eq!(Piece::try_from('y'), Err(()));
-        assert_eq!(Piece::try_from('!'), Err(()));
+        assert_eq!(Piece::try_from('Z'), Err(MoveParseError::Piece('Z')));
+        assert_eq!(Piece::try_from('y'), Err(MoveParseError::Piece('y')));
+        assert_eq!(Piece::try_from('!'), Err(MoveParseError::Piece('!')));
     }
 
     #[test]
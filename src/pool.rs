@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// One thread budget for the whole library. Everything that works in parallel (perft, batch
+// analysis, ...) goes through `map`, which only spawns as many threads as are still free, so
+// nested or concurrent users share the budget instead of each spawning their own.
+
+// 0 means "not set": use however many threads the machine has.
+static BUDGET: AtomicUsize = AtomicUsize::new(0);
+// Threads spawned by `map` and still running, across all callers.
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+
+// Caps the number of threads the library runs at once, the callers' own threads included.
+pub fn set_threads(n: usize) {
+    BUDGET.store(n.max(1), Ordering::Relaxed);
+}
+
+pub fn threads() -> usize {
+    match BUDGET.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+// Returns the reserved threads to the budget when the workers are done, even if one panicked.
+struct Reservation(usize);
+
+impl Reservation {
+    // Up to `wanted` extra threads, as many as the budget allows. The calling thread doesn't
+    // count against it, as it is already running.
+    fn take(wanted: usize) -> Self {
+        let limit = threads().saturating_sub(1);
+        let mut taken = 0;
+        let _ = IN_USE.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            taken = wanted.min(limit.saturating_sub(used));
+            Some(used + taken)
+        });
+        Self(taken)
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        IN_USE.fetch_sub(self.0, Ordering::AcqRel);
+    }
+}
+
+// `f` applied to every item, in order, spread over the calling thread and as many extra threads
+// as the budget has free. With none free, it all runs on the calling thread. A panic in `f`
+// is passed on to the caller once every worker has stopped.
+pub fn map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let reservation = Reservation::take(items.len().saturating_sub(1));
+    if reservation.0 == 0 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<R>>> = items.iter().map(|_| Mutex::new(None)).collect();
+    let work = || loop {
+        let i = next.fetch_add(1, Ordering::Relaxed);
+        let Some(item) = items.get(i) else {
+            break;
+        };
+        let r = f(item);
+        *results[i].lock().unwrap() = Some(r);
+    };
+
+    thread::scope(|s| {
+        for _ in 0..reservation.0 {
+            s.spawn(work);
+        }
+        work();
+    });
+    drop(reservation);
+
+    results
+        .into_iter()
+        .map(|r| r.into_inner().unwrap().unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_order_and_budget() {
+        let items: Vec<u64> = (0..100).collect();
+        let squares = map(&items, |&x| x * x);
+        assert_eq!(squares, items.iter().map(|x| x * x).collect::<Vec<_>>());
+
+        // Nested maps share the same threads rather than multiplying them.
+        let nested = map(&[1u64, 2, 3, 4], |&x| {
+            assert!(IN_USE.load(Ordering::Relaxed) < threads());
+            map(&items, |&y| x * y).iter().sum::<u64>()
+        });
+        assert_eq!(nested, [4950, 9900, 14850, 19800]);
+
+        assert!(map(&[] as &[u8], |&x| x).is_empty());
+    }
+}
@@ -1,35 +1,51 @@
 use crate::bitboard::Bitboard;
 use crate::color::Color;
-use crate::movegen::{Move, MoveKind};
+pub use crate::errors::FenError;
+use crate::errors::{MoveError, MoveParseError, SetupError, ValidationError};
+use crate::movegen::{generate, Move, MoveKind};
 use crate::piece::{Piece, PieceType};
-use crate::square::{File, Rank, Square};
-use crate::{precompute, strict_cond, strict_eq, strict_ne, strict_not};
+use crate::square::{Direction, File, Rank, Square};
+use crate::zobrist;
+use crate::{pawns, precompute, strict_cond, strict_eq, strict_ne, strict_not, values};
 
-#[derive(Debug)]
+// Clones are deep, history included, so a copy can unmake moves independently. For just the
+// current position, `detached` is cheaper.
+#[derive(Debug, Clone)]
 pub struct Position {
     to_move: Color,
     moves: i32,
 
     colors: [Bitboard; 2],
     pieces: [Bitboard; 6],
+    #[cfg(not(feature = "bitboard-only"))]
     board: [Option<Piece>; 64],
 
-    state: Option<Box<State>>,
+    // Every move made on this position, oldest first. Null moves aren't moves, so they're left out.
+    history: Vec<Move>,
+
+    // One state per position played through, the current one last: a move pushes a fresh one
+    // and unmaking it just pops it again. Never empty.
+    states: Vec<State>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct State {
     checkers: Bitboard,
     pinners: [Bitboard; 2],
     blockers: [Bitboard; 2],
     captured: Option<Piece>,
     en_passant: Option<Square>,
+    // The move that led here, if it can be undone.
+    last_move: Option<Move>,
 
     castle_rights: u8,
 
     halfmoves: i32,
-
-    previous: Option<Box<State>>,
+    // Plies since the last capture, pawn move or null move: how far back a repetition can be.
+    // A null move isn't a move, so nothing before one repeats anything after it.
+    repetition_plies: i32,
+    // Zobrist key of the position: pieces, side to move, castling rights and en passant file.
+    key: u64,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -51,6 +67,7 @@ impl CastleFlag {
             Self::BlackAll | Self::BlackShort | Self::BlackLong => Color::Black,
         }
     }
+    #[allow(clippy::wrong_self_convention)]
     pub const fn from_square(self) -> Square {
         match self.color() {
             Color::White => Square::E1,
@@ -91,6 +108,32 @@ impl CastleFlag {
         }
     }
 
+    // The squares between king and rook, which have to be empty to castle.
+    pub const fn empty_path(self) -> Bitboard {
+        match self {
+            Self::All | Self::WhiteAll | Self::BlackAll => {
+                panic!("CastleFlag::empty_path called on ambiguous variant.")
+            }
+            Self::WhiteShort => Bitboard::from_squares([Square::F1, Square::G1]),
+            Self::WhiteLong => Bitboard::from_squares([Square::B1, Square::C1, Square::D1]),
+            Self::BlackShort => Bitboard::from_squares([Square::F8, Square::G8]),
+            Self::BlackLong => Bitboard::from_squares([Square::B8, Square::C8, Square::D8]),
+        }
+    }
+    // The squares the king crosses or lands on, none of which may be attacked (its own square
+    // too, as it can't castle out of check).
+    pub const fn king_path(self) -> Bitboard {
+        match self {
+            Self::All | Self::WhiteAll | Self::BlackAll => {
+                panic!("CastleFlag::king_path called on ambiguous variant.")
+            }
+            Self::WhiteShort => Bitboard::from_squares([Square::E1, Square::F1, Square::G1]),
+            Self::WhiteLong => Bitboard::from_squares([Square::E1, Square::D1, Square::C1]),
+            Self::BlackShort => Bitboard::from_squares([Square::E8, Square::F8, Square::G8]),
+            Self::BlackLong => Bitboard::from_squares([Square::E8, Square::D8, Square::C8]),
+        }
+    }
+
     pub const fn variants_for(color: Color) -> [Self; 2] {
         match color {
             Color::White => [Self::WhiteShort, Self::WhiteLong],
@@ -125,156 +168,518 @@ impl From<CastleFlag> for u8 {
     }
 }
 
+// What a board edit changed besides the square itself: the piece that was there, and any
+// castling rights that the edit made impossible (these are removed from the builder).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub replaced: Option<Piece>,
+    pub lost_castling: Vec<CastleFlag>,
+}
+
+// How one position differs from another, as found by `Position::diff`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PositionDiff {
+    // Each square whose contents differ: (square, piece in this position, piece in the other).
+    pub squares: Vec<(Square, Option<Piece>, Option<Piece>)>,
+    pub to_move: bool,
+    // Castling rights held in only one of the two.
+    pub castling: Vec<CastleFlag>,
+    pub en_passant: Option<(Option<Square>, Option<Square>)>,
+}
+
+impl PositionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.squares.is_empty()
+            && !self.to_move
+            && self.castling.is_empty()
+            && self.en_passant.is_none()
+    }
+}
+
+impl std::fmt::Display for PositionDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let piece = |p: Option<Piece>| p.map_or('.', char::from);
+        let square = |s: Option<Square>| s.map_or("-".to_string(), |s| s.to_string());
+
+        for &(s, a, b) in &self.squares {
+            writeln!(f, "{}: {} -> {}", s, piece(a), piece(b))?;
+        }
+        if self.to_move {
+            writeln!(f, "side to move differs")?;
+        }
+        for cf in &self.castling {
+            writeln!(f, "castling differs: {:?}", cf)?;
+        }
+        if let Some((a, b)) = self.en_passant {
+            writeln!(f, "en passant: {} -> {}", square(a), square(b))?;
+        }
+        Ok(())
+    }
+}
+
+// Sets a position up a piece at a time, for when there's no FEN to start from, or edits one
+// that may not be playable yet: a board missing a king only ever lives here, never in a
+// `Position`. Nothing is checked until `build`, so pieces can go down in any order.
+//
+//     let pos = PositionBuilder::new()
+//         .piece(E1, Piece::new(PieceType::King, Color::White))
+//         .piece(E8, Piece::new(PieceType::King, Color::Black))
+//         .piece(A1, Piece::new(PieceType::Rook, Color::White))
+//         .castling(&[CastleFlag::WhiteLong])
+//         .build()?;
+#[derive(Debug, Clone)]
+pub struct PositionBuilder {
+    board: [Option<Piece>; 64],
+    to_move: Color,
+    castling: u8,
+    en_passant: Option<Square>,
+}
+
+impl PositionBuilder {
+    // An empty board, white to move, no castling rights.
+    pub fn new() -> Self {
+        Self {
+            board: [None; 64],
+            to_move: Color::White,
+            castling: 0,
+            en_passant: None,
+        }
+    }
+
+    // Puts `piece` on `square`, replacing whatever was there.
+    pub fn piece(mut self, square: Square, piece: Piece) -> Self {
+        self.board[square as usize] = Some(piece);
+        self
+    }
+    pub fn side_to_move(mut self, color: Color) -> Self {
+        self.to_move = color;
+        self
+    }
+    // Replaces the castling rights with `rights`.
+    pub fn castling(mut self, rights: &[CastleFlag]) -> Self {
+        self.castling = rights.iter().fold(0, |acc, &cf| acc | u8::from(cf));
+        self
+    }
+    // As with a FEN, a square that no pawn can actually take on is dropped by `build`.
+    pub fn ep(mut self, square: Option<Square>) -> Self {
+        self.en_passant = square;
+        self
+    }
+
+    // The pieces, side to move, castling and en passant of a FEN, with none of the checks
+    // `build` makes: a fragment with a king missing, say, can be finished off here. The move
+    // counters are read but not kept.
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+        Ok(Self::from(&Position::parse_fen_fields(fen)?))
+    }
+
+    pub fn piece_on(&self, square: Square) -> Option<Piece> {
+        self.board[square as usize]
+    }
+    // Where `color`'s king is, if there is one (the lowest square, if there are several).
+    pub fn king(&self, color: Color) -> Option<Square> {
+        let king = Some(Piece::new(PieceType::King, color));
+        Square::iter().find(|&s| self.piece_on(s) == king)
+    }
+
+    // In-place edits, as a board editor makes them. Unlike `piece`, these drop any castling
+    // right the change breaks at once, and say which.
+    pub fn put_piece(&mut self, square: Square, piece: Piece) -> Edit {
+        let replaced = self.board[square as usize].replace(piece);
+        Edit {
+            replaced,
+            lost_castling: self.strip_broken_castling(),
+        }
+    }
+    pub fn clear_square(&mut self, square: Square) -> Edit {
+        let replaced = self.board[square as usize].take();
+        Edit {
+            replaced,
+            lost_castling: self.strip_broken_castling(),
+        }
+    }
+    fn strip_broken_castling(&mut self) -> Vec<CastleFlag> {
+        let lost = broken_castle_rights(self.castling, |s| self.piece_on(s));
+        for &cf in &lost {
+            self.castling &= !u8::from(cf);
+        }
+        lost
+    }
+
+    // The position, if it's one that could be played from: a king each, no pawns on the back
+    // ranks, castling rights backed by the pieces, and the side not to move not in check.
+    pub fn build(&self) -> Result<Position, SetupError> {
+        let mut pos = Position::new();
+        for square in Square::iter() {
+            if let Some(piece) = self.board[square as usize] {
+                pos.add_piece(piece, square);
+            }
+        }
+        pos.to_move = self.to_move;
+        pos.moves = (self.to_move == Color::Black) as i32;
+        pos.state_mut().castle_rights = self.castling;
+
+        pos.check_setup()?;
+
+        if let Some(ep) = self.en_passant {
+            if ep.rank() != self.to_move.relative_rank(Rank::Six) {
+                return Err(SetupError::BadEnPassant(ep));
+            }
+            pos.state_mut().en_passant = Some(ep);
+        }
+
+        pos.update_state();
+        if !pos.has_legal_ep() {
+            pos.state_mut().en_passant = None;
+        }
+        pos.state_mut().key = pos.compute_key();
+        Ok(pos)
+    }
+}
+
+impl Default for PositionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&Position> for PositionBuilder {
+    // Everything but the move counters and history, to edit the position from.
+    fn from(pos: &Position) -> Self {
+        Self {
+            board: Square::ALL.map(|s| pos.piece_on(s)),
+            to_move: pos.to_move(),
+            castling: pos.state().castle_rights,
+            en_passant: pos.ep(),
+        }
+    }
+}
+
+// The rights in `rights` whose king or rook isn't on its starting square.
+fn broken_castle_rights(rights: u8, piece_on: impl Fn(Square) -> Option<Piece>) -> Vec<CastleFlag> {
+    [
+        CastleFlag::WhiteShort,
+        CastleFlag::WhiteLong,
+        CastleFlag::BlackShort,
+        CastleFlag::BlackLong,
+    ]
+    .into_iter()
+    .filter(|&cf| {
+        let color = cf.color();
+        rights & u8::from(cf) != 0
+            && (piece_on(cf.from_square()) != Some(Piece::new(PieceType::King, color))
+                || piece_on(cf.rook_from_square()) != Some(Piece::new(PieceType::Rook, color)))
+    })
+    .collect()
+}
+
+// Where a game stands, from the position alone (and its history, for repetitions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    Ongoing,
+    WhiteWins,
+    BlackWins,
+    Draw(DrawReason),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawReason {
+    Stalemate,
+    // A hundred plies without a capture or pawn move. Checkmate on the hundredth still wins.
+    FiftyMoves,
+    // The third time the position has come up, counting only moves this position remembers.
+    Repetition,
+    // Neither side has the material left to mate, however badly the other plays.
+    InsufficientMaterial,
+}
+
+impl GameResult {
+    pub fn is_over(self) -> bool {
+        self != Self::Ongoing
+    }
+}
+
+impl std::fmt::Display for GameResult {
+    // As the PGN `Result` tag writes it.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Ongoing => "*",
+            Self::WhiteWins => "1-0",
+            Self::BlackWins => "0-1",
+            Self::Draw(_) => "1/2-1/2",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 impl Position {
     pub const STARTING_FEN: &'static str =
         "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
     pub const KIWIPETE_FEN: &'static str =
         "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -  0 1";
 
+    // An empty board, kings and all, so only for setting up from: every position handed out
+    // has a king each.
     #[cfg_attr(feature = "inline", inline)]
-    pub fn new() -> Self {
+    fn new() -> Self {
         Self {
+            #[cfg(not(feature = "bitboard-only"))]
             board: [None; 64],
             colors: [Bitboard::EMPTY; 2],
             moves: 0,
             pieces: [Bitboard::EMPTY; 6],
             to_move: Color::White,
-            // SAFETY: We just created this.
-            state: Some(State::new()),
+            history: Vec::new(),
+            states: vec![State::new()],
         }
     }
 
+    // Panics on a malformed FEN, or one without exactly one king per side; see `try_from_fen`
+    // for a parser that returns the error instead, and `PositionBuilder::from_fen` for fragments
+    // that are still being set up.
     pub fn new_from_fen(fen: &str) -> Self {
-        let mut pos = Self::new();
+        match Self::parse_fen(fen) {
+            Ok(pos) => pos,
+            Err(e) => panic!("Position::new_from_fen: {}", e),
+        }
+    }
 
-        let mut iter = fen.chars();
+    // Parses a FEN describing a playable position, without panicking on bad input.
+    pub fn try_from_fen(fen: &str) -> Result<Self, FenError> {
+        let pos = Self::parse_fen(fen)?;
 
-        let mut rank = Rank::Eight;
-        let mut file = File::A;
+        let back_ranks = Bitboard::from(Rank::One) | Bitboard::from(Rank::Eight);
+        if bool::from(pos.pieces(PieceType::Pawn) & back_ranks) {
+            return Err(FenError::PawnOnBackRank);
+        }
+        if bool::from(pos.attackers_to(pos.king(!pos.to_move), pos.to_move)) {
+            return Err(FenError::OpponentInCheck);
+        }
 
-        for x in iter.by_ref() {
-            if x == ' ' {
-                break;
-            } else if x == '/' {
-                strict_eq!(
-                    file,
-                    File::H,
-                    panic!("Rank not filled in Position::new_from_fen")
-                );
+        Ok(pos)
+    }
 
-                assert_ne!(
-                    rank,
-                    Rank::One,
-                    "Too many ranks in FEN given to Position::new_from_fen"
-                );
+    // The FEN for this position. An en passant square is only written when the capture is
+    // actually possible, as that is the only time one is kept.
+    pub fn to_fen(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.position_fields(),
+            self.rule50(),
+            self.fullmoves()
+        )
+    }
 
-                file = File::A;
-                // SAFETY: We know rank != Rank::One and so (rank as u8) > 0.
-                rank = unsafe { Rank::try_from(rank as u8 - 1).unwrap_unchecked() };
+    // EPD: the first four FEN fields, with the move counters as the `hmvc` and `fmvn` operations.
+    pub fn to_epd(&self) -> String {
+        format!(
+            "{} hmvc {}; fmvn {};",
+            self.position_fields(),
+            self.rule50(),
+            self.fullmoves()
+        )
+    }
+    // Reads the position from an EPD line, and its counters from `hmvc`/`fmvn` if they are
+    // there. Any other operations are skipped.
+    pub fn try_from_epd(epd: &str) -> Result<Self, FenError> {
+        let mut fields = epd.split_whitespace();
+        let fen = fields.by_ref().take(4).collect::<Vec<_>>().join(" ");
+        let mut pos = Self::try_from_fen(&fen)?;
+
+        let operations = fields.collect::<Vec<_>>().join(" ");
+        for op in operations.split(';') {
+            let Some((opcode, operand)) = op.trim().split_once(' ') else {
                 continue;
+            };
+            let operand = operand.trim();
+            let counter = || {
+                operand
+                    .parse::<u16>()
+                    .map_err(|_| FenError::BadCounter(operand.to_string()))
+            };
+
+            match opcode {
+                "hmvc" => pos.state_mut().halfmoves = counter()? as i32,
+                "fmvn" => {
+                    let n = counter()?;
+                    if n == 0 {
+                        return Err(FenError::BadCounter(operand.to_string()));
+                    }
+                    pos.moves = 2 * (n as i32 - 1) + (pos.to_move == Color::Black) as i32;
+                }
+                _ => (),
             }
+        }
 
-            if ('1'..='8').contains(&x) {
-                let shiftness = x as u8 - b'0';
-                let file_index = file as u8 + shiftness;
+        Ok(pos)
+    }
 
-                if file_index >= 8 {
-                    strict_cond!(
-                        file_index <= 8,
-                        panic!("File overflow in Position::new_from_fen")
-                    );
+    // Board, side to move, castling and en passant: everything but the counters.
+    fn position_fields(&self) -> String {
+        let mut fen = String::new();
 
-                    file = File::H;
-                    continue;
+        for rank_index in (0..8).rev() {
+            let mut empty = 0;
+            for file_index in 0..8 {
+                // SAFETY: Both indices are in range.
+                let f = unsafe { File::try_from(file_index).unwrap_unchecked() };
+                let r = unsafe { Rank::try_from(rank_index).unwrap_unchecked() };
+                match self.piece_on(Square::new(f, r)) {
+                    Some(p) => {
+                        if empty > 0 {
+                            fen.push((b'0' + empty) as char);
+                            empty = 0;
+                        }
+                        fen.push(char::from(p));
+                    }
+                    None => empty += 1,
                 }
-
-                // SAFETY: We know file_index < 8.
-                file = unsafe { File::try_from(file_index).unwrap_unchecked() };
-                continue;
             }
+            if empty > 0 {
+                fen.push((b'0' + empty) as char);
+            }
+            if rank_index > 0 {
+                fen.push('/');
+            }
+        }
 
-            let Ok(p) = Piece::try_from(x) else {
-                panic!("Unknown piece passed in FEN: {}", x);
-            };
-
-            let s = Square::new(file, rank);
-            pos.add_piece(p, s);
-
-            if file != File::H {
-                // SAFETY: (file as u8) < 8 right now.
-                file = unsafe { File::try_from(file as u8 + 1).unwrap_unchecked() };
+        fen += if self.to_move == Color::White {
+            " w "
+        } else {
+            " b "
+        };
+
+        let rights = [
+            (CastleFlag::WhiteShort, 'K'),
+            (CastleFlag::WhiteLong, 'Q'),
+            (CastleFlag::BlackShort, 'k'),
+            (CastleFlag::BlackLong, 'q'),
+        ];
+        let before = fen.len();
+        for (cf, c) in rights {
+            if self.has_castle(cf) {
+                fen.push(c);
             }
         }
+        if fen.len() == before {
+            fen.push('-');
+        }
 
-        match iter.next() {
-            Some('w') | Some('-') => pos.to_move = Color::White,
-            Some('b') => pos.to_move = Color::Black,
-            Some(x) => panic!("Position::new_from_fen: Unknown side to move in FEN: {}", x),
-            None => panic!("Position::new_from_fen: FEN ended early, no side to move given."),
+        match self.ep() {
+            Some(s) => fen += &format!(" {}", s),
+            None => fen += " -",
         }
 
-        match iter.next() {
-            Some(' ') => (),
-            Some(x) => panic!("Position::new_from_fen: Unexpected character: {}", x),
-            None => panic!("Position::new_from_fen: FEN ended early, no castling rights given"),
+        fen
+    }
+
+    fn parse_fen(fen: &str) -> Result<Self, FenError> {
+        let mut pos = Self::parse_fen_fields(fen)?;
+        pos.check_kings()?;
+
+        pos.update_state();
+        if !pos.has_legal_ep() {
+            pos.state_mut().en_passant = None;
         }
+        pos.state_mut().key = pos.compute_key();
+        Ok(pos)
+    }
+    // Just what the FEN says, kings or not: nothing derived from it is filled in yet.
+    fn parse_fen_fields(fen: &str) -> Result<Self, FenError> {
+        let mut pos = Self::new();
+        let mut fields = fen.split_whitespace();
 
-        for x in iter.by_ref() {
-            if x == ' ' {
-                break;
-            }
+        let board = fields.next().ok_or(FenError::MissingField("board"))?;
+        let ranks: Vec<&str> = board.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::WrongRankCount(ranks.len()));
+        }
 
-            if x == '-' {
-                strict_eq!(pos.state().castle_rights, 0, panic!("Position::new_from_fen: Castle character '-' given with other rights given."));
+        for (row, pieces) in ranks.into_iter().enumerate() {
+            // SAFETY: There are exactly 8 ranks, so row < 8.
+            let rank = unsafe { Rank::try_from(7 - row as u8).unwrap_unchecked() };
+            let mut file_index = 0u8;
 
-                match iter.next() {
-                    Some(' ') => (),
-                    None | Some(_) => {
-                        panic!("Position::new_from_fen: FEN ended early, no En Passant data given.")
-                    }
+            for x in pieces.chars() {
+                if ('1'..='8').contains(&x) {
+                    file_index = file_index.saturating_add(x as u8 - b'0');
+                    continue;
                 }
-                break;
-            }
-
-            let cf = match x {
-                'K' => CastleFlag::WhiteShort,
-                'Q' => CastleFlag::WhiteLong,
-                'k' => CastleFlag::BlackShort,
-                'q' => CastleFlag::BlackLong,
-                c => panic!(
-                    "Position::new_from_fen: Unknown castle character given: {}",
-                    c
-                ),
-            };
 
-            strict_not!(
-                pos.has_castle(cf),
-                panic!("Position::new_from_fen: Castle flag given twice: {}", x)
-            );
+                let p = Piece::try_from(x).map_err(|_| FenError::BadPiece(x))?;
+                let f = File::try_from(file_index).map_err(|_| FenError::BadRankLength(rank))?;
+                pos.add_piece(p, Square::new(f, rank));
+                file_index = file_index.saturating_add(1);
+            }
 
-            pos.add_castle_right(cf);
+            if file_index != 8 {
+                return Err(FenError::BadRankLength(rank));
+            }
         }
 
-        let one = iter.next();
-        let two = iter.next();
+        pos.to_move = match fields.next() {
+            Some("w") | Some("-") => Color::White,
+            Some("b") => Color::Black,
+            Some(x) => return Err(FenError::BadSideToMove(x.to_string())),
+            None => return Err(FenError::MissingField("side to move")),
+        };
+
+        let castling = fields.next().ok_or(FenError::MissingField("castling"))?;
+        if castling != "-" {
+            for x in castling.chars() {
+                let cf = match x {
+                    'K' => CastleFlag::WhiteShort,
+                    'Q' => CastleFlag::WhiteLong,
+                    'k' => CastleFlag::BlackShort,
+                    'q' => CastleFlag::BlackLong,
+                    c => return Err(FenError::BadCastling(c)),
+                };
+
+                if pos.has_castle(cf) {
+                    return Err(FenError::BadCastling(x));
+                }
+                pos.add_castle_right(cf);
+            }
+        }
 
-        match one {
-            Some('-') => (),
-            None => return pos,
-            Some(f_char) => {
-                let r_char = two.expect("Position::new_from_fen: en passant rank not given.");
-                let f = File::try_from(f_char as u8).unwrap();
-                let r = Rank::try_from(r_char as u8).unwrap();
-                let s = Square::new(f, r);
+        // Some FENs stop after the castling rights, so no en passant field is fine.
+        match fields.next() {
+            None | Some("-") => (),
+            Some(ep) => {
+                let s = Square::try_from(ep.as_bytes())
+                    .map_err(|_| FenError::BadEnPassant(ep.to_string()))?;
+                let expected = match pos.to_move {
+                    Color::White => Rank::Six,
+                    Color::Black => Rank::Three,
+                };
+                if s.rank() != expected {
+                    return Err(FenError::BadEnPassant(ep.to_string()));
+                }
 
                 pos.state_mut().en_passant = Some(s);
             }
         }
 
-        // TODO parse move counts. not a prio.
+        // Both counters are optional, and default to a fresh game.
+        if let Some(halfmoves) = fields.next() {
+            pos.state_mut().halfmoves = halfmoves
+                .parse::<u16>()
+                .map_err(|_| FenError::BadCounter(halfmoves.to_string()))?
+                as i32;
+        }
+        if let Some(fullmoves) = fields.next() {
+            let n = fullmoves
+                .parse::<u16>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| FenError::BadCounter(fullmoves.to_string()))?;
+            pos.moves = 2 * (n as i32 - 1) + (pos.to_move == Color::Black) as i32;
+        }
+        if let Some(extra) = fields.next() {
+            return Err(FenError::TrailingField(extra.to_string()));
+        }
 
-        pos.update_state();
-        pos
+        Ok(pos)
     }
 
     // Misc data pulls
@@ -312,10 +717,65 @@ impl Position {
         self.pieces_list(ts) & self.color(c)
     }
 
+    #[cfg(not(feature = "bitboard-only"))]
     #[cfg_attr(feature = "inline", inline)]
     pub const fn piece_on(&self, s: Square) -> Option<Piece> {
         self.board[s as usize]
     }
+    // Without the mailbox, the piece is found by checking each bitboard in turn.
+    #[cfg(feature = "bitboard-only")]
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn piece_on(&self, s: Square) -> Option<Piece> {
+        let bb = Bitboard::from_square(s);
+        let color = if self.colors[0].bitand(bb).nonzero() {
+            Color::White
+        } else if self.colors[1].bitand(bb).nonzero() {
+            Color::Black
+        } else {
+            return None;
+        };
+
+        let mut i = 0;
+        while i < 6 {
+            if self.pieces[i].bitand(bb).nonzero() {
+                // SAFETY: i < 6, so it is a valid piece type.
+                let kind = unsafe { std::mem::transmute::<u8, PieceType>(i as u8) };
+                return Some(Piece::new(kind, color));
+            }
+            i += 1;
+        }
+
+        None
+    }
+    // For squares known to be occupied, skipping the `None` check. The mailbox already packs
+    // `Option<Piece>` into a byte (the niche in `Piece`), so this is only the branch saved.
+    /// # Safety
+    /// There must be a piece on `s`.
+    #[cfg(not(feature = "bitboard-only"))]
+    #[cfg_attr(feature = "inline", inline)]
+    pub(crate) const unsafe fn piece_on_unchecked(&self, s: Square) -> Piece {
+        debug_assert!(self.board[s as usize].is_some());
+        self.board[s as usize].unwrap_unchecked()
+    }
+    /// # Safety
+    /// There must be a piece on `s`.
+    #[cfg(feature = "bitboard-only")]
+    #[cfg_attr(feature = "inline", inline)]
+    pub(crate) const unsafe fn piece_on_unchecked(&self, s: Square) -> Piece {
+        let bb = Bitboard::from_square(s);
+        let color = if self.colors[0].bitand(bb).nonzero() {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        let mut i = 0;
+        while i < 5 && !self.pieces[i].bitand(bb).nonzero() {
+            i += 1;
+        }
+        // SAFETY: i < 6, so it is a valid piece type.
+        Piece::new(std::mem::transmute::<u8, PieceType>(i as u8), color)
+    }
     #[cfg_attr(feature = "inline", inline)]
     pub const fn empty(&self, s: Square) -> bool {
         self.piece_on(s).is_none()
@@ -324,32 +784,225 @@ impl Position {
     #[cfg_attr(feature = "inline", inline)]
     pub fn king(&self, color: Color) -> Square {
         debug_assert_ne!(self.spec(PieceType::King, color), Bitboard::EMPTY);
-        // SAFETY: King always has to exist.
+        // SAFETY: Every position has a king per side: FENs without are refused, and so are
+        // builders.
         unsafe { self.spec(PieceType::King, color).lsb_unchecked() }
     }
+    // Exactly one king per side, or which side is wrong.
+    fn check_kings(&self) -> Result<(), FenError> {
+        for color in [Color::White, Color::Black] {
+            match self.spec(PieceType::King, color).popcount() {
+                0 => return Err(FenError::MissingKing(color)),
+                1 => (),
+                _ => return Err(FenError::TooManyKings(color)),
+            }
+        }
+        Ok(())
+    }
+
+    // The rules `PositionBuilder` sets up by, apart from en passant.
+    fn check_setup(&self) -> Result<(), SetupError> {
+        for color in [Color::White, Color::Black] {
+            match self.spec(PieceType::King, color).popcount() {
+                0 => return Err(SetupError::MissingKing(color)),
+                1 => (),
+                _ => return Err(SetupError::TooManyKings(color)),
+            }
+        }
+        let back_ranks = Bitboard::from(Rank::One) | Bitboard::from(Rank::Eight);
+        if let Some(square) = (self.pieces(PieceType::Pawn) & back_ranks)
+            .into_iter()
+            .next()
+        {
+            return Err(SetupError::PawnOnBackRank(square));
+        }
+        if let Some(&cf) = self.inconsistent_castle_rights().first() {
+            return Err(SetupError::BadCastling(cf));
+        }
+        if self.is_attacked(self.king(!self.to_move), self.to_move) {
+            return Err(SetupError::OpponentInCheck);
+        }
+        Ok(())
+    }
+
+    // Checks everything that should hold of a playable position: the bitboards and the board
+    // agree, the setup rules `PositionBuilder` enforces, an en passant square only when it can be
+    // taken, and the key, checkers and pins kept in the state all up to date. For tracking down
+    // make/unmake bugs, and for positions from sources that can't be trusted.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let pieces = self
+            .pieces
+            .iter()
+            .fold(Bitboard::EMPTY, |acc, &bb| acc | bb);
+        let count: i32 = self.pieces.iter().map(|bb| bb.popcount()).sum();
+        if bool::from(self.colors[0] & self.colors[1])
+            || count != pieces.popcount()
+            || pieces != self.all()
+        {
+            return Err(ValidationError::Bitboards);
+        }
+        #[cfg(not(feature = "bitboard-only"))]
+        for square in Square::iter() {
+            let color = [Color::White, Color::Black]
+                .into_iter()
+                .find(|&c| self.color(c).has(square));
+            let kind = PieceType::all()
+                .into_iter()
+                .find(|&k| self.pieces(k).has(square));
+            let expected = color.zip(kind).map(|(c, k)| Piece::new(k, c));
+            if self.board[square as usize] != expected {
+                return Err(ValidationError::Board(square));
+            }
+        }
+
+        self.check_setup()?;
+        if let Some(ep) = self.ep() {
+            if !self.has_legal_ep() {
+                return Err(ValidationError::EnPassant(ep));
+            }
+        }
+
+        if self.key() != self.compute_key() {
+            return Err(ValidationError::Key);
+        }
+        let mut fresh = self.detached();
+        fresh.update_state();
+        if fresh.checkers() != self.checkers()
+            || fresh.state().pinners != self.state().pinners
+            || fresh.state().blockers != self.state().blockers
+        {
+            return Err(ValidationError::State);
+        }
+        Ok(())
+    }
+
+    // Pawn structure
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn pawn_attacks(&self, color: Color) -> Bitboard {
+        precompute::pawn_attacks_bb(self.spec(PieceType::Pawn, color), color)
+    }
+    // Squares `color` can put a piece on without it being hit by an enemy pawn.
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn pawn_safe_squares(&self, color: Color) -> Bitboard {
+        !self.pawn_attacks(!color)
+    }
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn passed_pawns(&self, color: Color) -> Bitboard {
+        pawns::passed(
+            self.spec(PieceType::Pawn, color),
+            self.spec(PieceType::Pawn, !color),
+            color,
+        )
+    }
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn isolated_pawns(&self, color: Color) -> Bitboard {
+        pawns::isolated(self.spec(PieceType::Pawn, color))
+    }
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn doubled_pawns(&self, color: Color) -> Bitboard {
+        pawns::doubled(self.spec(PieceType::Pawn, color), color)
+    }
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn backward_pawns(&self, color: Color) -> Bitboard {
+        pawns::backward(
+            self.spec(PieceType::Pawn, color),
+            self.spec(PieceType::Pawn, !color),
+            color,
+        )
+    }
+    // Pawns on a half-open file which are not passed yet, but have at least as many
+    // supporting pawns beside or behind them as there are enemy pawns guarding the way.
+    pub fn candidate_passers(&self, color: Color) -> Bitboard {
+        let ours = self.spec(PieceType::Pawn, color);
+        let theirs = self.spec(PieceType::Pawn, !color);
+        let mut rv = Bitboard::EMPTY;
+        for p in ours {
+            let span = precompute::front_span(p, color);
+            if (span & (ours | theirs)).nonzero() {
+                continue;
+            }
+
+            let mask = precompute::passed_pawn_mask(p, color);
+            let sentries = mask & theirs;
+            if sentries.zero() {
+                continue; // Already passed.
+            }
+
+            let file = Bitboard::from(p.file());
+            let neighbours = (file << Direction::East) | (file << Direction::West);
+            let helpers = neighbours & !mask & ours;
+
+            if helpers.popcount() >= sentries.popcount() {
+                rv |= Bitboard::from(p);
+            }
+        }
+        rv
+    }
 
     // Castling
     pub fn has_castle(&self, cf: CastleFlag) -> bool {
         let cf_u8: u8 = cf.into();
         self.state().castle_rights & cf_u8 == cf_u8
     }
-    pub fn can_castle(&self, cf: CastleFlag) -> bool {
-        strict_not!(self.has_castle(cf), return false);
+    // What would have to change to turn this position into `other`.
+    pub fn diff(&self, other: &Position) -> PositionDiff {
+        let squares = Square::iter()
+            .filter(|&s| self.piece_on(s) != other.piece_on(s))
+            .map(|s| (s, self.piece_on(s), other.piece_on(s)))
+            .collect();
+
+        let castling = [
+            CastleFlag::WhiteShort,
+            CastleFlag::WhiteLong,
+            CastleFlag::BlackShort,
+            CastleFlag::BlackLong,
+        ]
+        .into_iter()
+        .filter(|&cf| self.has_castle(cf) != other.has_castle(cf))
+        .collect();
+
+        PositionDiff {
+            squares,
+            to_move: self.to_move != other.to_move,
+            castling,
+            en_passant: (self.ep() != other.ep()).then_some((self.ep(), other.ep())),
+        }
+    }
 
-        // XXX Should this check more than just plegal?
-        let inb = Bitboard::interval(cf.from_square(), cf.rook_from_square());
+    // Rights held without the king and that rook both still on their starting squares.
+    pub fn inconsistent_castle_rights(&self) -> Vec<CastleFlag> {
+        broken_castle_rights(self.state().castle_rights, |s| self.piece_on(s))
+    }
+    // Whether nothing stands between king and rook: enough for a pseudo-legal castle, but it may
+    // still be through or into check (see `castle_is_safe`).
+    pub fn can_castle(&self, cf: CastleFlag) -> bool {
+        strict_cond!(self.has_castle(cf), return false);
 
-        !bool::from(inb & self.all())
+        !bool::from(cf.empty_path() & self.all())
+    }
+    // Whether the king is out of check and crosses no attacked square. With `can_castle`, this
+    // makes castling legal.
+    pub fn castle_is_safe(&self, cf: CastleFlag) -> bool {
+        let them = !cf.color();
+        let occupancy = self.all() ^ Bitboard::from(cf.from_square());
+        cf.king_path()
+            .into_iter()
+            .all(|sq| !bool::from(self.attacks_to_with_occ(sq, them, occupancy)))
     }
 
     // State access, and mutations
     #[cfg_attr(feature = "inline", inline)]
     pub const fn state(&self) -> &State {
-        self.state.as_ref().unwrap()
+        self.states.as_slice().last().unwrap()
     }
     #[cfg_attr(feature = "inline", inline)]
     const fn state_mut(&mut self) -> &mut State {
-        self.state.as_mut().unwrap()
+        self.states.as_mut_slice().last_mut().unwrap()
+    }
+    // The state before the last move (or null move), if there was one.
+    #[cfg_attr(feature = "inline", inline)]
+    fn previous_state(&self) -> Option<&State> {
+        self.states.len().checked_sub(2).map(|i| &self.states[i])
     }
 
     // Non-setting access
@@ -369,31 +1022,407 @@ impl Position {
     pub const fn blockers(&self, color: Color) -> Bitboard {
         self.state().blockers[color as usize]
     }
+    // The Zobrist key: equal for positions that are the same for repetition purposes, and
+    // (barring collisions) different for any others. Move counters are not part of it.
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn key(&self) -> u64 {
+        self.state().key
+    }
+    // `key`, but leaving out castling rights the pieces don't back up and an en passant square
+    // nobody can use, so that positions set up from FENs differing only in those still match.
+    // For positions reached by play it is the same as `key`.
+    pub fn canonical_key(&self) -> u64 {
+        let rights = self.state().castle_rights;
+        let lost = self
+            .inconsistent_castle_rights()
+            .into_iter()
+            .fold(0, |acc, cf| acc | u8::from(cf));
+        let mut key = self.key() ^ zobrist::castling(rights) ^ zobrist::castling(rights & !lost);
+
+        if let Some(ep) = self.ep() {
+            if !self.has_legal_ep() {
+                key ^= zobrist::en_passant(ep);
+            }
+        }
+        key
+    }
     #[cfg_attr(feature = "inline", inline)]
     pub const fn rule50(&self) -> i32 {
         self.state().halfmoves
     }
+    // How many plies back a repetition of this position could be: `rule50`, but cut short by
+    // null moves.
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn repetition_plies(&self) -> i32 {
+        self.state().repetition_plies
+    }
+    // The FEN fullmove number: starts at 1, and goes up after each Black move.
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn fullmoves(&self) -> i32 {
+        self.moves / 2 + 1
+    }
+
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn in_check(&self) -> bool {
+        bool::from(self.checkers())
+    }
+
+    pub fn is_checkmate(&self) -> bool {
+        self.in_check() && !generate::has_legal_move(self)
+    }
+    pub fn is_stalemate(&self) -> bool {
+        !self.in_check() && !generate::has_legal_move(self)
+    }
+    // Whether this position has come up twice before, with the same side to move, rights and en
+    // passant. Only the moves made on this `Position` are known; a FEN has no history.
+    pub fn is_threefold_repetition(&self) -> bool {
+        let key = self.key();
+        self.states
+            .iter()
+            .rev()
+            .skip(1)
+            .take(self.repetition_plies().max(0) as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|st| st.key == key)
+            .count()
+            >= 2
+    }
+    // Bare kings, a single minor piece, or only bishops all on squares of one color.
+    pub fn is_insufficient_material(&self) -> bool {
+        use PieceType::*;
+        if bool::from(self.pieces_list(&[Pawn, Rook, Queen])) {
+            return false;
+        }
+
+        let minors = self.pieces_list(&[Knight, Bishop]);
+        if minors.popcount() <= 1 {
+            return true;
+        }
+
+        let bishops = self.pieces(Bishop);
+        let dark = Bitboard::new(0x55AA_55AA_55AA_55AA);
+        bishops == minors && ((bishops & dark) == bishops || (bishops & !dark) == bishops)
+    }
+    // Mate and stalemate first, so a mate on the hundredth ply or the third repetition counts.
+    pub fn result(&self) -> GameResult {
+        if !generate::has_legal_move(self) {
+            return match (self.in_check(), self.to_move) {
+                (false, _) => GameResult::Draw(DrawReason::Stalemate),
+                (true, Color::White) => GameResult::BlackWins,
+                (true, Color::Black) => GameResult::WhiteWins,
+            };
+        }
+
+        if self.rule50() >= 100 {
+            GameResult::Draw(DrawReason::FiftyMoves)
+        } else if self.is_threefold_repetition() {
+            GameResult::Draw(DrawReason::Repetition)
+        } else if self.is_insufficient_material() {
+            GameResult::Draw(DrawReason::InsufficientMaterial)
+        } else {
+            GameResult::Ongoing
+        }
+    }
+
+    // The pieces of `by` attacking `square`, whatever is on it. Pins are ignored: a pinned piece
+    // still attacks, as it still stops the other king from going there.
+    pub fn attackers_to(&self, square: Square, by: Color) -> Bitboard {
+        self.attacks_to_with_occ(square, by, self.all())
+    }
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn is_attacked(&self, square: Square, by: Color) -> bool {
+        bool::from(self.attackers_to(square, by))
+    }
+    // Every square the piece on `square` attacks (own pieces included, as they are defended),
+    // or nothing for an empty square. For pawns that is the two capture squares, not pushes.
+    pub fn attacks_from(&self, square: Square) -> Bitboard {
+        match self.piece_on(square) {
+            Some(piece) => self.piece_attacks(piece, square, self.all()),
+            None => Bitboard::EMPTY,
+        }
+    }
+
+    // Every square the piece on `square` can legally move to, pins and check included, without
+    // generating any moves. A promotion counts once, and castling shows as the king's two-square
+    // step. Empty for an empty square or a piece of the side not to move.
+    pub fn mobility_of(&self, square: Square) -> Bitboard {
+        let us = self.to_move();
+        let Some(piece) = self.piece_on(square).filter(|p| p.color() == us) else {
+            return Bitboard::EMPTY;
+        };
+        let own = self.color(us);
+        let king = self.king(us);
+
+        if piece.kind() == PieceType::King {
+            let without_king = self.all() ^ Bitboard::from(king);
+            let mut targets = Bitboard::EMPTY;
+            for to in precompute::king_attacks(king) & !own {
+                if !bool::from(self.attacks_to_with_occ(to, !us, without_king)) {
+                    targets |= Bitboard::from(to);
+                }
+            }
+            for cf in CastleFlag::variants_for(us) {
+                if self.has_castle(cf) && self.can_castle(cf) && self.castle_is_safe(cf) {
+                    targets |= Bitboard::from(cf.to_square());
+                }
+            }
+            return targets;
+        }
+
+        let checkers = self.checkers();
+        if checkers.popcount() > 1 {
+            return Bitboard::EMPTY;
+        }
+
+        let mut targets = match piece.kind() {
+            PieceType::Pawn => {
+                let mut pushes = Bitboard::EMPTY;
+                if let Some(up) = square.shift(us.forward()).filter(|&sq| self.empty(sq)) {
+                    pushes |= Bitboard::from(up);
+                    if square.rank() == us.relative_rank(Rank::Two) {
+                        pushes |=
+                            Bitboard::from(up.shift(us.forward()).filter(|&sq| self.empty(sq)));
+                    }
+                }
+                pushes | (precompute::pawn_attacks(square, us) & self.color(!us))
+            }
+            _ => self.piece_attacks(piece, square, self.all()) & !own,
+        };
+
+        // Out of check only by taking the checker or getting in its way.
+        if checkers.nonzero() {
+            let checker = checkers.lsb();
+            targets &= Bitboard::interval(king, checker) | checkers;
+        }
+        if (self.blockers(us) & own).has(square) {
+            targets &= precompute::line(king, square);
+        }
+
+        // En passant is awkward enough (the captured pawn isn't on the target square, and two
+        // pawns leave the rank at once) to be checked in full.
+        if let Some(ep) = self.ep() {
+            let m = Move::new_with_kind(square, ep, MoveKind::EnPassant);
+            if piece.kind() == PieceType::Pawn
+                && precompute::pawn_attacks(square, us).has(ep)
+                && self.is_legal(m)
+            {
+                targets |= Bitboard::from(ep);
+            }
+        }
+
+        targets
+    }
+
+    // Static exchange evaluation: the material `mov` wins (or loses, if negative) for the side
+    // making it, once every capture back and forth on its destination has been played out, with
+    // each side free to stop when it likes. Pins are not taken into account.
+    pub fn see(&self, mov: Move) -> i32 {
+        let to = mov.to();
+        let mut occupancy = self.all() ^ Bitboard::from(mov.from());
+
+        let mut gains = [0; 32];
+        gains[0] = match mov.kind() {
+            MoveKind::EnPassant => {
+                // SAFETY: The captured pawn is right behind the en passant square.
+                let captured = unsafe { to.shift_unchecked(!self.to_move.forward()) };
+                occupancy ^= Bitboard::from(captured);
+                values::PAWN
+            }
+            _ => self
+                .piece_on(to)
+                .map_or(0, |p| values::piece_value(p.kind())),
+        };
+
+        let mover = self
+            .piece_on(mov.from())
+            .map_or(PieceType::Pawn, |p| p.kind());
+        let mut on_square = match mov.get_promo() {
+            Some(kind) => {
+                gains[0] += values::promotion_delta(kind);
+                values::piece_value(kind)
+            }
+            None => values::piece_value(mover),
+        };
+
+        let mut side = !self.to_move;
+        let mut depth = 0;
+        while depth + 1 < gains.len() {
+            // Sliders are found with the current occupancy, so x-ray attackers join in as the
+            // pieces in front of them are used up.
+            let attackers = self.attacks_to_with_occ(to, side, occupancy) & occupancy;
+            let Some((kind, from)) = PieceType::all().into_iter().find_map(|k| {
+                (attackers & self.pieces(k))
+                    .into_iter()
+                    .next()
+                    .map(|s| (k, s))
+            }) else {
+                break;
+            };
+
+            // The king can only take last.
+            if kind == PieceType::King
+                && bool::from(self.attacks_to_with_occ(to, !side, occupancy) & occupancy)
+            {
+                break;
+            }
+
+            depth += 1;
+            gains[depth] = on_square - gains[depth - 1];
+            on_square = values::piece_value(kind);
+            occupancy ^= Bitboard::from(from);
+            side = !side;
+        }
+
+        while depth > 0 {
+            gains[depth - 1] = -(-gains[depth - 1]).max(gains[depth]);
+            depth -= 1;
+        }
+        gains[0]
+    }
+
+    // Move related
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn is_capture(&self, mov: Move) -> bool {
+        mov.kind() == MoveKind::EnPassant || self.color(!self.to_move()).has(mov.to())
+    }
+    // Whether `mov` (legal here) checks the other king, without making it: direct checks from
+    // the piece where it lands, and discovered ones from our pieces in `blockers` of their king.
+    pub fn gives_check(&self, mov: Move) -> bool {
+        let us = self.to_move();
+        let king = self.king(!us);
+        let (from, to) = (mov.from(), mov.to());
+        let Some(mover) = self.piece_on(from) else {
+            return false;
+        };
+
+        let occupancy = (self.all() ^ Bitboard::from(from)) | Bitboard::from(to);
+        let placed = match mov.kind() {
+            MoveKind::Promotion(kind) => Piece::new(kind, us),
+            _ => mover,
+        };
+        if self.piece_attacks(placed, to, occupancy).has(king) {
+            return true;
+        }
+
+        let discovered = (self.blockers(!us) & self.color(us)).has(from)
+            && !precompute::line(from, king).has(to);
+        match mov.kind() {
+            _ if discovered => true,
+            MoveKind::Normal | MoveKind::Promotion(_) => false,
+            // The captured pawn leaves a square too, which may open a line no pin covered.
+            MoveKind::EnPassant => {
+                let captured = Square::new(to.file(), from.rank());
+                let occupancy = occupancy ^ Bitboard::from(captured);
+                bool::from(self.sliders_to(king, occupancy) & self.color(us))
+            }
+            MoveKind::Castle => {
+                let cf = if CastleFlag::short_for(us).to_square() == to {
+                    CastleFlag::short_for(us)
+                } else {
+                    CastleFlag::long_for(us)
+                };
+                let occupancy = (occupancy ^ Bitboard::from(cf.rook_from_square()))
+                    | Bitboard::from(cf.rook_to_square());
+                precompute::rook_attacks(cf.rook_to_square(), occupancy).has(king)
+            }
+        }
+    }
+    // The key `mov` (legal here) leads to, without making it: the same as `key` once it is
+    // made. Mostly for the search to prefetch the table entry it is about to probe.
+    pub fn key_after(&self, mov: Move) -> u64 {
+        let us = self.to_move();
+        let (from, to) = (mov.from(), mov.to());
+        let Some(mover) = self.piece_on(from) else {
+            return self.key();
+        };
+
+        let mut key = self.key() ^ zobrist::side();
+        if let Some(ep) = self.ep() {
+            key ^= zobrist::en_passant(ep);
+        }
+
+        let placed = match mov.kind() {
+            MoveKind::Promotion(kind) => Piece::new(kind, us),
+            _ => mover,
+        };
+        key ^= zobrist::piece(mover, from) ^ zobrist::piece(placed, to);
+        match mov.kind() {
+            MoveKind::EnPassant => {
+                let captured = Square::new(to.file(), from.rank());
+                key ^= zobrist::piece(Piece::new(PieceType::Pawn, !us), captured);
+            }
+            MoveKind::Castle => {
+                let rook = Piece::new(PieceType::Rook, us);
+                let cf = if CastleFlag::short_for(us).to_square() == to {
+                    CastleFlag::short_for(us)
+                } else {
+                    CastleFlag::long_for(us)
+                };
+                key ^= zobrist::piece(rook, cf.rook_from_square())
+                    ^ zobrist::piece(rook, cf.rook_to_square());
+            }
+            _ => {
+                if let Some(captured) = self.piece_on(to) {
+                    key ^= zobrist::piece(captured, to);
+                }
+            }
+        }
+
+        // Rights go with a king or rook leaving home, or a rook taken at home.
+        let rights = self.state().castle_rights;
+        let mut lost = 0;
+        for cf in CastleFlag::variants_for(us) {
+            if mover.kind() == PieceType::King || cf.rook_from_square() == from {
+                lost |= u8::from(cf);
+            }
+        }
+        for cf in CastleFlag::variants_for(!us) {
+            if cf.rook_from_square() == to {
+                lost |= u8::from(cf);
+            }
+        }
+        key ^= zobrist::castling(rights) ^ zobrist::castling(rights & !lost);
 
-    #[cfg_attr(feature = "inline", inline)]
-    pub fn in_check(&self) -> bool {
-        bool::from(self.checkers())
+        if mover.kind() == PieceType::Pawn && from.distance(to) == 2 {
+            let ep = Square::new(from.file(), us.relative_rank(Rank::Three));
+            if self.can_take_after_push(from, ep) {
+                key ^= zobrist::en_passant(ep);
+            }
+        }
+        key
+    }
+    // Whether they could take en passant on `ep` after our double push from `from`: what
+    // `has_legal_ep` decides once the push is made.
+    fn can_take_after_push(&self, from: Square, ep: Square) -> bool {
+        let us = self.to_move();
+        let takers = precompute::pawn_attacks(ep, us) & self.spec(PieceType::Pawn, !us);
+        let king = self.king(!us);
+        // After the capture: our pawn gone from both squares, theirs moved from `taker` to `ep`.
+        takers.into_iter().any(|taker| {
+            let occupancy = (self.all() ^ Bitboard::from([from, taker])) | Bitboard::from(ep);
+            let attackers = self.attacks_to_with_occ(king, us, occupancy) & occupancy;
+            !bool::from(attackers)
+        })
     }
-
-    // Move related
     pub fn is_legal(&self, mov: Move) -> bool {
-        strict_not!(self.is_pseudo_legal(mov), return false);
+        strict_cond!(self.is_pseudo_legal(mov), return false);
 
         let us = self.to_move();
         let to = mov.to();
         let from = mov.from();
         let flag = mov.kind();
 
+        // Castling is settled by its own masks, in check or not.
+        if flag == MoveKind::Castle {
+            return CastleFlag::variants_for(us)
+                .into_iter()
+                .find(|cf| cf.to_square() == to)
+                .is_some_and(|cf| self.castle_is_safe(cf));
+        }
+
         if self.in_check() {
             if from == self.king(us) {
-                if flag == MoveKind::Castle {
-                    return false;
-                }
-
                 if bool::from(self.attacks_to_with_occ(to, !us, self.all() ^ Bitboard::from(from)))
                 {
                     return false;
@@ -401,12 +1430,12 @@ impl Position {
             } else {
                 // If double check, then king must be the mover!
                 if self.checkers().more_than_one() {
-                    strict_eq!(self.checkers().popcount(), 2);
+                    strict_eq!(in self; self.checkers().popcount(), 2);
                     return false;
                 }
 
                 if flag == MoveKind::EnPassant {
-                    strict_eq!(Some(to), self.ep());
+                    strict_eq!(in self; Some(to), self.ep());
                     let ep_able_pawn = Square::new(to.file(), from.rank());
                     if !self.checkers().has(ep_able_pawn) {
                         return false; // EP can only get out of check if the checking piece IS the pawn that gets taken.
@@ -435,13 +1464,10 @@ impl Position {
         }
 
         if from == self.king(us) {
-            let line_of_travel = Bitboard::interval(from, to) | Bitboard::from(to);
-            for x in line_of_travel {
-                // TODO(960) If to support C960, must also remove rook to check for xray?
-                // This also prevents us from hiding behind our (ghost, in the past) self when in check.
-                if bool::from(self.attacks_to_with_occ(x, !us, self.all() ^ Bitboard::from(from))) {
-                    return false;
-                }
+            // The king is taken off the board first, so it can't hide behind its old self from a
+            // slider checking it.
+            if bool::from(self.attacks_to_with_occ(to, !us, self.all() ^ Bitboard::from(from))) {
+                return false;
             }
         }
 
@@ -456,18 +1482,71 @@ impl Position {
 
         true
     }
+    // Whether `mov` could have come out of `generate::pseudo_legal` for this position.
+    // Anything from outside the generator (UCI, books, hash tables) should pass this before `is_legal`.
     pub fn is_pseudo_legal(&self, mov: Move) -> bool {
-        todo!()
+        let us = self.to_move();
+        let from = mov.from();
+        let to = mov.to();
+
+        let Some(mover) = self.piece_on(from) else {
+            return false;
+        };
+        if mover.color() != us || self.color(us).has(to) {
+            return false;
+        }
+
+        let last_rank = us.relative_rank(Rank::Eight);
+
+        match mov.kind() {
+            MoveKind::Castle => {
+                if mover.kind() != PieceType::King {
+                    return false;
+                }
+
+                let Some(cf) = CastleFlag::variants_for(us)
+                    .into_iter()
+                    .find(|cf| cf.from_square() == from && cf.to_square() == to)
+                else {
+                    return false;
+                };
+
+                self.has_castle(cf)
+                    && self.piece_on(cf.rook_from_square()) == Some(Piece::new(PieceType::Rook, us))
+                    && self.can_castle(cf)
+            }
+            MoveKind::EnPassant => {
+                let captured = Square::new(to.file(), from.rank());
+                mover.kind() == PieceType::Pawn
+                    && self.ep() == Some(to)
+                    && self.empty(to)
+                    && precompute::pawn_attacks(from, us).has(to)
+                    && self.piece_on(captured) == Some(Piece::new(PieceType::Pawn, !us))
+            }
+            MoveKind::Promotion(_) => {
+                mover.kind() == PieceType::Pawn
+                    && to.rank() == last_rank
+                    && self.is_pawn_step(from, to, us)
+            }
+            MoveKind::Normal => match mover.kind() {
+                PieceType::Pawn => to.rank() != last_rank && self.is_pawn_step(from, to, us),
+                PieceType::Knight => precompute::knight_attacks(from).has(to),
+                PieceType::Bishop => precompute::bishop_attacks(from, self.all()).has(to),
+                PieceType::Rook => precompute::rook_attacks(from, self.all()).has(to),
+                PieceType::Queen => precompute::queen_attacks(from, self.all()).has(to),
+                PieceType::King => precompute::king_attacks(from).has(to),
+            },
+        }
     }
 
     pub fn make_move(&mut self, mov: Move) {
-        strict_cond!(self.is_legal(mov));
+        strict_cond!(in self; self.is_legal(mov));
 
-        let new_state = self.state.clone().unwrap();
-        let old = self.state.replace(new_state);
-        self.state_mut().previous = old;
+        self.states.push(self.state().next());
+        self.state_mut().last_move = Some(mov);
 
         self.state_mut().halfmoves += 1;
+        self.state_mut().repetition_plies += 1;
 
         let us = self.to_move();
         let them = !us;
@@ -476,13 +1555,13 @@ impl Position {
         let to = mov.to();
         let flag = mov.kind();
 
-        strict_ne!(from, to);
+        strict_ne!(in self; from, to);
 
         let mover = self
             .piece_on(from)
             .expect("No piece found on the `from` square");
 
-        strict_eq!(mover.color(), us);
+        strict_eq!(in self; mover.color(), us);
 
         // This is the square we want to REMOVE a piece from after this.
         let mut capture_square = to;
@@ -491,23 +1570,19 @@ impl Position {
             self.state_mut().halfmoves = 0;
 
             if from.distance(to) == 2 {
-                strict_eq!(from.file(), to.file());
+                strict_eq!(in self; from.file(), to.file());
                 self.state_mut().en_passant =
                     Some(Square::new(from.file(), us.relative_rank(Rank::Three)));
             } else if flag == MoveKind::EnPassant {
-                strict_eq!(
-                    self.state()
-                        .previous
-                        .as_ref()
-                        .map(|st| st.en_passant)
-                        .flatten(),
+                strict_eq!(in self;
+                    self.previous_state().and_then(|st| st.en_passant),
                     Some(to)
                 );
 
                 capture_square = Square::new(to.file(), from.rank());
             } else if let MoveKind::Promotion(promo_type) = flag {
-                strict_ne!(promo_type, PieceType::Pawn);
-                strict_ne!(promo_type, PieceType::King);
+                strict_ne!(in self; promo_type, PieceType::Pawn);
+                strict_ne!(in self; promo_type, PieceType::King);
                 let _ = self.remove_piece(from);
                 self.add_piece(Piece::new(promo_type, us), from);
             }
@@ -520,6 +1595,9 @@ impl Position {
 
         self.move_piece(from, to);
 
+        // Every square whose contents changed, for the incremental pin update.
+        let mut changed = Bitboard::from([from, to, capture_square]);
+
         if flag == MoveKind::Castle {
             // We have to find our castle-flag first.
             let castle_flag = if CastleFlag::short_for(us).to_square() == to {
@@ -528,10 +1606,12 @@ impl Position {
                 CastleFlag::long_for(us)
             };
 
-            strict_eq!(castle_flag.to_square(), to);
-            strict_eq!(castle_flag.from_square(), from);
+            strict_eq!(in self; castle_flag.to_square(), to);
+            strict_eq!(in self; castle_flag.from_square(), from);
 
             self.move_piece(castle_flag.rook_from_square(), castle_flag.rook_to_square());
+            changed |=
+                Bitboard::from([castle_flag.rook_from_square(), castle_flag.rook_to_square()]);
         }
 
         // TODO what is most efficient way? no checks?
@@ -565,11 +1645,54 @@ impl Position {
 
         self.to_move = !self.to_move;
         self.moves += 1;
-        self.update_state();
+        self.history.push(mov);
+        self.update_state_after_move(
+            mov,
+            changed,
+            (mover.kind() == PieceType::King).then_some(us),
+        );
+
+        if self.ep().is_some() && !self.has_legal_ep() {
+            self.state_mut().en_passant = None;
+        }
+        // Captures and pawn moves reset it along with the fifty-move count.
+        let st = self.state_mut();
+        st.repetition_plies = st.repetition_plies.min(st.halfmoves);
+
+        self.update_key(mover, mov, capture_square);
+    }
+    // Takes back the last move made, giving it back. `None` if there is nothing to take back.
+    pub fn undo(&mut self) -> Option<Move> {
+        let mov = self.last_move()?;
+        self.unmake_move(mov);
+        Some(mov)
+    }
+    // The move that led to this position, when it was made here (and not e.g. loaded from FEN).
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn last_move(&self) -> Option<Move> {
+        self.state().last_move
     }
+    // The moves made on this position since it was set up, oldest first. A position from a FEN
+    // (or `detached`) starts with none, whatever its move number.
+    pub fn move_history(&self) -> &[Move] {
+        &self.history
+    }
+    // Half-moves since the start of the game, counting from the FEN's move number and side to
+    // move: 0 at the standard start, 1 once White has moved.
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn ply(&self) -> i32 {
+        self.moves
+    }
+    // Prefer `undo`; `mov` has to be the last move made, which only debug builds check.
     pub fn unmake_move(&mut self, mov: Move) {
+        debug_assert_eq!(
+            self.last_move(),
+            Some(mov),
+            "Position::unmake_move: Not the last move made"
+        );
         self.to_move = !self.to_move;
         self.moves -= 1;
+        self.history.pop();
 
         let us = self.to_move();
         let to = mov.to();
@@ -577,14 +1700,13 @@ impl Position {
         let flag = mov.kind();
 
         self.move_piece(to, from);
-        strict_eq!(self.piece_on(from).map(|p| p.color()), Some(us));
+        strict_eq!(in self; self.piece_on(from).map(|p| p.color()), Some(us));
 
         if let Some(p) = self.state().captured {
             self.add_piece(p, to);
         }
 
-        let old_state = self.state_mut().previous.take();
-        self.state = old_state;
+        self.states.pop();
 
         match flag {
             MoveKind::EnPassant => {
@@ -608,15 +1730,73 @@ impl Position {
                     }
                 }
 
-                strict_cond!(used);
+                strict_cond!(in self; used);
             }
             _ => {}
         }
     }
 
+    // Passes the move to the other side: for null move pruning, or a GUI asking "what if it
+    // were their turn". Not allowed in check. A null move has no `last_move`, so `undo` stops
+    // there; take it back with `unmake_null_move`.
+    pub fn make_null_move(&mut self) {
+        strict_not!(in self; self.in_check());
+
+        // Pins don't depend on the side to move, so they carry over. Whoever is to move now
+        // can't be in check, or the position before was illegal, and there is no en passant.
+        let prev = self.state();
+        let (pinners, blockers, ep) = (prev.pinners, prev.blockers, prev.en_passant);
+
+        self.states.push(self.state().next());
+
+        let st = self.state_mut();
+        st.pinners = pinners;
+        st.blockers = blockers;
+        st.halfmoves += 1;
+        st.repetition_plies = 0;
+        st.key ^= zobrist::side();
+        if let Some(ep) = ep {
+            st.key ^= zobrist::en_passant(ep);
+        }
+
+        self.to_move = !self.to_move;
+        self.moves += 1;
+
+        strict_eq!(in self; self.state().key, self.compute_key());
+    }
+    pub fn unmake_null_move(&mut self) {
+        assert!(
+            self.last_move().is_none() && self.previous_state().is_some(),
+            "Position::unmake_null_move: The last move was not a null move"
+        );
+        self.to_move = !self.to_move;
+        self.moves -= 1;
+
+        self.states.pop();
+    }
+
+    // A copy of just the current position, without the history behind it. Enough to try a move
+    // out and look at the result, but it cannot be unmade past the point it was copied at.
+    pub(crate) fn detached(&self) -> Position {
+        let st = self.state();
+        Position {
+            to_move: self.to_move,
+            moves: self.moves,
+            colors: self.colors,
+            pieces: self.pieces,
+            #[cfg(not(feature = "bitboard-only"))]
+            board: self.board,
+            history: Vec::new(),
+            states: vec![State {
+                last_move: None,
+                ..*st
+            }],
+        }
+    }
+
     pub fn make_moves(&mut self, moves: &[Move]) -> Result<(), Move> {
         for &m in moves {
-            if !self.is_legal(m) {
+            if !self.is_pseudo_legal(m) || !self.is_legal(m) {
                 return Err(m);
             }
 
@@ -625,11 +1805,24 @@ impl Position {
 
         Ok(())
     }
+    // Plays one move given in UCI notation, if it is legal here.
+    pub fn play_uci(&mut self, uci: &str) -> Result<Move, MoveError> {
+        let m = Move::new_from_uci(uci.as_bytes(), self)
+            .ok_or_else(|| MoveParseError::Uci(uci.to_string()))?;
+        if !self.is_pseudo_legal(m) || !self.is_legal(m) {
+            return Err(MoveError::Illegal(m));
+        }
+        self.make_move(m);
+        Ok(m)
+    }
     pub fn make_uci_moves<'a>(&mut self, moves: &[&'a [u8]]) -> Result<(), &'a [u8]> {
         for &uci_str in moves {
             let Some(x) = Move::new_from_uci(uci_str, self) else {
                 return Err(uci_str);
             };
+            if !self.is_pseudo_legal(x) || !self.is_legal(x) {
+                return Err(uci_str);
+            }
             self.make_move(x);
         }
         Ok(())
@@ -638,11 +1831,14 @@ impl Position {
     // Rest private helpers
     #[cfg_attr(feature = "inline-aggressive", inline)]
     fn add_piece(&mut self, piece: Piece, square: Square) {
-        if self.board[square as usize].is_some() {
+        if self.piece_on(square).is_some() {
             panic!("Position::add_piece: Square already occupied");
         }
 
-        self.board[square as usize] = Some(piece);
+        #[cfg(not(feature = "bitboard-only"))]
+        {
+            self.board[square as usize] = Some(piece);
+        }
         let bb = Bitboard::from(square);
 
         self.colors[piece.color() as usize] |= bb;
@@ -651,32 +1847,96 @@ impl Position {
     #[must_use]
     #[cfg_attr(feature = "inline-aggressive", inline)]
     fn remove_piece(&mut self, square: Square) -> Option<Piece> {
-        let pc = self.board[square as usize].take()?;
+        let pc = self.piece_on(square)?;
+        #[cfg(not(feature = "bitboard-only"))]
+        {
+            self.board[square as usize] = None;
+        }
 
         let bb = Bitboard::from(square);
 
         self.colors[pc.color() as usize] ^= bb;
         self.pieces[pc.kind() as usize] ^= bb;
 
-        strict_cond!(self.piece_on(square).is_none());
+        strict_cond!(in self; self.piece_on(square).is_none());
 
         Some(pc)
     }
     #[cfg_attr(feature = "inline-aggressive", inline)]
     fn move_piece(&mut self, from: Square, to: Square) {
-        strict_ne!(from, to);
-        strict_not!(self.piece_on(to).is_some());
-        strict_cond!(self.piece_on(from).is_some());
+        strict_ne!(in self; from, to);
+        strict_not!(in self; self.piece_on(to).is_some());
+        strict_cond!(in self; self.piece_on(from).is_some());
 
         let x = Bitboard::from([from, to]);
-        let pc = self.board[from as usize]
-            .take()
+        let pc = self
+            .piece_on(from)
             .expect("move_piece: Cannot move non-extant piece.");
-        self.board[to as usize] = Some(pc);
+        #[cfg(not(feature = "bitboard-only"))]
+        {
+            self.board[from as usize] = None;
+            self.board[to as usize] = Some(pc);
+        }
         self.colors[pc.color() as usize] ^= x;
         self.pieces[pc.kind() as usize] ^= x;
     }
 
+    // The key from scratch, as opposed to the incremental `update_key`.
+    fn compute_key(&self) -> u64 {
+        let mut key = zobrist::castling(self.state().castle_rights);
+        for square in self.all() {
+            // SAFETY: `square` is occupied.
+            let piece = unsafe { self.piece_on_unchecked(square) };
+            key ^= zobrist::piece(piece, square);
+        }
+        if self.to_move() == Color::Black {
+            key ^= zobrist::side();
+        }
+        if let Some(ep) = self.ep() {
+            key ^= zobrist::en_passant(ep);
+        }
+        key
+    }
+    // The key after `mov`, from the one before: `mover` left `from`, whatever was on
+    // `capture_square` is gone, and rights and en passant changed from the previous state's.
+    fn update_key(&mut self, mover: Piece, mov: Move, capture_square: Square) {
+        let (from, to) = (mov.from(), mov.to());
+        let prev = *self
+            .previous_state()
+            .expect("Position::update_key: No previous state");
+        let mut key = prev.key ^ zobrist::side() ^ zobrist::castling(prev.castle_rights);
+        if let Some(ep) = prev.en_passant {
+            key ^= zobrist::en_passant(ep);
+        }
+
+        let placed = match mov.kind() {
+            MoveKind::Promotion(kind) => Piece::new(kind, mover.color()),
+            _ => mover,
+        };
+        key ^= zobrist::piece(mover, from) ^ zobrist::piece(placed, to);
+        if let Some(captured) = self.state().captured {
+            key ^= zobrist::piece(captured, capture_square);
+        }
+        if mov.kind() == MoveKind::Castle {
+            let rook = Piece::new(PieceType::Rook, mover.color());
+            let cf = if CastleFlag::short_for(mover.color()).to_square() == to {
+                CastleFlag::short_for(mover.color())
+            } else {
+                CastleFlag::long_for(mover.color())
+            };
+            key ^= zobrist::piece(rook, cf.rook_from_square())
+                ^ zobrist::piece(rook, cf.rook_to_square());
+        }
+
+        key ^= zobrist::castling(self.state().castle_rights);
+        if let Some(ep) = self.ep() {
+            key ^= zobrist::en_passant(ep);
+        }
+
+        self.state_mut().key = key;
+        strict_eq!(in self; key, self.compute_key());
+    }
+
     fn add_castle_right(&mut self, cf: CastleFlag) {
         self.state_mut().castle_rights |= u8::from(cf);
     }
@@ -684,10 +1944,56 @@ impl Position {
         self.state_mut().castle_rights &= !u8::from(cf);
     }
 
-    fn attacks_to(&self, square: Square, by: Color) -> Bitboard {
-        self.attacks_to_with_occ(square, by, self.all())
+    // Whether the side to move can actually take en passant. The EP square is only kept when this
+    // holds, so that two positions never differ by an EP square that cannot be used.
+    fn has_legal_ep(&self) -> bool {
+        let Some(ep) = self.ep() else {
+            return false;
+        };
+        let us = self.to_move();
+
+        if ep.rank() != us.relative_rank(Rank::Six) || !self.empty(ep) {
+            return false;
+        }
+        // The pawn that just pushed past `ep`, and the square it came from.
+        let (Some(pushed), Some(origin)) = (ep.shift(!us.forward()), ep.shift(us.forward())) else {
+            return false;
+        };
+        if self.piece_on(pushed) != Some(Piece::new(PieceType::Pawn, !us)) || !self.empty(origin) {
+            return false;
+        }
+
+        let takers = precompute::pawn_attacks(ep, !us) & self.spec(PieceType::Pawn, us);
+        takers
+            .into_iter()
+            .any(|from| self.is_legal(Move::new_with_kind(from, ep, MoveKind::EnPassant)))
+    }
+
+    // A pawn push onto empty squares, or a diagonal capture of an enemy piece (not en passant).
+    fn is_pawn_step(&self, from: Square, to: Square, us: Color) -> bool {
+        if precompute::pawn_attacks(from, us).has(to) {
+            return self.color(!us).has(to);
+        }
+
+        let Some(one_up) = from.shift(us.forward()) else {
+            return false;
+        };
+        if one_up == to {
+            return self.empty(to);
+        }
+
+        from.rank() == us.relative_rank(Rank::Two)
+            && one_up.shift(us.forward()) == Some(to)
+            && self.empty(one_up)
+            && self.empty(to)
     }
-    fn attacks_to_with_occ(&self, square: Square, by: Color, occupancy: Bitboard) -> Bitboard {
+
+    pub(crate) fn attacks_to_with_occ(
+        &self,
+        square: Square,
+        by: Color,
+        occupancy: Bitboard,
+    ) -> Bitboard {
         let pawns = precompute::pawn_attacks(square, !by) & self.pieces(PieceType::Pawn);
 
         let knights = precompute::knight_attacks(square) & self.pieces(PieceType::Knight);
@@ -701,6 +2007,17 @@ impl Position {
         moves & self.color(by)
     }
 
+    // Everything `piece` would attack from `square`.
+    fn piece_attacks(&self, piece: Piece, square: Square, occupancy: Bitboard) -> Bitboard {
+        match piece.kind() {
+            PieceType::Pawn => precompute::pawn_attacks(square, piece.color()),
+            PieceType::Knight => precompute::knight_attacks(square),
+            PieceType::Bishop => precompute::bishop_attacks(square, occupancy),
+            PieceType::Rook => precompute::rook_attacks(square, occupancy),
+            PieceType::Queen => precompute::queen_attacks(square, occupancy),
+            PieceType::King => precompute::king_attacks(square),
+        }
+    }
     fn sliders_to(&self, square: Square, occupancy: Bitboard) -> Bitboard {
         let bishops = precompute::bishop_attacks(square, occupancy)
             & self.pieces_list(&[PieceType::Bishop, PieceType::Queen]);
@@ -711,13 +2028,82 @@ impl Position {
 
     fn update_state(&mut self) {
         let mov_color = self.to_move();
-        let king = self.king(mov_color);
+        self.state_mut().checkers = self.attackers_to(self.king(mov_color), !mov_color);
 
-        self.state_mut().checkers = self.attacks_to(king, !mov_color);
+        self.state_mut().pinners = [Bitboard::EMPTY; 2];
+        self.state_mut().blockers = [Bitboard::EMPTY; 2];
 
         self.update_checkers_blockers(Color::White);
         self.update_checkers_blockers(Color::Black);
     }
+    // Check can only come from the piece that moved (or the castling rook), or be discovered
+    // from behind the square it left. Pins only change for a king that moved, or one with a
+    // changed square somewhere on its lines; everything else carries over from the previous state.
+    fn update_state_after_move(&mut self, mov: Move, changed: Bitboard, king_moved: Option<Color>) {
+        let (pinners, blockers) = {
+            let prev = self
+                .previous_state()
+                .expect("Position::update_state_after_move: No previous state");
+            (prev.pinners, prev.blockers)
+        };
+        self.state_mut().pinners = pinners;
+        self.state_mut().blockers = blockers;
+
+        let them = self.to_move();
+        let us = !them;
+        let king = self.king(them);
+        let occupancy = self.all();
+        let to = mov.to();
+
+        let mut checkers = Bitboard::EMPTY;
+        // SAFETY: The move just put its piece on `to`.
+        let piece = unsafe { self.piece_on_unchecked(to) };
+        if self.piece_attacks(piece, to, occupancy).has(king) {
+            checkers |= Bitboard::from(to);
+        }
+        let discovered = match mov.kind() {
+            MoveKind::Castle => {
+                let rook_to = CastleFlag::variants_for(us)
+                    .into_iter()
+                    .find(|cf| cf.to_square() == to)
+                    .map(CastleFlag::rook_to_square);
+                if let Some(rook_to) = rook_to {
+                    if precompute::rook_attacks(rook_to, occupancy).has(king) {
+                        checkers |= Bitboard::from(rook_to);
+                    }
+                }
+                blockers[them as usize].has(mov.from())
+            }
+            // Two pawns leave the same rank at once, so neither was a lone blocker before.
+            MoveKind::EnPassant => true,
+            _ => blockers[them as usize].has(mov.from()),
+        };
+        if discovered {
+            checkers |= self.sliders_to(king, occupancy) & self.color(us);
+        }
+        self.state_mut().checkers = checkers;
+
+        for color in [Color::White, Color::Black] {
+            let king = self.king(color);
+            let lines = precompute::queen_attacks(king, Bitboard::EMPTY);
+            if king_moved == Some(color) || bool::from(changed & lines) {
+                self.state_mut().blockers[color as usize] = Bitboard::EMPTY;
+                self.state_mut().pinners[(!color) as usize] = Bitboard::EMPTY;
+                self.update_checkers_blockers(color);
+            }
+        }
+
+        if cfg!(feature = "strict_checks") {
+            let incremental = (self.checkers(), self.state().pinners, self.state().blockers);
+            self.update_state();
+            if incremental != (self.checkers(), self.state().pinners, self.state().blockers) {
+                crate::repro::strict_failure(
+                    self,
+                    "Incremental check/pin update disagrees with a full update",
+                );
+            }
+        }
+    }
     fn update_checkers_blockers(&mut self, color: Color) {
         let king = self.king(color);
         // TODO Is it SUBSTANTIALLY better to just have slider attacks calculated separately to avoid overhead of pawn/king/knight generations?
@@ -744,36 +2130,39 @@ impl Default for Position {
     }
 }
 
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl State {
     #[cfg_attr(feature = "inline", inline)]
-    pub fn new() -> Box<Self> {
-        Box::new(Self {
+    pub fn new() -> Self {
+        Self {
             blockers: [Bitboard::EMPTY; 2],
             pinners: [Bitboard::EMPTY; 2],
             checkers: Bitboard::EMPTY,
             captured: None,
             castle_rights: 0,
             en_passant: None,
+            last_move: None,
             halfmoves: 0,
-            previous: None,
-        })
+            repetition_plies: 0,
+            key: 0,
+        }
     }
-}
 
-impl Clone for State {
+    // What the state after a move starts from: only what carries over, the rest is filled in
+    // as the move is made.
     #[cfg_attr(feature = "inline-aggressive", inline)]
-    fn clone(&self) -> Self {
+    fn next(&self) -> Self {
         Self {
-            captured: None,
-            en_passant: None,
-            pinners: [Bitboard::EMPTY; 2],
-            blockers: [Bitboard::EMPTY; 2],
-            checkers: Bitboard::EMPTY,
-
             halfmoves: self.halfmoves,
+            repetition_plies: self.repetition_plies,
             castle_rights: self.castle_rights,
-
-            previous: None,
+            key: self.key,
+            ..Self::new()
         }
     }
 }
@@ -815,4 +2204,997 @@ impl std::fmt::Display for Position {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::bb;
+    use crate::movegen::generate;
+    use crate::testing::board;
+    use Square::*;
+
+    #[test]
+    fn kingless_fens_go_to_the_builder() {
+        let fen = "8/8/8/3q4/8/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            Position::try_from_fen(fen).err(),
+            Some(FenError::MissingKing(Color::Black))
+        );
+
+        let builder = PositionBuilder::from_fen(fen).unwrap();
+        assert_eq!(builder.king(Color::White), Some(E1));
+        assert_eq!(builder.king(Color::Black), None);
+        assert_eq!(
+            builder.piece_on(D5),
+            Some(Piece::new(PieceType::Queen, Color::Black))
+        );
+        assert_eq!(
+            builder.build().err(),
+            Some(SetupError::MissingKing(Color::Black))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no Black king")]
+    fn new_from_fen_refuses_kingless_boards() {
+        Position::new_from_fen("8/8/8/3q4/8/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn editing_round_trip() {
+        let mut builder = PositionBuilder::from(&Position::default());
+        assert_eq!(builder.build().unwrap().to_fen(), Position::STARTING_FEN);
+
+        let edit = builder.clear_square(E8);
+        assert_eq!(
+            edit.replaced,
+            Some(Piece::new(PieceType::King, Color::Black))
+        );
+        assert_eq!(
+            edit.lost_castling,
+            [CastleFlag::BlackShort, CastleFlag::BlackLong]
+        );
+        assert_eq!(
+            builder.build().err(),
+            Some(SetupError::MissingKing(Color::Black))
+        );
+
+        builder.put_piece(D6, Piece::new(PieceType::King, Color::Black));
+        let pos = builder.build().unwrap();
+        assert_eq!(pos.king(Color::Black), D6);
+        assert_eq!(
+            pos.to_fen(),
+            "rnbq1bnr/pppppppp/3k4/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1"
+        );
+    }
+
+    #[test]
+    fn castling_through_attacks() {
+        // The a6 bishop covers f1; the a3 knight only b1, which the king never crosses.
+        let pos = Position::new_from_fen("r3k2r/8/b7/8/8/n7/8/R3K2R w KQkq - 0 1");
+        let short = Move::new_with_kind(E1, G1, MoveKind::Castle);
+        let long = Move::new_with_kind(E1, C1, MoveKind::Castle);
+        assert!(pos.can_castle(CastleFlag::WhiteShort) && pos.can_castle(CastleFlag::WhiteLong));
+        assert!(!pos.castle_is_safe(CastleFlag::WhiteShort));
+        assert!(pos.castle_is_safe(CastleFlag::WhiteLong));
+        assert!(!pos.is_legal(short) && pos.is_legal(long));
+        let moves = generate::legal(&pos);
+        assert!(moves.as_slice().contains(&long) && !moves.as_slice().contains(&short));
+
+        // Never out of check.
+        let pos = Position::new_from_fen("r3k2r/8/8/8/4q3/8/8/R3K2R w KQkq - 0 1");
+        assert!(pos.can_castle(CastleFlag::WhiteShort));
+        assert!(!pos.castle_is_safe(CastleFlag::WhiteShort));
+        assert!(!pos.is_legal(short) && !pos.is_legal(long));
+        assert_eq!(CastleFlag::BlackLong.empty_path().popcount(), 3);
+    }
+
+    #[test]
+    fn edits_strip_broken_castle_rights() {
+        let mut builder = PositionBuilder::from(&Position::default());
+
+        let edit = builder.clear_square(H1);
+        assert_eq!(edit.lost_castling, vec![CastleFlag::WhiteShort]);
+
+        // Putting the rook back does not bring the right back.
+        let edit = builder.put_piece(H1, Piece::new(PieceType::Rook, Color::White));
+        assert!(edit.lost_castling.is_empty());
+
+        // Nor does a rook of the wrong colour count.
+        let edit = builder.put_piece(A8, Piece::new(PieceType::Rook, Color::White));
+        assert_eq!(
+            edit.replaced,
+            Some(Piece::new(PieceType::Rook, Color::Black))
+        );
+        assert_eq!(edit.lost_castling, vec![CastleFlag::BlackLong]);
+
+        let edit = builder.put_piece(D8, Piece::new(PieceType::King, Color::Black));
+        assert_eq!(edit.lost_castling, vec![]);
+        let edit = builder.clear_square(E8);
+        assert_eq!(edit.lost_castling, vec![CastleFlag::BlackShort]);
+
+        let pos = builder.build().unwrap();
+        assert!(pos.inconsistent_castle_rights().is_empty());
+        assert!(pos.has_castle(CastleFlag::WhiteLong));
+        assert!(!pos.has_castle(CastleFlag::WhiteShort));
+    }
+
+    // Every structurally valid move should be pseudo-legal exactly when the generator produces it.
+    fn check_pseudo_legal_matches_generator(fen: &str) {
+        let pos = Position::new_from_fen(fen);
+        let generated = generate::pseudo_legal(&pos);
+        let generated: Vec<Move> = (&generated).into_iter().collect();
+
+        for raw in 1..=u16::MAX {
+            let Ok(m) = Move::try_from(raw) else {
+                continue;
+            };
+            assert_eq!(
+                pos.is_pseudo_legal(m),
+                generated.contains(&m),
+                "{fen}: {m} ({:?})",
+                m.kind()
+            );
+        }
+    }
+
+    #[test]
+    fn pseudo_legal_matches_generator() {
+        check_pseudo_legal_matches_generator(Position::STARTING_FEN);
+        check_pseudo_legal_matches_generator(Position::KIWIPETE_FEN);
+        check_pseudo_legal_matches_generator(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        );
+        check_pseudo_legal_matches_generator(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        );
+        check_pseudo_legal_matches_generator("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -");
+    }
+
+    #[test]
+    fn external_moves_are_validated() {
+        let mut pos = Position::default();
+        assert_eq!(pos.make_uci_moves(&[b"e2e4", b"e7e5"]), Ok(()));
+        assert_eq!(pos.make_uci_moves(&[b"e1e3"]), Err(&b"e1e3"[..]));
+        assert_eq!(pos.make_uci_moves(&[b"d1d3"]), Err(&b"d1d3"[..]));
+        assert_eq!(pos.make_uci_moves(&[b"e1g1"]), Err(&b"e1g1"[..]));
+        assert_eq!(pos.make_uci_moves(&[b"g1f3"]), Ok(()));
+    }
+
+    #[test]
+    fn en_passant_square_from_fen() {
+        let pos = board(
+            "
+            . . . . k . . .
+            . . . . . . . .
+            . . . . . . . .
+            . . . p P . . .
+            . . . . . . . .
+            . . . . . . . .
+            . . . . . . . .
+            . . . . K . . .
+            ",
+            Color::White,
+            "-",
+            Some(D6),
+        );
+        assert_eq!(pos.ep(), Some(D6));
+        assert!(pos.is_pseudo_legal(Move::new_with_kind(E5, D6, MoveKind::EnPassant)));
+    }
+
+    #[test]
+    fn unusable_en_passant_is_dropped() {
+        // Nothing can take.
+        let pos =
+            Position::new_from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        assert_eq!(pos.ep(), None);
+
+        // Taking would leave the king open along the rank.
+        let pos = Position::new_from_fen("8/8/8/K2pP2q/8/8/8/7k w - d6 0 1");
+        assert_eq!(pos.ep(), None);
+
+        // No pawn actually made the double push.
+        let pos = Position::new_from_fen("4k3/8/8/4P3/8/8/8/4K3 w - d6 0 1");
+        assert_eq!(pos.ep(), None);
+
+        let pos = Position::new_from_fen("8/8/8/K2pP3/8/8/8/7k w - d6 0 1");
+        assert_eq!(pos.ep(), Some(D6));
+    }
+
+    #[test]
+    fn en_passant_only_set_when_usable() {
+        let mut pos = Position::default();
+        pos.make_uci_moves(&[b"e2e4"]).unwrap();
+        assert_eq!(pos.ep(), None);
+
+        pos.make_uci_moves(&[b"a7a6", b"e4e5", b"d7d5"]).unwrap();
+        assert_eq!(pos.ep(), Some(D6));
+    }
+
+    #[test]
+    fn pawn_attack_masks() {
+        let pos = Position::new_from_fen("4k3/6p1/8/4P3/6PP/8/8/4K3 w - - 0 1");
+        assert_eq!(pos.pawn_attacks(Color::White), bb![D6, F6, F5, H5, G5]);
+        assert_eq!(pos.pawn_attacks(Color::Black), bb![F6, H6]);
+        assert!(!pos.pawn_safe_squares(Color::White).has(F6));
+        assert!(pos.pawn_safe_squares(Color::White).has(G6));
+    }
+
+    #[test]
+    fn passed_and_candidate_pawns() {
+        let pos = board(
+            "
+            . . . . k . . .
+            . . . . . . p .
+            . . . . . . . .
+            . . . . P . . .
+            . . . . . . P P
+            . . . . . . . .
+            . . . . . . . .
+            . . . . K . . .
+            ",
+            Color::White,
+            "-",
+            None,
+        );
+        assert_eq!(pos.passed_pawns(Color::White), Bitboard::from(E5));
+        assert_eq!(pos.candidate_passers(Color::White), Bitboard::from(H4));
+        assert_eq!(pos.passed_pawns(Color::Black), Bitboard::EMPTY);
+        assert_eq!(pos.candidate_passers(Color::Black), Bitboard::EMPTY);
+    }
+
+    #[test]
+    fn builder_sets_up_and_validates() {
+        use PieceType::*;
+        let white = |kind| Piece::new(kind, Color::White);
+        let black = |kind| Piece::new(kind, Color::Black);
+        let kings = PositionBuilder::new()
+            .piece(E1, white(King))
+            .piece(E8, black(King));
+
+        let pos = kings
+            .clone()
+            .piece(A1, white(Rook))
+            .piece(E5, white(Pawn))
+            .piece(D5, black(Pawn))
+            .castling(&[CastleFlag::WhiteLong])
+            .ep(Some(D6))
+            .build()
+            .unwrap();
+        let fen = Position::new_from_fen("4k3/8/8/3pP3/8/8/8/R3K3 w Q d6 0 1");
+        assert_eq!(pos.to_fen(), fen.to_fen());
+        assert_eq!(pos.key(), fen.key());
+
+        // Nothing can take on d6 without the e5 pawn, so the square goes.
+        let pos = kings.clone().piece(D5, black(Pawn)).ep(Some(D6));
+        assert_eq!(pos.build().unwrap().ep(), None);
+
+        let cases = [
+            (
+                PositionBuilder::new().piece(E1, white(King)),
+                SetupError::MissingKing(Color::Black),
+            ),
+            (
+                kings.clone().piece(A8, black(King)),
+                SetupError::TooManyKings(Color::Black),
+            ),
+            (
+                kings.clone().piece(B8, white(Pawn)),
+                SetupError::PawnOnBackRank(B8),
+            ),
+            (
+                kings.clone().castling(&[CastleFlag::BlackShort]),
+                SetupError::BadCastling(CastleFlag::BlackShort),
+            ),
+            (
+                kings.clone().piece(E4, white(Rook)),
+                SetupError::OpponentInCheck,
+            ),
+            (kings.clone().ep(Some(D3)), SetupError::BadEnPassant(D3)),
+        ];
+        for (builder, error) in cases {
+            assert_eq!(builder.build().unwrap_err(), error);
+        }
+
+        let pos = kings.side_to_move(Color::Black).build().unwrap();
+        assert_eq!(pos.to_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+    }
+
+    #[test]
+    fn validate_catches_broken_positions() {
+        assert_eq!(Position::default().validate(), Ok(()));
+        assert_eq!(
+            Position::new_from_fen(Position::KIWIPETE_FEN).validate(),
+            Ok(())
+        );
+
+        // A knight added to its bitboard alone, not to White's.
+        let mut pos = Position::default();
+        pos.pieces[PieceType::Knight as usize] |= Bitboard::from(E4);
+        assert_eq!(pos.validate(), Err(ValidationError::Bitboards));
+
+        #[cfg(not(feature = "bitboard-only"))]
+        {
+            let mut pos = Position::default();
+            pos.board[E2 as usize] = None;
+            assert_eq!(pos.validate(), Err(ValidationError::Board(E2)));
+        }
+
+        let mut pos = Position::default();
+        pos.state_mut().key ^= 1;
+        assert_eq!(pos.validate(), Err(ValidationError::Key));
+
+        // In check from the e2 rook, with no checkers kept.
+        let mut pos = Position::new_from_fen("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1");
+        pos.state_mut().checkers = Bitboard::EMPTY;
+        assert_eq!(pos.validate(), Err(ValidationError::State));
+
+        // Short castling with no rook on h1.
+        let mut pos = Position::new_from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        pos.add_castle_right(CastleFlag::WhiteShort);
+        assert_eq!(
+            pos.validate(),
+            Err(ValidationError::Setup(SetupError::BadCastling(
+                CastleFlag::WhiteShort
+            )))
+        );
+
+        // c6, with no black pawn on c5 to take.
+        let mut pos = Position::new_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - - 0 1");
+        pos.state_mut().en_passant = Some(C6);
+        assert_eq!(pos.validate(), Err(ValidationError::EnPassant(C6)));
+    }
+
+    #[test]
+    fn try_from_fen_reports_errors() {
+        use FenError::*;
+
+        assert!(Position::try_from_fen(Position::STARTING_FEN).is_ok());
+        assert!(Position::try_from_fen(Position::KIWIPETE_FEN).is_ok());
+
+        let cases = [
+            ("", MissingField("board")),
+            ("8/8/8/8/8/8/8 w - -", WrongRankCount(7)),
+            (
+                "rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w",
+                BadRankLength(Rank::Seven),
+            ),
+            (
+                "rnbqkbnr/pppppppp/81/8/8/8/PPPPPPPP/RNBQKBNR w",
+                BadRankLength(Rank::Six),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w",
+                BadRankLength(Rank::One),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPXPP/RNBQKBNR w",
+                BadPiece('X'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+                MissingField("side to move"),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq",
+                BadSideToMove("x".into()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w",
+                MissingField("castling"),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkK",
+                BadCastling('K'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ-",
+                BadCastling('-'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9",
+                BadEnPassant("e9".into()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3",
+                BadEnPassant("e3".into()),
+            ),
+            ("8/8/8/8/8/8/8/4K3 w - -", MissingKing(Color::Black)),
+            ("k7/8/8/8/8/8/8/4K2K w - -", TooManyKings(Color::White)),
+            ("k6P/8/8/8/8/8/8/4K3 w - -", PawnOnBackRank),
+            ("k7/8/8/8/8/8/8/R3K3 w - -", OpponentInCheck),
+        ];
+        for (fen, err) in cases {
+            assert_eq!(Position::try_from_fen(fen).err(), Some(err), "{}", fen);
+        }
+
+        // Kingless boards are still fine for setting up, just not to play on.
+        assert!(PositionBuilder::from_fen("8/8/8/8/8/8/8/4K3 w - -").is_ok());
+    }
+
+    // FEN -> Position -> FEN has to give back exactly the same text, as long as it is already
+    // normalised (single spaces, and an en passant square only when the capture is possible).
+    const FEN_CORPUS: [&str; 10] = [
+        Position::STARTING_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        "rnbqkbnr/pp2pppp/8/2ppP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3",
+        "rnbqkbnr/pppp1ppp/8/8/3Pp3/4P3/PPP2PPP/RNBQKBNR b KQkq d3 0 3",
+        "8/8/4k3/8/8/4K3/8/7R b - - 99 150",
+        "r3k3/8/8/8/8/8/8/4K2R b Kq - 12 34",
+    ];
+
+    #[test]
+    fn fen_and_epd_corpus_round_trips() {
+        for fen in FEN_CORPUS {
+            let pos = Position::try_from_fen(fen).unwrap();
+            assert_eq!(pos.to_fen(), fen);
+
+            let epd = pos.to_epd();
+            let again = Position::try_from_epd(&epd).unwrap();
+            assert_eq!(again.to_fen(), fen, "{}", epd);
+        }
+
+        let pos = Position::try_from_epd(
+            "4k3/8/8/8/8/8/8/4K2R b K - bm Rh8+; hmvc 7; id \"test 1\"; fmvn 20;",
+        )
+        .unwrap();
+        assert_eq!(pos.to_fen(), "4k3/8/8/8/8/8/8/4K2R b K - 7 20");
+        assert_eq!(
+            Position::try_from_epd("4k3/8/8/8/8/8/8/4K2R b K - fmvn 0;").err(),
+            Some(FenError::BadCounter("0".into()))
+        );
+    }
+
+    #[test]
+    fn move_counters_round_trip() {
+        for fen in [
+            Position::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/8/8/K2pP3/8/8/8/7k w - d6 0 3",
+            "4k3/8/8/8/8/8/8/4K2R b K - 37 61",
+        ] {
+            assert_eq!(Position::new_from_fen(fen).to_fen(), fen);
+        }
+
+        let mut pos = Position::new_from_fen("4k3/8/8/8/8/8/8/4K2R b K - 37 61");
+        assert_eq!(pos.rule50(), 37);
+        assert_eq!(pos.fullmoves(), 61);
+        pos.make_move(Move::new(E8, D8));
+        assert_eq!(pos.rule50(), 38);
+        assert_eq!(pos.fullmoves(), 62);
+        assert_eq!(pos.to_fen(), "3k4/8/8/8/8/8/8/4K2R w K - 38 62");
+
+        // Counters may be left off, but not be nonsense.
+        let pos = Position::new_from_fen("4k3/8/8/8/8/8/8/4K3 w - -");
+        assert_eq!((pos.rule50(), pos.fullmoves()), (0, 1));
+        let bad = |fen| Position::try_from_fen(fen).err();
+        assert_eq!(
+            bad("4k3/8/8/8/8/8/8/4K3 w - - x 1"),
+            Some(FenError::BadCounter("x".into()))
+        );
+        assert_eq!(
+            bad("4k3/8/8/8/8/8/8/4K3 w - - 0 0"),
+            Some(FenError::BadCounter("0".into()))
+        );
+        assert_eq!(
+            bad("4k3/8/8/8/8/8/8/4K3 w - - 0 1 2"),
+            Some(FenError::TrailingField("2".into()))
+        );
+    }
+
+    #[test]
+    fn diff_lists_changes() {
+        let before = Position::default();
+        assert!(before.diff(&Position::default()).is_empty());
+
+        let mut after = Position::default();
+        after.make_uci_moves(&[&b"e2e4"[..]]).unwrap();
+        let diff = before.diff(&after);
+        assert_eq!(
+            diff.squares,
+            vec![
+                (E2, Some(Piece::new(PieceType::Pawn, Color::White)), None),
+                (E4, None, Some(Piece::new(PieceType::Pawn, Color::White))),
+            ]
+        );
+        assert!(diff.to_move);
+        assert!(diff.castling.is_empty());
+        assert_eq!(diff.en_passant, None);
+        assert_eq!(
+            diff.to_string(),
+            "e2: P -> .\ne4: . -> P\nside to move differs\n"
+        );
+
+        let other = Position::new_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        let diff = other.diff(&Position::new_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(diff.en_passant, Some((Some(D6), None)));
+        let diff = Position::default().diff(&Position::new_from_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Kq - 0 1",
+        ));
+        assert_eq!(
+            diff.castling,
+            vec![CastleFlag::WhiteLong, CastleFlag::BlackShort]
+        );
+    }
+
+    #[test]
+    fn static_exchange() {
+        let see = |fen: &str, from, to| Position::new_from_fen(fen).see(Move::new(from, to));
+
+        // Undefended, and defended by a pawn.
+        assert_eq!(see("4k3/8/8/3n4/8/8/8/3RK3 w - - 0 1", D1, D5), 320);
+        assert_eq!(see("4k3/8/4p3/3n4/8/8/8/3RK3 w - - 0 1", D1, D5), 320 - 500);
+        // Pawn takes knight, pawn takes back.
+        assert_eq!(
+            see("4k3/8/4p3/3n4/4P3/8/8/4K3 w - - 0 1", E4, D5),
+            320 - 100
+        );
+        // Pieces behind the first attacker join in through the x-ray: with doubled black rooks
+        // the queen cannot safely take back, without them she wins the exchange.
+        assert_eq!(
+            see("3rk3/3r4/8/3p4/8/8/3R4/3QK3 w - - 0 1", D2, D5),
+            100 - 500
+        );
+        assert_eq!(see("3rk3/8/8/3p4/8/8/3R4/3QK3 w - - 0 1", D2, D5), 100);
+        // A quiet move onto an attacked square loses the piece.
+        assert_eq!(see("4k3/8/4p3/8/8/8/8/3RK3 w - - 0 1", D1, D5), -500);
+        // The king may only recapture when nothing else can take back.
+        assert_eq!(see("4k3/3p4/8/3n4/8/8/8/3RK3 b - - 0 1", E8, D8), 0);
+        assert_eq!(see("3qk3/8/8/8/8/8/8/3RK3 w - - 0 1", D1, D8), 900 - 500);
+        assert_eq!(see("3qk3/8/8/8/8/8/8/3RK2B w - - 0 1", D1, D8), 900 - 500);
+        // En passant and promotions.
+        assert_eq!(
+            Position::new_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").see(Move::new_with_kind(
+                E5,
+                D6,
+                MoveKind::EnPassant
+            )),
+            100
+        );
+        assert_eq!(
+            Position::new_from_fen("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1").see(Move::new_with_kind(
+                A7,
+                B8,
+                MoveKind::Promotion(PieceType::Queen)
+            )),
+            500 + 800
+        );
+    }
+
+    #[test]
+    fn undo_stack() {
+        let mut pos = Position::default();
+        assert_eq!(pos.undo(), None);
+
+        pos.make_uci_moves(&[&b"e2e4"[..], &b"e7e5"[..], &b"g1f3"[..]])
+            .unwrap();
+        assert_eq!(pos.last_move(), Some(Move::new(G1, F3)));
+        assert_eq!(pos.undo(), Some(Move::new(G1, F3)));
+        assert_eq!(pos.undo(), Some(Move::new(E7, E5)));
+        assert_eq!(pos.undo(), Some(Move::new(E2, E4)));
+        assert_eq!(pos.undo(), None);
+        assert!(pos.diff(&Position::default()).is_empty());
+
+        // A copy starts its own history.
+        pos.make_move(Move::new(D2, D4));
+        assert_eq!(pos.detached().last_move(), None);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Not the last move made")]
+    fn unmake_checks_the_move() {
+        let mut pos = Position::default();
+        pos.make_move(Move::new(E2, E4));
+        pos.unmake_move(Move::new(D2, D4));
+    }
+
+    #[test]
+    // A clean panic, never undefined behaviour (with strict checks on, theirs comes first).
+    #[should_panic]
+    fn undo_after_losing_the_moved_piece_panics() {
+        let mut pos = Position::default();
+        pos.make_move(Move::new(E2, E4));
+        let _ = pos.remove_piece(E4);
+        pos.undo();
+    }
+
+    #[test]
+    fn clones_are_independent() {
+        let mut pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        pos.make_uci_moves(&[&b"e2a6"[..], &b"b4c3"[..]]).unwrap();
+
+        let mut copy = pos.clone();
+        assert_eq!(copy.to_fen(), pos.to_fen());
+        assert_eq!(copy.checkers(), pos.checkers());
+
+        // The copy can go back through the whole history on its own thread.
+        let handle = std::thread::spawn(move || {
+            let nodes = crate::perft::perft(&mut copy, 2);
+            while copy.undo().is_some() {}
+            (nodes, copy.to_fen())
+        });
+        let (nodes, start) = handle.join().unwrap();
+
+        assert_eq!(nodes, crate::perft::perft(&mut pos, 2));
+        assert_eq!(
+            start,
+            Position::new_from_fen(Position::KIWIPETE_FEN).to_fen()
+        );
+        assert_eq!(pos.last_move(), Some(Move::new(B4, C3)));
+    }
+
+    #[test]
+    fn key_after_matches_make_move() {
+        let fens = [
+            Position::STARTING_FEN,
+            Position::KIWIPETE_FEN,
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/8/8/1k1p4/8/2P5/4K3 w - - 0 1",
+            "8/8/8/3pP3/4K3/8/8/3k4 w - d6 0 1",
+            // Double pushes past a pawn that can't take en passant: pinned along the rank...
+            "8/8/8/8/k2p3R/8/4P3/4K3 w - - 0 1",
+            // ...or with the king left in check by the push itself.
+            "8/8/8/8/3p4/8/k3P2R/4K3 w - - 0 1",
+            // And one that can, taking the pawn that gives check.
+            "8/8/8/5k2/3p4/8/4P3/4K3 w - - 0 1",
+        ];
+        for fen in fens {
+            let mut pos = Position::new_from_fen(fen);
+            for m in &generate::legal(&pos) {
+                let key = pos.key_after(m);
+                pos.make_move(m);
+                assert_eq!(key, pos.key(), "{} after {}", fen, m);
+                pos.unmake_move(m);
+            }
+        }
+    }
+
+    #[test]
+    fn unchecked_lookup_matches_piece_on() {
+        let pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        for s in pos.all() {
+            // SAFETY: `s` is occupied.
+            assert_eq!(Some(unsafe { pos.piece_on_unchecked(s) }), pos.piece_on(s));
+        }
+    }
+
+    #[test]
+    fn make_and_unmake_do_not_allocate() {
+        fn walk(pos: &mut Position, depth: u32) {
+            if depth == 0 {
+                return;
+            }
+            for m in &generate::legal(pos) {
+                pos.make_move(m);
+                walk(pos, depth - 1);
+                pos.unmake_move(m);
+            }
+        }
+
+        let mut pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        let fen = pos.to_fen();
+        // The first time round the stack grows to its depth; after that it's only reused.
+        walk(&mut pos, 3);
+        let before = crate::testing::allocations();
+        walk(&mut pos, 3);
+        assert_eq!(crate::testing::allocations(), before);
+        assert_eq!(pos.to_fen(), fen);
+    }
+
+    #[test]
+    fn gives_check_matches_make_move() {
+        let fens = [
+            // Discovered check by the e4 knight.
+            "4k3/8/8/8/4N3/8/8/4RK2 w - - 0 1",
+            // Discovered check along the rank by en passant.
+            "8/8/8/k2pP2R/8/8/8/7K w - d6 0 1",
+            // Check by the rook after castling, either way.
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            "8/8/8/8/8/8/8/R3K2k w Q - 0 1",
+            // Promotions to a queen or rook check, to a knight or bishop don't.
+            "3k4/1P6/8/8/8/8/8/4K3 w - - 0 1",
+        ];
+
+        for fen in fens {
+            let mut pos = Position::new_from_fen(fen);
+            let mut checks = 0;
+            for m in &generate::legal(&pos) {
+                let gives_check = pos.gives_check(m);
+                pos.make_move(m);
+                assert_eq!(gives_check, pos.in_check(), "{} in {}", m, fen);
+                pos.unmake_move(m);
+                checks += gives_check as usize;
+            }
+            assert!(checks > 0, "{}", fen);
+        }
+
+        // And the moves each position is there for.
+        let cases = [
+            (
+                fens[1],
+                Move::new_with_kind(E5, D6, MoveKind::EnPassant),
+                true,
+            ),
+            (fens[2], Move::new_with_kind(E1, G1, MoveKind::Castle), true),
+            (fens[3], Move::new_with_kind(E1, C1, MoveKind::Castle), true),
+            (
+                fens[4],
+                Move::new_with_kind(B7, B8, MoveKind::Promotion(PieceType::Queen)),
+                true,
+            ),
+            (
+                fens[4],
+                Move::new_with_kind(B7, B8, MoveKind::Promotion(PieceType::Knight)),
+                false,
+            ),
+        ];
+        for (fen, m, check) in cases {
+            assert_eq!(
+                Position::new_from_fen(fen).gives_check(m),
+                check,
+                "{} in {}",
+                m,
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn null_move() {
+        let mut pos = Position::new_from_fen("4k3/8/8/8/3pP3/8/8/4K2R b K e3 0 1");
+        let before = pos.clone();
+        assert_eq!(pos.ep(), Some(E3));
+
+        pos.make_null_move();
+        assert_eq!(pos.to_move(), Color::White);
+        assert_eq!(pos.ep(), None);
+        assert_eq!(pos.rule50(), 1);
+        assert_eq!(pos.fullmoves(), 2);
+        assert_eq!(pos.last_move(), None);
+        assert_eq!(
+            pos.key(),
+            Position::new_from_fen("4k3/8/8/8/3pP3/8/8/4K2R w K - 1 2").key()
+        );
+        assert!(pos.can_castle(CastleFlag::WhiteShort));
+
+        // Moves made on top of it come off as usual, and `undo` stops at the null move.
+        pos.play_uci("h1h8").unwrap();
+        assert!(pos.in_check());
+        assert_eq!(pos.undo(), Some(Move::new(H1, H8)));
+        assert_eq!(pos.undo(), None);
+
+        pos.unmake_null_move();
+        assert_eq!(pos.to_fen(), before.to_fen());
+        assert_eq!(pos.key(), before.key());
+        assert_eq!(pos.checkers(), before.checkers());
+    }
+
+    #[test]
+    fn null_moves_end_the_repetition_window() {
+        // Twice round Nf3, pass, Ng1, pass: the start position, white to move, comes up three
+        // times, but only ever with a null move in between.
+        let mut pos = Position::default();
+        for _ in 0..2 {
+            pos.make_move(Move::new(G1, F3));
+            pos.make_null_move();
+            pos.make_move(Move::new(F3, G1));
+            pos.make_null_move();
+        }
+        assert_eq!(pos.key(), Position::default().key());
+        assert_eq!((pos.rule50(), pos.repetition_plies()), (8, 0));
+        assert!(!pos.is_threefold_repetition());
+
+        // Without them, it's a draw.
+        let mut pos = Position::default();
+        let shuffle: [&[u8]; 4] = [b"g1f3", b"g8f6", b"f3g1", b"f6g8"];
+        pos.make_uci_moves(&shuffle.repeat(2)).unwrap();
+        assert_eq!(pos.repetition_plies(), 8);
+        assert!(pos.is_threefold_repetition());
+    }
+
+    #[test]
+    fn move_history_and_ply() {
+        let mut pos = Position::default();
+        assert_eq!(pos.move_history(), &[]);
+        assert_eq!(pos.ply(), 0);
+
+        pos.make_uci_moves(&[b"e2e4", b"e7e5", b"g1f3"]).unwrap();
+        let played = [Move::new(E2, E4), Move::new(E7, E5), Move::new(G1, F3)];
+        assert_eq!(pos.move_history(), &played);
+        assert_eq!(pos.ply(), 3);
+        assert_eq!(pos.clone().move_history(), &played);
+        assert_eq!(pos.detached().move_history(), &[]);
+
+        pos.make_null_move();
+        assert_eq!(pos.move_history(), &played);
+        assert_eq!(pos.ply(), 4);
+        pos.unmake_null_move();
+
+        pos.undo();
+        assert_eq!(pos.move_history(), &played[..2]);
+        assert_eq!(pos.last_move(), Some(Move::new(E7, E5)));
+        assert_eq!(pos.ply(), 2);
+
+        // The ply carries on from the FEN, the history doesn't.
+        let pos = Position::new_from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 20");
+        assert_eq!(pos.ply(), 39);
+        assert_eq!(pos.move_history(), &[]);
+    }
+
+    #[test]
+    fn game_results() {
+        let mut pos = Position::default();
+        assert_eq!(pos.result(), GameResult::Ongoing);
+        for m in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            pos.play_uci(m).unwrap();
+        }
+        assert!(pos.is_checkmate());
+        assert_eq!(pos.result(), GameResult::BlackWins);
+        assert_eq!(pos.result().to_string(), "0-1");
+
+        let pos = Position::new_from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert!(pos.is_stalemate() && !pos.is_checkmate());
+        assert_eq!(pos.result(), GameResult::Draw(DrawReason::Stalemate));
+
+        let pos = Position::new_from_fen("7k/8/6K1/8/8/8/8/R7 w - - 100 80");
+        assert_eq!(pos.result(), GameResult::Draw(DrawReason::FiftyMoves));
+        // Mate on the hundredth ply still counts.
+        let pos = Position::new_from_fen("R6k/8/6K1/8/8/8/8/8 b - - 100 80");
+        assert_eq!(pos.result(), GameResult::WhiteWins);
+
+        let mut pos = Position::default();
+        for round in 0..2 {
+            assert!(!pos.is_threefold_repetition());
+            for m in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                pos.play_uci(m).unwrap();
+            }
+            assert_eq!(pos.result().is_over(), round == 1);
+        }
+        assert_eq!(pos.result(), GameResult::Draw(DrawReason::Repetition));
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let insufficient = [
+            "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/8/6N1 w - - 0 1",
+            "8/8/4k3/8/8/3K4/8/5B2 b - - 0 1",
+            "2b5/8/4k3/8/8/3K4/8/5B2 w - - 0 1",
+            "2b5/8/4k3/8/8/3K4/8/3B1B2 w - - 0 1",
+        ];
+        let sufficient = [
+            "8/2b5/4k3/8/8/3K4/8/5B2 w - - 0 1",
+            "8/8/4k3/8/8/3K4/8/5NN1 w - - 0 1",
+            "8/8/4k3/8/8/3K4/8/5BN1 w - - 0 1",
+            "8/8/4k3/8/8/3K4/6P1/8 w - - 0 1",
+        ];
+        for fen in insufficient {
+            let pos = Position::new_from_fen(fen);
+            assert!(pos.is_insufficient_material(), "{}", fen);
+            assert_eq!(
+                pos.result(),
+                GameResult::Draw(DrawReason::InsufficientMaterial)
+            );
+        }
+        for fen in sufficient {
+            assert!(
+                !Position::new_from_fen(fen).is_insufficient_material(),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn attack_queries() {
+        let pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        // The d5 pawn: hit by two knights and a pawn, held by a knight and a pawn.
+        assert_eq!(pos.attackers_to(D5, Color::Black), bb![B6, E6, F6]);
+        assert_eq!(pos.attackers_to(D5, Color::White), bb![E4, C3]);
+        assert!(pos.is_attacked(G2, Color::Black));
+        assert!(!pos.is_attacked(B1, Color::Black));
+
+        assert_eq!(pos.attacks_from(E5), bb![C4, C6, D3, D7, F3, F7, G4, G6]);
+        assert_eq!(pos.attacks_from(D5), bb![C6, E6]);
+        assert_eq!(pos.attacks_from(D4), Bitboard::EMPTY);
+
+        // Consistent with each other on every square, for both sides.
+        for color in [Color::White, Color::Black] {
+            for square in Bitboard::new(!0) {
+                let attackers = pos
+                    .color(color)
+                    .into_iter()
+                    .filter(|&s| pos.attacks_from(s).has(square))
+                    .fold(Bitboard::EMPTY, |acc, s| acc | Bitboard::from(s));
+                assert_eq!(pos.attackers_to(square, color), attackers, "{}", square);
+            }
+        }
+    }
+
+    #[test]
+    fn mobility_matches_movegen() {
+        let fens = [
+            Position::KIWIPETE_FEN,
+            // A rook pinned along its file, free to move on it.
+            "4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1",
+            // In check with the knight pinned: it can neither block nor take.
+            "k3r3/8/8/8/1b6/8/4N3/4K3 w - - 0 1",
+            // Double check, so only the king moves.
+            "4k3/8/8/8/8/5n2/8/r3K3 w - - 0 1",
+            // En passant would uncover the king along the rank.
+            "8/8/8/K2pP2r/8/8/8/7k w - d6 0 1",
+        ];
+
+        for fen in fens {
+            let pos = Position::new_from_fen(fen);
+            let moves = generate::legal(&pos);
+            for square in pos.all() {
+                let expected = moves
+                    .into_iter()
+                    .filter(|m| m.from() == square)
+                    .fold(Bitboard::EMPTY, |acc, m| acc | Bitboard::from(m.to()));
+                assert_eq!(pos.mobility_of(square), expected, "{} in {}", square, fen);
+            }
+        }
+
+        assert_eq!(Position::default().mobility_of(E7), Bitboard::EMPTY);
+        assert_eq!(Position::default().mobility_of(E4), Bitboard::EMPTY);
+    }
+
+    #[test]
+    fn canonical_keys_ignore_dead_rights() {
+        // A castling right with the rook gone, and an en passant square with no taker.
+        let canonical = Position::new_from_fen("4k3/8/8/4p3/8/8/8/4K3 w - - 0 1");
+        let loose = Position::new_from_fen("4k3/8/8/4p3/8/8/8/4K3 w K e6 0 1");
+        assert_ne!(loose.key(), canonical.key());
+        assert_eq!(loose.canonical_key(), canonical.key());
+        assert_eq!(canonical.canonical_key(), canonical.key());
+
+        let mut pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        pos.make_move(Move::new(A2, A4));
+        assert_eq!(pos.canonical_key(), pos.key());
+    }
+
+    #[test]
+    fn keys_follow_moves() {
+        let mut pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        let start = pos.key();
+        assert_eq!(start, pos.compute_key());
+
+        for m in &generate::legal(&pos.clone()) {
+            pos.make_move(m);
+            assert_eq!(pos.key(), pos.compute_key(), "{}", m);
+            assert_ne!(pos.key(), start);
+            pos.unmake_move(m);
+            assert_eq!(pos.key(), start);
+        }
+
+        // Transpositions meet on the same key, whatever the move counters say.
+        let mut a = Position::default();
+        a.make_moves(&[Move::new(G1, F3), Move::new(G8, F6), Move::new(B1, C3)])
+            .unwrap();
+        let mut b = Position::default();
+        b.make_moves(&[Move::new(B1, C3), Move::new(G8, F6), Move::new(G1, F3)])
+            .unwrap();
+        assert_eq!(a.key(), b.key());
+        let fen = a.to_fen();
+        assert!(fen.ends_with(" 3 2"));
+        let renumbered = fen.replace(" 3 2", " 7 40");
+        assert_eq!(a.key(), Position::new_from_fen(&renumbered).key());
+
+        // Side to move, castling and en passant all count.
+        let key = |fen: &str| Position::new_from_fen(fen).key();
+        let base = "4k2r/8/8/8/3p4/8/4P3/4K3 w k - 0 1";
+        assert_ne!(key(base), key(&base.replace(" w ", " b ")));
+        assert_ne!(key(base), key(&base.replace(" k ", " - ")));
+        let mut pushed = Position::new_from_fen(base);
+        pushed.make_move(Move::new(E2, E4));
+        assert_eq!(pushed.ep(), Some(E3));
+        assert_ne!(pushed.key(), key("4k2r/8/8/8/3pP3/8/8/4K3 b k - 0 1"));
+        assert_eq!(pushed.key(), key("4k2r/8/8/8/3pP3/8/8/4K3 b k e3 0 1"));
+    }
+}
@@ -1,141 +1,297 @@
-#[cfg(feature = "magic")]
-use crate::magic;
-use std::sync::OnceLock;
-
 // TODO Precompute elements
 // - Piece moves, including sliding pieces (start with rays for simplicity, transition to magic bitboards if required)
 use crate::bitboard::Bitboard;
 use crate::color::Color::{self, *};
+use crate::sliders::SliderBackend;
 use crate::square::{Direction, Square};
 
-static IS_INIT: OnceLock<bool> = OnceLock::new();
+// Everything is built at compile time; the magic tables come from `build.rs`.
+static BB_RAYS: [[Bitboard; 8]; 64] = build_rays();
+static BB_LINES: [[Bitboard; 64]; 64] = build_lines();
+static BB_BETWEEN: [[Bitboard; 64]; 64] = build_between();
 
-static mut BB_RAYS: [[Bitboard; 8]; 64] = [[Bitboard::EMPTY; 8]; 64];
-static mut BB_LINES: [[Bitboard; 64]; 64] = [[Bitboard::EMPTY; 64]; 64];
+static ATT_KNIGHT: [Bitboard; 64] = build_knight_attacks();
+static ATT_KING: [Bitboard; 64] = build_king_attacks();
+static ATT_PAWNS: [[Bitboard; 2]; 64] = build_pawn_attacks();
 
-static mut ATT_KNIGHT: [Bitboard; 64] = [Bitboard::EMPTY; 64];
-static mut ATT_KING: [Bitboard; 64] = [Bitboard::EMPTY; 64];
-static mut ATT_PAWNS: [[Bitboard; 2]; 64] = [[Bitboard::EMPTY; 2]; 64];
+static PASSED_MASKS: [[Bitboard; 2]; 64] = build_passed_masks();
+static KING_ZONES: [[Bitboard; 2]; 64] = build_king_zones();
+static SHELTER_MASKS: [[Bitboard; 2]; 64] = build_shelter_masks();
 
-pub fn initialize() {
-    if IS_INIT.get() == Some(&true) {
-        return;
-    }
+const fn square_bb(square: usize) -> Bitboard {
+    Bitboard::new(1 << square)
+}
 
-    #[cfg(feature = "magic")]
-    magic::init_magics();
+// Indexed like `Direction` itself: the direction pointing the other way.
+const OPPOSITE: [usize; 8] = [1, 0, 3, 2, 7, 6, 5, 4];
 
-    // Setup for ray/line caching
-    for square in Bitboard::FULL {
-        for d in Direction::all() {
-            let mut s = Bitboard::from(square);
+const fn build_rays() -> [[Bitboard; 8]; 64] {
+    let mut rays = [[Bitboard::EMPTY; 8]; 64];
+    let dirs = Direction::all();
+
+    let mut square = 0;
+    while square < 64 {
+        let mut d = 0;
+        while d < 8 {
+            let mut s = square_bb(square);
             let mut r = Bitboard::EMPTY;
-            while bool::from(s) {
-                s <<= d;
-                r |= s;
+            while s.nonzero() {
+                s = s.shift(dirs[d]);
+                r = r.bitor(s);
             }
-            unsafe { BB_RAYS[square as usize][d as usize] = r };
+            rays[square][dirs[d] as usize] = r;
+            d += 1;
         }
+        square += 1;
+    }
 
-        for other in Bitboard::FULL {
-            // If it's not on the same line OR the entry is nonzero, we can continue forward.
-            if !square.same_line(other)
-                || bool::from(unsafe { BB_LINES[square as usize][other as usize] })
-            {
-                continue;
+    rays
+}
+
+// The whole line through two squares (edge to edge, both included), or nothing if they aren't
+// on one.
+const fn build_lines() -> [[Bitboard; 64]; 64] {
+    let rays = build_rays();
+    let mut lines = [[Bitboard::EMPTY; 64]; 64];
+
+    let mut a = 0;
+    while a < 64 {
+        let mut d = 0;
+        while d < 8 {
+            let line = rays[a][d].bitor(rays[a][OPPOSITE[d]]).bitor(square_bb(a));
+            let mut b = 0;
+            while b < 64 {
+                if rays[a][d].bitand(square_bb(b)).nonzero() {
+                    lines[a][b] = line;
+                }
+                b += 1;
             }
+            d += 1;
+        }
+        a += 1;
+    }
+
+    lines
+}
 
-            // SAFETY: We know they are on the same line, so it cannot be `None`.
-            let a = unsafe { square.dir_to(other).unwrap_unchecked() };
-            let b = unsafe { other.dir_to(square).unwrap_unchecked() };
+// The squares strictly between two squares on a line, or nothing if they aren't on one (or are
+// next to each other).
+const fn build_between() -> [[Bitboard; 64]; 64] {
+    let dirs = Direction::all();
+    let mut between = [[Bitboard::EMPTY; 64]; 64];
 
-            unsafe {
-                let line = BB_RAYS[square as usize][a as usize]
-                    | BB_RAYS[square as usize][b as usize]
-                    | Bitboard::from(square);
-                BB_LINES[square as usize][other as usize] = line;
-                BB_LINES[other as usize][square as usize] = line;
+    let mut a = 0;
+    while a < 64 {
+        let mut d = 0;
+        while d < 8 {
+            let mut s = square_bb(a).shift(dirs[d]);
+            let mut passed = Bitboard::EMPTY;
+            while s.nonzero() {
+                between[a][s.into_inner().trailing_zeros() as usize] = passed;
+                passed = passed.bitor(s);
+                s = s.shift(dirs[d]);
             }
+            d += 1;
         }
+        a += 1;
     }
 
-    // Setup for king + pawn moves
-    for square in Bitboard::FULL {
-        // Pawns first
-        let s = Bitboard::from(square);
-        let sides = (s << Direction::West) | (s << Direction::East);
+    between
+}
 
-        unsafe {
-            ATT_PAWNS[square as usize][White as usize] = sides << Direction::North;
-            ATT_PAWNS[square as usize][Black as usize] = sides << Direction::South;
+const fn build_pawn_attacks() -> [[Bitboard; 2]; 64] {
+    let mut attacks = [[Bitboard::EMPTY; 2]; 64];
+    let mut square = 0;
+    while square < 64 {
+        attacks[square][White as usize] = pawn_attacks_bb(square_bb(square), White);
+        attacks[square][Black as usize] = pawn_attacks_bb(square_bb(square), Black);
+        square += 1;
+    }
+    attacks
+}
+
+const fn build_king_attacks() -> [Bitboard; 64] {
+    let mut attacks = [Bitboard::EMPTY; 64];
+    let dirs = Direction::all();
+    let mut square = 0;
+    while square < 64 {
+        let mut d = 0;
+        while d < 8 {
+            attacks[square] = attacks[square].bitor(square_bb(square).shift(dirs[d]));
+            d += 1;
         }
+        square += 1;
+    }
+    attacks
+}
 
-        // Then use those to generate kings
-        unsafe {
-            ATT_KING[square as usize] = ATT_PAWNS[square as usize][White as usize]
-                | ATT_PAWNS[square as usize][Black as usize]
-                | sides
-                | (s << Direction::North)
-                | (s << Direction::South);
+const fn build_knight_attacks() -> [Bitboard; 64] {
+    use Direction::*;
+
+    let mut attacks = [Bitboard::EMPTY; 64];
+    let mut square = 0;
+    while square < 64 {
+        let s = square_bb(square);
+        // Two steps one way, one step to the side.
+        let jumps = [
+            s.shift(North).shift(North).shift(East),
+            s.shift(North).shift(North).shift(West),
+            s.shift(South).shift(South).shift(East),
+            s.shift(South).shift(South).shift(West),
+            s.shift(East).shift(East).shift(North),
+            s.shift(East).shift(East).shift(South),
+            s.shift(West).shift(West).shift(North),
+            s.shift(West).shift(West).shift(South),
+        ];
+
+        let mut i = 0;
+        while i < 8 {
+            attacks[square] = attacks[square].bitor(jumps[i]);
+            i += 1;
         }
+        square += 1;
+    }
+    attacks
+}
 
-        // Now, knight moves
-        for dir in [Direction::North, Direction::South] {
-            let dde = s << dir << dir << Direction::East;
-            let ddw = s << dir << dir << Direction::West;
-            let dee = (s << dir << Direction::East) << Direction::East;
-            let dww = (s << dir << Direction::West) << Direction::West;
+// Passed pawn masks are the front span plus its neighbouring files.
+const fn build_passed_masks() -> [[Bitboard; 2]; 64] {
+    let rays = build_rays();
+    let mut masks = [[Bitboard::EMPTY; 2]; 64];
 
-            unsafe {
-                ATT_KNIGHT[square as usize] |= dde | ddw | dee | dww;
-            }
+    let mut square = 0;
+    while square < 64 {
+        let colors = [White, Black];
+        let mut c = 0;
+        while c < 2 {
+            let span = rays[square][colors[c].forward() as usize];
+            masks[square][colors[c] as usize] = span
+                .bitor(span.shift(Direction::East))
+                .bitor(span.shift(Direction::West));
+            c += 1;
         }
+        square += 1;
+    }
+    masks
+}
+
+// The king's square and its neighbours, plus one more rank in front of those.
+const fn build_king_zones() -> [[Bitboard; 2]; 64] {
+    let kings = build_king_attacks();
+    let mut zones = [[Bitboard::EMPTY; 2]; 64];
+
+    let mut square = 0;
+    while square < 64 {
+        let ring = kings[square].bitor(square_bb(square));
+        zones[square][White as usize] = ring.bitor(ring.shift(White.forward()));
+        zones[square][Black as usize] = ring.bitor(ring.shift(Black.forward()));
+        square += 1;
     }
+    zones
+}
+
+// The two ranks in front of the king, on its own file and the ones beside it.
+const fn build_shelter_masks() -> [[Bitboard; 2]; 64] {
+    let mut masks = [[Bitboard::EMPTY; 2]; 64];
 
-    IS_INIT.set(true).unwrap();
+    let mut square = 0;
+    while square < 64 {
+        let s = square_bb(square);
+        let files = s
+            .bitor(s.shift(Direction::East))
+            .bitor(s.shift(Direction::West));
+        let colors = [White, Black];
+        let mut c = 0;
+        while c < 2 {
+            let up = colors[c].forward();
+            let first = files.shift(up);
+            masks[square][colors[c] as usize] = first.bitor(first.shift(up));
+            c += 1;
+        }
+        square += 1;
+    }
+    masks
 }
 
 // TODO Maybe store in a module not named `precompute`?
 #[cfg_attr(feature = "inline", inline)]
 pub(crate) fn ray(square: Square, dir: Direction) -> Bitboard {
-    unsafe { BB_RAYS[square as usize][dir as usize] }
+    BB_RAYS[square as usize][dir as usize]
 }
+// The whole line through `a` and `b`, edge to edge and including both, or empty if they don't
+// share a rank, file or diagonal.
 #[cfg_attr(feature = "inline", inline)]
-pub(crate) fn line(a: Square, b: Square) -> Bitboard {
-    unsafe { BB_LINES[a as usize][b as usize] }
+pub fn line(a: Square, b: Square) -> Bitboard {
+    BB_LINES[a as usize][b as usize]
+}
+// Just the squares between `a` and `b`, neither included; empty off a line and for neighbours.
+#[cfg_attr(feature = "inline", inline)]
+pub fn between(a: Square, b: Square) -> Bitboard {
+    BB_BETWEEN[a as usize][b as usize]
 }
 
 #[cfg_attr(feature = "inline", inline)]
 pub(crate) fn pawn_attacks(square: Square, color: Color) -> Bitboard {
-    unsafe { ATT_PAWNS[square as usize][color as usize] }
+    ATT_PAWNS[square as usize][color as usize]
+}
+// Every square in front of `square` on its file, from `color`'s point of view.
+#[cfg_attr(feature = "inline", inline)]
+pub fn front_span(square: Square, color: Color) -> Bitboard {
+    ray(square, color.forward())
+}
+#[cfg_attr(feature = "inline", inline)]
+pub fn passed_pawn_mask(square: Square, color: Color) -> Bitboard {
+    PASSED_MASKS[square as usize][color as usize]
+}
+
+// King safety. The zone is where attacks on the king are counted; the shelter is where its own
+// pawns should stand, and the storm mask where enemy pawns coming at it would be.
+#[cfg_attr(feature = "inline", inline)]
+pub fn king_zone(square: Square, color: Color) -> Bitboard {
+    KING_ZONES[square as usize][color as usize]
+}
+#[cfg_attr(feature = "inline", inline)]
+pub fn shelter_mask(square: Square, color: Color) -> Bitboard {
+    SHELTER_MASKS[square as usize][color as usize]
+}
+// Everything in front of the king on its three files: the same squares as a passed pawn mask.
+#[cfg_attr(feature = "inline", inline)]
+pub fn storm_mask(square: Square, color: Color) -> Bitboard {
+    PASSED_MASKS[square as usize][color as usize]
+}
+
+// Set-wise version of the above, for when a whole group of pawns is being looked at.
+#[cfg_attr(feature = "inline", inline)]
+pub const fn pawn_attacks_bb(pawns: Bitboard, color: Color) -> Bitboard {
+    let up = pawns.shift(color.forward());
+    up.shift(Direction::East).bitor(up.shift(Direction::West))
 }
 #[cfg_attr(feature = "inline", inline)]
 pub(crate) fn knight_attacks(square: Square) -> Bitboard {
-    unsafe { ATT_KNIGHT[square as usize] }
+    ATT_KNIGHT[square as usize]
 }
 #[cfg_attr(feature = "inline", inline)]
 pub(crate) fn king_attacks(square: Square) -> Bitboard {
-    unsafe { ATT_KING[square as usize] }
+    ATT_KING[square as usize]
 }
 
-#[cfg(not(feature = "magic"))]
+// Whichever backend is fastest on this machine; see `SliderBackend::active`.
 #[cfg_attr(feature = "inline", inline)]
 pub(crate) fn bishop_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
-    sliders(square, occupancy, &Direction::diagonal())
+    SliderBackend::active().bishop_attacks(square, occupancy)
 }
-#[cfg(not(feature = "magic"))]
 #[cfg_attr(feature = "inline", inline)]
 pub(crate) fn rook_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
-    sliders(square, occupancy, &Direction::orthogonal())
+    SliderBackend::active().rook_attacks(square, occupancy)
 }
-#[cfg(not(feature = "magic"))]
 #[cfg_attr(feature = "inline", inline)]
 pub(crate) fn queen_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
-    sliders(square, occupancy, &Direction::all())
+    bishop_attacks(square, occupancy) | rook_attacks(square, occupancy)
 }
 
-#[cfg(not(feature = "magic"))]
-fn sliders(square: Square, occupancy: Bitboard, dirs: &[Direction]) -> Bitboard {
+// The plain ray walker, with no tables beyond the rays: the reference the others are checked
+// against.
+pub(crate) fn ray_attacks(square: Square, occupancy: Bitboard, dirs: &[Direction]) -> Bitboard {
     let mut rv = Bitboard::EMPTY;
 
     for &dir in dirs {
@@ -157,18 +313,81 @@ fn sliders(square: Square, occupancy: Bitboard, dirs: &[Direction]) -> Bitboard
     rv
 }
 
-#[cfg(feature = "magic")]
-#[cfg_attr(feature = "inline", inline)]
-pub(crate) fn bishop_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
-    magic::bishop_attacks(square, occupancy)
-}
-#[cfg(feature = "magic")]
-#[cfg_attr(feature = "inline", inline)]
-pub(crate) fn rook_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
-    magic::rook_attacks(square, occupancy)
-}
-#[cfg(feature = "magic")]
-#[cfg_attr(feature = "inline", inline)]
-pub(crate) fn queen_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
-    magic::bishop_attacks(square, occupancy) | magic::rook_attacks(square, occupancy)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bb;
+    use Square::*;
+
+    #[test]
+    fn pawn_attacks_bb_matches_table() {
+        let pawns = bb![A2, D4, H7, E8];
+        for color in [White, Black] {
+            let mut expected = Bitboard::EMPTY;
+            for p in pawns {
+                expected |= pawn_attacks(p, color);
+            }
+            assert_eq!(pawn_attacks_bb(pawns, color), expected);
+        }
+
+        assert_eq!(pawn_attacks_bb(bb![A2, H2], White), bb![B3, G3]);
+    }
+
+    #[test]
+    fn const_tables() {
+        assert_eq!(knight_attacks(A1), bb![B3, C2]);
+        assert_eq!(knight_attacks(E4).popcount(), 8);
+        assert_eq!(king_attacks(H8), bb![G8, G7, H7]);
+        assert_eq!(pawn_attacks(E4, Black), bb![D3, F3]);
+
+        assert_eq!(ray(C3, Direction::SouthWest), bb![B2, A1]);
+        assert_eq!(line(B2, D4), line(H8, A1));
+        assert!(line(B2, D4).has(A1) && line(B2, D4).has(H8));
+        assert_eq!(line(B2, C4), Bitboard::EMPTY);
+        assert_eq!(line(E1, E8), Bitboard::from(crate::square::File::E));
+
+        assert_eq!(between(A1, D4), bb![B2, C3]);
+        assert_eq!(between(D4, A1), bb![B2, C3]);
+        assert_eq!(between(E1, E2), Bitboard::EMPTY);
+        assert_eq!(between(B2, C4), Bitboard::EMPTY);
+        assert_eq!(between(A3, A3), Bitboard::EMPTY);
+        for a in Square::iter() {
+            for b in Square::iter() {
+                let expected = match a.dir_to(b) {
+                    Some(dir) => ray(a, dir) & ray(b, !dir),
+                    None => Bitboard::EMPTY,
+                };
+                assert_eq!(between(a, b), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn passed_pawn_masks() {
+        assert_eq!(front_span(E6, White), bb![E7, E8]);
+        assert_eq!(front_span(E3, Black), bb![E2, E1]);
+        assert_eq!(passed_pawn_mask(A6, White), bb![A7, A8, B7, B8]);
+        assert_eq!(passed_pawn_mask(H3, Black), bb![H2, H1, G2, G1]);
+    }
+
+    #[test]
+    fn king_safety_masks() {
+        assert_eq!(
+            king_zone(G1, White),
+            bb![F1, G1, H1, F2, G2, H2, F3, G3, H3]
+        );
+        assert_eq!(
+            king_zone(E8, Black),
+            bb![D8, E8, F8, D7, E7, F7, D6, E6, F6]
+        );
+        assert_eq!(king_zone(E4, White).popcount(), 12);
+        assert_eq!(king_zone(A8, White), bb![A8, B8, A7, B7]);
+
+        assert_eq!(shelter_mask(G1, White), bb![F2, G2, H2, F3, G3, H3]);
+        assert_eq!(shelter_mask(B8, Black), bb![A7, B7, C7, A6, B6, C6]);
+        assert_eq!(shelter_mask(E8, White), Bitboard::EMPTY);
+
+        assert_eq!(storm_mask(G1, White).popcount(), 3 * 7);
+        assert!((shelter_mask(C1, White) & !storm_mask(C1, White)).zero());
+    }
 }
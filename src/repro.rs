@@ -0,0 +1,120 @@
+use std::cell::Cell;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::info::engine_info;
+use crate::position::Position;
+use crate::rng;
+
+// Crash reproducers: when a strict check fails, everything needed to replay the failure (the
+// position, how it was reached, the build and the random seeds) is written to a file before the
+// panic, as a rare movegen failure is next to impossible to track down from the panic message.
+//
+// Files go to `$FCPW_REPRO_DIR`, or the system's temporary directory.
+
+thread_local! {
+    // Set while a reproducer is being put together, as that replays moves and so can fail a
+    // strict check of its own.
+    static DUMPING: Cell<bool> = const { Cell::new(false) };
+}
+
+// The reproducer bundle for `pos` as text: one `key: value` line each.
+pub fn reproducer(pos: &Position, message: &str) -> String {
+    let guarded = |f: &dyn Fn() -> String| {
+        panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| "unavailable".to_string())
+    };
+
+    // Undoing every move gives the start position; the moves come out last first.
+    let history = || {
+        let mut start = pos.clone();
+        let mut moves = Vec::new();
+        while let Some(m) = start.undo() {
+            moves.push(m.to_string());
+        }
+        moves.reverse();
+        format!("start: {}\nmoves: {}", start.to_fen(), moves.join(" "))
+    };
+
+    // The master seed, and every stream drawn from it so far.
+    let seed = match rng::seed() {
+        Some(seed) => seed.to_string(),
+        None => "default".to_string(),
+    };
+    let streams: Vec<String> = rng::seeds_used()
+        .iter()
+        .map(|(purpose, seed)| format!("{}={}", purpose, seed))
+        .collect();
+
+    let was_dumping = DUMPING.replace(true);
+    let bundle = format!(
+        "message: {}\nbuild: {}\nseed: {}\nstreams: {}\nfen: {}\n{}\n",
+        message,
+        engine_info(),
+        seed,
+        streams.join(" "),
+        guarded(&|| pos.to_fen()),
+        guarded(&history),
+    );
+    DUMPING.set(was_dumping);
+
+    bundle
+}
+
+// Writes the reproducer for `pos` to a new file, and returns its path.
+pub fn dump(pos: &Position, message: &str) -> std::io::Result<PathBuf> {
+    let dir = std::env::var_os("FCPW_REPRO_DIR").map_or_else(std::env::temp_dir, PathBuf::from);
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let path = dir.join(format!("fcpw-repro-{}-{}.txt", std::process::id(), millis));
+
+    fs::write(&path, reproducer(pos, message))?;
+    Ok(path)
+}
+
+// Where every strict check that knows its position ends up.
+#[cold]
+pub(crate) fn strict_failure(pos: &Position, message: &str) -> ! {
+    if !DUMPING.get() {
+        match dump(pos, message) {
+            Ok(path) => eprintln!("reproducer written to {}", path.display()),
+            Err(e) => eprintln!("could not write reproducer: {}", e),
+        }
+    }
+    panic!("strict check failed: {}", message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::Move;
+    use crate::square::Square::*;
+
+    #[test]
+    fn bundle_replays_the_game() {
+        let mut pos = Position::default();
+        pos.make_moves(&[Move::new(E2, E4), Move::new(C7, C5), Move::new(G1, F3)])
+            .unwrap();
+
+        let bundle = reproducer(&pos, "testing");
+        assert!(bundle.starts_with("message: testing\n"));
+        assert!(bundle.contains(&format!("build: {}\n", engine_info())));
+        assert!(bundle.contains(&format!("fen: {}\n", pos.to_fen())));
+        assert!(bundle.contains(&format!("start: {}\n", Position::STARTING_FEN)));
+        assert!(bundle.contains("moves: e2e4 c7c5 g1f3\n"));
+
+        let path = dump(&pos, "testing").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), bundle);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "strict_checks")]
+    #[should_panic(expected = "strict check failed")]
+    fn strict_failures_panic() {
+        let mut pos = Position::default();
+        pos.make_move(Move::new(E2, E5));
+    }
+}
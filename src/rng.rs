@@ -0,0 +1,142 @@
+use std::sync::Mutex;
+
+// The one source of randomness in the library. Everything random draws from its own named
+// stream, seeded from a single master seed, so a run is reproduced by its master seed alone;
+// the seeds actually handed out are recorded for crash reports and logs.
+
+// xorshift64*, https://vigna.di.unimi.it/ftp/papers/xorshift.pdf
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    // Any seed will do; 0 (which xorshift can't leave) is swapped for a fixed one.
+    pub const fn new(seed: u64) -> Self {
+        Self(if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        })
+    }
+
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+
+        self.0.wrapping_mul(2685821657736338717)
+    }
+
+    // About one bit in eight set, the sort of number that makes a good magic.
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn sparse_u64(&mut self) -> u64 {
+        self.next_u64() & self.next_u64() & self.next_u64()
+    }
+
+    // Uniform in `0..n`; `n` must not be 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        assert_ne!(n, 0, "Rng::below: empty range");
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+
+    // Fisher-Yates.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+// Used when no master seed has been set, so runs are repeatable by default.
+const DEFAULT_SEED: u64 = 0x00F0_CB77_5EED_0001;
+
+static SEED: Mutex<Option<u64>> = Mutex::new(None);
+static USED: Mutex<Vec<(String, u64)>> = Mutex::new(Vec::new());
+
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Sets the master seed for every stream created from here on.
+pub fn set_seed(seed: u64) {
+    *lock(&SEED) = Some(seed);
+}
+// The master seed, if one was set.
+pub fn seed() -> Option<u64> {
+    *lock(&SEED)
+}
+
+// A generator for one purpose (`"book"`, `"skill"`, `"datagen"`, ...), seeded from the master
+// seed and the purpose, so streams for different purposes don't mirror each other.
+pub fn stream(purpose: &str) -> Rng {
+    let master = seed().unwrap_or(DEFAULT_SEED);
+    // FNV-1a of the purpose, mixed into the master seed.
+    let name = purpose.bytes().fold(0xCBF2_9CE4_8422_2325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100_0000_01B3)
+    });
+    let mut mix = Rng::new(master ^ name);
+    let seed = mix.next_u64();
+
+    record(purpose, seed);
+    Rng::new(seed)
+}
+
+fn record(purpose: &str, seed: u64) {
+    let mut used = lock(&USED);
+    match used.iter_mut().find(|(p, _)| p == purpose) {
+        Some(entry) => entry.1 = seed,
+        None => used.push((purpose.to_string(), seed)),
+    }
+}
+
+// Every stream handed out so far with the seed it got (the latest, for one asked for twice).
+// With `magic`, the seeds the tables were searched with at build time come first; those are
+// set with `FCPW_MAGIC_SEEDS` when building.
+pub fn seeds_used() -> Vec<(String, u64)> {
+    let mut used = Vec::new();
+    #[cfg(feature = "magic")]
+    for (rank, &seed) in crate::magic::SEEDS.iter().enumerate() {
+        used.push((format!("magic/{}", rank + 1), seed));
+    }
+    used.extend(lock(&USED).iter().cloned());
+    used
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_are_reproducible() {
+        let mut a = stream("test/a");
+        let mut b = stream("test/a");
+        let mut c = stream("test/c");
+        let (x, y, z) = (a.next_u64(), b.next_u64(), c.next_u64());
+        assert_eq!(x, y);
+        assert_ne!(x, z);
+
+        let used = seeds_used();
+        assert!(used.iter().any(|(p, _)| p == "test/a"));
+        assert!(used.iter().any(|(p, _)| p == "test/c"));
+    }
+
+    #[test]
+    fn ranges_and_shuffles() {
+        let mut rng = Rng::new(0);
+        let mut seen = [false; 6];
+        for _ in 0..200 {
+            seen[rng.below(6) as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+
+        let mut items: Vec<u32> = (0..20).collect();
+        Rng::new(7).shuffle(&mut items);
+        let mut again: Vec<u32> = (0..20).collect();
+        Rng::new(7).shuffle(&mut again);
+        assert_eq!(items, again);
+        assert_ne!(items, (0..20).collect::<Vec<_>>());
+        items.sort_unstable();
+        assert_eq!(items, (0..20).collect::<Vec<_>>());
+    }
+}
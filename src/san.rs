@@ -0,0 +1,253 @@
+use crate::movegen::{generate, Move, MoveKind};
+use crate::piece::PieceType;
+use crate::position::Position;
+use crate::square::{File, Rank, Square};
+
+impl Move {
+    // Standard Algebraic Notation for this move, which must be legal in `pos`.
+    pub fn to_san(self, pos: &Position) -> String {
+        let mut san = String::new();
+
+        let from = self.from();
+        let to = self.to();
+        let mover = pos
+            .piece_on(from)
+            .expect("Move::to_san: No piece on the `from` square");
+
+        if self.kind() == MoveKind::Castle {
+            san += if to.file() == File::G { "O-O" } else { "O-O-O" };
+        } else {
+            let capture = pos.is_capture(self);
+
+            if mover.kind() == PieceType::Pawn {
+                if capture {
+                    san.push(char::from(from.file()));
+                }
+            } else {
+                san.push(char::from(mover.kind()).to_ascii_uppercase());
+                san += &disambiguation(self, pos);
+            }
+
+            if capture {
+                san.push('x');
+            }
+            san += &to.to_string();
+
+            if let Some(promo) = self.get_promo() {
+                san.push('=');
+                san.push(char::from(promo).to_ascii_uppercase());
+            }
+        }
+
+        if pos.gives_check(self) {
+            let mut after = pos.detached();
+            after.make_move(self);
+            san.push(if generate::has_legal_move(&after) {
+                '+'
+            } else {
+                '#'
+            });
+        }
+
+        san
+    }
+
+    // Parses a SAN move against `pos`, giving back the one legal move it names.
+    // Check/mate markers and annotations are accepted but not verified, and over-specified
+    // moves (`Ng1f3`) or a missing `x` are tolerated.
+    pub fn from_san(san: &str, pos: &Position) -> Option<Self> {
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        let legal = generate::legal(pos);
+
+        if san == "O-O" || san == "0-0" || san == "O-O-O" || san == "0-0-0" {
+            let long = san.len() == 5;
+            return (&legal)
+                .into_iter()
+                .find(|m| m.kind() == MoveKind::Castle && (m.to().file() == File::C) == long);
+        }
+
+        let mut body = san.as_bytes();
+
+        let kind = match body.first()? {
+            b'N' => PieceType::Knight,
+            b'B' => PieceType::Bishop,
+            b'R' => PieceType::Rook,
+            b'Q' => PieceType::Queen,
+            b'K' => PieceType::King,
+            _ => PieceType::Pawn,
+        };
+        if kind != PieceType::Pawn {
+            body = &body[1..];
+        }
+
+        let mut promo = None;
+        if let Some(&last) = body.last() {
+            let p = match last {
+                b'N' => Some(PieceType::Knight),
+                b'B' => Some(PieceType::Bishop),
+                b'R' => Some(PieceType::Rook),
+                b'Q' => Some(PieceType::Queen),
+                _ => None,
+            };
+            if p.is_some() {
+                promo = p;
+                body = &body[..body.len() - 1];
+                if body.last() == Some(&b'=') {
+                    body = &body[..body.len() - 1];
+                }
+            }
+        }
+
+        if body.len() < 2 {
+            return None;
+        }
+        let to = Square::try_from(&body[body.len() - 2..]).ok()?;
+
+        let mut from_file = None;
+        let mut from_rank = None;
+        for &c in &body[..body.len() - 2] {
+            match c {
+                b'a'..=b'h' => from_file = Some(File::try_from(c - b'a').ok()?),
+                b'1'..=b'8' => from_rank = Some(Rank::try_from(c - b'1').ok()?),
+                b'x' | b'-' => (),
+                _ => return None,
+            }
+        }
+
+        let mut found = None;
+        for m in &legal {
+            let Some(p) = pos.piece_on(m.from()) else {
+                continue;
+            };
+            if p.kind() != kind
+                || m.to() != to
+                || m.kind() == MoveKind::Castle
+                || m.get_promo() != promo
+                || from_file.is_some_and(|f| m.from().file() != f)
+                || from_rank.is_some_and(|r| m.from().rank() != r)
+            {
+                continue;
+            }
+
+            if found.is_some() {
+                return None; // Ambiguous
+            }
+            found = Some(m);
+        }
+
+        found
+    }
+}
+
+// Just enough of the from-square to tell this move apart from the same piece type's other moves to `to`.
+fn disambiguation(mov: Move, pos: &Position) -> String {
+    let from = mov.from();
+    let kind = pos.piece_on(from).map(|p| p.kind());
+
+    let mut others = Vec::new();
+    for m in &generate::legal(pos) {
+        if m.to() == mov.to()
+            && m.from() != from
+            && pos.piece_on(m.from()).map(|p| p.kind()) == kind
+        {
+            others.push(m.from());
+        }
+    }
+
+    if others.is_empty() {
+        String::new()
+    } else if others.iter().all(|s| s.file() != from.file()) {
+        from.file().to_string()
+    } else if others.iter().all(|s| s.rank() != from.rank()) {
+        from.rank().to_string()
+    } else {
+        from.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use MoveKind::*;
+    use PieceType::*;
+    use Square::*;
+
+    fn round_trip(fen: &str, mov: Move, san: &str) {
+        let pos = Position::new_from_fen(fen);
+        assert_eq!(mov.to_san(&pos), san);
+        assert_eq!(Move::from_san(san, &pos), Some(mov));
+    }
+
+    #[test]
+    fn simple_moves() {
+        round_trip(Position::STARTING_FEN, Move::new(E2, E4), "e4");
+        round_trip(Position::STARTING_FEN, Move::new(G1, F3), "Nf3");
+        round_trip(Position::KIWIPETE_FEN, Move::new(E5, F7), "Nxf7");
+        round_trip(Position::KIWIPETE_FEN, Move::new(D5, E6), "dxe6");
+    }
+
+    #[test]
+    fn castling() {
+        round_trip(
+            Position::KIWIPETE_FEN,
+            Move::new_with_kind(E1, G1, Castle),
+            "O-O",
+        );
+        round_trip(
+            Position::KIWIPETE_FEN,
+            Move::new_with_kind(E1, C1, Castle),
+            "O-O-O",
+        );
+        let pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        assert_eq!(
+            Move::from_san("0-0", &pos),
+            Some(Move::new_with_kind(E1, G1, Castle))
+        );
+    }
+
+    #[test]
+    fn disambiguation() {
+        let fen = "4k3/8/8/8/8/8/4K3/R6R w - - 0 1";
+        round_trip(fen, Move::new(A1, D1), "Rad1");
+        round_trip(fen, Move::new(H1, D1), "Rhd1");
+
+        let fen = "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1";
+        round_trip(fen, Move::new(A1, A3), "R1a3");
+        round_trip(fen, Move::new(A5, A3), "R5a3");
+
+        let fen = "7k/8/8/8/2Q1Q3/8/2Q5/4K3 w - - 0 1";
+        round_trip(fen, Move::new(C4, D3), "Qc4d3");
+
+        let pos = Position::new_from_fen("4k3/8/8/8/8/8/4K3/R6R w - - 0 1");
+        assert_eq!(Move::from_san("Rd1", &pos), None);
+        assert_eq!(Move::from_san("Ra1d1", &pos), Some(Move::new(A1, D1)));
+    }
+
+    #[test]
+    fn promotion_and_check_markers() {
+        let fen = "8/4P1k1/8/8/8/8/8/4K3 w - - 0 1";
+        round_trip(fen, Move::new_with_kind(E7, E8, Promotion(Queen)), "e8=Q");
+        round_trip(fen, Move::new_with_kind(E7, E8, Promotion(Knight)), "e8=N+");
+        let pos = Position::new_from_fen(fen);
+        assert_eq!(
+            Move::from_san("e8Q", &pos),
+            Some(Move::new_with_kind(E7, E8, Promotion(Queen)))
+        );
+        assert_eq!(Move::from_san("e8", &pos), None);
+
+        round_trip(
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            Move::new(A1, A8),
+            "Ra8#",
+        );
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let pos = Position::default();
+        assert_eq!(Move::from_san("", &pos), None);
+        assert_eq!(Move::from_san("e5", &pos), None);
+        assert_eq!(Move::from_san("Zf3", &pos), None);
+        assert_eq!(Move::from_san("O-O", &pos), None);
+    }
+}
@@ -0,0 +1,677 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::eval::evaluate;
+use crate::movegen::{generate, GenOptions, Move, MoveList, Promotions};
+use crate::ordering::MoveOrderer;
+use crate::position::Position;
+use crate::time::TimeManager;
+use crate::tt::{Bound, TranspositionTable, TtEntry};
+
+pub const INFINITY: i32 = 32001;
+pub const MATE: i32 = 32000;
+pub const MAX_PLY: i32 = 128;
+// The clock and the stop flag are only looked at once in this many nodes.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+// Scores at least this far from zero are mates: `MATE` less the plies to it.
+pub const MATE_BOUND: i32 = MATE - MAX_PLY;
+
+// The score for mating at `ply` plies from the root, and for being mated there.
+#[cfg_attr(feature = "inline", inline)]
+pub const fn mate_in(ply: i32) -> i32 {
+    MATE - ply
+}
+#[cfg_attr(feature = "inline", inline)]
+pub const fn mated_in(ply: i32) -> i32 {
+    -MATE + ply
+}
+#[cfg_attr(feature = "inline", inline)]
+pub const fn is_mate(score: i32) -> bool {
+    score.abs() >= MATE_BOUND
+}
+
+// A search score as a GUI wants it: centipawns, or a mate some moves (not plies) away. Mates
+// for the side to move are positive, mates against it negative; `Mate(0)` means it is already
+// checkmated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    Cp(i32),
+    Mate(i32),
+}
+
+impl Score {
+    pub const fn from_raw(score: i32) -> Self {
+        if !is_mate(score) {
+            Self::Cp(score)
+        } else if score > 0 {
+            // Mate in 1 is one ply away, mate in 2 three plies...
+            Self::Mate((MATE - score + 1) / 2)
+        } else {
+            // ...but being mated in 1 is two plies away: our move, then theirs.
+            Self::Mate(-(MATE + score) / 2)
+        }
+    }
+
+    // Back to the search's scale, with the mate as close as the move count allows.
+    pub const fn to_raw(self) -> i32 {
+        match self {
+            Self::Cp(cp) => cp,
+            Self::Mate(n) if n > 0 => mate_in(2 * n - 1),
+            Self::Mate(n) => mated_in(-2 * n),
+        }
+    }
+
+    pub const fn is_mate(self) -> bool {
+        matches!(self, Self::Mate(_))
+    }
+}
+
+impl From<i32> for Score {
+    fn from(score: i32) -> Self {
+        Self::from_raw(score)
+    }
+}
+
+// The UCI form, as in `info ... score mate -2`.
+impl std::fmt::Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cp(cp) => write!(f, "cp {}", cp),
+            Self::Mate(n) => write!(f, "mate {}", n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+}
+
+// When a position repeated inside the search tree is scored as a draw. Repeats of positions
+// from before the root always need the full three occurrences, as the rules say.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepetitionPolicy {
+    // The first repeat of a position since the root is a draw, as either side could keep repeating it.
+    #[default]
+    FirstRepetition,
+    // Only an actual threefold repetition is a draw.
+    Threefold,
+}
+
+// Flags for steering a search from another thread; clones share them. A stop is noticed within
+// a few thousand nodes, though never during the first iteration. While pondering, the time
+// limits are ignored; after `ponderhit` they count from when the search started.
+#[derive(Debug, Clone, Default)]
+pub struct Signals {
+    stop: Arc<AtomicBool>,
+    pondering: Arc<AtomicBool>,
+}
+
+impl Signals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn pondering() -> Self {
+        let signals = Self::default();
+        signals.pondering.store(true, Ordering::Relaxed);
+        signals
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::Relaxed);
+    }
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Searcher {
+    nodes: u64,
+    repetition: RepetitionPolicy,
+    orderer: MoveOrderer,
+    time: TimeManager,
+    signals: Signals,
+    node_limit: Option<u64>,
+    tt: Option<Arc<TranspositionTable>>,
+    // Whether the time may cut the current iteration short (not the first), and whether it has:
+    // once stopped, everything searched since is thrown away.
+    stoppable: bool,
+    stopped: bool,
+
+    // Keys of the positions played before the root, oldest first, then of the line currently
+    // being searched (root included).
+    history: Vec<u64>,
+    path: Vec<u64>,
+}
+
+impl Searcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_repetition_policy(&mut self, policy: RepetitionPolicy) {
+        self.repetition = policy;
+    }
+
+    // Limits for the searches to come. They still only go as deep as asked, but stop early
+    // when the time runs out; the first iteration is always finished, so there is a move.
+    pub fn set_time(&mut self, time: TimeManager) {
+        self.time = time;
+    }
+
+    // Like the hard time limit: a search stops within a few thousand nodes of `nodes`, but
+    // always finishes its first iteration. Counted afresh for each search.
+    pub fn set_node_limit(&mut self, nodes: Option<u64>) {
+        self.node_limit = nodes;
+    }
+
+    pub fn set_signals(&mut self, signals: Signals) {
+        self.signals = signals;
+    }
+
+    // A table to share what is found with later searches, and with other threads searching at
+    // the same time.
+    pub fn set_tt(&mut self, tt: Arc<TranspositionTable>) {
+        self.tt = Some(tt);
+    }
+
+    // The game so far: `moves` played from `start` lead up to the position that will be searched.
+    pub fn set_history(&mut self, start: &Position, moves: &[Move]) {
+        self.history.clear();
+
+        let mut pos = start.detached();
+        for &m in moves {
+            self.history.push(pos.key());
+            pos.make_move(m);
+        }
+    }
+
+    // Iterative deepening up to `depth`. The result is from the side to move's point of view,
+    // and `best_move` is only `None` if there were no legal moves at all.
+    pub fn search(&mut self, pos: &mut Position, depth: u32) -> SearchResult {
+        self.nodes = 0;
+        self.stopped = false;
+        self.orderer.clear();
+
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            nodes: 0,
+        };
+
+        for d in 1..=depth.max(1) {
+            self.stoppable = d > 1;
+            if d > 1 && !self.can_continue() {
+                break;
+            }
+            let (best_move, score) = match self.root(pos, d, result.best_move, 1)[..] {
+                _ if self.stopped => break,
+                [(m, score), ..] => (Some(m), score),
+                [] => (None, self.no_moves_score(pos, 0)),
+            };
+            result = SearchResult {
+                best_move,
+                score,
+                depth: d,
+                nodes: self.nodes,
+            };
+
+            if best_move.is_none() {
+                break;
+            }
+        }
+
+        result
+    }
+
+    // Like `search`, but scores the best `lines` root moves exactly rather than just the best one
+    // (MultiPV). Best first; fewer than `lines` only if there are fewer legal moves.
+    pub fn search_lines(
+        &mut self,
+        pos: &mut Position,
+        depth: u32,
+        lines: usize,
+    ) -> Vec<(Move, i32)> {
+        self.nodes = 0;
+        self.stopped = false;
+        self.orderer.clear();
+
+        let mut best = Vec::new();
+        for d in 1..=depth.max(1) {
+            self.stoppable = d > 1;
+            if d > 1 && !self.can_continue() {
+                break;
+            }
+            let first = best.first().map(|&(m, _)| m);
+            let lines = self.root(pos, d, first, lines.max(1));
+            if self.stopped {
+                break;
+            }
+            best = lines;
+        }
+        best
+    }
+
+    pub const fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    // The `lines` best moves at `depth`, best first. Each move only has to beat the worst of
+    // those kept so far, so everything kept has an exact score and the rest are cut off early.
+    fn root(
+        &mut self,
+        pos: &mut Position,
+        depth: u32,
+        first: Option<Move>,
+        lines: usize,
+    ) -> Vec<(Move, i32)> {
+        self.path.clear();
+        self.path.push(pos.key());
+
+        let moves = generate::legal(pos);
+        let mut best: Vec<(Move, i32)> = Vec::with_capacity(lines + 1);
+
+        // The best move from the last iteration goes first, to get a good bound early.
+        let mut moves = self.orderer.scored(pos, &moves, first, 0);
+
+        while let Some((m, _)) = moves.pick_best() {
+            let alpha = if best.len() < lines {
+                -INFINITY
+            } else {
+                best[lines - 1].1
+            };
+
+            pos.make_move(m);
+            self.path.push(pos.key());
+            let score = -self.negamax(pos, depth - 1, 1, -INFINITY, -alpha);
+            self.path.pop();
+            pos.unmake_move(m);
+            if self.stopped {
+                break;
+            }
+
+            if score > alpha || best.len() < lines {
+                let at = best.partition_point(|&(_, s)| s >= score);
+                best.insert(at, (m, score));
+                best.truncate(lines);
+            }
+        }
+
+        // Whatever has been kept is exact, so the best of it can go in the table as it is.
+        if let Some(&(m, score)) = best.first().filter(|_| !self.stopped) {
+            self.store(pos.key(), Some(m), score, depth, Bound::Exact, 0);
+        }
+        best
+    }
+
+    fn negamax(
+        &mut self,
+        pos: &mut Position,
+        depth: u32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.stoppable && self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) && self.must_stop() {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
+
+        if pos.rule50() >= 100 || self.is_repetition(pos.repetition_plies()) {
+            return 0;
+        }
+        if depth == 0 || ply >= MAX_PLY {
+            return evaluate(pos);
+        }
+
+        // A deep enough result for this position that settles it against this window ends the
+        // search here; any stored move is tried first regardless.
+        let key = pos.key();
+        let mut hash_move = None;
+        if let Some(entry) = self.tt.as_ref().and_then(|tt| tt.probe(key, ply)) {
+            hash_move = entry.best_move;
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return entry.score.clamp(alpha, beta),
+                    Bound::Lower if entry.score >= beta => return beta,
+                    Bound::Upper if entry.score <= alpha => return alpha,
+                    _ => (),
+                }
+            }
+        }
+
+        // Underpromotions are pruned below the root, apart from knight promotions with check.
+        let mut moves: MoveList = MoveList::new();
+        let options = GenOptions {
+            promotions: Promotions::QueenAndKnightChecks,
+        };
+        generate::legal_with(pos, options, &mut moves);
+        if moves.is_empty() {
+            return self.no_moves_score(pos, ply);
+        }
+        let mut moves = self.orderer.scored(pos, &moves, hash_move, ply as usize);
+
+        let mut best_move = None;
+        while let Some((m, _)) = moves.pick_best() {
+            // The child probes the table first thing, unless it's a leaf.
+            if let Some(tt) = self.tt.as_ref().filter(|_| depth > 1) {
+                tt.prefetch(pos.key_after(m));
+            }
+            pos.make_move(m);
+            self.path.push(pos.key());
+            let score = -self.negamax(pos, depth - 1, ply + 1, -beta, -alpha);
+            self.path.pop();
+            pos.unmake_move(m);
+
+            if score >= beta {
+                if !pos.is_capture(m) && !m.is_promo() {
+                    self.orderer.store_killer(m, ply as usize);
+                    self.orderer.update_history(pos.to_move(), m, depth);
+                }
+                self.store(key, Some(m), beta, depth, Bound::Lower, ply);
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = Some(m);
+            }
+        }
+
+        let bound = if best_move.is_some() {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.store(key, best_move, alpha, depth, bound, ply);
+        alpha
+    }
+
+    // Scores from a search cut short by the clock are worthless, so they never go in.
+    fn store(
+        &self,
+        key: u64,
+        best_move: Option<Move>,
+        score: i32,
+        depth: u32,
+        bound: Bound,
+        ply: i32,
+    ) {
+        if let Some(tt) = self.tt.as_ref().filter(|_| !self.stopped) {
+            let entry = TtEntry {
+                best_move,
+                score,
+                depth,
+                bound,
+            };
+            tt.store(key, entry, ply);
+        }
+    }
+
+    // Whether the last position on the path is a draw by repetition. Only the last `window`
+    // plies (see `Position::repetition_plies`) can hold a repeat, and only every other one has the same side to move.
+    fn is_repetition(&self, window: i32) -> bool {
+        let Some(current) = self.path.last() else {
+            return false;
+        };
+        let root = self.history.len();
+        let newest = root + self.path.len() - 1;
+
+        let mut seen = 0;
+        for back in (4..=window.max(0) as usize).step_by(2) {
+            let Some(index) = newest.checked_sub(back) else {
+                break;
+            };
+            let earlier = match index.checked_sub(root) {
+                Some(i) => &self.path[i],
+                None => &self.history[index],
+            };
+
+            if earlier == current {
+                seen += 1;
+                if seen >= 2
+                    || (index >= root && self.repetition == RepetitionPolicy::FirstRepetition)
+                {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    fn must_stop(&self) -> bool {
+        self.signals.is_stopped()
+            || (!self.signals.is_pondering() && self.time.must_stop())
+            || self.node_limit.is_some_and(|limit| self.nodes >= limit)
+    }
+    // Between iterations: whether to start another one.
+    fn can_continue(&self) -> bool {
+        (self.signals.is_pondering() || self.time.can_continue())
+            && self.node_limit.is_none_or(|limit| self.nodes < limit)
+    }
+
+    fn no_moves_score(&self, pos: &Position, ply: i32) -> i32 {
+        if pos.in_check() {
+            mated_in(ply)
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::square::Square::*;
+
+    #[test]
+    fn finds_back_rank_mate() {
+        let mut pos = Position::new_from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let res = Searcher::new().search(&mut pos, 3);
+        assert_eq!(res.best_move, Some(Move::new(A1, A8)));
+        assert_eq!(res.score, MATE - 1);
+    }
+
+    #[test]
+    fn multiple_lines() {
+        // Taking the queen, by far the best, then anything that doesn't hang the rook.
+        let mut pos = Position::new_from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1");
+        let mut searcher = Searcher::new();
+        let lines = searcher.search_lines(&mut pos, 2, 3);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].0, Move::new(D2, D5));
+        assert!(lines[0].1 > lines[1].1 && lines[1].1 >= lines[2].1);
+
+        let best = searcher.search(&mut pos, 2);
+        assert_eq!((best.best_move, best.score), (Some(lines[0].0), lines[0].1));
+
+        // Asking for more lines than there are moves.
+        let mut pos = Position::new_from_fen("k7/8/2Q5/8/8/8/8/7K b - - 0 1");
+        let lines = Searcher::new().search_lines(&mut pos, 1, 5);
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[test]
+    fn stops_on_time() {
+        use crate::time::Limits;
+        use std::time::{Duration, Instant};
+
+        let limits = Limits {
+            movetime: Some(Duration::from_millis(60)),
+            ..Limits::default()
+        };
+        let mut searcher = Searcher::new();
+        let mut pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        let key = pos.key();
+        let started = Instant::now();
+        searcher.set_time(TimeManager::new(&limits, pos.to_move()));
+        let res = searcher.search(&mut pos, MAX_PLY as u32);
+
+        assert!(res.best_move.is_some());
+        assert!(res.depth >= 1 && res.depth < 10);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(pos.key(), key);
+
+        // Even with no time at all, the first iteration is finished.
+        searcher.set_time(TimeManager::new(
+            &Limits {
+                movetime: Some(Duration::ZERO),
+                ..limits
+            },
+            pos.to_move(),
+        ));
+        let res = searcher.search(&mut pos, 5);
+        assert_eq!(res.depth, 1);
+        assert!(res.best_move.is_some());
+    }
+
+    #[test]
+    fn stops_on_nodes() {
+        let mut searcher = Searcher::new();
+        let mut pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        searcher.set_node_limit(Some(20_000));
+        let res = searcher.search(&mut pos, MAX_PLY as u32);
+        assert!(res.best_move.is_some());
+        assert!(res.depth > 1 && res.depth < 10);
+        assert!(searcher.nodes() < 20_000 + TIME_CHECK_INTERVAL);
+
+        // As with time, the first iteration is always finished.
+        searcher.set_node_limit(Some(0));
+        let res = searcher.search(&mut pos, 5);
+        assert_eq!(res.depth, 1);
+        assert!(res.best_move.is_some());
+
+        searcher.set_node_limit(None);
+        assert_eq!(searcher.search(&mut pos, 3).depth, 3);
+    }
+
+    #[test]
+    fn mate_scores() {
+        assert_eq!(Score::from(35), Score::Cp(35));
+        assert_eq!(Score::from(-MATE_BOUND + 1), Score::Cp(-MATE_BOUND + 1));
+        assert_eq!(Score::from(mate_in(1)), Score::Mate(1));
+        assert_eq!(Score::from(mate_in(3)), Score::Mate(2));
+        assert_eq!(Score::from(mated_in(2)), Score::Mate(-1));
+        assert_eq!(Score::from(mated_in(4)), Score::Mate(-2));
+        assert_eq!(Score::from(mated_in(0)), Score::Mate(0));
+        for raw in [-125, mate_in(5), mated_in(6), mated_in(0)] {
+            assert_eq!(Score::from_raw(raw).to_raw(), raw);
+        }
+        assert_eq!(Score::Mate(-3).to_string(), "mate -3");
+        assert_eq!(Score::Cp(-40).to_string(), "cp -40");
+
+        // Kg8 is forced, and Qb8 mates: mated in one move, two plies down.
+        let mut pos = Position::new_from_fen("7k/8/6K1/8/8/8/8/1Q6 b - - 0 1");
+        let res = Searcher::new().search(&mut pos, 3);
+        assert_eq!(res.score, mated_in(2));
+        assert_eq!(Score::from(res.score), Score::Mate(-1));
+    }
+
+    #[test]
+    fn takes_hanging_queen() {
+        let mut pos = Position::new_from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1");
+        let res = Searcher::new().search(&mut pos, 2);
+        assert_eq!(res.best_move, Some(Move::new(D2, D5)));
+        assert!(res.score > 0);
+    }
+
+    // Keys of the start position and every position after it while both sides shuffle knights.
+    fn knight_shuffle(plies: usize) -> (Vec<u64>, i32) {
+        let mut pos = Position::default();
+        let cycle = [
+            Move::new(G1, F3),
+            Move::new(G8, F6),
+            Move::new(F3, G1),
+            Move::new(F6, G8),
+        ];
+
+        let mut keys = vec![pos.key()];
+        for m in cycle.iter().cycle().take(plies) {
+            pos.make_move(*m);
+            keys.push(pos.key());
+        }
+        (keys, pos.rule50())
+    }
+
+    #[test]
+    fn repetition_policies() {
+        // Start position seen twice, both times inside the tree.
+        let (keys, rule50) = knight_shuffle(4);
+        let mut searcher = Searcher::new();
+        searcher.path = keys.clone();
+        assert!(searcher.is_repetition(rule50));
+        searcher.set_repetition_policy(RepetitionPolicy::Threefold);
+        assert!(!searcher.is_repetition(rule50));
+
+        // Once before the root and once in the tree is just a repeat of the game, not a draw yet.
+        for policy in [
+            RepetitionPolicy::FirstRepetition,
+            RepetitionPolicy::Threefold,
+        ] {
+            let mut searcher = Searcher::new();
+            searcher.set_repetition_policy(policy);
+            searcher.history = keys[..2].to_vec();
+            searcher.path = keys[2..].to_vec();
+            assert!(!searcher.is_repetition(rule50));
+        }
+
+        // Twice before the root already, so the third time is always a draw.
+        let (keys, rule50) = knight_shuffle(8);
+        for policy in [
+            RepetitionPolicy::FirstRepetition,
+            RepetitionPolicy::Threefold,
+        ] {
+            let mut searcher = Searcher::new();
+            searcher.set_repetition_policy(policy);
+            searcher.history = keys[..8].to_vec();
+            searcher.path = keys[8..].to_vec();
+            assert!(searcher.is_repetition(rule50));
+
+            // An irreversible move in between means nothing before it can repeat.
+            assert!(!searcher.is_repetition(3));
+        }
+    }
+
+    #[test]
+    fn set_history_records_positions_before_root() {
+        let mut pos = Position::default();
+        let moves = [Move::new(G1, F3), Move::new(G8, F6)];
+        pos.make_moves(&moves).unwrap();
+
+        let mut searcher = Searcher::new();
+        searcher.set_history(&Position::default(), &moves);
+        assert_eq!(searcher.history.len(), 2);
+        assert_eq!(searcher.history[0], Position::default().key());
+        assert_ne!(searcher.history[1], pos.key());
+    }
+
+    #[test]
+    fn no_moves_results() {
+        // Stalemate
+        let mut pos = Position::new_from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        let res = Searcher::new().search(&mut pos, 2);
+        assert_eq!(res.best_move, None);
+        assert_eq!(res.score, 0);
+
+        // Checkmated
+        let mut pos = Position::new_from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1");
+        let res = Searcher::new().search(&mut pos, 2);
+        assert_eq!(res.best_move, None);
+        assert_eq!(res.score, -MATE);
+    }
+}
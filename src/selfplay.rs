@@ -0,0 +1,581 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::color::Color;
+use crate::errors::{FenError, PgnError};
+use crate::info::engine_info;
+use crate::pgn::Game;
+use crate::piece::PieceType;
+use crate::position::{GameResult, Position, PositionBuilder};
+use crate::search::{SearchResult, Searcher, MAX_PLY};
+use crate::stats::MatchStats;
+use crate::time::{Limits, TimeManager};
+
+// How long one side may think.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeControl {
+    // The same depth every move, with no clock at all.
+    Depth(u32),
+    // `base` for the whole game, and `increment` more after each move.
+    Clock { base: Duration, increment: Duration },
+}
+
+impl TimeControl {
+    // The PGN `TimeControl` value, in seconds: "60+0.5". `None` for fixed depths.
+    fn tag(self) -> Option<String> {
+        match self {
+            Self::Depth(_) => None,
+            Self::Clock { base, increment } => Some(format!(
+                "{}+{}",
+                base.as_secs_f64(),
+                increment.as_secs_f64()
+            )),
+        }
+    }
+}
+
+// One side of a game: a search of its own and the time it plays at.
+#[derive(Debug)]
+pub struct Player {
+    pub name: String,
+    pub searcher: Searcher,
+    pub time: TimeControl,
+}
+
+impl Player {
+    pub fn new(name: &str, time: TimeControl) -> Self {
+        Self {
+            name: name.to_string(),
+            searcher: Searcher::new(),
+            time,
+        }
+    }
+
+    // The clock is shown to the search as a UCI `go` would: both sides' time left is known but
+    // only ours matters.
+    fn think(&mut self, pos: &mut Position, left: Duration) -> SearchResult {
+        let increment = match self.time {
+            TimeControl::Depth(depth) => return self.searcher.search(pos, depth),
+            TimeControl::Clock { increment, .. } => increment,
+        };
+
+        let limits = Limits {
+            wtime: Some(left),
+            btime: Some(left),
+            winc: Some(increment),
+            binc: Some(increment),
+            ..Limits::default()
+        };
+        self.searcher
+            .set_time(TimeManager::new(&limits, pos.to_move()));
+        self.searcher.search(pos, MAX_PLY as u32)
+    }
+}
+
+// Ends games whose outcome both players already agree on, as cutechess-cli's `-resign` and
+// `-draw` do. Scores are the ones each search gave for the move it made, in centipawns. The
+// default adjudicates nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Adjudication {
+    pub resign: Option<ResignRule>,
+    pub draw: Option<DrawRule>,
+}
+
+// A side loses once, for its last `moves` moves and its opponent's, its own search has had it
+// at least `score` behind and the opponent's has had it that far ahead. Never to a side left
+// without the material to mate, though.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResignRule {
+    pub moves: u32,
+    pub score: i32,
+}
+
+// A draw once `after_move` full moves have been played from the start position and both
+// players' scores have stayed within `score` of level for their last `moves` moves each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawRule {
+    pub after_move: u32,
+    pub moves: u32,
+    pub score: i32,
+}
+
+// How many plies in a row, up to the last, each rule has held for. Scores are White's.
+#[derive(Debug, Default)]
+struct Streaks {
+    white_lost: u32,
+    black_lost: u32,
+    level: u32,
+}
+
+impl Adjudication {
+    // Counts one more move, made with `score` for White, into `streaks`; the game's end if a
+    // rule now calls it. `pos` is the position after the move, `plies` the moves played.
+    fn update(
+        &self,
+        streaks: &mut Streaks,
+        pos: &Position,
+        score: i32,
+        plies: usize,
+    ) -> Option<Termination> {
+        let extend = |streak: &mut u32, holds: bool| *streak = if holds { *streak + 1 } else { 0 };
+
+        if let Some(rule) = self.resign {
+            extend(&mut streaks.white_lost, score <= -rule.score);
+            extend(&mut streaks.black_lost, score >= rule.score);
+            for (loser, streak) in [
+                (Color::White, streaks.white_lost),
+                (Color::Black, streaks.black_lost),
+            ] {
+                if streak >= 2 * rule.moves && has_mating_material(pos, !loser) {
+                    return Some(Termination::Resigned(loser));
+                }
+            }
+        }
+        if let Some(rule) = self.draw {
+            extend(&mut streaks.level, score.abs() <= rule.score);
+            if streaks.level >= 2 * rule.moves && plies >= 2 * rule.after_move as usize {
+                return Some(Termination::AdjudicatedDraw);
+            }
+        }
+        None
+    }
+}
+
+// Whether `color` could still mate were the other side down to its bare king.
+fn has_mating_material(pos: &Position, color: Color) -> bool {
+    // The bare king moves, so `color`'s own checks don't make the board unbuildable.
+    let mut bare = PositionBuilder::from(pos).side_to_move(!color).ep(None);
+    for square in pos.color(!color) & !pos.pieces(PieceType::King) {
+        bare.clear_square(square);
+    }
+    bare.build()
+        .is_ok_and(|pos| !pos.is_insufficient_material())
+}
+
+// How a self-play game came to an end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    // Mate, or a draw by the rules; never `GameResult::Ongoing`.
+    Rules(GameResult),
+    // This side's clock ran out, and it loses.
+    TimeForfeit(Color),
+    // The game reached the ply limit and was called a draw.
+    PlyLimit,
+    // Both players' scores had this side lost, and it was made to resign.
+    Resigned(Color),
+    // Both players' scores had the game level for long enough to call it a draw.
+    AdjudicatedDraw,
+}
+
+impl Termination {
+    pub fn pgn_result(self) -> &'static str {
+        match self {
+            Self::Rules(GameResult::WhiteWins)
+            | Self::TimeForfeit(Color::Black)
+            | Self::Resigned(Color::Black) => "1-0",
+            Self::Rules(GameResult::BlackWins)
+            | Self::TimeForfeit(Color::White)
+            | Self::Resigned(Color::White) => "0-1",
+            Self::Rules(GameResult::Draw(_)) | Self::PlyLimit | Self::AdjudicatedDraw => "1/2-1/2",
+            Self::Rules(GameResult::Ongoing) => "*",
+        }
+    }
+
+    // White's points from the game: 1, 0.5 or 0.
+    pub fn white_score(self) -> f64 {
+        match self.pgn_result() {
+            "1-0" => 1.0,
+            "0-1" => 0.0,
+            _ => 0.5,
+        }
+    }
+
+    // The PGN `Termination` tag.
+    fn tag(self) -> &'static str {
+        match self {
+            Self::Rules(_) => "normal",
+            Self::TimeForfeit(_) => "time forfeit",
+            Self::PlyLimit | Self::Resigned(_) | Self::AdjudicatedDraw => "adjudication",
+        }
+    }
+}
+
+// A finished game, ready to be written out with `game.to_pgn()`.
+#[derive(Debug, Clone)]
+pub struct Played {
+    pub game: Game,
+    pub termination: Termination,
+}
+
+// Plays `white` against `black` from `start` until the game is over by the rules, a flag falls,
+// `adjudication` calls it or `max_plies` moves have been made. Each player keeps its own
+// searcher, so anything it learns carries over from move to move.
+pub fn play_game(
+    white: &mut Player,
+    black: &mut Player,
+    start: &Position,
+    max_plies: usize,
+    adjudication: &Adjudication,
+) -> Played {
+    let start = start.detached();
+    let mut pos = start.clone();
+    let mut moves = Vec::new();
+    let mut clocks = [white.time, black.time].map(|time| match time {
+        TimeControl::Depth(_) => Duration::MAX,
+        TimeControl::Clock { base, .. } => base,
+    });
+    let mut streaks = Streaks::default();
+
+    let termination = loop {
+        let result = pos.result();
+        if result.is_over() {
+            break Termination::Rules(result);
+        }
+        if moves.len() >= max_plies {
+            break Termination::PlyLimit;
+        }
+
+        let us = pos.to_move();
+        let player = match us {
+            Color::White => &mut *white,
+            Color::Black => &mut *black,
+        };
+        player.searcher.set_history(&start, &moves);
+
+        let thinking = Instant::now();
+        let result = player.think(&mut pos, clocks[us as usize]);
+        let m = result
+            .best_move
+            .expect("a position that isn't over has a move");
+        let clock = &mut clocks[us as usize];
+        match clock.checked_sub(thinking.elapsed()) {
+            Some(left) => *clock = left,
+            None => break Termination::TimeForfeit(us),
+        }
+        if let TimeControl::Clock { increment, .. } = player.time {
+            *clock += increment;
+        }
+
+        pos.make_move(m);
+        moves.push(m);
+
+        // Mate and the other rules come first: only a game still going is adjudicated.
+        if pos.result().is_over() {
+            continue;
+        }
+        let score = match us {
+            Color::White => result.score,
+            Color::Black => -result.score,
+        };
+        if let Some(end) = adjudication.update(&mut streaks, &pos, score, moves.len()) {
+            break end;
+        }
+    };
+
+    // Both sides are this build, told apart by their player names.
+    let info = engine_info();
+    let mut game = Game::new();
+    game.set_tag("Event", "Self-play");
+    game.set_tag("White", &format!("{} ({})", info.id(), white.name));
+    game.set_tag("Black", &format!("{} ({})", info.id(), black.name));
+    game.set_tag("Annotator", &info.to_string());
+    game.set_tag("Result", termination.pgn_result());
+    if start.to_fen() != Position::STARTING_FEN {
+        game.set_tag("SetUp", "1");
+        game.set_tag("FEN", &start.to_fen());
+    }
+    if let Some(tc) = white.time.tag().filter(|_| white.time == black.time) {
+        game.set_tag("TimeControl", &tc);
+    }
+    game.set_tag("Termination", termination.tag());
+    game.moves = moves;
+
+    Played { game, termination }
+}
+
+// Openings for `play_match`, one a line from an EPD suite or a file of FENs; the operations or
+// move counters after the four position fields are ignored. Blank lines and `#` comments are
+// skipped, and a line that doesn't parse stops the load, with its (1 based) line number.
+pub fn openings_from_epd(suite: &str) -> Result<Vec<Position>, (usize, FenError)> {
+    suite
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'))
+        .map(|(i, line)| Position::try_from_epd(line.trim()).map_err(|e| (i + 1, e)))
+        .collect()
+}
+
+// Openings for `play_match` from a PGN file: where each game's moves end. A game that doesn't
+// parse stops the load, with its (1 based) number.
+pub fn openings_from_pgn(pgn: &str) -> Result<Vec<Position>, (usize, PgnError)> {
+    Ok(Game::parse_all(pgn)?
+        .iter()
+        .map(Game::final_position)
+        .collect())
+}
+
+// Plays `first` against `second` in pairs of games, one pair from each opening with the colors
+// swapped, so neither gains from an opening that favours one side. After each game a line
+// with the score so far goes to `out`, and the full statistics once the match is over.
+pub fn play_match(
+    first: &mut Player,
+    second: &mut Player,
+    openings: &[Position],
+    max_plies: usize,
+    adjudication: &Adjudication,
+    out: &mut impl Write,
+) -> io::Result<(Vec<Played>, MatchStats)> {
+    let mut games = Vec::with_capacity(2 * openings.len());
+    let mut stats = MatchStats::new();
+
+    for opening in openings {
+        for first_is_white in [true, false] {
+            let (white, black) = if first_is_white {
+                (&mut *first, &mut *second)
+            } else {
+                (&mut *second, &mut *first)
+            };
+            let played = play_game(white, black, opening, max_plies, adjudication);
+            let score = played.termination.white_score();
+            stats.add_game(if first_is_white { score } else { 1.0 - score });
+
+            write!(
+                out,
+                "Game {} ({} vs {}): {} {{{}}}; ",
+                games.len() + 1,
+                white.name,
+                black.name,
+                played.termination.pgn_result(),
+                played.termination.tag(),
+            )?;
+            stats.report_game(out)?;
+            games.push(played);
+        }
+    }
+
+    writeln!(out, "Match {} vs {}:", first.name, second.name)?;
+    stats.report_summary(out)?;
+    Ok((games, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::DrawReason;
+
+    #[test]
+    fn plays_to_mate() {
+        let mut white = Player::new("one", TimeControl::Depth(2));
+        let mut black = Player::new("two", TimeControl::Depth(2));
+        let start = Position::new_from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+
+        let played = play_game(
+            &mut white,
+            &mut black,
+            &start,
+            100,
+            &Adjudication::default(),
+        );
+        assert_eq!(
+            played.termination,
+            Termination::Rules(GameResult::WhiteWins)
+        );
+        assert_eq!(played.game.moves.len(), 1);
+        assert_eq!(played.game.result(), "1-0");
+        assert_eq!(played.game.tag("FEN"), Some(start.to_fen().as_str()));
+
+        let pgn = played.game.to_pgn();
+        let info = engine_info();
+        assert_eq!(
+            played.game.tag("White"),
+            Some(format!("{} (one)", info.id()).as_str())
+        );
+        assert_eq!(
+            played.game.tag("Black"),
+            Some(format!("{} (two)", info.id()).as_str())
+        );
+        assert!(pgn.contains(&format!("[Annotator \"{}\"]", info)));
+        assert!(pgn.contains("1. Ra8# 1-0"));
+        let parsed = Game::parse(&pgn).unwrap();
+        assert_eq!(parsed.final_position().result(), GameResult::WhiteWins);
+    }
+
+    #[test]
+    fn draws_and_limits() {
+        let mut white = Player::new("white", TimeControl::Depth(1));
+        let mut black = Player::new("black", TimeControl::Depth(1));
+
+        let bare = Position::new_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        let played = play_game(&mut white, &mut black, &bare, 100, &Adjudication::default());
+        assert_eq!(
+            played.termination,
+            Termination::Rules(GameResult::Draw(DrawReason::InsufficientMaterial))
+        );
+        assert!(played.game.moves.is_empty());
+
+        let clock = TimeControl::Clock {
+            base: Duration::from_secs(10),
+            increment: Duration::from_millis(100),
+        };
+        let mut white = Player::new("white", clock);
+        let mut black = Player::new("black", clock);
+        let played = play_game(
+            &mut white,
+            &mut black,
+            &Position::default(),
+            4,
+            &Adjudication::default(),
+        );
+        assert_eq!(played.termination, Termination::PlyLimit);
+        assert_eq!(played.game.moves.len(), 4);
+        assert_eq!(played.game.result(), "1/2-1/2");
+        assert_eq!(played.game.tag("TimeControl"), Some("10+0.1"));
+        assert_eq!(played.game.tag("Termination"), Some("adjudication"));
+        assert_eq!(played.game.tag("FEN"), None);
+    }
+
+    #[test]
+    fn adjudicates_resignations() {
+        let resign = Adjudication {
+            resign: Some(ResignRule {
+                moves: 1,
+                score: 500,
+            }),
+            draw: None,
+        };
+        let mut white = Player::new("white", TimeControl::Depth(1));
+        let mut black = Player::new("black", TimeControl::Depth(1));
+
+        // Two queens up, but no mate in the first two plies: one move each and Black is lost.
+        let queens = Position::new_from_fen("4k3/8/8/8/8/8/8/QQ2K3 w - - 0 1");
+        let played = play_game(&mut white, &mut black, &queens, 100, &resign);
+        assert_eq!(played.termination, Termination::Resigned(Color::Black));
+        assert_eq!(played.game.moves.len(), 2);
+        assert_eq!(played.game.result(), "1-0");
+        assert_eq!(played.game.tag("Termination"), Some("adjudication"));
+
+        // Too far ahead to play on, but a lone knight can't mate: the game goes on.
+        let knight = Position::new_from_fen("4k3/7p/8/8/8/8/8/N3K3 w - - 0 1");
+        let lenient = Adjudication {
+            resign: Some(ResignRule {
+                moves: 1,
+                score: 100,
+            }),
+            draw: None,
+        };
+        let played = play_game(&mut white, &mut black, &knight, 6, &lenient);
+        assert_eq!(played.termination, Termination::PlyLimit);
+
+        let played = play_game(&mut white, &mut black, &queens, 2, &Adjudication::default());
+        assert_eq!(played.termination, Termination::PlyLimit);
+    }
+
+    #[test]
+    fn adjudicates_draws() {
+        let mut white = Player::new("white", TimeControl::Depth(2));
+        let mut black = Player::new("black", TimeControl::Depth(2));
+        let draw = |after_move| Adjudication {
+            resign: None,
+            draw: Some(DrawRule {
+                after_move,
+                moves: 2,
+                score: 100,
+            }),
+        };
+
+        let played = play_game(&mut white, &mut black, &Position::default(), 100, &draw(0));
+        assert_eq!(played.termination, Termination::AdjudicatedDraw);
+        assert_eq!(played.game.moves.len(), 4);
+        assert_eq!(played.game.result(), "1/2-1/2");
+
+        // Not before the fourth move, however level it's been.
+        let played = play_game(&mut white, &mut black, &Position::default(), 100, &draw(4));
+        assert_eq!(played.termination, Termination::AdjudicatedDraw);
+        assert_eq!(played.game.moves.len(), 8);
+    }
+
+    #[test]
+    fn matches_swap_colors() {
+        let mut first = Player::new("first", TimeControl::Depth(2));
+        let mut second = Player::new("second", TimeControl::Depth(2));
+        let openings = [
+            Position::new_from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"),
+            Position::new_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+        ];
+
+        let mut out = Vec::new();
+        let (games, stats) = play_match(
+            &mut first,
+            &mut second,
+            &openings,
+            100,
+            &Adjudication::default(),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(games.len(), 4);
+        // Whoever is White mates at once, so the first pair is a win and a loss.
+        assert_eq!(stats.pairs.0, [0, 0, 2, 0, 0]);
+        assert_eq!(
+            (stats.games.wins, stats.games.draws, stats.games.losses),
+            (1, 2, 1)
+        );
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[0].starts_with("Game 1 (first vs second): 1-0 {normal}; Games: 1, W: 1"));
+        assert!(lines[1].starts_with("Game 2 (second vs first): 1-0 {normal}; Games: 2, W: 1"));
+        assert_eq!(lines[4], "Match first vs second:");
+        assert!(lines
+            .iter()
+            .any(|l| l.starts_with("Ptnml(0-2): [0, 0, 2, 0, 0]")));
+    }
+
+    #[test]
+    fn loads_openings() {
+        let epd = "# two openings\n\
+            rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - id \"e4\";\n\
+            \n\
+            4k3/8/8/8/8/8/8/4K2R w K - 0 1\n";
+        let openings = openings_from_epd(epd).unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(openings[0].to_move(), Color::Black);
+        assert_eq!(openings[1].to_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert!(matches!(
+            openings_from_epd("4k3/8/8/8/8/8/8/4K3 w - -\nnot a fen\n"),
+            Err((2, _))
+        ));
+
+        let pgn = "[Event \"a\"]\n\n1. e4 e5 *\n\n[Event \"b\"]\n\n1. d4 *\n";
+        let from_pgn = openings_from_pgn(pgn).unwrap();
+        assert_eq!(from_pgn.len(), 2);
+        assert_eq!(
+            from_pgn[1].to_fen(),
+            "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert!(matches!(
+            openings_from_pgn("[Event \"a\"]\n\n1. e4 e4 *\n"),
+            Err((1, PgnError::Move { ply: 1, .. }))
+        ));
+
+        // Every opening is played from both sides.
+        let mut first = Player::new("first", TimeControl::Depth(1));
+        let mut second = Player::new("second", TimeControl::Depth(1));
+        let adjudication = Adjudication::default();
+        let (games, stats) = play_match(
+            &mut first,
+            &mut second,
+            &from_pgn,
+            0,
+            &adjudication,
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert_eq!(games.len(), 4);
+        assert_eq!(stats.pairs.0, [0, 0, 2, 0, 0]);
+        assert_eq!(
+            games[3].game.tag("FEN"),
+            Some(from_pgn[1].to_fen().as_str())
+        );
+    }
+}
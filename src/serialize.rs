@@ -0,0 +1,153 @@
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+
+use crate::bitboard::Bitboard;
+use crate::movegen::{Move, MoveKind};
+use crate::piece::{Piece, PieceType};
+use crate::position::Position;
+use crate::square::Square;
+
+// `serde` support, in the notation a person would write: a position as its FEN, a move in UCI,
+// a square by name and a piece by its FEN letter. Bitboards are plain numbers.
+
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_fen())
+    }
+}
+
+// Only playable positions are accepted, as with `Position::try_from_fen`. Move history isn't
+// part of a FEN, so it doesn't survive the round trip.
+impl<'de> Deserialize<'de> for Position {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fen = String::deserialize(deserializer)?;
+        Position::try_from_fen(&fen).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for Move {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// UCI doesn't say which moves castle or take en passant; that takes the position they're played
+// in. Those come back as normal moves, so read moves to play through `Position::play_uci` or
+// `Move::new_from_uci` instead.
+impl<'de> Deserialize<'de> for Move {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let uci = String::deserialize(deserializer)?;
+        let bad = || D::Error::custom(format!("not a UCI move: {}", uci));
+        let bytes = uci.as_bytes();
+        if bytes.len() != 4 && bytes.len() != 5 {
+            return Err(bad());
+        }
+
+        let from = Square::try_from(&bytes[0..2]).map_err(|_| bad())?;
+        let to = Square::try_from(&bytes[2..4]).map_err(|_| bad())?;
+        if from == to {
+            return Err(bad());
+        }
+        let kind = match bytes.get(4) {
+            None => MoveKind::Normal,
+            Some(b'n') => MoveKind::Promotion(PieceType::Knight),
+            Some(b'b') => MoveKind::Promotion(PieceType::Bishop),
+            Some(b'r') => MoveKind::Promotion(PieceType::Rook),
+            Some(b'q') => MoveKind::Promotion(PieceType::Queen),
+            Some(_) => return Err(bad()),
+        };
+        Ok(Move::new_with_kind(from, to, kind))
+    }
+}
+
+impl Serialize for Bitboard {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(u64::from(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Bitboard {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(Bitboard::new)
+    }
+}
+
+impl Serialize for Square {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Square {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Square::try_from(name.as_bytes()).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for Piece {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_char(char::from(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Piece {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let c = char::deserialize(deserializer)?;
+        Piece::try_from(c).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::square::Square::*;
+
+    #[test]
+    fn json_round_trips() {
+        let pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        let json = serde_json::to_string(&pos).unwrap();
+        assert_eq!(json, format!("\"{}\"", pos.to_fen()));
+        assert_eq!(
+            serde_json::from_str::<Position>(&json).unwrap().key(),
+            pos.key()
+        );
+
+        let moves = [
+            Move::new(E2, E4),
+            Move::new_with_kind(B7, A8, MoveKind::Promotion(PieceType::Knight)),
+        ];
+        let json = serde_json::to_string(&moves).unwrap();
+        assert_eq!(json, r#"["e2e4","b7a8n"]"#);
+        assert_eq!(serde_json::from_str::<Vec<Move>>(&json).unwrap(), moves);
+
+        let bb = Bitboard::from([A1, H8]);
+        assert_eq!(
+            serde_json::to_string(&bb).unwrap(),
+            (1u64 | 1 << 63).to_string()
+        );
+        assert_eq!(
+            serde_json::from_str::<Bitboard>("9223372036854775809").unwrap(),
+            bb
+        );
+
+        assert_eq!(serde_json::to_string(&G7).unwrap(), r#""g7""#);
+        assert_eq!(serde_json::from_str::<Square>(r#""g7""#).unwrap(), G7);
+
+        let knight = Piece::new(PieceType::Knight, Color::Black);
+        assert_eq!(serde_json::to_string(&knight).unwrap(), r#""n""#);
+        assert_eq!(serde_json::from_str::<Piece>(r#""n""#).unwrap(), knight);
+    }
+
+    #[test]
+    fn json_rejects_garbage() {
+        assert!(serde_json::from_str::<Position>(r#""8/8/8/8/8/8/8/8 w - -""#).is_err());
+        assert!(serde_json::from_str::<Move>(r#""e2e9""#).is_err());
+        assert!(serde_json::from_str::<Move>(r#""e2e4k""#).is_err());
+        assert!(serde_json::from_str::<Move>(r#""e2e2""#).is_err());
+        assert!(serde_json::from_str::<Square>(r#""i1""#).is_err());
+        assert!(serde_json::from_str::<Piece>(r#""x""#).is_err());
+        assert!(serde_json::from_str::<Bitboard>("-1").is_err());
+    }
+}
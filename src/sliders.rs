@@ -0,0 +1,150 @@
+use crate::bitboard::Bitboard;
+use crate::hyperbola;
+#[cfg(feature = "magic")]
+use crate::magic;
+use crate::precompute;
+use crate::square::{Direction, Square};
+
+// The ways sliding attacks can be looked up. Movegen always uses `active()`; the others are here
+// to be compared against it at runtime, in tests and in `benches/`. Only those whose feature is
+// enabled are compiled in, and `Pext` also needs a CPU with BMI2; see `available`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliderBackend {
+    // Walks the rays out to the first blocker. Always there, as the reference for the others.
+    Rays,
+    // Hyperbola quintessence, with small tables and no special instructions. Always there, and
+    // the default without `magic`.
+    Hyperbola,
+    // Plain magic bitboards (`magic`).
+    Magic,
+    // The same tables indexed with BMI2's `pext` (`pext`).
+    Pext,
+    // Fixed-shift black magics, one layout for rooks and bishops (`black-magic`).
+    BlackMagic,
+}
+
+impl SliderBackend {
+    pub const ALL: [Self; 5] = [
+        Self::Rays,
+        Self::Hyperbola,
+        Self::Magic,
+        Self::Pext,
+        Self::BlackMagic,
+    ];
+
+    // The fastest one that works here. With `pext` on a CPU without BMI2, that is whichever
+    // magics would have been used without it, so one binary runs everywhere. `pext` is ignored
+    // outside x86_64.
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn active() -> Self {
+        if pext_supported() {
+            Self::Pext
+        } else if cfg!(feature = "black-magic") {
+            Self::BlackMagic
+        } else if cfg!(feature = "magic") {
+            Self::Magic
+        } else {
+            Self::Hyperbola
+        }
+    }
+
+    pub fn available(self) -> bool {
+        match self {
+            Self::Rays | Self::Hyperbola => true,
+            Self::Magic => cfg!(feature = "magic"),
+            Self::Pext => pext_supported(),
+            Self::BlackMagic => cfg!(feature = "black-magic"),
+        }
+    }
+
+    // Panics if the backend isn't available.
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn bishop_attacks(self, square: Square, occupancy: Bitboard) -> Bitboard {
+        match self {
+            Self::Rays => precompute::ray_attacks(square, occupancy, &Direction::diagonal()),
+            Self::Hyperbola => hyperbola::bishop_attacks(square, occupancy),
+            #[cfg(feature = "magic")]
+            Self::Magic => magic::bishop_attacks(square, occupancy),
+            // SAFETY: `pext_supported` checked for BMI2.
+            #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+            Self::Pext if pext_supported() => unsafe {
+                magic::pext_bishop_attacks(square, occupancy)
+            },
+            #[cfg(feature = "black-magic")]
+            Self::BlackMagic => magic::black_bishop_attacks(square, occupancy),
+            #[allow(unreachable_patterns)]
+            other => panic!("slider backend not available: {:?}", other),
+        }
+    }
+    // Panics if the backend isn't available.
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn rook_attacks(self, square: Square, occupancy: Bitboard) -> Bitboard {
+        match self {
+            Self::Rays => precompute::ray_attacks(square, occupancy, &Direction::orthogonal()),
+            Self::Hyperbola => hyperbola::rook_attacks(square, occupancy),
+            #[cfg(feature = "magic")]
+            Self::Magic => magic::rook_attacks(square, occupancy),
+            // SAFETY: `pext_supported` checked for BMI2.
+            #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+            Self::Pext if pext_supported() => unsafe {
+                magic::pext_rook_attacks(square, occupancy)
+            },
+            #[cfg(feature = "black-magic")]
+            Self::BlackMagic => magic::black_rook_attacks(square, occupancy),
+            #[allow(unreachable_patterns)]
+            other => panic!("slider backend not available: {:?}", other),
+        }
+    }
+}
+
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+#[cfg_attr(feature = "inline", inline)]
+fn pext_supported() -> bool {
+    magic::bmi2_supported()
+}
+#[cfg(not(all(feature = "pext", target_arch = "x86_64")))]
+const fn pext_supported() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    #[test]
+    fn backends_agree() {
+        let active = SliderBackend::active();
+        assert!(active.available());
+        #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+        assert_eq!(
+            active == SliderBackend::Pext,
+            std::arch::is_x86_feature_detected!("bmi2")
+        );
+
+        let mut rng = Rng::new(3532);
+        let backends = SliderBackend::ALL.into_iter().filter(|b| b.available());
+        for backend in backends {
+            for square in Bitboard::new(!0) {
+                for _ in 0..32 {
+                    let occ = Bitboard::new(rng.next_u64() & rng.next_u64());
+                    let rays = SliderBackend::Rays;
+                    assert_eq!(
+                        backend.bishop_attacks(square, occ),
+                        rays.bishop_attacks(square, occ),
+                        "{:?} bishop on {}",
+                        backend,
+                        square
+                    );
+                    assert_eq!(
+                        backend.rook_attacks(square, occ),
+                        rays.rook_attacks(square, occ),
+                        "{:?} rook on {}",
+                        backend,
+                        square
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use crate::color::Color;
+use crate::errors::{CodecError, FenError, MoveError, MoveParseError};
+use crate::movegen::Move;
+use crate::position::{CastleFlag, Position};
+
+// A compact binary form of a live game: where it started, every move since, and the players'
+// clocks. Meant for servers to store and resend games (on a reconnect, say) without going
+// through FEN and PGN text, and typically a few dozen bytes plus two per move.
+//
+// Layout, all numbers little endian or LEB128 varints:
+//   "FCPW", version (u8), flags (u8, bit 0: clocks follow)
+//   start board: occupancy (u64), then a nibble per occupied square from a1 up, low nibble first
+//   side, castling and en passant (u8), the en passant file (u8) if any
+//   halfmove clock, fullmove number, move count (varints), then each move's 16 bit code
+//   clocks if flagged: white, black and increment in milliseconds (varints)
+
+const MAGIC: &[u8; 4] = b"FCPW";
+// Bumped on any change to the layout. Older versions stay readable.
+pub const VERSION: u8 = 1;
+
+const HAS_CLOCKS: u8 = 1;
+
+// Nibble codes for the pieces, as their FEN letters.
+const PIECES: &[u8; 12] = b"PNBRQKpnbrqk";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Clocks {
+    pub white_ms: u64,
+    pub black_ms: u64,
+    pub increment_ms: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    // Restored with its full history, so every move can still be undone.
+    pub position: Position,
+    pub clocks: Option<Clocks>,
+}
+
+impl Snapshot {
+    pub fn new(position: Position, clocks: Option<Clocks>) -> Self {
+        Self { position, clocks }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut start = self.position.clone();
+        let mut moves = Vec::new();
+        while let Some(m) = start.undo() {
+            moves.push(m);
+        }
+        moves.reverse();
+
+        let mut out = Vec::with_capacity(32 + 2 * moves.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.push(if self.clocks.is_some() { HAS_CLOCKS } else { 0 });
+
+        write_board(&mut out, &start);
+        write_varint(&mut out, start.rule50() as u64);
+        write_varint(&mut out, start.fullmoves() as u64);
+
+        write_varint(&mut out, moves.len() as u64);
+        for m in moves {
+            out.extend_from_slice(&m.to_u16().to_le_bytes());
+        }
+
+        if let Some(c) = self.clocks {
+            write_varint(&mut out, c.white_ms);
+            write_varint(&mut out, c.black_ms);
+            write_varint(&mut out, c.increment_ms);
+        }
+
+        out
+    }
+
+    // Never panics, whatever the bytes: every field is checked, and every move must be legal
+    // where it is played.
+    pub fn decode(bytes: &[u8]) -> Result<Self, CodecError> {
+        let mut r = Reader(bytes);
+        if r.take(4)? != MAGIC {
+            return Err(CodecError::BadMagic);
+        }
+        let version = r.byte()?;
+        if version == 0 || version > VERSION {
+            return Err(CodecError::UnsupportedVersion(version));
+        }
+        let flags = r.byte()?;
+
+        let board = read_board(&mut r)?;
+        let halfmoves = r.varint()?;
+        let fullmoves = r.varint()?;
+        let mut position =
+            Position::try_from_fen(&format!("{} {} {}", board, halfmoves, fullmoves))?;
+
+        let count = r.varint()?;
+        // Checked before anything is allocated for them.
+        if count > r.0.len() as u64 / 2 {
+            return Err(CodecError::Truncated);
+        }
+        for ply in 0..count as usize {
+            let code = u16::from_le_bytes([r.byte()?, r.byte()?]);
+            let error = |error| CodecError::Move { ply, error };
+
+            let m = Move::from_u16_checked(code)
+                .ok_or_else(|| error(MoveParseError::Code(code).into()))?;
+            if !position.is_pseudo_legal(m) || !position.is_legal(m) {
+                return Err(error(MoveError::Illegal(m)));
+            }
+            position.make_move(m);
+        }
+
+        let clocks = if flags & HAS_CLOCKS != 0 {
+            Some(Clocks {
+                white_ms: r.varint()?,
+                black_ms: r.varint()?,
+                increment_ms: r.varint()?,
+            })
+        } else {
+            None
+        };
+
+        if !r.0.is_empty() {
+            return Err(CodecError::TrailingBytes(r.0.len()));
+        }
+
+        Ok(Self { position, clocks })
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn write_board(out: &mut Vec<u8>, pos: &Position) {
+    out.extend_from_slice(&pos.all().into_inner().to_le_bytes());
+
+    let mut nibbles = Vec::with_capacity(32);
+    for s in pos.all() {
+        let piece = pos
+            .piece_on(s)
+            .expect("write_board: Occupied square without a piece");
+        let c = char::from(piece) as u8;
+        let nibble = PIECES.iter().position(|&p| p == c);
+        nibbles.push(nibble.expect("write_board: Piece without a letter") as u8);
+    }
+    for pair in nibbles.chunks(2) {
+        out.push(pair[0] | pair.get(1).map_or(0, |n| n << 4));
+    }
+
+    let mut bits = (pos.to_move() == Color::Black) as u8;
+    let rights = [
+        CastleFlag::WhiteShort,
+        CastleFlag::WhiteLong,
+        CastleFlag::BlackShort,
+        CastleFlag::BlackLong,
+    ];
+    for (i, cf) in rights.into_iter().enumerate() {
+        if pos.has_castle(cf) {
+            bits |= 2 << i;
+        }
+    }
+    match pos.ep() {
+        Some(s) => {
+            out.push(bits | 0x20);
+            out.push(s.file() as u8);
+        }
+        None => out.push(bits),
+    }
+}
+
+// Reads the start board back as the first four fields of a FEN, for `try_from_fen` to check.
+fn read_board(r: &mut Reader) -> Result<String, CodecError> {
+    let occupancy = u64::from_le_bytes(r.array()?);
+    let count = occupancy.count_ones() as usize;
+    let packed = r.take(count.div_ceil(2))?;
+
+    let mut squares = [None; 64];
+    let mut bits = occupancy;
+    for i in 0..count {
+        let code = (packed[i / 2] >> (4 * (i % 2))) & 0xF;
+        let piece = *PIECES
+            .get(code as usize)
+            .ok_or(CodecError::BadPiece(code))?;
+        squares[bits.trailing_zeros() as usize] = Some(piece as char);
+        bits &= bits - 1;
+    }
+
+    let mut fen = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            match squares[rank * 8 + file] {
+                Some(c) => {
+                    if empty > 0 {
+                        fen.push((b'0' + empty) as char);
+                        empty = 0;
+                    }
+                    fen.push(c);
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            fen.push((b'0' + empty) as char);
+        }
+        if rank > 0 {
+            fen.push('/');
+        }
+    }
+
+    let flags = r.byte()?;
+    let black = flags & 1 != 0;
+    fen += if black { " b " } else { " w " };
+
+    let before = fen.len();
+    for (i, c) in "KQkq".chars().enumerate() {
+        if flags & (2 << i) != 0 {
+            fen.push(c);
+        }
+    }
+    if fen.len() == before {
+        fen.push('-');
+    }
+
+    if flags & 0x20 != 0 {
+        let file = r.byte()?;
+        if file >= 8 {
+            return Err(FenError::BadEnPassant(format!("file {}", file)).into());
+        }
+        // The pawn that can be taken has just moved, so it belongs to the side not to move.
+        let rank = if black { '3' } else { '6' };
+        fen += &format!(" {}{}", (b'a' + file) as char, rank);
+    } else {
+        fen += " -";
+    }
+
+    Ok(fen)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], CodecError> {
+        if self.0.len() < n {
+            return Err(CodecError::Truncated);
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], CodecError> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+    fn byte(&mut self) -> Result<u8, CodecError> {
+        Ok(self.take(1)?[0])
+    }
+    fn varint(&mut self) -> Result<u64, CodecError> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            n |= ((b & 0x7F) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(CodecError::BadVarint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::Game;
+    use crate::rng::Rng;
+    use crate::square::Square;
+
+    const MOVES: [&str; 8] = [
+        "e2e4", "g8f6", "e4e5", "d7d5", "e5d6", "e7d6", "g1f3", "f8e7",
+    ];
+
+    fn game() -> Snapshot {
+        let mut pos = Position::default();
+        for m in MOVES {
+            pos.play_uci(m).unwrap();
+        }
+        let clocks = Clocks {
+            white_ms: 179_250,
+            black_ms: 1 << 40,
+            increment_ms: 2000,
+        };
+        Snapshot::new(pos, Some(clocks))
+    }
+
+    #[test]
+    fn round_trips() {
+        let snap = game();
+        let bytes = snap.encode();
+        let back = Snapshot::decode(&bytes).unwrap();
+        assert_eq!(back.clocks, snap.clocks);
+        assert_eq!(back.position.to_fen(), snap.position.to_fen());
+        assert_eq!(back.position.key(), snap.position.key());
+
+        // The history comes back too, en passant capture included.
+        let mut pos = back.position;
+        for m in MOVES.iter().rev() {
+            assert_eq!(pos.undo().unwrap().to_string(), *m);
+        }
+        assert_eq!(pos.to_fen(), Position::STARTING_FEN);
+
+        // Against the same game as a FEN and bare PGN movetext.
+        let mut pgn = Game::new();
+        pgn.tags.clear();
+        let mut replay = Position::default();
+        for m in MOVES {
+            pgn.moves.push(replay.play_uci(m).unwrap());
+        }
+        assert!(bytes.len() < Position::STARTING_FEN.len() + pgn.to_pgn().len());
+    }
+
+    #[test]
+    fn keeps_start_position_details() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b Kq a3 7 23";
+        let pos = Position::try_from_fen(fen).unwrap();
+        let back = Snapshot::decode(&Snapshot::new(pos, None).encode()).unwrap();
+        assert_eq!(back.position.to_fen(), fen);
+        assert_eq!(back.clocks, None);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let bytes = game().encode();
+
+        assert_eq!(Snapshot::decode(b"FCPX").unwrap_err(), CodecError::BadMagic);
+        let mut newer = bytes.clone();
+        newer[4] = VERSION + 1;
+        assert_eq!(
+            Snapshot::decode(&newer).unwrap_err(),
+            CodecError::UnsupportedVersion(VERSION + 1)
+        );
+        assert_eq!(
+            Snapshot::decode(&bytes[..bytes.len() - 1]).unwrap_err(),
+            CodecError::Truncated
+        );
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(
+            Snapshot::decode(&longer).unwrap_err(),
+            CodecError::TrailingBytes(1)
+        );
+
+        // A history of one move no piece can make.
+        let mut illegal = Snapshot::new(Position::default(), None).encode();
+        illegal.pop();
+        illegal.push(1);
+        illegal.extend_from_slice(&Move::new(Square::E2, Square::E5).to_u16().to_le_bytes());
+        assert!(matches!(
+            Snapshot::decode(&illegal).unwrap_err(),
+            CodecError::Move {
+                ply: 0,
+                error: MoveError::Illegal(_)
+            }
+        ));
+    }
+
+    // Whatever the decoder is fed, it gives back a result rather than panicking.
+    #[test]
+    fn fuzz_decoder() {
+        let valid = game().encode();
+        let mut rng = Rng::new(0x5EED);
+
+        for n in 0..valid.len() {
+            assert!(Snapshot::decode(&valid[..n]).is_err());
+        }
+
+        for _ in 0..20_000 {
+            let mut bytes = valid.clone();
+            for _ in 0..=rng.below(4) {
+                let i = rng.below(bytes.len() as u64) as usize;
+                bytes[i] ^= 1 << rng.below(8);
+            }
+            if let Ok(snap) = Snapshot::decode(&bytes) {
+                // Anything accepted is a real game, and encodes back to something it accepts.
+                assert!(Snapshot::decode(&snap.encode()).is_ok());
+            }
+        }
+
+        for _ in 0..20_000 {
+            let len = rng.below(64) as usize;
+            let mut bytes: Vec<u8> = (0..len).map(|_| rng.next_u64() as u8).collect();
+            if len >= 5 && rng.below(2) == 0 {
+                bytes[..4].copy_from_slice(MAGIC);
+                bytes[4] = VERSION;
+            }
+            let _ = Snapshot::decode(&bytes);
+        }
+    }
+}
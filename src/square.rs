@@ -1,8 +1,10 @@
 use std::mem::transmute;
-use std::ops::Not;
+use std::ops::{Add, Not};
 
-use crate::bitboard::Bitboard;
+use crate::bitboard::{Bitboard, BitboardIter};
 use crate::color::Color;
+use crate::errors::MoveParseError;
+use crate::precompute;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[rustfmt::skip]
@@ -42,6 +44,18 @@ pub enum Rank {
 }
 
 impl Square {
+    // Every square, A1 to H8, in the order of their indices.
+    pub const ALL: [Self; 64] = {
+        let mut all = [Self::A1; 64];
+        let mut i = 0;
+        while i < 64 {
+            // SAFETY: i < 64.
+            all[i] = unsafe { transmute::<u8, Self>(i as u8) };
+            i += 1;
+        }
+        all
+    };
+
     #[cfg_attr(feature = "inline", inline)]
     pub const fn new(file: File, rank: Rank) -> Self {
         let sq_idx = ((rank as u8) << 3) + (file as u8);
@@ -49,6 +63,20 @@ impl Square {
         unsafe { transmute(sq_idx) }
     }
 
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn iter() -> std::array::IntoIter<Self, 64> {
+        Self::ALL.into_iter()
+    }
+    // The square with index `index` (A1 is 0, H1 7, H8 63), if there is one.
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn from_index(index: u8) -> Option<Self> {
+        if index < 64 {
+            Some(Self::ALL[index as usize])
+        } else {
+            None
+        }
+    }
+
     #[cfg_attr(feature = "inline", inline)]
     pub const fn file(self) -> File {
         // SAFETY: Limits of square enum makes this bounded properly.
@@ -113,22 +141,104 @@ impl Square {
         file_diff == rank_diff
     }
 
+    // The square as `color` sees it from its own side of the board: unchanged for White,
+    // mirrored top to bottom for Black. `E1.relative(Black)` is E8, Black's king square, and
+    // `sq.relative(us) == A8` asks whether `sq` is the opponent's queenside rook corner.
     #[cfg_attr(feature = "inline", inline)]
     pub const fn relative(self, color: Color) -> Self {
         match color {
             Color::White => self,
-            Color::Black => Self::new(self.file(), Color::Black.relative_rank(self.rank())),
+            Color::Black => self.flip_vertical(),
         }
     }
+    // The rank of the square counted from `color`'s side: Rank::Two is always the pawns' home.
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn relative_rank(self, color: Color) -> Rank {
+        color.relative_rank(self.rank())
+    }
+
+    // The same file on the mirrored rank: A1 <-> A8.
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn flip_vertical(self) -> Self {
+        Self::ALL[self as usize ^ 56]
+    }
+    // The same rank on the mirrored file: A1 <-> H1.
+    #[cfg_attr(feature = "inline", inline)]
+    pub const fn flip_horizontal(self) -> Self {
+        Self::ALL[self as usize ^ 7]
+    }
 
     #[cfg_attr(feature = "inline", inline)]
     pub fn shift(self, dir: Direction) -> Option<Self> {
         Bitboard::from_square(self).shift(dir).into_iter().next()
     }
+    // `n` steps in `dir`, if that stays on the board the whole way.
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn try_shift_by(self, dir: Direction, n: u32) -> Option<Self> {
+        Bitboard::from_square(self)
+            .shift_n(dir, n)
+            .into_iter()
+            .next()
+    }
+    /// # Safety
+    /// The shifted square must still be on the board.
     #[cfg_attr(feature = "inline", inline)]
     pub unsafe fn shift_unchecked(self, dir: Direction) -> Self {
         self.shift(dir).unwrap_unchecked()
     }
+
+    // Walks away from this square in `dir`, nearest square first, until the edge of the board.
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn ray_iter(self, dir: Direction) -> SquareWalk {
+        SquareWalk::new(precompute::ray(self, dir), dir)
+    }
+    // Walks the squares strictly between this square and `other`, starting next to this one.
+    // Empty if the two are not on a shared line.
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn between_iter(self, other: Square) -> SquareWalk {
+        match self.dir_to(other) {
+            Some(dir) => SquareWalk::new(Bitboard::interval(self, other), dir),
+            None => SquareWalk::new(Bitboard::EMPTY, Direction::North),
+        }
+    }
+}
+
+// Iterates a set of squares along a line, in the order they are met going in one direction.
+#[derive(Debug)]
+pub struct SquareWalk {
+    squares: BitboardIter,
+    forward: bool,
+}
+
+impl SquareWalk {
+    #[cfg_attr(feature = "inline", inline)]
+    fn new(squares: Bitboard, dir: Direction) -> Self {
+        Self {
+            squares: squares.into_iter(),
+            forward: dir.is_forward(),
+        }
+    }
+}
+
+impl Iterator for SquareWalk {
+    type Item = Square;
+    #[cfg_attr(feature = "inline", inline)]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.forward {
+            self.squares.next()
+        } else {
+            self.squares.next_back()
+        }
+    }
+}
+
+// `square + dir` is `square.shift(dir)`: `None` off the edge of the board.
+impl Add<Direction> for Square {
+    type Output = Option<Self>;
+    #[cfg_attr(feature = "inline", inline)]
+    fn add(self, dir: Direction) -> Self::Output {
+        self.shift(dir)
+    }
 }
 
 impl From<Square> for u8 {
@@ -139,32 +249,35 @@ impl From<Square> for u8 {
 }
 
 impl TryFrom<[u8; 2]> for Square {
-    type Error = ();
+    type Error = MoveParseError;
     #[cfg_attr(feature = "inline-aggressive", inline)]
     fn try_from(value: [u8; 2]) -> Result<Self, Self::Error> {
-        if value[0] < b'a' || value[1] < b'0' {
-            return Err(());
+        let error = || MoveParseError::Square(String::from_utf8_lossy(&value).into_owned());
+        if value[0] < b'a' || value[1] < b'1' {
+            return Err(error());
         }
 
         let f = value[0] - b'a';
-        let r = value[1] - b'0';
+        let r = value[1] - b'1';
 
         if f >= 8 || r >= 8 {
-            return Err(());
+            return Err(error());
         }
 
         // SAFETY: Bounds checked above.
-        let file = unsafe { std::mem::transmute(f) };
-        let rank = unsafe { std::mem::transmute(r) };
+        let file = unsafe { std::mem::transmute::<u8, File>(f) };
+        let rank = unsafe { std::mem::transmute::<u8, Rank>(r) };
         Ok(Self::new(file, rank))
     }
 }
 impl TryFrom<&[u8]> for Square {
-    type Error = ();
+    type Error = MoveParseError;
     #[cfg_attr(feature = "inline-aggressive", inline)]
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
         if value.len() != 2 {
-            Err(())
+            Err(MoveParseError::Square(
+                String::from_utf8_lossy(value).into_owned(),
+            ))
         } else {
             Self::try_from([value[0], value[1]])
         }
@@ -219,10 +332,7 @@ impl Direction {
     #[cfg_attr(feature = "inline", inline)]
     pub const fn is_forward(self) -> bool {
         use Direction::*;
-        match self {
-            North | NorthEast | NorthWest | East => true,
-            _ => false,
-        }
+        matches!(self, North | NorthEast | NorthWest | East)
     }
 
     #[cfg_attr(feature = "inline", inline)]
@@ -279,22 +389,107 @@ impl From<File> for char {
 }
 
 impl TryFrom<u8> for Rank {
-    type Error = ();
+    type Error = MoveParseError;
     #[cfg_attr(feature = "inline-aggressive", inline)]
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            0..8 => Ok(unsafe { std::mem::transmute(value) }),
-            8.. => Err(()),
+            0..8 => Ok(unsafe { std::mem::transmute::<u8, Rank>(value) }),
+            8.. => Err(MoveParseError::Rank(value)),
         }
     }
 }
 impl TryFrom<u8> for File {
-    type Error = ();
+    type Error = MoveParseError;
     #[cfg_attr(feature = "inline-aggressive", inline)]
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            0..8 => Ok(unsafe { std::mem::transmute(value) }),
-            8.. => Err(()),
+            0..8 => Ok(unsafe { std::mem::transmute::<u8, File>(value) }),
+            8.. => Err(MoveParseError::File(value)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Square::*;
+
+    #[test]
+    fn square_from_coordinates() {
+        assert_eq!(Square::try_from(&b"a1"[..]), Ok(A1));
+        assert_eq!(Square::try_from(&b"e2"[..]), Ok(E2));
+        assert_eq!(Square::try_from(&b"h8"[..]), Ok(H8));
+        assert_eq!(
+            Square::try_from(&b"a0"[..]),
+            Err(MoveParseError::Square("a0".to_string()))
+        );
+        assert!(Square::try_from(&b"a9"[..]).is_err());
+        assert!(Square::try_from(&b"i1"[..]).is_err());
+        assert_eq!(File::try_from(8), Err(MoveParseError::File(8)));
+    }
+
+    #[test]
+    fn all_squares_and_flips() {
+        assert_eq!(Square::ALL.len(), 64);
+        assert!(Square::iter().enumerate().all(|(i, sq)| sq as usize == i));
+        assert_eq!(Square::iter().next(), Some(A1));
+        assert_eq!(Square::iter().last(), Some(H8));
+
+        assert_eq!(Square::from_index(0), Some(A1));
+        assert_eq!(Square::from_index(28), Some(E4));
+        assert_eq!(Square::from_index(64), None);
+
+        assert_eq!(A1.flip_vertical(), A8);
+        assert_eq!(E2.flip_vertical(), E7);
+        assert_eq!(A1.flip_horizontal(), H1);
+        assert_eq!(C6.flip_horizontal(), F6);
+        assert!(Square::iter().all(|sq| sq.flip_vertical().flip_vertical() == sq));
+
+        assert_eq!(E4 + Direction::NorthEast, Some(F5));
+        assert_eq!(H4 + Direction::East, None);
+        assert_eq!(A1 + Direction::South, None);
+
+        assert_eq!(E4.try_shift_by(Direction::NorthWest, 3), Some(B7));
+        assert_eq!(E4.try_shift_by(Direction::NorthWest, 4), Some(A8));
+        assert_eq!(E4.try_shift_by(Direction::NorthWest, 5), None);
+        assert_eq!(E4.try_shift_by(Direction::East, 0), Some(E4));
+    }
+
+    #[test]
+    fn relative_mirrors_for_black() {
+        use crate::color::Color::*;
+
+        assert_eq!(E1.relative(White), E1);
+        assert_eq!(E1.relative(Black), E8);
+        assert_eq!(H8.relative(Black), H1);
+        assert!(Square::iter().all(|sq| sq.relative(Black).relative(Black) == sq));
+
+        assert_eq!(E2.relative_rank(White), Rank::Two);
+        assert_eq!(E7.relative_rank(Black), Rank::Two);
+        assert_eq!(A1.relative_rank(Black), Rank::Eight);
+        assert!(Square::iter().all(|sq| sq.relative(Black).relative_rank(Black) == sq.rank()));
+    }
+
+    #[test]
+    fn ray_iter_walks_outwards() {
+        let v: Vec<_> = C3.ray_iter(Direction::NorthEast).collect();
+        assert_eq!(v, [D4, E5, F6, G7, H8]);
+        let v: Vec<_> = D4.ray_iter(Direction::West).collect();
+        assert_eq!(v, [C4, B4, A4]);
+        let v: Vec<_> = H8.ray_iter(Direction::SouthWest).collect();
+        assert_eq!(v, [G7, F6, E5, D4, C3, B2, A1]);
+        assert_eq!(A1.ray_iter(Direction::South).next(), None);
+    }
+
+    #[test]
+    fn between_iter_walks_in_order() {
+        let v: Vec<_> = E1.between_iter(A1).collect();
+        assert_eq!(v, [D1, C1, B1]);
+        let v: Vec<_> = A1.between_iter(E1).collect();
+        assert_eq!(v, [B1, C1, D1]);
+        let v: Vec<_> = H1.between_iter(E4).collect();
+        assert_eq!(v, [G2, F3]);
+        assert_eq!(A1.between_iter(B3).next(), None);
+        assert_eq!(A1.between_iter(B2).next(), None);
+    }
+}
@@ -0,0 +1,306 @@
+use std::io::Write;
+
+// Match statistics: Elo estimates, error bars and likelihood of superiority, from either
+// plain win/draw/loss counts or pentanomial (game pair) counts.
+
+// Two-sided 95% confidence.
+const Z_95: f64 = 1.959963984540054;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Trinomial {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+// Counts of game pairs scoring 0, 0.5, 1, 1.5 and 2 points respectively.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pentanomial(pub [u32; 5]);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EloEstimate {
+    pub elo: f64,
+    // Half-width of the 95% confidence interval.
+    pub error: f64,
+    pub los: f64,
+}
+
+impl Trinomial {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn add_result(&mut self, score: f64) {
+        if score > 0.5 {
+            self.wins += 1;
+        } else if score < 0.5 {
+            self.losses += 1;
+        } else {
+            self.draws += 1;
+        }
+    }
+
+    pub fn estimate(&self) -> Option<EloEstimate> {
+        let (mean, stderr) =
+            mean_and_stderr(&[(0.0, self.losses), (0.5, self.draws), (1.0, self.wins)])?;
+        Some(EloEstimate {
+            elo: elo_from_score(mean),
+            error: elo_error(mean, stderr),
+            los: los(self.wins, self.losses),
+        })
+    }
+}
+
+impl Pentanomial {
+    pub fn pairs(&self) -> u32 {
+        self.0.iter().sum()
+    }
+
+    // Records one pair, given the first player's points from each game (0, 0.5 or 1).
+    pub fn add_pair(&mut self, first: f64, second: f64) {
+        let index = ((first + second) * 2.0).round() as usize;
+        self.0[index.min(4)] += 1;
+    }
+
+    // The per-game counts these pairs add up to, as best as can be told.
+    // Pairs of a win and a loss count as two draws, the same way a tester would see the score.
+    pub fn trinomial(&self) -> Trinomial {
+        let [ll, ld, dd_wl, dw, ww] = self.0;
+        Trinomial {
+            wins: 2 * ww + dw,
+            draws: ld + 2 * dd_wl + dw,
+            losses: 2 * ll + ld,
+        }
+    }
+
+    pub fn estimate(&self) -> Option<EloEstimate> {
+        let (mean, stderr) = mean_and_stderr(&[
+            (0.0, self.0[0]),
+            (0.25, self.0[1]),
+            (0.5, self.0[2]),
+            (0.75, self.0[3]),
+            (1.0, self.0[4]),
+        ])?;
+        let t = self.trinomial();
+        Some(EloEstimate {
+            elo: elo_from_score(mean),
+            error: elo_error(mean, stderr),
+            los: los(t.wins, t.losses),
+        })
+    }
+
+    // Ratio of drawn pairs (both games drawn, or a win each way) to all pairs.
+    pub fn draw_pair_ratio(&self) -> f64 {
+        match self.pairs() {
+            0 => 0.0,
+            n => f64::from(self.0[2]) / f64::from(n),
+        }
+    }
+}
+
+// A match as it goes, from the first player's side. Games come in pairs, the same opening
+// with colors swapped, and every two games make a pair.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MatchStats {
+    pub games: Trinomial,
+    pub pairs: Pentanomial,
+    // The first game of a pair, until its partner is played.
+    pending: Option<f64>,
+}
+
+impl MatchStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Records a game the first player scored `score` (0, 0.5 or 1) in.
+    pub fn add_game(&mut self, score: f64) {
+        self.games.add_result(score);
+        match self.pending.take() {
+            Some(first) => self.pairs.add_pair(first, score),
+            None => self.pending = Some(score),
+        }
+    }
+
+    // One line on the match so far, for after each game.
+    pub fn report_game(&self, out: &mut impl Write) -> std::io::Result<()> {
+        match self.games.estimate() {
+            Some(e) => writeln!(out, "{}, {}", self.games, e),
+            None => writeln!(out, "{}", self.games),
+        }
+    }
+
+    // The whole match once it is over: per game, then per pair when there are any.
+    pub fn report_summary(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "{}", self.games)?;
+        if let Some(e) = self.games.estimate() {
+            writeln!(out, "{}", e)?;
+        }
+        if self.pairs.pairs() > 0 {
+            writeln!(
+                out,
+                "{}, draw pairs: {:.1} %",
+                self.pairs,
+                self.pairs.draw_pair_ratio() * 100.0
+            )?;
+        }
+        if let Some(e) = self.pairs.estimate() {
+            writeln!(out, "Pairs {}", e)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn elo_from_score(score: f64) -> f64 {
+    let score = score.clamp(1e-9, 1.0 - 1e-9);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// Likelihood that the first player is actually the stronger, ignoring draws.
+pub fn los(wins: u32, losses: u32) -> f64 {
+    let decisive = f64::from(wins + losses);
+    if decisive == 0.0 {
+        return 0.5;
+    }
+    let diff = f64::from(wins) - f64::from(losses);
+    0.5 * (1.0 + erf(diff / (2.0 * decisive).sqrt()))
+}
+
+fn mean_and_stderr(buckets: &[(f64, u32)]) -> Option<(f64, f64)> {
+    let n: f64 = buckets.iter().map(|&(_, c)| f64::from(c)).sum();
+    if n == 0.0 {
+        return None;
+    }
+    let mean = buckets.iter().map(|&(x, c)| x * f64::from(c)).sum::<f64>() / n;
+    let variance = buckets
+        .iter()
+        .map(|&(x, c)| f64::from(c) * (x - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    Some((mean, (variance / n).sqrt()))
+}
+
+fn elo_error(mean: f64, stderr: f64) -> f64 {
+    let hi = elo_from_score(mean + Z_95 * stderr);
+    let lo = elo_from_score(mean - Z_95 * stderr);
+    (hi - lo) / 2.0
+}
+
+// Abramowitz & Stegun 7.1.26, good to about 1e-7 which is plenty here.
+fn erf(x: f64) -> f64 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    sign * (1.0 - poly * (-x * x).exp())
+}
+
+impl std::fmt::Display for EloEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Elo: {:.2} +/- {:.2}, LOS: {:.2} %",
+            self.elo,
+            self.error,
+            self.los * 100.0
+        )
+    }
+}
+impl std::fmt::Display for Trinomial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Games: {}, W: {}, D: {}, L: {}",
+            self.games(),
+            self.wins,
+            self.draws,
+            self.losses
+        )
+    }
+}
+impl std::fmt::Display for Pentanomial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e] = self.0;
+        write!(f, "Ptnml(0-2): [{a}, {b}, {c}, {d}, {e}]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn even_match_is_zero_elo() {
+        let t = Trinomial {
+            wins: 30,
+            draws: 40,
+            losses: 30,
+        };
+        let e = t.estimate().unwrap();
+        assert!(close(e.elo, 0.0));
+        assert!(close(e.los, 0.5));
+        assert!(e.error > 0.0);
+    }
+
+    #[test]
+    fn known_score_to_elo() {
+        assert!(close(elo_from_score(0.75), 190.849));
+        assert!(close(elo_from_score(0.25), -190.849));
+        assert_eq!(Trinomial::default().estimate(), None);
+    }
+
+    #[test]
+    fn match_reports() {
+        let mut stats = MatchStats::new();
+        let mut out = Vec::new();
+        stats.report_summary(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Games: 0, W: 0, D: 0, L: 0\n"
+        );
+
+        // A win and a loss with the same opening is a drawn pair; the last game has no partner.
+        let mut out = Vec::new();
+        for score in [1.0, 0.0, 0.5] {
+            stats.add_game(score);
+            stats.report_game(&mut out).unwrap();
+        }
+        assert_eq!(stats.pairs.0, [0, 0, 1, 0, 0]);
+        stats.report_summary(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[0].starts_with("Games: 1, W: 1, D: 0, L: 0, Elo: "));
+        assert!(lines[2].starts_with("Games: 3, W: 1, D: 1, L: 1, Elo: "));
+        assert!(lines[2].ends_with("LOS: 50.00 %"));
+        assert_eq!(lines[3], "Games: 3, W: 1, D: 1, L: 1");
+        assert_eq!(lines[5], "Ptnml(0-2): [0, 0, 1, 0, 0], draw pairs: 100.0 %");
+        assert!(lines[6].starts_with("Pairs Elo: ") && lines[6].ends_with("LOS: 50.00 %"));
+    }
+
+    #[test]
+    fn pentanomial_pairs() {
+        let mut p = Pentanomial::default();
+        p.add_pair(1.0, 0.5);
+        p.add_pair(0.5, 0.5);
+        p.add_pair(1.0, 0.0);
+        p.add_pair(0.0, 0.0);
+        assert_eq!(p.0, [1, 0, 2, 1, 0]);
+        assert_eq!(
+            p.trinomial(),
+            Trinomial {
+                wins: 1,
+                draws: 5,
+                losses: 2
+            }
+        );
+        assert!(close(p.draw_pair_ratio(), 0.5));
+        assert!(p.estimate().unwrap().elo < 0.0);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::color::Color;
+use crate::piece::Piece;
+use crate::position::{CastleFlag, Position, PositionBuilder};
+use crate::square::{File, Rank, Square};
+
+// Builds a position from a drawn board, so tests can show the position rather than a FEN.
+// The board is eight rows from rank 8 down to rank 1, using FEN piece letters and `.` for
+// empty squares. Whitespace between squares, and blank lines around the board, are ignored.
+//
+//     let pos = board(
+//         "
+//         . . . . k . . .
+//         . . . . . . p .
+//         . . . . . . . .
+//         . . . . P . . .
+//         . . . . . . P P
+//         . . . . . . . .
+//         . . . . . . . .
+//         . . . . K . . .
+//         ",
+//         Color::White,
+//         "-",
+//         None,
+//     );
+pub fn board(rows: &str, to_move: Color, castling: &str, ep: Option<Square>) -> Position {
+    let rows: Vec<Vec<char>> = rows
+        .lines()
+        .map(|l| l.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>())
+        .filter(|r| !r.is_empty())
+        .collect();
+    assert_eq!(rows.len(), 8, "board: expected 8 ranks, got {}", rows.len());
+
+    let mut builder = PositionBuilder::new().side_to_move(to_move).ep(ep);
+    for (i, row) in rows.iter().enumerate() {
+        assert_eq!(
+            row.len(),
+            8,
+            "board: rank {} has {} squares",
+            8 - i,
+            row.len()
+        );
+
+        for (file, &c) in row.iter().enumerate() {
+            if c == '.' {
+                continue;
+            }
+            let piece = Piece::try_from(c).unwrap_or_else(|_| panic!("board: unknown piece {c}"));
+            let square = Square::new(
+                File::try_from(file as u8).unwrap(),
+                Rank::try_from(7 - i as u8).unwrap(),
+            );
+            builder = builder.piece(square, piece);
+        }
+    }
+
+    let rights: Vec<CastleFlag> = castling
+        .chars()
+        .filter(|&c| c != '-')
+        .map(|c| match c {
+            'K' => CastleFlag::WhiteShort,
+            'Q' => CastleFlag::WhiteLong,
+            'k' => CastleFlag::BlackShort,
+            'q' => CastleFlag::BlackLong,
+            _ => panic!("board: unknown castling right {c}"),
+        })
+        .collect();
+
+    builder
+        .castling(&rights)
+        .build()
+        .unwrap_or_else(|e| panic!("board: {e}"))
+}
+
+// Counts heap allocations per thread, so tests can check a code path never allocates
+// without being thrown off by other tests running at the same time.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+unsafe impl std::alloc::GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|c| c.set(c.get() + 1));
+        std::alloc::System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        std::alloc::System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// Number of allocations made by the current thread so far.
+pub fn allocations() -> usize {
+    ALLOCATIONS.with(|c| c.get())
+}
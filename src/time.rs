@@ -0,0 +1,218 @@
+use std::time::{Duration, Instant};
+
+use crate::color::Color;
+use crate::errors::UciError;
+
+// Kept back from every clocked move for the GUI and the pipe between us.
+pub const MOVE_OVERHEAD: Duration = Duration::from_millis(10);
+// How many more moves to plan for when the GUI doesn't say (no `movestogo`).
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+// The limits of a UCI `go`, as given. Everything the GUI left out is `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub depth: Option<u32>,
+    // Look for a mate in this many moves (not plies).
+    pub mate: Option<u32>,
+    // Search until told to stop, however long that takes.
+    pub infinite: bool,
+    // Think on the opponent's time: the limits only apply after `ponderhit`.
+    pub ponder: bool,
+}
+
+impl Limits {
+    // The words after `go`. Anything this doesn't know (`searchmoves`, `nodes` ...) is skipped,
+    // but a known limit must have a number after it.
+    pub fn parse(words: &[&str]) -> Result<Self, UciError> {
+        let mut limits = Self::default();
+        let mut words = words.iter();
+        while let Some(&word) = words.next() {
+            let mut number = || -> Result<u64, UciError> {
+                let value = words.next().copied().unwrap_or("");
+                value
+                    .parse()
+                    .map_err(|_| UciError::Syntax(format!("bad {}: {:?}", word, value)))
+            };
+            let millis = |n: u64| Some(Duration::from_millis(n));
+
+            match word {
+                "wtime" => limits.wtime = millis(number()?),
+                "btime" => limits.btime = millis(number()?),
+                "winc" => limits.winc = millis(number()?),
+                "binc" => limits.binc = millis(number()?),
+                "movetime" => limits.movetime = millis(number()?),
+                "movestogo" => limits.movestogo = Some(number()? as u32),
+                "depth" => limits.depth = Some(number()? as u32),
+                "mate" => limits.mate = Some(number()? as u32),
+                "infinite" => limits.infinite = true,
+                "ponder" => limits.ponder = true,
+                _ => (),
+            }
+        }
+        Ok(limits)
+    }
+
+    // Whether there is any time limit at all, for either side.
+    pub fn is_timed(&self) -> bool {
+        self.movetime.is_some() || self.wtime.is_some() || self.btime.is_some()
+    }
+}
+
+// When a search has to finish. The soft limit is checked between iterations: past it, another
+// one isn't started. The hard limit is polled inside the search, which gives up as soon as it
+// is reached.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeManager {
+    start: Instant,
+    soft: Option<Duration>,
+    hard: Option<Duration>,
+}
+
+impl TimeManager {
+    // No limits: the search runs to its depth.
+    pub fn infinite() -> Self {
+        Self {
+            start: Instant::now(),
+            soft: None,
+            hard: None,
+        }
+    }
+
+    // Limits for `us` to move, counted from now. A `movetime` is used whole; on a clock, a
+    // share of what's left plus most of the increment, and never more than three quarters of it.
+    // `infinite` overrides them all.
+    pub fn new(limits: &Limits, us: Color) -> Self {
+        if limits.infinite {
+            return Self::infinite();
+        }
+        let (time, inc) = match us {
+            Color::White => (limits.wtime, limits.winc),
+            Color::Black => (limits.btime, limits.binc),
+        };
+
+        let (soft, hard) = if let Some(movetime) = limits.movetime {
+            let t = movetime.saturating_sub(MOVE_OVERHEAD);
+            (Some(t), Some(t))
+        } else if let Some(time) = time {
+            let available = time.saturating_sub(MOVE_OVERHEAD);
+            let inc = inc.unwrap_or(Duration::ZERO);
+            let moves = limits.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+            let soft = (available / moves + inc * 3 / 4).min(available / 2);
+            let hard = (soft * 3).min(available * 3 / 4);
+            (Some(soft), Some(hard))
+        } else {
+            (None, None)
+        };
+
+        Self {
+            start: Instant::now(),
+            soft,
+            hard,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+    pub const fn soft_limit(&self) -> Option<Duration> {
+        self.soft
+    }
+    pub const fn hard_limit(&self) -> Option<Duration> {
+        self.hard
+    }
+
+    // Between iterations: whether there is time to start another.
+    pub fn can_continue(&self) -> bool {
+        self.soft.is_none_or(|soft| self.elapsed() < soft)
+    }
+    // Inside the search: whether it has to stop right now.
+    pub fn must_stop(&self) -> bool {
+        self.hard.is_some_and(|hard| self.elapsed() >= hard)
+    }
+}
+
+impl Default for TimeManager {
+    fn default() -> Self {
+        Self::infinite()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_go() {
+        let limits =
+            Limits::parse(&["wtime", "60000", "btime", "50000", "winc", "1000", "ponder"]).unwrap();
+        assert_eq!(limits.wtime, Some(Duration::from_secs(60)));
+        assert_eq!(limits.btime, Some(Duration::from_secs(50)));
+        assert_eq!(limits.winc, Some(Duration::from_secs(1)));
+        assert_eq!(limits.binc, None);
+        assert!(limits.is_timed() && limits.ponder && !limits.infinite);
+
+        let limits = Limits::parse(&["depth", "7", "movestogo", "5", "infinite"]).unwrap();
+        assert_eq!((limits.depth, limits.movestogo), (Some(7), Some(5)));
+        assert_eq!(Limits::parse(&["mate", "3"]).unwrap().mate, Some(3));
+        assert!(Limits::parse(&["mate", "soon"]).is_err());
+        assert!(!limits.is_timed() && limits.infinite);
+
+        assert!(Limits::parse(&["movetime", "soon"]).is_err());
+        assert!(Limits::parse(&["wtime"]).is_err());
+        assert_eq!(Limits::parse(&[]), Ok(Limits::default()));
+    }
+
+    #[test]
+    fn limits() {
+        let infinite = TimeManager::new(&Limits::default(), Color::White);
+        assert_eq!((infinite.soft_limit(), infinite.hard_limit()), (None, None));
+        assert!(infinite.can_continue() && !infinite.must_stop());
+
+        let fixed = Limits {
+            movetime: Some(Duration::from_millis(510)),
+            ..Limits::default()
+        };
+        let tm = TimeManager::new(&fixed, Color::Black);
+        assert_eq!(tm.soft_limit(), Some(Duration::from_millis(500)));
+        assert_eq!(tm.hard_limit(), Some(Duration::from_millis(500)));
+
+        let clock = Limits {
+            wtime: Some(Duration::from_millis(30_010)),
+            btime: Some(Duration::from_millis(1_010)),
+            winc: Some(Duration::from_millis(400)),
+            ..Limits::default()
+        };
+        let white = TimeManager::new(&clock, Color::White);
+        assert_eq!(white.soft_limit(), Some(Duration::from_millis(1_300)));
+        assert_eq!(white.hard_limit(), Some(Duration::from_millis(3_900)));
+        let black = TimeManager::new(&clock, Color::Black);
+        assert!(black.hard_limit().unwrap() <= Duration::from_millis(750));
+
+        // One move left to the control: at most half of what's there, to be safe.
+        let last = Limits {
+            movestogo: Some(1),
+            ..clock
+        };
+        let tm = TimeManager::new(&last, Color::White);
+        assert_eq!(tm.soft_limit(), Some(Duration::from_millis(15_000)));
+        assert_eq!(tm.hard_limit(), Some(Duration::from_millis(22_500)));
+
+        let out_of_time = Limits {
+            wtime: Some(Duration::ZERO),
+            ..Limits::default()
+        };
+        assert!(TimeManager::new(&out_of_time, Color::White).must_stop());
+        let forever = Limits {
+            infinite: true,
+            ..out_of_time
+        };
+        assert!(!TimeManager::new(&forever, Color::White).must_stop());
+    }
+}
@@ -0,0 +1,276 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::movegen::Move;
+use crate::search::is_mate;
+
+// What a stored score says about the real one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // The score is at least this: the search failed high.
+    Lower,
+    // At most this: every move failed low.
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtEntry {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+}
+
+// The key is kept xored with the data, so a slot two threads wrote at once (one's key, the
+// other's data) just fails to match rather than handing back another position's entry.
+#[derive(Default)]
+struct Slot {
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+// A hash table of search results that any number of threads can share without locks, for
+// Lazy SMP. One entry per slot, and a slot only ever holds one position.
+pub struct TranspositionTable {
+    slots: Box<[Slot]>,
+}
+
+impl TranspositionTable {
+    pub const DEFAULT_MB: usize = 16;
+
+    pub fn new(megabytes: usize) -> Self {
+        let count = (megabytes.max(1) << 20) / std::mem::size_of::<Slot>();
+        Self {
+            slots: (0..count).map(|_| Slot::default()).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.check.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    // Mate scores are kept relative to the position, not the root, so `ply` is how far the
+    // position is from the root of the search probing it.
+    pub fn probe(&self, key: u64, ply: i32) -> Option<TtEntry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+        if slot.check.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        let mut entry = unpack(data)?;
+        entry.score = from_tt(entry.score, ply);
+        Some(entry)
+    }
+
+    // Replaces whatever is there unless it is the same position searched deeper. A move already
+    // stored for the position is kept if the new entry has none.
+    pub fn store(&self, key: u64, entry: TtEntry, ply: i32) {
+        let slot = self.slot(key);
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let old = (slot.check.load(Ordering::Relaxed) ^ old_data == key)
+            .then(|| unpack(old_data))
+            .flatten();
+
+        let mut entry = TtEntry {
+            score: to_tt(entry.score, ply),
+            ..entry
+        };
+        if let Some(old) = old {
+            if old.depth > entry.depth && entry.bound != Bound::Exact {
+                return;
+            }
+            entry.best_move = entry.best_move.or(old.best_move);
+        }
+
+        let data = pack(entry);
+        slot.check.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    // Starts loading the slot for `key` into cache, for a probe that's coming soon. Only a hint:
+    // it does nothing where there's no instruction for it.
+    #[cfg_attr(feature = "inline", inline)]
+    pub fn prefetch(&self, key: u64) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            // SAFETY: Prefetching never faults, and this is a valid address anyway.
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(self.slot(key) as *const Slot as *const i8) };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = key;
+    }
+
+    // How full the table is, in thousandths, from a sample of the first thousand slots: what UCI
+    // reports as `hashfull`.
+    pub fn hashfull(&self) -> usize {
+        let sample = &self.slots[..self.slots.len().min(1000)];
+        let used = sample
+            .iter()
+            .filter(|s| unpack(s.data.load(Ordering::Relaxed)).is_some())
+            .count();
+        used * 1000 / sample.len().max(1)
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        // The high bits of key * len: an index in range without a division.
+        let index = ((key as u128 * self.slots.len() as u128) >> 64) as usize;
+        &self.slots[index]
+    }
+}
+
+impl std::fmt::Debug for TranspositionTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TranspositionTable({} slots)", self.slots.len())
+    }
+}
+
+// Move in the low 16 bits (0 for none), then the score, the depth and the bound. A bound of 0
+// marks an empty slot.
+fn pack(entry: TtEntry) -> u64 {
+    let mov = entry.best_move.map_or(0, Move::to_u16) as u64;
+    let score = entry.score as i16 as u16 as u64;
+    let depth = entry.depth.min(u8::MAX as u32) as u64;
+    let bound = match entry.bound {
+        Bound::Exact => 1,
+        Bound::Lower => 2,
+        Bound::Upper => 3,
+    };
+    mov | score << 16 | depth << 32 | bound << 40
+}
+fn unpack(data: u64) -> Option<TtEntry> {
+    let bound = match (data >> 40) & 3 {
+        1 => Bound::Exact,
+        2 => Bound::Lower,
+        3 => Bound::Upper,
+        _ => return None,
+    };
+    Some(TtEntry {
+        best_move: Move::from_u16_checked(data as u16),
+        score: (data >> 16) as u16 as i16 as i32,
+        depth: ((data >> 32) & 0xFF) as u32,
+        bound,
+    })
+}
+
+// Mate scores count plies from the root; stored, they count from the position itself.
+fn to_tt(score: i32, ply: i32) -> i32 {
+    match score {
+        s if !is_mate(s) => s,
+        s if s > 0 => s + ply,
+        s => s - ply,
+    }
+}
+fn from_tt(score: i32, ply: i32) -> i32 {
+    match score {
+        s if !is_mate(s) => s,
+        s if s > 0 => s - ply,
+        s => s + ply,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE;
+    use crate::square::Square::*;
+    use std::thread;
+
+    fn entry(score: i32, depth: u32, bound: Bound) -> TtEntry {
+        TtEntry {
+            best_move: Some(Move::new(E2, E4)),
+            score,
+            depth,
+            bound,
+        }
+    }
+
+    #[test]
+    fn stores_and_probes() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.len(), (1 << 20) / 16);
+        assert_eq!(tt.probe(12345, 0), None);
+        assert_eq!(tt.hashfull(), 0);
+
+        tt.store(12345, entry(-37, 6, Bound::Lower), 0);
+        assert_eq!(tt.probe(12345, 0), Some(entry(-37, 6, Bound::Lower)));
+        assert_eq!(tt.probe(12346, 0), None);
+
+        // Shallower results don't push out deeper ones, unless exact.
+        tt.store(12345, entry(10, 2, Bound::Upper), 0);
+        assert_eq!(tt.probe(12345, 0).unwrap().depth, 6);
+        let no_move = TtEntry {
+            best_move: None,
+            ..entry(10, 2, Bound::Exact)
+        };
+        tt.store(12345, no_move, 0);
+        assert_eq!(tt.probe(12345, 0), Some(entry(10, 2, Bound::Exact)));
+
+        tt.clear();
+        assert_eq!(tt.probe(12345, 0), None);
+    }
+
+    #[test]
+    fn prefetch_is_only_a_hint() {
+        use crate::movegen::generate;
+        use crate::position::Position;
+
+        let tt = TranspositionTable::new(1);
+        tt.store(12345, entry(-37, 6, Bound::Lower), 0);
+        // Every key maps to some slot, the first and last included.
+        for key in [0, 12345, u64::MAX] {
+            tt.prefetch(key);
+        }
+        let pos = Position::new_from_fen(Position::KIWIPETE_FEN);
+        for m in &generate::legal(&pos) {
+            tt.prefetch(pos.key_after(m));
+        }
+        assert_eq!(tt.probe(12345, 0), Some(entry(-37, 6, Bound::Lower)));
+        assert_eq!(tt.probe(u64::MAX, 0), None);
+    }
+
+    #[test]
+    fn mate_scores_are_relative_to_the_position() {
+        let tt = TranspositionTable::new(1);
+        // Mate in 3 plies from a position 4 plies into the search...
+        tt.store(99, entry(MATE - 7, 5, Bound::Exact), 4);
+        // ...is mate in 3 plies from wherever it is found again.
+        assert_eq!(tt.probe(99, 4).unwrap().score, MATE - 7);
+        assert_eq!(tt.probe(99, 1).unwrap().score, MATE - 4);
+        tt.store(98, entry(-MATE + 6, 5, Bound::Exact), 2);
+        assert_eq!(tt.probe(98, 0).unwrap().score, -MATE + 4);
+        tt.store(97, entry(250, 5, Bound::Exact), 2);
+        assert_eq!(tt.probe(97, 9).unwrap().score, 250);
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let tt = TranspositionTable::new(1);
+        // Keys with the same high bits land in the same slot, so these all fight over one.
+        let keys: Vec<u64> = (0..4).map(|i| i + 1).collect();
+        thread::scope(|s| {
+            for &key in &keys {
+                let tt = &tt;
+                s.spawn(move || {
+                    for n in 0..10_000 {
+                        tt.store(key, entry(key as i32, n % 50, Bound::Exact), 0);
+                        if let Some(e) = tt.probe(key, 0) {
+                            assert_eq!(e.score, key as i32);
+                        }
+                    }
+                });
+            }
+        });
+        assert!(keys.iter().filter(|&&k| tt.probe(k, 0).is_some()).count() <= 1);
+    }
+}
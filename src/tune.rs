@@ -0,0 +1,343 @@
+use std::error::Error;
+use std::fs;
+
+use crate::color::Color::*;
+use crate::errors::LabelError;
+use crate::eval::{self, EvalParams};
+use crate::piece::PieceType;
+use crate::position::Position;
+use crate::values;
+
+// Texel tuning of `EvalParams`: fit the evaluation to the results of the games a set of
+// positions came from. A score maps to an expected result through a logistic curve, and the
+// parameters are moved to bring the mean squared difference from the actual results down.
+
+// A position with how its game ended for White: 1, 0.5 or 0. The evaluation is linear in the
+// parameters, so only the weight each one gets in this position is kept, as `EvalParams::quick`
+// would add them up from White's side. The king's tables get a fraction each, by phase.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    features: Vec<(usize, f64)>,
+    result: f64,
+}
+
+impl Sample {
+    pub fn new(pos: &Position, result: f64) -> Self {
+        let mut weights = vec![0.0; EvalParams::LEN];
+        let phase = eval::phase(pos) as f64 / values::TOTAL_PHASE as f64;
+        let king_endgame = 6 + 6 * 64;
+
+        for (color, sign) in [(White, 1.0), (Black, -1.0)] {
+            for kind in PieceType::all() {
+                for sq in pos.spec(kind, color) {
+                    let index = match color {
+                        White => sq.flip_vertical() as usize,
+                        Black => sq as usize,
+                    };
+                    let table = 6 + 64 * kind as usize + index;
+
+                    weights[kind as usize] += sign;
+                    if kind == PieceType::King {
+                        weights[table] += sign * phase;
+                        weights[king_endgame + index] += sign * (1.0 - phase);
+                    } else {
+                        weights[table] += sign;
+                    }
+                }
+            }
+        }
+
+        Self {
+            features: weights
+                .into_iter()
+                .enumerate()
+                .filter(|&(_, w)| w != 0.0)
+                .collect(),
+            result,
+        }
+    }
+
+    // A FEN (the counters may be left off) followed somewhere by its game's result: `1-0`,
+    // `0-1` or `1/2-1/2`, quoted or not, or `[1.0]`, `[0.5]` or `[0.0]`. Anything else on the
+    // line, an EPD opcode say, is ignored.
+    pub fn parse(line: &str) -> Result<Self, LabelError> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let fen = fields.iter().take(4).copied().collect::<Vec<_>>().join(" ");
+        let pos = Position::try_from_fen(&fen)?;
+
+        let result = fields
+            .iter()
+            .skip(4)
+            .find_map(
+                |f| match f.trim_matches(|c| matches!(c, '"' | ';' | '[' | ']')) {
+                    "1-0" | "1.0" => Some(1.0),
+                    "1/2-1/2" | "0.5" => Some(0.5),
+                    "0-1" | "0.0" => Some(0.0),
+                    _ => None,
+                },
+            )
+            .ok_or_else(|| LabelError::MissingResult(line.to_string()))?;
+
+        Ok(Self::new(&pos, result))
+    }
+
+    pub fn result(&self) -> f64 {
+        self.result
+    }
+
+    // What `EvalParams::quick` gives for White, from the parameters as a flat list.
+    fn score(&self, values: &[f64]) -> f64 {
+        self.features.iter().map(|&(i, w)| values[i] * w).sum()
+    }
+}
+
+pub struct Tuner {
+    samples: Vec<Sample>,
+    // For each parameter, the samples it counts in, with its weight there.
+    uses: Vec<Vec<(usize, f64)>>,
+    // Scales centipawns on the logistic curve; fitted to the starting parameters.
+    k: f64,
+}
+
+impl Tuner {
+    pub fn new(samples: Vec<Sample>) -> Self {
+        let mut uses = vec![Vec::new(); EvalParams::LEN];
+        for (s, sample) in samples.iter().enumerate() {
+            for &(i, w) in &sample.features {
+                uses[i].push((s, w));
+            }
+        }
+
+        let mut tuner = Self {
+            samples,
+            uses,
+            k: 1.0,
+        };
+        tuner.k = tuner.fit_k(&EvalParams::DEFAULT);
+        tuner
+    }
+
+    pub fn k(&self) -> f64 {
+        self.k
+    }
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    // The mean squared difference between the results and what `params` predicts.
+    pub fn error(&self, params: &EvalParams) -> f64 {
+        let values = as_floats(params);
+        let scores: Vec<f64> = self.samples.iter().map(|s| s.score(&values)).collect();
+        self.total_error(&scores, self.k) / self.samples.len().max(1) as f64
+    }
+
+    // Texel's local search: each parameter in turn is nudged by one either way, and the change
+    // kept if the error goes down. Passes repeat until one changes nothing, or `passes` have
+    // been made, calling `progress` after each with its number and the error.
+    pub fn local_search(
+        &self,
+        params: &EvalParams,
+        passes: usize,
+        mut progress: impl FnMut(usize, f64),
+    ) -> EvalParams {
+        let mut values = as_floats(params);
+        let mut scores: Vec<f64> = self.samples.iter().map(|s| s.score(&values)).collect();
+
+        for pass in 1..=passes {
+            let mut improved = false;
+            for (i, uses) in self.uses.iter().enumerate() {
+                for delta in [1.0, -1.0] {
+                    let change: f64 = uses
+                        .iter()
+                        .map(|&(s, w)| {
+                            let result = self.samples[s].result;
+                            let before = result - sigmoid(scores[s], self.k);
+                            let after = result - sigmoid(scores[s] + w * delta, self.k);
+                            after * after - before * before
+                        })
+                        .sum();
+                    if change < 0.0 {
+                        values[i] += delta;
+                        for &(s, w) in uses {
+                            scores[s] += w * delta;
+                        }
+                        improved = true;
+                        break;
+                    }
+                }
+            }
+
+            progress(
+                pass,
+                self.total_error(&scores, self.k) / self.samples.len().max(1) as f64,
+            );
+            if !improved {
+                break;
+            }
+        }
+
+        EvalParams::from_slice(&values.iter().map(|&v| v as i32).collect::<Vec<_>>())
+    }
+
+    // The `k` that best fits the results with `params` as they are, by ternary search.
+    fn fit_k(&self, params: &EvalParams) -> f64 {
+        let values = as_floats(params);
+        let scores: Vec<f64> = self.samples.iter().map(|s| s.score(&values)).collect();
+
+        let (mut lo, mut hi) = (0.0, 10.0);
+        for _ in 0..100 {
+            let a = lo + (hi - lo) / 3.0;
+            let b = hi - (hi - lo) / 3.0;
+            if self.total_error(&scores, a) < self.total_error(&scores, b) {
+                hi = b;
+            } else {
+                lo = a;
+            }
+        }
+        (lo + hi) / 2.0
+    }
+
+    fn total_error(&self, scores: &[f64], k: f64) -> f64 {
+        self.samples
+            .iter()
+            .zip(scores)
+            .map(|(s, &score)| (s.result - sigmoid(score, k)).powi(2))
+            .sum()
+    }
+}
+
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+fn as_floats(params: &EvalParams) -> Vec<f64> {
+    params.to_vec().into_iter().map(f64::from).collect()
+}
+
+// The `tune` command: `tune <positions> [passes] [parameters]`. Reads one labeled position per
+// line (see `Sample::parse`; blank lines and `#` comments are skipped), tunes from the given
+// parameters file or the built in values, and prints the result in `EvalParams::to_text` form.
+// Progress goes to stderr.
+pub fn command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [data, rest @ ..] = args else {
+        return Err("usage: tune <positions> [passes] [parameters]".into());
+    };
+    let passes = match rest.first() {
+        Some(p) => p.parse().map_err(|_| format!("bad pass count: {}", p))?,
+        None => 100,
+    };
+    let start = match rest.get(1) {
+        Some(path) => EvalParams::from_text(&fs::read_to_string(path)?)?,
+        None => EvalParams::DEFAULT,
+    };
+
+    let mut samples = Vec::new();
+    for (i, line) in fs::read_to_string(data)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        samples.push(Sample::parse(line).map_err(|e| format!("line {}: {}", i + 1, e))?);
+    }
+
+    let tuner = Tuner::new(samples);
+    eprintln!(
+        "{} positions, k {:.4}, error {:.6}",
+        tuner.len(),
+        tuner.k(),
+        tuner.error(&start)
+    );
+    let tuned = tuner.local_search(&start, passes, |pass, error| {
+        eprintln!("pass {}: error {:.6}", pass, error);
+    });
+    print!("{}", tuned.to_text());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_score_like_quick() {
+        for fen in [
+            Position::KIWIPETE_FEN,
+            "4k3/8/8/8/8/8/8/R3K3 b - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            let pos = Position::new_from_fen(fen);
+            let white = match pos.to_move() {
+                White => eval::quick(&pos),
+                Black => -eval::quick(&pos),
+            };
+            let score = Sample::new(&pos, 0.5).score(&as_floats(&EvalParams::DEFAULT));
+            // The king tables blend by phase in integers in `quick`.
+            assert!(
+                (score - white as f64).abs() < 2.0,
+                "{fen}: {score} vs {white}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_labels() {
+        let results = [
+            ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 1-0", 1.0),
+            ("4k3/8/8/8/8/8/8/R3K3 w - - c9 \"1/2-1/2\";", 0.5),
+            ("4k3/8/8/8/8/8/8/R3K3 w - - [0.0]", 0.0),
+            ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 \"0-1\"", 0.0),
+        ];
+        for (line, result) in results {
+            assert_eq!(Sample::parse(line).unwrap().result(), result, "{line}");
+        }
+
+        assert!(matches!(
+            Sample::parse("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"),
+            Err(LabelError::MissingResult(_))
+        ));
+        assert!(matches!(
+            Sample::parse("4k3/8/8/8 w - - 1-0"),
+            Err(LabelError::Fen(_))
+        ));
+    }
+
+    #[test]
+    fn local_search_lowers_the_error() {
+        // Games where a knight against a pawn was only ever drawn: the knight is overvalued.
+        let lines = [
+            "4k3/8/8/8/8/8/7p/1N2K3 w - - 1/2-1/2",
+            "4k3/p7/8/8/8/8/8/2N1K3 w - - 1/2-1/2",
+            "4k3/8/8/8/8/6p1/8/1N2K3 b - - 1/2-1/2",
+            "4k3/8/1p6/8/8/8/3N4/4K3 b - - 1/2-1/2",
+            "4k3/8/8/8/8/8/8/R3K3 w - - 1-0",
+            "r3k3/8/8/8/8/8/8/4K3 w - - 0-1",
+        ];
+        let samples = lines.iter().map(|l| Sample::parse(l).unwrap()).collect();
+        let tuner = Tuner::new(samples);
+
+        let before = tuner.error(&EvalParams::DEFAULT);
+        let mut errors = Vec::new();
+        let tuned = tuner.local_search(&EvalParams::DEFAULT, 5, |_, e| errors.push(e));
+        assert_eq!(errors.len(), 5);
+        assert!(errors.windows(2).all(|w| w[1] <= w[0]));
+        assert!((tuner.error(&tuned) - errors[4]).abs() < 1e-9);
+        assert!(errors[4] < before);
+        assert!(tuned.material[PieceType::Knight as usize] < values::KNIGHT);
+        // The king never counts towards material, so nothing moves it.
+        assert_eq!(tuned.material[PieceType::King as usize], 0);
+    }
+
+    #[test]
+    fn no_samples() {
+        let tuner = Tuner::new(Vec::new());
+        assert!(tuner.is_empty() && tuner.k().is_finite());
+        assert_eq!(tuner.error(&EvalParams::DEFAULT), 0.0);
+
+        let mut errors = Vec::new();
+        let tuned = tuner.local_search(&EvalParams::DEFAULT, 3, |_, e| errors.push(e));
+        assert_eq!(errors, [0.0]);
+        assert_eq!(tuned, EvalParams::DEFAULT);
+    }
+}
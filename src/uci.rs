@@ -0,0 +1,510 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Instant;
+
+use crate::bench::STANDARD_FENS;
+use crate::engine::{panic_message, Engine, GoOutcome};
+use crate::errors::UciError;
+use crate::info::engine_info;
+use crate::mate::MateSolver;
+use crate::perft;
+use crate::position::Position;
+use crate::search::{Score, Searcher, Signals};
+use crate::time::Limits;
+use crate::wdl::WdlModel;
+
+const DEFAULT_DEPTH: u32 = 5;
+const BENCH_DEPTH: u32 = 4;
+const MAX_THREADS: usize = 256;
+
+// What the protocol loop waits on: the GUI's next line, or a search coming back.
+enum Event {
+    Line(std::io::Result<String>),
+    Eof,
+    Done(GoOutcome),
+}
+
+// A search on its own thread. After `go infinite`, and while pondering, its bestmove is held
+// back until the GUI asks for it with `stop` or `ponderhit`, even if the search is long done.
+#[derive(Debug)]
+struct Running {
+    signals: Signals,
+    infinite: bool,
+    outcome: Option<GoOutcome>,
+}
+
+impl Running {
+    fn holds(&self) -> bool {
+        self.infinite || self.signals.is_pondering()
+    }
+}
+
+// The game, the options the GUI has set, and the search if one is running.
+#[derive(Debug)]
+struct Session {
+    engine: Engine,
+    show_wdl: bool,
+    wdl_model: WdlModel,
+    search: Option<Running>,
+    events: Sender<Event>,
+}
+
+impl Session {
+    fn new(events: Sender<Event>) -> Self {
+        Self {
+            engine: Engine::new(),
+            show_wdl: false,
+            wdl_model: WdlModel::default(),
+            search: None,
+            events,
+        }
+    }
+
+    fn start(&mut self, limits: Limits) {
+        let signals = if limits.ponder {
+            Signals::pondering()
+        } else {
+            Signals::new()
+        };
+        let engine = self.engine.clone();
+        let events = self.events.clone();
+        let search_signals = signals.clone();
+        thread::spawn(move || {
+            let outcome = engine.go_with(&limits, search_signals);
+            let _ = events.send(Event::Done(outcome));
+        });
+
+        self.search = Some(Running {
+            signals,
+            infinite: limits.infinite,
+            outcome: None,
+        });
+    }
+
+    fn stop(&mut self, reply: &mut Vec<String>) {
+        if let Some(running) = &mut self.search {
+            running.infinite = false;
+            running.signals.ponderhit();
+            running.signals.stop();
+        }
+        self.release(reply);
+    }
+    fn ponderhit(&mut self, reply: &mut Vec<String>) {
+        if let Some(running) = &self.search {
+            running.signals.ponderhit();
+        }
+        self.release(reply);
+    }
+    fn finished(&mut self, outcome: GoOutcome, reply: &mut Vec<String>) {
+        if let Some(running) = &mut self.search {
+            running.outcome = Some(outcome);
+        }
+        self.release(reply);
+    }
+    // Reports the search once it is done and nothing holds it back any more.
+    fn release(&mut self, reply: &mut Vec<String>) {
+        let Some(running) = &mut self.search else {
+            return;
+        };
+        if running.holds() {
+            return;
+        }
+        if let Some(outcome) = running.outcome.take() {
+            self.search = None;
+            go(self, outcome, reply);
+        }
+    }
+}
+
+// The UCI protocol loop, until `quit` or the end of `input`. Searches run in the background, so
+// `stop`, `ponderhit` and `isready` are answered at once; any other command waits until the
+// search has reported. A command that panics is reported as an `info string` and the loop
+// carries on with the next one.
+pub fn run(input: impl BufRead + Send + 'static, output: &mut impl Write) -> std::io::Result<()> {
+    let (events, received) = mpsc::channel();
+    let lines = events.clone();
+    thread::spawn(move || {
+        for line in input.lines() {
+            let failed = line.is_err();
+            if lines.send(Event::Line(line)).is_err() || failed {
+                return;
+            }
+        }
+        let _ = lines.send(Event::Eof);
+    });
+
+    let mut session = Session::new(events);
+    let mut waiting = VecDeque::new();
+    let (mut quit, mut eof) = (false, false);
+
+    // The session keeps a sender, so this only ends by breaking out.
+    while let Ok(event) = received.recv() {
+        let mut reply = Vec::new();
+        match event {
+            Event::Line(line) => {
+                let line = line?;
+                match line.split_whitespace().next() {
+                    Some("quit") => {
+                        quit = true;
+                        session.stop(&mut reply);
+                    }
+                    Some("stop") if session.search.is_some() => session.stop(&mut reply),
+                    Some("ponderhit") if session.search.is_some() => session.ponderhit(&mut reply),
+                    Some("isready") => reply.push("readyok".to_string()),
+                    _ => waiting.push_back(line),
+                }
+            }
+            Event::Eof => eof = true,
+            Event::Done(outcome) => session.finished(outcome, &mut reply),
+        }
+
+        while session.search.is_none() && !quit {
+            let Some(line) = waiting.pop_front() else {
+                break;
+            };
+            handle(&mut session, &line, &mut reply);
+        }
+        // Nothing is coming to stop a search that waits for it.
+        if eof && session.search.as_ref().is_some_and(Running::holds) {
+            session.stop(&mut reply);
+        }
+
+        for r in reply {
+            writeln!(output, "{}", r)?;
+        }
+        output.flush()?;
+
+        if (quit || eof && waiting.is_empty()) && session.search.is_none() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn handle(session: &mut Session, line: &str, reply: &mut Vec<String>) {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let handled = panic::catch_unwind(AssertUnwindSafe(|| command(session, &words, reply)));
+    match handled {
+        Ok(Ok(())) => (),
+        Ok(Err(e)) => reply.push(format!("info string {}", e)),
+        Err(payload) => {
+            reply.push(format!("info string error: {}", panic_message(&*payload)));
+        }
+    }
+}
+
+fn command(session: &mut Session, words: &[&str], reply: &mut Vec<String>) -> Result<(), UciError> {
+    match words {
+        ["uci", ..] => {
+            reply.push(format!("id name {}", engine_info().id()));
+            reply.push("option name UCI_ShowWDL type check default false".to_string());
+            reply.push("option name Ponder type check default false".to_string());
+            reply.push(format!(
+                "option name Threads type spin default 1 min 1 max {}",
+                MAX_THREADS
+            ));
+            reply.push("uciok".to_string());
+        }
+        ["setoption", "name", name, "value", value] => {
+            if name.eq_ignore_ascii_case("UCI_ShowWDL") {
+                session.show_wdl = value.eq_ignore_ascii_case("true");
+            } else if name.eq_ignore_ascii_case("Ponder") {
+                // Only tells us the GUI may send `go ponder`, which works either way.
+            } else if name.eq_ignore_ascii_case("Threads") {
+                let threads = value
+                    .parse()
+                    .ok()
+                    .filter(|n| (1..=MAX_THREADS).contains(n))
+                    .ok_or_else(|| UciError::Syntax(format!("bad thread count: {}", value)))?;
+                session.engine.set_threads(threads);
+            } else {
+                return Err(UciError::UnknownOption(name.to_string()));
+            }
+        }
+        ["ucinewgame", ..] => session.engine.new_game(),
+        ["position", rest @ ..] => position(&mut session.engine, rest)?,
+        ["go", "perft", depth, ..] => {
+            let depth = depth
+                .parse()
+                .map_err(|_| UciError::Syntax(format!("bad perft depth: {}", depth)))?;
+            go_perft(&mut session.engine.position(), depth, reply);
+        }
+        ["go", rest @ ..] => {
+            let mut limits = Limits::parse(rest)?;
+            if let Some(moves) = limits.mate {
+                if go_mate(&mut session.engine.position(), moves, reply) {
+                    return Ok(());
+                }
+                // No mate that short: still a move, from a search as deep as the mate would be.
+                limits.depth = Some((2 * moves).max(2) - 1);
+            }
+            let unbounded = !limits.is_timed() && limits.depth.is_none();
+            if unbounded && !limits.infinite && !limits.ponder {
+                limits.depth = Some(DEFAULT_DEPTH);
+            }
+            session.start(limits);
+        }
+        // Nothing to stop.
+        ["stop" | "ponderhit", ..] => (),
+        ["bench", rest @ ..] => {
+            let depth = match rest {
+                [d, ..] => d.parse().unwrap_or(BENCH_DEPTH),
+                [] => BENCH_DEPTH,
+            };
+            bench(depth, reply);
+        }
+        [] => (),
+        [other, ..] => return Err(UciError::UnknownCommand(other.to_string())),
+    }
+
+    Ok(())
+}
+
+// `position (startpos | fen <fields>) [moves <m1> ...]`
+fn position(engine: &mut Engine, words: &[&str]) -> Result<(), UciError> {
+    let moves_at = words.iter().position(|&w| w == "moves");
+    let (setup, moves) = match moves_at {
+        Some(i) => (&words[..i], &words[i + 1..]),
+        None => (words, &[][..]),
+    };
+
+    match setup {
+        ["startpos"] => engine.set_position(None, moves),
+        ["fen", fen @ ..] => engine.set_position(Some(&fen.join(" ")), moves),
+        _ => Err(UciError::Syntax("expected `startpos` or `fen`".to_string())),
+    }
+}
+
+fn go(session: &Session, outcome: GoOutcome, reply: &mut Vec<String>) {
+    if let Some(res) = outcome.result {
+        let wdl = if session.show_wdl {
+            let pos = session.engine.position();
+            format!(" wdl {}", session.wdl_model.wdl(res.score, &pos))
+        } else {
+            String::new()
+        };
+        reply.push(format!(
+            "info depth {} score {}{} nodes {}",
+            res.depth,
+            Score::from(res.score),
+            wdl,
+            res.nodes
+        ));
+    }
+    if let Some(e) = outcome.error {
+        reply.push(format!("info string search failed: {}", e));
+    }
+
+    match outcome.best_move {
+        Some(m) => reply.push(format!("bestmove {}", m)),
+        None => reply.push("bestmove 0000".to_string()),
+    }
+}
+
+// A proven mate in at most `moves`, reported as a search would report it. Nothing is said if
+// there is none, and it's up to the caller to find a move some other way.
+fn go_mate(pos: &mut Position, moves: u32, reply: &mut Vec<String>) -> bool {
+    let mut solver = MateSolver::new();
+    let Some(line) = solver.solve(pos, moves) else {
+        return false;
+    };
+    let pv: Vec<String> = line.iter().map(|m| m.to_string()).collect();
+    reply.push(format!(
+        "info depth {} score {} nodes {} pv {}",
+        line.len(),
+        Score::Mate(line.len().div_ceil(2) as i32),
+        solver.nodes(),
+        pv.join(" ")
+    ));
+    reply.push(format!("bestmove {}", line[0]));
+    true
+}
+
+// Divide output as most engines print it: each root move with its count, then the total.
+fn go_perft(pos: &mut Position, depth: usize, reply: &mut Vec<String>) {
+    let counts = perft::divide(pos, depth);
+    for (m, nodes) in &counts {
+        reply.push(format!("{}: {}", m, nodes));
+    }
+    reply.push(String::new());
+    let total = match depth {
+        0 => 1,
+        _ => counts.iter().map(|&(_, n)| n).sum(),
+    };
+    reply.push(format!("Nodes searched: {}", total));
+}
+
+// A fixed-depth search of every bench position. The node count is a signature of the search:
+// it only changes when the search does.
+fn bench(depth: u32, reply: &mut Vec<String>) {
+    let start = Instant::now();
+    let mut nodes = 0;
+
+    for (i, fen) in STANDARD_FENS.iter().enumerate() {
+        let mut pos = Position::new_from_fen(fen);
+        let res = Searcher::new().search(&mut pos, depth);
+        reply.push(format!(
+            "info string position {}/{} nodes {} bestmove {}",
+            i + 1,
+            STANDARD_FENS.len(),
+            res.nodes,
+            res.best_move.map_or("0000".to_string(), |m| m.to_string())
+        ));
+        nodes += res.nodes;
+    }
+
+    let ms = start.elapsed().as_millis().max(1) as u64;
+    reply.push(format!("Total time (ms) : {}", ms));
+    reply.push(format!("Nodes searched  : {}", nodes));
+    reply.push(format!("Nodes/second    : {}", nodes * 1000 / ms));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(input: &str) -> Vec<String> {
+        let mut out = Vec::new();
+        run(std::io::Cursor::new(input.to_string()), &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn handshake_and_mate() {
+        let out = session(
+            "uci\nisready\nposition fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\ngo depth 2\nquit\ngo\n",
+        );
+        assert_eq!(out[0], format!("id name {}", engine_info().id()));
+        assert_eq!(out[4..6], ["uciok", "readyok"]);
+        assert!(out[6].starts_with("info depth 2 score mate 1 nodes "));
+        assert_eq!(out[7], "bestmove a1a8");
+        assert_eq!(out.len(), 8);
+    }
+
+    #[test]
+    fn go_mate() {
+        // The ladder mates in 2, but not in 1: then it's an ordinary search 1 ply deep.
+        let out = session(
+            "position fen 7k/8/8/8/8/8/R7/1R4K1 w - - 0 1
+go mate 2
+go mate 1
+",
+        );
+        assert!(out[0].starts_with("info depth 3 score mate 2 nodes "));
+        assert!(out[0].contains(" pv "));
+        assert!(["bestmove b1b7", "bestmove a2a7"].contains(&out[1].as_str()));
+        assert!(out[2].starts_with("info depth 1 score cp "));
+        assert!(out[3].starts_with("bestmove "));
+        assert_eq!(out.len(), 4);
+    }
+
+    #[test]
+    fn threads_option() {
+        let out = session(
+            "setoption name Threads value 4
+ucinewgame
+position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1
+go depth 3
+setoption name Threads value 0
+",
+        );
+        assert!(out[0].starts_with("info depth 3 score mate 1 nodes "));
+        assert_eq!(out[1], "bestmove a1a8");
+        assert_eq!(out[2], "info string bad thread count: 0");
+    }
+
+    #[test]
+    fn go_on_the_clock() {
+        let out =
+            session("position startpos\ngo wtime 200 btime 200 winc 0 binc 0\ngo movetime x\n");
+        assert!(out[0].starts_with("info depth "));
+        assert!(out[1].starts_with("bestmove "));
+        assert_eq!(out[2], "info string bad movetime: \"x\"");
+    }
+
+    #[test]
+    fn infinite_waits_for_stop() {
+        // Whatever the search has found, the bestmove waits for the stop.
+        let out =
+            session("position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\ngo infinite\nisready\nstop\n");
+        assert_eq!(out[0], "readyok");
+        assert!(out[1].starts_with("info depth "));
+        assert_eq!(out[2], "bestmove a1a8");
+        assert_eq!(out.len(), 3);
+
+        // Commands sent during a search wait for it; the end of input stops it.
+        let out =
+            session("position startpos\ngo infinite\nposition startpos moves e2e4\ngo depth 1\n");
+        assert_eq!(out.len(), 4);
+        assert!(out[1].starts_with("bestmove "));
+        assert!(out[3].starts_with("bestmove "));
+        let out = session("position startpos\ngo infinite\nquit\ngo depth 1\n");
+        assert_eq!(out.len(), 2);
+    }
+
+    #[test]
+    fn ponder_until_ponderhit() {
+        let out = session("position startpos\ngo ponder movetime 50\nponderhit\n");
+        assert!(out[0].starts_with("info depth "));
+        assert!(out[1].starts_with("bestmove "));
+
+        let out = session(
+            "uci\nsetoption name Ponder value true\nposition startpos\ngo ponder wtime 10\nstop\n",
+        );
+        assert!(out.contains(&"option name Ponder type check default false".to_string()));
+        assert!(out.last().unwrap().starts_with("bestmove "));
+        assert!(!out.iter().any(|l| l.contains("unknown option")));
+    }
+
+    #[test]
+    fn bad_input_is_reported() {
+        let out = session("position startpos moves e2e5\nposition fen 8/8 w\nfoo\ngo depth 1\n");
+        assert_eq!(out[0], "info string illegal move: e2e5");
+        assert!(out[1].starts_with("info string "));
+        assert_eq!(out[2], "info string unknown command: foo");
+        assert!(out[4].starts_with("bestmove "));
+    }
+
+    #[test]
+    fn wdl_on_request() {
+        let out = session(
+            "setoption name UCI_ShowWDL value true\nposition fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\ngo depth 2\n",
+        );
+        assert!(out[0].starts_with("info depth 2 score mate 1 wdl 1000 0 0 nodes "));
+    }
+
+    #[test]
+    fn perft_and_bench() {
+        let out = session("position startpos moves e2e4\ngo perft 2\ngo perft x\n");
+        assert_eq!(out.len(), 20 + 2 + 1);
+        assert!(out.contains(&"e7e5: 29".to_string()));
+        assert_eq!(out[20], "");
+        assert_eq!(out[21], "Nodes searched: 600");
+        assert_eq!(out[22], "info string bad perft depth: x");
+
+        let out = session("bench 1\n");
+        assert_eq!(out.len(), STANDARD_FENS.len() + 3);
+        assert!(out[0].starts_with("info string position 1/6 nodes "));
+        assert!(out[STANDARD_FENS.len()].starts_with("Total time (ms) : "));
+        let nodes: u64 = STANDARD_FENS
+            .iter()
+            .map(|fen| {
+                Searcher::new()
+                    .search(&mut Position::new_from_fen(fen), 1)
+                    .nodes
+            })
+            .sum();
+        assert_eq!(
+            out[STANDARD_FENS.len() + 1],
+            format!("Nodes searched  : {}", nodes)
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use crate::piece::PieceType;
+
+// Centipawn values shared by everything that needs to weigh material against itself.
+// The king is never actually traded, so it carries no material value.
+pub const PAWN: i32 = 100;
+pub const KNIGHT: i32 = 320;
+pub const BISHOP: i32 = 330;
+pub const ROOK: i32 = 500;
+pub const QUEEN: i32 = 900;
+pub const KING: i32 = 0;
+
+pub const PIECE_VALUES: [i32; 6] = [PAWN, KNIGHT, BISHOP, ROOK, QUEEN, KING];
+
+// Game phase weights. A full set of non-pawn material adds up to `TOTAL_PHASE`,
+// and a bare-kings-and-pawns board is zero.
+pub const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const TOTAL_PHASE: i32 = 24;
+
+#[cfg_attr(feature = "inline", inline)]
+pub const fn piece_value(kind: PieceType) -> i32 {
+    PIECE_VALUES[kind as usize]
+}
+#[cfg_attr(feature = "inline", inline)]
+pub const fn phase_weight(kind: PieceType) -> i32 {
+    PHASE_WEIGHTS[kind as usize]
+}
+// Material gained by promoting to `kind`, i.e. the new piece minus the pawn it replaces.
+#[cfg_attr(feature = "inline", inline)]
+pub const fn promotion_delta(kind: PieceType) -> i32 {
+    piece_value(kind) - PAWN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PieceType::*;
+
+    #[test]
+    fn phase_weights_sum_to_total() {
+        let per_side = 2 * phase_weight(Knight)
+            + 2 * phase_weight(Bishop)
+            + 2 * phase_weight(Rook)
+            + phase_weight(Queen);
+        assert_eq!(2 * per_side, TOTAL_PHASE);
+    }
+
+    #[test]
+    fn promotion_deltas() {
+        assert_eq!(promotion_delta(Queen), 800);
+        assert_eq!(promotion_delta(Knight), 220);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::piece::PieceType;
+use crate::position::Position;
+use crate::search::is_mate;
+
+// Win/draw/loss chances in per mille, adding up to 1000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wdl {
+    pub win: u32,
+    pub draw: u32,
+    pub loss: u32,
+}
+
+// Turns a centipawn score into win/draw/loss chances. The chance of winning is logistic in the
+// score, `1 / (1 + exp((a - score) / b))`, and losing is the same for the negated score. `a` is
+// the score that wins half the time, `b` how quickly that changes; both are cubics in the
+// material left (as a fraction of the starting material), since the same edge wins more often
+// with less on the board. The coefficients are highest power first.
+//
+// They have to be fitted to this engine's own games (scores against results), as every
+// evaluation has its own scale. Refit whenever the evaluation changes noticeably.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WdlModel {
+    pub a: [f64; 4],
+    pub b: [f64; 4],
+}
+
+impl WdlModel {
+    pub const DEFAULT: Self = Self {
+        a: [0.0, -60.0, 160.0, 150.0],
+        b: [0.0, 0.0, 40.0, 60.0],
+    };
+
+    pub fn wdl(&self, score: i32, pos: &Position) -> Wdl {
+        if is_mate(score) {
+            return if score > 0 {
+                Wdl {
+                    win: 1000,
+                    draw: 0,
+                    loss: 0,
+                }
+            } else {
+                Wdl {
+                    win: 0,
+                    draw: 0,
+                    loss: 1000,
+                }
+            };
+        }
+
+        let m = (material(pos) as f64 / 78.0).clamp(0.0, 1.0);
+        let poly = |c: [f64; 4]| ((c[0] * m + c[1]) * m + c[2]) * m + c[3];
+        let (a, b) = (poly(self.a), poly(self.b).max(1.0));
+
+        let chance = |x: f64| (1000.0 / (1.0 + ((a - x) / b).exp())).round() as u32;
+        let win = chance(score as f64);
+        let loss = chance(-score as f64);
+        Wdl {
+            win,
+            draw: 1000u32.saturating_sub(win + loss),
+            loss,
+        }
+    }
+}
+
+impl Default for WdlModel {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl std::fmt::Display for Wdl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.win, self.draw, self.loss)
+    }
+}
+
+// Material on the board in the usual 1/3/3/5/9 pawn units, 78 at the start.
+pub fn material(pos: &Position) -> i32 {
+    [
+        (PieceType::Pawn, 1),
+        (PieceType::Knight, 3),
+        (PieceType::Bishop, 3),
+        (PieceType::Rook, 5),
+        (PieceType::Queen, 9),
+    ]
+    .into_iter()
+    .map(|(kind, units)| pos.pieces(kind).popcount() * units)
+    .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{mate_in, mated_in};
+
+    #[test]
+    fn wdl_adds_up_and_is_symmetric() {
+        let pos = Position::default();
+        assert_eq!(material(&pos), 78);
+
+        let model = WdlModel::DEFAULT;
+        for score in [-800, -150, -20, 0, 20, 150, 800] {
+            let wdl = model.wdl(score, &pos);
+            assert_eq!(wdl.win + wdl.draw + wdl.loss, 1000);
+
+            let flipped = model.wdl(-score, &pos);
+            assert_eq!((wdl.win, wdl.loss), (flipped.loss, flipped.win));
+        }
+
+        let even = model.wdl(0, &pos);
+        assert!(even.draw > even.win && even.win == even.loss);
+        assert!(model.wdl(300, &pos).win > model.wdl(100, &pos).win);
+    }
+
+    #[test]
+    fn less_material_converts_better() {
+        let model = WdlModel::DEFAULT;
+        let opening = model.wdl(200, &Position::default());
+        let endgame = model.wdl(
+            200,
+            &Position::new_from_fen("4k3/4p3/8/8/8/8/3PP3/4K3 w - - 0 1"),
+        );
+        assert!(endgame.win > opening.win);
+    }
+
+    #[test]
+    fn mates_are_certain() {
+        let pos = Position::default();
+        let model = WdlModel::DEFAULT;
+        assert_eq!(model.wdl(mate_in(3), &pos).to_string(), "1000 0 0");
+        assert_eq!(model.wdl(mated_in(4), &pos).to_string(), "0 0 1000");
+    }
+}
@@ -0,0 +1,89 @@
+use crate::piece::Piece;
+use crate::square::Square;
+
+// Random keys for Zobrist hashing: a position's key is the XOR of the keys of everything in it,
+// so a move only has to XOR out what it removes and XOR in what it adds. Generated at compile
+// time from a fixed seed, so keys are the same from one build to the next.
+
+const fn splitmix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+struct Keys {
+    pieces: [[u64; 64]; 12],
+    castling: [u64; 16],
+    en_passant: [u64; 8],
+    side: u64,
+}
+
+const KEYS: Keys = {
+    let mut state = 0x00F0_CB77_2024_0001;
+    let mut keys = Keys {
+        pieces: [[0; 64]; 12],
+        castling: [0; 16],
+        en_passant: [0; 8],
+        side: 0,
+    };
+
+    let mut p = 0;
+    while p < 12 {
+        let mut s = 0;
+        while s < 64 {
+            keys.pieces[p][s] = splitmix(&mut state);
+            s += 1;
+        }
+        p += 1;
+    }
+
+    // Each castling right gets its own key, and a set of rights the XOR of theirs.
+    let mut rights = [0; 4];
+    let mut r = 0;
+    while r < 4 {
+        rights[r] = splitmix(&mut state);
+        r += 1;
+    }
+    let mut set = 0;
+    while set < 16 {
+        let mut r = 0;
+        while r < 4 {
+            if set & (1 << r) != 0 {
+                keys.castling[set] ^= rights[r];
+            }
+            r += 1;
+        }
+        set += 1;
+    }
+
+    let mut f = 0;
+    while f < 8 {
+        keys.en_passant[f] = splitmix(&mut state);
+        f += 1;
+    }
+
+    keys.side = splitmix(&mut state);
+    keys
+};
+
+#[cfg_attr(feature = "inline", inline)]
+pub(crate) const fn piece(piece: Piece, square: Square) -> u64 {
+    KEYS.pieces[piece.color() as usize * 6 + piece.kind() as usize][square as usize]
+}
+// For the rights as stored in `State::castle_rights`.
+#[cfg_attr(feature = "inline", inline)]
+pub(crate) const fn castling(rights: u8) -> u64 {
+    KEYS.castling[(rights & 0xF) as usize]
+}
+// Only the file matters, as the rank follows from the side to move.
+#[cfg_attr(feature = "inline", inline)]
+pub(crate) const fn en_passant(square: Square) -> u64 {
+    KEYS.en_passant[square.file() as usize]
+}
+// XORed in when Black is to move.
+#[cfg_attr(feature = "inline", inline)]
+pub(crate) const fn side() -> u64 {
+    KEYS.side
+}